@@ -16,9 +16,16 @@ ARG TARGETPLATFORM
 COPY zig.sh /
 RUN /zig.sh $TARGETPLATFORM
 
+# the runner is selected by cross for each target, since the image is
+# shared by all targets. binaries of other architectures are emulated
+# with qemu-user, which only works for statically linked binaries.
+RUN apt-get update && apt-get install --assume-yes --no-install-recommends \
+    qemu-user-static
+COPY zig-runner base-runner.sh /
+
 # we don't export `BINDGEN_EXTRA_CLANG_ARGS`, `QEMU_LD_PREFIX`, or
 # `PKG_CONFIG_PATH` since zig doesn't have a traditional sysroot structure,
-# and we're not using standard, shared packages. none of the packages
-# have runners either, since they do not ship with the required
-# dynamic linker (`ld-linux-${arch}.so`).
+# and we're not using standard, shared packages. dynamically linked
+# binaries of other architectures cannot be run, since zig does not ship
+# with the required dynamic linker (`ld-linux-${arch}.so`).
 ENV PATH=$PATH:/opt/zig
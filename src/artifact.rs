@@ -0,0 +1,240 @@
+//! Verify the architecture of the binaries produced by the build, enabled
+//! with `verify-artifacts`.
+//!
+//! The header of every ELF file cargo reported in the artifact directory is
+//! compared with the machine, class, byte order, and OS/ABI expected for the
+//! target triple, so a misconfigured image or linker fails the build instead
+//! of producing binaries for the wrong architecture. Other files, such as
+//! rlibs, and targets which do not produce ELF files are skipped.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use color_eyre::SectionExt;
+
+use crate::errors::*;
+use crate::file::ToUtf8;
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ELFOSABI_NONE: u8 = 0;
+const ELFOSABI_GNU: u8 = 3;
+const ELFOSABI_FREEBSD: u8 = 9;
+
+/// The fields of an ELF header identifying the platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ElfHeader {
+    is_64bit: bool,
+    little_endian: bool,
+    osabi: u8,
+    machine: u16,
+}
+
+impl ElfHeader {
+    fn parse(bytes: &[u8]) -> Option<ElfHeader> {
+        if bytes.len() < 20 || &bytes[..4] != ELF_MAGIC {
+            return None;
+        }
+        let little_endian = bytes[5] == ELFDATA2LSB;
+        let machine = [bytes[18], bytes[19]];
+        Some(ElfHeader {
+            is_64bit: bytes[4] != ELFCLASS32,
+            little_endian,
+            osabi: bytes[7],
+            machine: match little_endian {
+                true => u16::from_le_bytes(machine),
+                false => u16::from_be_bytes(machine),
+            },
+        })
+    }
+
+    fn read(path: &Path) -> Result<Option<ElfHeader>> {
+        let mut bytes = vec![];
+        fs::File::open(path)
+            .and_then(|file| file.take(20).read_to_end(&mut bytes))
+            .wrap_err_with(|| format!("could not read artifact `{path:?}`"))?;
+        Ok(ElfHeader::parse(&bytes))
+    }
+}
+
+impl fmt::Display for ElfHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ELF machine {}, {}, {}, OS/ABI {}",
+            self.machine,
+            if self.is_64bit { "64-bit" } else { "32-bit" },
+            if self.little_endian {
+                "little-endian"
+            } else {
+                "big-endian"
+            },
+            self.osabi,
+        )
+    }
+}
+
+/// The ELF header expected for the artifacts of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Expected {
+    is_64bit: bool,
+    little_endian: bool,
+    machine: u16,
+    osabi: Option<&'static [u8]>,
+}
+
+impl Expected {
+    fn for_triple(triple: &str) -> Option<Expected> {
+        let (arch, rest) = triple.split_once('-')?;
+        if ["windows", "apple", "darwin", "wasm", "emscripten", "uefi"]
+            .iter()
+            .any(|os| rest.contains(os))
+        {
+            return None;
+        }
+        let big_endian = arch.ends_with("_be")
+            || arch.starts_with("armeb")
+            || matches!(
+                arch,
+                "powerpc" | "powerpc64" | "s390x" | "sparc" | "sparc64" | "sparcv9"
+            )
+            || (arch.starts_with("mips") && !arch.ends_with("el"));
+        let (machine, is_64bit) = match arch {
+            "x86_64" => (62, !rest.ends_with("gnux32")),
+            "i386" | "i586" | "i686" => (3, false),
+            "aarch64" | "aarch64_be" => (183, true),
+            "powerpc" => (20, false),
+            "powerpc64" | "powerpc64le" => (21, true),
+            "s390x" => (22, true),
+            "sparc" => (2, false),
+            "sparc64" | "sparcv9" => (43, true),
+            "loongarch64" => (258, true),
+            _ if arch.starts_with("arm") || arch.starts_with("thumb") => (40, false),
+            _ if arch.starts_with("mips64") || arch.starts_with("mipsisa64") => (8, true),
+            _ if arch.starts_with("mips") => (8, false),
+            _ if arch.starts_with("riscv64") => (243, true),
+            _ if arch.starts_with("riscv32") => (243, false),
+            _ => return None,
+        };
+        let osabi: Option<&'static [u8]> = if rest.contains("freebsd") {
+            Some(&[ELFOSABI_FREEBSD])
+        } else if rest.contains("linux") {
+            Some(&[ELFOSABI_NONE, ELFOSABI_GNU])
+        } else {
+            None
+        };
+        Some(Expected {
+            is_64bit,
+            little_endian: !big_endian,
+            machine,
+            osabi,
+        })
+    }
+
+    fn matches(&self, header: &ElfHeader) -> bool {
+        header.machine == self.machine
+            && header.is_64bit == self.is_64bit
+            && header.little_endian == self.little_endian
+            && self
+                .osabi
+                .map_or(true, |osabi| osabi.contains(&header.osabi))
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ELF machine {}, {}, {}",
+            self.machine,
+            if self.is_64bit { "64-bit" } else { "32-bit" },
+            if self.little_endian {
+                "little-endian"
+            } else {
+                "big-endian"
+            },
+        )?;
+        if let Some(osabi) = self.osabi {
+            let osabi: Vec<_> = osabi.iter().map(u8::to_string).collect();
+            write!(f, ", OS/ABI {}", osabi.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the ELF files of `artifacts`, as reported by cargo, were built for
+/// `triple`.
+pub fn verify(artifacts: &[PathBuf], triple: &str) -> Result<()> {
+    let Some(expected) = Expected::for_triple(triple) else {
+        return Ok(());
+    };
+    let mut mismatches = vec![];
+    for path in artifacts {
+        if let Some(header) = ElfHeader::read(path)? {
+            if !expected.matches(&header) {
+                mismatches.push(format!("{}: {header}", path.to_utf8()?));
+            }
+        }
+    }
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    Err(eyre::eyre!(
+        "{} artifacts were not built for `{triple}`, which expects {expected}",
+        mismatches.len()
+    ))
+    .with_section(|| mismatches.join("\n").header("Artifacts:"))
+    .with_suggestion(|| {
+        format!("check the image, linker, and `build-env` configured for `{triple}`")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(is_64bit: bool, little_endian: bool, osabi: u8, machine: u16) -> Vec<u8> {
+        let mut bytes = ELF_MAGIC.to_vec();
+        bytes.push(if is_64bit { 2 } else { ELFCLASS32 });
+        bytes.push(if little_endian { ELFDATA2LSB } else { 2 });
+        bytes.extend([1, osabi]);
+        bytes.extend([0; 10]);
+        bytes.extend(match little_endian {
+            true => machine.to_le_bytes(),
+            false => machine.to_be_bytes(),
+        });
+        bytes
+    }
+
+    #[test]
+    fn artifact_architectures() -> Result<()> {
+        let aarch64 = Expected::for_triple("aarch64-unknown-linux-gnu").unwrap();
+        let arm64_elf = ElfHeader::parse(&header(true, true, ELFOSABI_NONE, 183)).unwrap();
+        let x86_64_elf = ElfHeader::parse(&header(true, true, ELFOSABI_NONE, 62)).unwrap();
+        assert!(aarch64.matches(&arm64_elf));
+        assert!(!aarch64.matches(&x86_64_elf));
+
+        let mips = Expected::for_triple("mips-unknown-linux-gnu").unwrap();
+        assert!(mips.matches(&ElfHeader::parse(&header(false, false, ELFOSABI_NONE, 8)).unwrap()));
+        assert!(!mips.matches(&ElfHeader::parse(&header(false, true, ELFOSABI_NONE, 8)).unwrap()));
+        let freebsd = Expected::for_triple("x86_64-unknown-freebsd").unwrap();
+        assert!(!freebsd.matches(&x86_64_elf));
+        assert!(Expected::for_triple("x86_64-pc-windows-gnu").is_none());
+        assert!(ElfHeader::parse(b"!<arch>\n").is_none());
+
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        fs::write(dir.join("hello"), header(true, true, ELFOSABI_NONE, 183))?;
+        fs::write(dir.join("libhello.rlib"), b"!<arch>\n")?;
+        let artifacts = [dir.join("hello"), dir.join("libhello.rlib")];
+        verify(&artifacts, "aarch64-unknown-linux-gnu")?;
+        let err = verify(&artifacts, "x86_64-unknown-linux-gnu").unwrap_err();
+        assert!(err.to_string().contains("1 artifacts were not built"));
+
+        Ok(())
+    }
+}
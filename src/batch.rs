@@ -0,0 +1,477 @@
+//! `cross batch jobs.json`: run the builds described by a job file, such as
+//! the release builds of a project.
+//!
+//! The jobs run one after the other in the same workspace and share their
+//! setup, so the toolchain is set up and the images are pulled once, while
+//! the target directory keeps the dependencies built for each target. The
+//! outcome of every job is written to a summary, by default
+//! `<target-dir>/cross-batch.json`.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use color_eyre::SectionExt;
+use serde::{Deserialize, Serialize};
+
+use crate::cargo;
+use crate::cli::{self, Args};
+use crate::errors::*;
+use crate::file::{self, write_file, ToUtf8};
+use crate::report::BuildReport;
+use crate::rustc::TargetList;
+use crate::setup_cache::SetupCache;
+use crate::shell::MessageInfo;
+
+/// Version of the summary schema, incremented on breaking changes.
+pub const BATCH_SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct JobFile {
+    jobs: Vec<Job>,
+}
+
+/// A single build of the job file.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Job {
+    name: Option<String>,
+    target: String,
+    #[serde(default = "default_subcommand")]
+    subcommand: String,
+    profile: Option<String>,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    no_default_features: bool,
+    #[serde(default)]
+    all_features: bool,
+    /// Additional arguments, such as `["--package", "foo"]`.
+    #[serde(default)]
+    args: Vec<String>,
+    /// The directory to copy the artifacts to, relative to the job file.
+    artifacts_dir: Option<PathBuf>,
+}
+
+fn default_subcommand() -> String {
+    "build".to_owned()
+}
+
+impl Job {
+    fn name(&self) -> String {
+        match (&self.name, &self.profile) {
+            (Some(name), _) => name.clone(),
+            (None, Some(profile)) => format!("{} ({profile})", self.target),
+            (None, None) => self.target.clone(),
+        }
+    }
+
+    /// The arguments of `cross` for the job.
+    fn cross_args(&self, args: &Args) -> Result<Vec<String>> {
+        let mut cross_args = vec![];
+        if let Some(channel) = &args.channel {
+            cross_args.push(format!("+{channel}"));
+        }
+        cross_args.extend([
+            self.subcommand.clone(),
+            "--target".to_owned(),
+            self.target.clone(),
+        ]);
+        if let Some(profile) = &self.profile {
+            cross_args.extend(["--profile".to_owned(), profile.clone()]);
+        }
+        if !self.features.is_empty() {
+            cross_args.extend(["--features".to_owned(), self.features.join(",")]);
+        }
+        if self.no_default_features {
+            cross_args.push("--no-default-features".to_owned());
+        }
+        if self.all_features {
+            cross_args.push("--all-features".to_owned());
+        }
+        if let Some(path) = &args.manifest_path {
+            cross_args.extend(["--manifest-path".to_owned(), path.to_utf8()?.to_owned()]);
+        }
+        if let Some(dir) = &args.target_dir {
+            cross_args.extend(["--target-dir".to_owned(), dir.to_utf8()?.to_owned()]);
+        }
+        if let Some(profile) = &args.config_profile {
+            cross_args.extend(["--config-profile".to_owned(), profile.clone()]);
+        }
+        cross_args.extend(self.args.iter().cloned());
+        Ok(cross_args)
+    }
+}
+
+/// The arguments of `cross batch`.
+#[derive(Debug, PartialEq, Eq)]
+struct BatchArgs {
+    path: PathBuf,
+    keep_going: bool,
+    summary: Option<PathBuf>,
+}
+
+impl BatchArgs {
+    fn parse(cross_args: &Args) -> Result<BatchArgs> {
+        let mut path = None;
+        let mut keep_going = false;
+        let mut summary = None;
+        // skip the subcommand
+        let mut args = cross_args.cargo_args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            // the flags passed to every job were already parsed by cross
+            let parsed = [
+                ("--target-dir", cross_args.target_dir.is_some()),
+                ("--manifest-path", cross_args.manifest_path.is_some()),
+            ]
+            .into_iter()
+            .find(|(flag, is_set)| {
+                *is_set && (arg == flag || arg.starts_with(&format!("{flag}=")))
+            });
+            if let Some((flag, _)) = parsed {
+                if arg == flag {
+                    args.next();
+                }
+            } else if arg == "--keep-going" {
+                keep_going = true;
+            } else if arg == "--summary" {
+                let value = args
+                    .next()
+                    .ok_or_else(|| eyre::eyre!("`--summary` requires a value"))?;
+                summary = Some(PathBuf::from(value));
+            } else if let Some(value) = arg.strip_prefix("--summary=") {
+                summary = Some(PathBuf::from(value));
+            } else if arg == "--color" {
+                args.next();
+            } else if arg.starts_with('-') || path.is_some() {
+                // verbosity and color were already parsed by cross
+                let is_parsed = cli::is_verbose(arg) != 0
+                    || matches!(arg.as_str(), "--quiet" | "-q")
+                    || arg.starts_with("--color=");
+                if !is_parsed {
+                    eyre::bail!("unexpected argument `{arg}` for `cross batch`");
+                }
+            } else {
+                path = Some(PathBuf::from(arg));
+            }
+        }
+        let path = path
+            .ok_or_else(|| eyre::eyre!("`cross batch` requires a job file"))
+            .with_suggestion(|| "run `cross batch jobs.json`")?;
+        Ok(BatchArgs {
+            path,
+            keep_going,
+            summary,
+        })
+    }
+}
+
+/// The outcome of a job.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct JobSummary {
+    name: String,
+    target: String,
+    success: bool,
+    /// The job was not run, since a previous job failed.
+    skipped: bool,
+    exit_code: Option<i32>,
+    duration_ms: u64,
+    artifacts: Vec<PathBuf>,
+    error: Option<String>,
+}
+
+impl JobSummary {
+    fn new(job: &Job) -> JobSummary {
+        JobSummary {
+            name: job.name(),
+            target: job.target.clone(),
+            success: false,
+            skipped: false,
+            exit_code: None,
+            duration_ms: 0,
+            artifacts: vec![],
+            error: None,
+        }
+    }
+
+    fn finish(&mut self, result: Result<(ExitStatus, Vec<PathBuf>)>, duration: Duration) {
+        self.duration_ms = duration.as_millis().try_into().unwrap_or(u64::MAX);
+        match result {
+            Ok((status, artifacts)) => {
+                self.success = status.success();
+                self.exit_code = status.code();
+                self.artifacts = artifacts;
+            }
+            Err(err) => self.error = Some(format!("{err:#}")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct BatchSummary {
+    schema_version: u32,
+    success: bool,
+    jobs: Vec<JobSummary>,
+}
+
+fn read_jobs(path: &Path) -> Result<Vec<Job>> {
+    let contents = file::read(path)?;
+    let jobs: JobFile =
+        serde_json::from_str(&contents).wrap_err_with(|| format!("invalid job file `{path:?}`"))?;
+    if jobs.jobs.is_empty() {
+        eyre::bail!("job file `{path:?}` has no jobs");
+    }
+    Ok(jobs.jobs)
+}
+
+/// Copy the `artifacts` cargo reported for the build to `dir`.
+fn copy_artifacts(artifacts: &[PathBuf], dir: &Path) -> Result<Vec<PathBuf>> {
+    file::create_dir_all(dir)?;
+    let mut copied = vec![];
+    for artifact in artifacts {
+        let Some(name) = artifact.file_name() else {
+            continue;
+        };
+        let path = dir.join(name);
+        fs::copy(artifact, &path)
+            .wrap_err_with(|| format!("could not copy `{artifact:?}` to `{path:?}`"))?;
+        copied.push(path);
+    }
+    Ok(copied)
+}
+
+fn run_job(
+    job: &Job,
+    args: &Args,
+    target_list: &TargetList,
+    job_dir: &Path,
+    setup_cache: &SetupCache,
+    msg_info: &mut MessageInfo,
+) -> Result<(ExitStatus, Vec<PathBuf>)> {
+    let cross_args = job.cross_args(args)?;
+    let mut job_args = cli::parse_args(cross_args.clone(), target_list)?;
+    job_args.verbose = args.verbose;
+    job_args.quiet = args.quiet;
+    job_args.color = args.color.clone();
+
+    // the report records the artifacts cargo reports for the build
+    let mut report = job.artifacts_dir.as_ref().map(|_| BuildReport::new());
+    let status = match crate::run_build(
+        job_args,
+        target_list.clone(),
+        None,
+        report.as_mut(),
+        Some(setup_cache),
+        msg_info,
+    )? {
+        Some(status) => status,
+        None if msg_info.should_fail() => {
+            eyre::bail!("errors were encountered before the build")
+        }
+        // targets which do not need a container are built on the host.
+        None => {
+            if job.artifacts_dir.is_some() {
+                msg_info.note(format_args!(
+                    "the artifacts of `{}` are not copied, since it is built on the host.",
+                    job.name()
+                ))?;
+            }
+            report = None;
+            cargo::run(&cli::strip_cross_args(cross_args), msg_info)?
+        }
+    };
+    let artifacts = match (&job.artifacts_dir, report) {
+        (Some(dir), Some(report)) if status.success() => {
+            copy_artifacts(report.artifacts(), &job_dir.join(dir))?
+        }
+        _ => vec![],
+    };
+    Ok((status, artifacts))
+}
+
+pub(crate) fn run(
+    args: Args,
+    target_list: TargetList,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<ExitStatus>> {
+    let batch = BatchArgs::parse(&args)?;
+    let jobs = read_jobs(&batch.path)?;
+    let job_dir = match batch.path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => std::env::current_dir()?,
+    };
+    let summary_path = match &batch.summary {
+        Some(path) => path.clone(),
+        None => crate::cargo_metadata_with_args(None, Some(&args), msg_info)?
+            .map(|metadata| metadata.target_directory.join("cross-batch.json"))
+            .ok_or_else(|| eyre::eyre!("could not find the target directory of the workspace"))?,
+    };
+
+    // the toolchains set up by a job are not checked again by the next ones
+    let setup_cache =
+        SetupCache::or_disabled(SetupCache::new(!args.no_cache_setup), msg_info)?.shared();
+    let mut summaries = vec![];
+    let mut failed = false;
+    for job in &jobs {
+        let mut summary = JobSummary::new(job);
+        if failed && !batch.keep_going {
+            summary.skipped = true;
+            summaries.push(summary);
+            continue;
+        }
+        msg_info.status(format_args!("running job `{}`", summary.name))?;
+        let start = Instant::now();
+        let result = run_job(job, &args, &target_list, &job_dir, &setup_cache, msg_info);
+        summary.finish(result, start.elapsed());
+        if let Some(error) = &summary.error {
+            msg_info.error(format_args!("job `{}` failed: {error}", summary.name))?;
+        }
+        failed |= !summary.success;
+        summaries.push(summary);
+    }
+
+    let summary = BatchSummary {
+        schema_version: BATCH_SUMMARY_SCHEMA_VERSION,
+        success: !failed,
+        jobs: summaries,
+    };
+    write_file(&summary_path, true)?
+        .write_all(serde_json::to_string_pretty(&summary)?.as_bytes())
+        .wrap_err_with(|| format!("could not write batch summary to `{summary_path:?}`"))?;
+
+    let failures: Vec<_> = summary
+        .jobs
+        .iter()
+        .filter(|job| !job.success)
+        .map(|job| match job.skipped {
+            true => format!("{}: skipped", job.name),
+            false => format!("{}: failed", job.name),
+        })
+        .collect();
+    if !failures.is_empty() {
+        return Err(eyre::eyre!(
+            "{} of {} jobs did not succeed",
+            failures.len(),
+            summary.jobs.len()
+        ))
+        .with_section(|| failures.join("\n").header("Jobs:"))
+        .with_note(|| format!("the summary was written to `{summary_path:?}`"));
+    }
+    msg_info.status(format_args!(
+        "{} jobs succeeded, the summary was written to `{}`",
+        summary.jobs.len(),
+        summary_path.to_utf8()?
+    ))?;
+    Ok(Some(ExitStatus::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    fn batch_args(cross_args: &[&str]) -> Result<BatchArgs> {
+        let target_list = TargetList { triples: vec![] };
+        BatchArgs::parse(&cli::parse_args(args(cross_args), &target_list)?)
+    }
+
+    #[test]
+    fn batch_jobs() -> Result<()> {
+        assert_eq!(
+            batch_args(&["batch", "ci/jobs.json", "--keep-going", "-v"])?,
+            BatchArgs {
+                path: PathBuf::from("ci/jobs.json"),
+                keep_going: true,
+                summary: None,
+            }
+        );
+        assert_eq!(
+            batch_args(&["batch", "--summary=out.json", "jobs.json"])?.summary,
+            Some(PathBuf::from("out.json"))
+        );
+        assert!(batch_args(&["batch"]).is_err());
+        assert!(batch_args(&["batch", "a.json", "b.json"]).is_err());
+        assert!(batch_args(&["batch", "jobs.json", "--release"]).is_err());
+        // the flags passed to every job are parsed by cross
+        assert_eq!(
+            batch_args(&[
+                "batch",
+                "jobs.json",
+                "--target-dir",
+                "/tmp/target",
+                "--manifest-path=Cargo.toml",
+                "--color",
+                "never",
+            ])?
+            .path,
+            PathBuf::from("jobs.json")
+        );
+
+        let jobs: JobFile = serde_json::from_str(
+            r#"{"jobs": [
+                {"target": "aarch64-unknown-linux-gnu", "profile": "release", "features": ["a", "b"], "artifacts-dir": "dist/aarch64"},
+                {"name": "windows", "target": "x86_64-pc-windows-gnu", "subcommand": "test", "no-default-features": true, "args": ["-p", "foo"]}
+            ]}"#,
+        )?;
+        let [release, windows] = &jobs.jobs[..] else {
+            panic!("expected 2 jobs");
+        };
+        assert_eq!(release.name(), "aarch64-unknown-linux-gnu (release)");
+        assert_eq!(windows.name(), "windows");
+
+        let target_list = TargetList { triples: vec![] };
+        let mut batch_args = cli::parse_args(
+            args(&[
+                "+nightly",
+                "batch",
+                "jobs.json",
+                "--target-dir",
+                "/tmp/target",
+            ]),
+            &target_list,
+        )?;
+        assert_eq!(
+            release.cross_args(&batch_args)?,
+            args(&[
+                "+nightly",
+                "build",
+                "--target",
+                "aarch64-unknown-linux-gnu",
+                "--profile",
+                "release",
+                "--features",
+                "a,b",
+                "--target-dir",
+                "/tmp/target",
+            ])
+        );
+        batch_args.channel = None;
+        batch_args.target_dir = None;
+        assert_eq!(
+            windows.cross_args(&batch_args)?,
+            args(&[
+                "test",
+                "--target",
+                "x86_64-pc-windows-gnu",
+                "--no-default-features",
+                "-p",
+                "foo",
+            ])
+        );
+
+        assert!(
+            serde_json::from_str::<JobFile>(r#"{"jobs": [{"target": "a", "feature": []}]}"#)
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
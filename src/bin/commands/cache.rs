@@ -0,0 +1,66 @@
+use std::time::SystemTime;
+
+use clap::{Args, Subcommand};
+use cross::shell::MessageInfo;
+use cross::temp;
+
+#[derive(Args, Debug)]
+pub struct CleanCache {
+    /// Only evict the least recently used entries, until the cache is at most this size, such as `5G`.
+    #[clap(long)]
+    pub max_size: Option<String>,
+    /// Remove the cache entries. Default is a dry run.
+    #[clap(short, long)]
+    pub execute: bool,
+}
+
+impl CleanCache {
+    pub fn run(&self, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        // the cache directory of the workspace in the current directory
+        cross::configure_cache_dir(None)?;
+        let entries = temp::cache_entries()?;
+        let entries = match &self.max_size {
+            Some(size) => {
+                temp::entries_to_evict(entries, temp::parse_size(size)?, SystemTime::now())
+            }
+            None => entries,
+        };
+        for entry in &entries {
+            match self.execute {
+                true => temp::remove_entry(entry)?,
+                false => {
+                    let function = match entry.path.is_dir() {
+                        true => "fs::remove_dir_all",
+                        false => "fs::remove_file",
+                    };
+                    msg_info.print(format_args!(
+                        "{function}({})",
+                        cross::pretty_path(&entry.path, |_| false)
+                    ))?;
+                }
+            }
+        }
+        if self.execute {
+            let size: u64 = entries.iter().map(|e| e.size).sum();
+            msg_info.info(format_args!(
+                "removed {} cache entries, {size} bytes",
+                entries.len()
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Cache {
+    /// Remove temporary files and fingerprints from the cache directory.
+    Clean(CleanCache),
+}
+
+impl Cache {
+    pub fn run(&self, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        match self {
+            Cache::Clean(args) => args.run(msg_info),
+        }
+    }
+}
@@ -19,34 +19,52 @@ pub struct Clean {
     /// Container engine (such as docker or podman).
     #[clap(long)]
     pub engine: Option<String>,
+    /// Clean the data of every container engine found, such as both docker
+    /// and podman, reporting the totals of each.
+    #[clap(long, conflicts_with = "engine")]
+    pub all_engines: bool,
 }
 
 impl Clean {
     pub fn run(
         &self,
-        engine: cross::docker::Engine,
+        engines: &[cross::docker::Engine],
         msg_info: &mut MessageInfo,
     ) -> cross::Result<()> {
-        let tempdir = cross::temp::dir()?;
-        match self.execute {
-            true => {
-                if tempdir.exists() {
-                    fs::remove_dir_all(tempdir)?;
+        for dir in [cross::temp::dir()?, cross::temp::fingerprint_dir()?] {
+            match self.execute {
+                true => {
+                    if dir.exists() {
+                        fs::remove_dir_all(dir)?;
+                    }
                 }
+                false => msg_info.print(format_args!(
+                    "fs::remove_dir_all({})",
+                    cross::pretty_path(&dir, |_| false)
+                ))?,
             }
-            false => msg_info.print(format_args!(
-                "fs::remove_dir_all({})",
-                cross::pretty_path(&tempdir, |_| false)
-            ))?,
         }
 
+        for engine in engines {
+            self.clean_engine(engine, msg_info)?;
+        }
+
+        Ok(())
+    }
+
+    fn clean_engine(
+        &self,
+        engine: &cross::docker::Engine,
+        msg_info: &mut MessageInfo,
+    ) -> cross::Result<()> {
         // containers -> images -> volumes -> prune to ensure no conflicts.
         let remove_containers = RemoveAllContainers {
             force: self.force,
             execute: self.execute,
             engine: None,
+            all_engines: false,
         };
-        remove_containers.run(engine.clone(), msg_info)?;
+        let containers = remove_all_containers(&remove_containers, engine, msg_info)?;
 
         let remove_images = RemoveImages {
             targets: vec![],
@@ -54,26 +72,39 @@ impl Clean {
             local: self.local,
             execute: self.execute,
             engine: None,
+            all_engines: false,
         };
-        remove_images.run(engine.clone(), msg_info)?;
+        let images = remove_images.remove(engine, msg_info)?;
 
         let remove_volumes = RemoveAllVolumes {
             force: self.force,
             execute: self.execute,
             engine: None,
         };
-        remove_volumes.run(engine.clone(), msg_info)?;
+        let volumes = remove_all_volumes(&remove_volumes, engine, msg_info)?;
 
         let prune_volumes = PruneVolumes {
             execute: self.execute,
             engine: None,
         };
-        prune_volumes.run(engine, msg_info)?;
+        prune_volumes.run(engine.clone(), msg_info)?;
 
+        if self.all_engines {
+            let totals = [
+                (containers, "container"),
+                (images, "image"),
+                (volumes, "volume"),
+            ];
+            super::print_engine_totals(engine, &totals, self.execute, msg_info)?;
+        }
         Ok(())
     }
 
     pub fn engine(&self) -> Option<&str> {
         self.engine.as_deref()
     }
+
+    pub fn all_engines(&self) -> bool {
+        self.all_engines
+    }
 }
@@ -1,13 +1,21 @@
 use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use clap::{Args, Subcommand};
+use color_eyre::Section;
 use cross::docker::ImagePlatform;
 use cross::rustc::{QualifiedToolchain, Toolchain};
 use cross::shell::{MessageInfo, Stream};
 use cross::{docker, CommandExt, TargetTriple};
+use eyre::Context;
 
 #[derive(Args, Debug)]
 pub struct ListVolumes {
+    /// Only list the volumes whose toolchain is not installed, or whose
+    /// workspaces no longer exist.
+    #[clap(long)]
+    pub orphaned: bool,
     /// Container engine (such as docker or podman).
     #[clap(long)]
     pub engine: Option<String>,
@@ -15,7 +23,10 @@ pub struct ListVolumes {
 
 impl ListVolumes {
     pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
-        list_volumes(&engine, msg_info)
+        match self.orphaned {
+            true => list_orphaned_volumes(&engine, msg_info),
+            false => list_volumes(&engine, msg_info),
+        }
     }
 }
 
@@ -34,7 +45,7 @@ pub struct RemoveAllVolumes {
 
 impl RemoveAllVolumes {
     pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
-        remove_all_volumes(self, &engine, msg_info)
+        remove_all_volumes(self, &engine, msg_info).map(drop)
     }
 }
 
@@ -62,6 +73,10 @@ pub struct CreateVolume {
     /// If we should copy the cargo registry to the volume.
     #[clap(short, long)]
     pub copy_registry: bool,
+    /// Seed the volume from a toolchain snapshot, a directory or tarball
+    /// with the toolchain in `sysroot`, and optionally `cargo` and `xargo`.
+    #[clap(long, conflicts_with = "copy_registry")]
+    pub from_dir: Option<PathBuf>,
     /// Container engine (such as docker or podman).
     #[clap(long)]
     pub engine: Option<String>,
@@ -183,11 +198,114 @@ pub struct RemoveAllContainers {
     /// Container engine (such as docker or podman).
     #[clap(long)]
     pub engine: Option<String>,
+    /// Remove the containers of every container engine found, such as both
+    /// docker and podman.
+    #[clap(long, conflicts_with = "engine")]
+    pub all_engines: bool,
 }
 
 impl RemoveAllContainers {
     pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
-        remove_all_containers(self, &engine, msg_info)
+        let removed = remove_all_containers(self, &engine, msg_info)?;
+        if self.all_engines {
+            super::print_engine_totals(&engine, &[(removed, "container")], self.execute, msg_info)?;
+        }
+        Ok(())
+    }
+}
+
+/// The cross containers to stop or remove, by the labels cross adds to them.
+#[derive(Args, Debug)]
+pub struct ContainerFilters {
+    /// Select every cross container, which is required without a filter.
+    #[clap(long)]
+    pub all: bool,
+    /// Only select the containers building the workspace at this path.
+    #[clap(long)]
+    pub workspace: Option<PathBuf>,
+    /// Only select the containers building this target triple.
+    #[clap(long)]
+    pub target: Option<String>,
+    /// Only select the containers created longer ago than this, such as `2h`.
+    #[clap(long)]
+    pub older_than: Option<String>,
+}
+
+impl ContainerFilters {
+    /// The `--filter` arguments of `ps` selecting the containers.
+    fn ps_filters(&self) -> cross::Result<Vec<String>> {
+        use cross::docker::VOLUME_PREFIX;
+        let ContainerFilters {
+            all,
+            workspace,
+            target,
+            older_than,
+        } = self;
+        if !all && workspace.is_none() && target.is_none() && older_than.is_none() {
+            return Err(eyre::eyre!("no containers were selected"))
+                .with_suggestion(|| "pass `--all`, `--workspace`, `--target`, or `--older-than`");
+        }
+
+        // handles simple regex: ^ for start of line.
+        let mut filters = vec![format!("name=^{VOLUME_PREFIX}")];
+        if let Some(workspace) = workspace {
+            let label = docker::workspace_label(workspace)
+                .wrap_err_with(|| format!("could not find the workspace {workspace:?}"))?;
+            filters.push(format!("label={label}"));
+        }
+        if let Some(target) = target {
+            filters.push(format!(
+                "label={}.for-cross-target={target}",
+                cross::CROSS_LABEL_DOMAIN
+            ));
+        }
+        Ok(filters)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct StopContainers {
+    #[clap(flatten)]
+    pub filters: ContainerFilters,
+    /// Print the commands instead of stopping the containers.
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Container engine (such as docker or podman).
+    #[clap(long)]
+    pub engine: Option<String>,
+}
+
+impl StopContainers {
+    pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        stop_containers(&self.filters, None, self.dry_run, &engine, msg_info).map(drop)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RemoveContainers {
+    #[clap(flatten)]
+    pub filters: ContainerFilters,
+    /// Force removal of containers.
+    #[clap(short, long)]
+    pub force: bool,
+    /// Print the commands instead of removing the containers.
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Container engine (such as docker or podman).
+    #[clap(long)]
+    pub engine: Option<String>,
+}
+
+impl RemoveContainers {
+    pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        stop_containers(
+            &self.filters,
+            Some(self.force),
+            self.dry_run,
+            &engine,
+            msg_info,
+        )
+        .map(drop)
     }
 }
 
@@ -197,6 +315,10 @@ pub enum Containers {
     List(ListContainers),
     /// Stop and remove cross containers in local storage.
     RemoveAll(RemoveAllContainers),
+    /// Stop the cross containers selected by workspace, target, or age.
+    Stop(StopContainers),
+    /// Stop and remove the cross containers selected by workspace, target, or age.
+    Remove(RemoveContainers),
 }
 
 impl Containers {
@@ -204,6 +326,8 @@ impl Containers {
         match self {
             Containers::List(args) => args.run(engine, msg_info),
             Containers::RemoveAll(args) => args.run(engine, msg_info),
+            Containers::Stop(args) => args.run(engine, msg_info),
+            Containers::Remove(args) => args.run(engine, msg_info),
         }
     }
 
@@ -211,11 +335,22 @@ impl Containers {
         match self {
             Containers::List(l) => l.engine.as_deref(),
             Containers::RemoveAll(l) => l.engine.as_deref(),
+            Containers::Stop(l) => l.engine.as_deref(),
+            Containers::Remove(l) => l.engine.as_deref(),
+        }
+    }
+
+    pub fn all_engines(&self) -> bool {
+        match self {
+            Containers::List(_) => false,
+            Containers::RemoveAll(l) => l.all_engines,
+            Containers::Stop(_) => false,
+            Containers::Remove(_) => false,
         }
     }
 }
 
-fn get_cross_volumes(
+pub fn get_cross_volumes(
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
 ) -> cross::Result<Vec<String>> {
@@ -242,11 +377,22 @@ pub fn list_volumes(engine: &docker::Engine, msg_info: &mut MessageInfo) -> cros
     Ok(())
 }
 
+pub fn list_orphaned_volumes(
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<()> {
+    for (volume, reason) in super::toolchains::orphaned_volumes(engine, msg_info)? {
+        msg_info.print(format_args!("{volume} ({reason})"))?;
+    }
+
+    Ok(())
+}
+
 pub fn remove_all_volumes(
     RemoveAllVolumes { force, execute, .. }: &RemoveAllVolumes,
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
-) -> cross::Result<()> {
+) -> cross::Result<usize> {
     let volumes = get_cross_volumes(engine, msg_info)?;
 
     let mut command = engine.subcommand("volume");
@@ -256,14 +402,14 @@ pub fn remove_all_volumes(
     }
     command.args(&volumes);
     if volumes.is_empty() {
-        Ok(())
+        return Ok(0);
     } else if *execute {
-        command.run(msg_info, false)
+        command.run(msg_info, false)?;
     } else {
         msg_info.note("this is a dry run. to remove the volumes, pass the `--execute` flag.")?;
         command.print(msg_info)?;
-        Ok(())
     }
+    Ok(volumes.len())
 }
 
 pub fn prune_volumes(
@@ -285,6 +431,7 @@ pub fn prune_volumes(
 pub fn create_persistent_volume(
     CreateVolume {
         copy_registry,
+        from_dir,
         toolchain,
         ..
     }: &CreateVolume,
@@ -297,7 +444,7 @@ pub fn create_persistent_volume(
         toolchain.channel = channel.channel.clone();
     };
     let mount_finder = docker::MountFinder::create(engine, msg_info)?;
-    let dirs = docker::ToolchainDirectories::assemble(&mount_finder, toolchain.clone())?;
+    let dirs = docker::ToolchainDirectories::assemble(&mount_finder, toolchain.clone(), msg_info)?;
     let container_id = dirs.unique_container_identifier(&toolchain.host().target)?;
     let volume_id = dirs.unique_toolchain_identifier()?;
     let volume = docker::DockerVolume::new(engine, &volume_id);
@@ -306,6 +453,19 @@ pub fn create_persistent_volume(
         eyre::bail!("Error: volume {volume_id} already exists.");
     }
 
+    // verify the snapshot before creating the volume, so a mismatched
+    // snapshot doesn't leave an empty volume behind.
+    let snapshot = match from_dir {
+        Some(path) => {
+            let snapshot = docker::remote::ToolchainSnapshot::open(path, msg_info)?;
+            snapshot
+                .verify(&toolchain)
+                .wrap_err_with(|| format!("toolchain snapshot {path:?} cannot be used"))?;
+            Some(snapshot)
+        }
+        None => None,
+    };
+
     volume.create(msg_info)?;
 
     // stop the container if it's already running
@@ -345,9 +505,13 @@ pub fn create_persistent_volume(
     docker.run_and_get_status(msg_info, true)?;
 
     let data_volume = docker::ContainerDataVolume::new(engine, &container_id, &dirs);
-    data_volume.copy_xargo(mount_prefix, msg_info)?;
-    data_volume.copy_cargo(mount_prefix, *copy_registry, msg_info)?;
-    data_volume.copy_rust(None, mount_prefix, msg_info)?;
+    if let Some(snapshot) = &snapshot {
+        data_volume.copy_snapshot(snapshot, mount_prefix, msg_info)?;
+    } else {
+        data_volume.copy_xargo(mount_prefix, msg_info)?;
+        data_volume.copy_cargo(mount_prefix, *copy_registry, msg_info)?;
+        data_volume.copy_rust(None, mount_prefix, msg_info)?;
+    }
 
     docker::ChildContainer::finish_static(is_tty, msg_info);
 
@@ -365,7 +529,7 @@ pub fn remove_persistent_volume(
         toolchain.channel = channel.channel.clone();
     };
     let mount_finder = docker::MountFinder::create(engine, msg_info)?;
-    let dirs = docker::ToolchainDirectories::assemble(&mount_finder, toolchain)?;
+    let dirs = docker::ToolchainDirectories::assemble(&mount_finder, toolchain, msg_info)?;
     let volume_id = dirs.unique_toolchain_identifier()?;
     let volume = docker::DockerVolume::new(engine, &volume_id);
 
@@ -378,7 +542,7 @@ pub fn remove_persistent_volume(
     Ok(())
 }
 
-fn get_cross_containers(
+pub fn get_cross_containers(
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
 ) -> cross::Result<Vec<String>> {
@@ -409,7 +573,7 @@ pub fn remove_all_containers(
     RemoveAllContainers { force, execute, .. }: &RemoveAllContainers,
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
-) -> cross::Result<()> {
+) -> cross::Result<usize> {
     let containers = get_cross_containers(engine, msg_info)?;
     let mut running = vec![];
     let mut stopped = vec![];
@@ -452,7 +616,143 @@ pub fn remove_all_containers(
         }
     }
 
-    Ok(())
+    Ok(running.len() + stopped.len())
+}
+
+/// Seconds since the Unix epoch of the creation time of a container, such as
+/// `2024-05-20 16:48:00 +0000 UTC` from docker or `2024-05-20T16:48:00Z`.
+fn parse_created(created: &str) -> Option<i64> {
+    let created = created.trim();
+    let (date, time) = (created.get(..10)?, created.get(11..)?);
+    let mut date = date.split('-').map(|v| v.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    // the clock, with an optional fraction, is followed by the offset
+    let end = time.find(['Z', 'z', '+', '-', ' ']).unwrap_or(time.len());
+    let (clock, offset) = time.split_at(end);
+    let mut clock = clock.split(':');
+    let hour: i64 = clock.next()?.parse().ok()?;
+    let minute: i64 = clock.next()?.parse().ok()?;
+    let second: i64 = clock.next()?.split('.').next()?.parse().ok()?;
+    let offset = match offset.split_whitespace().next() {
+        None | Some("Z" | "z") => 0,
+        Some(offset) => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let digits = offset.get(1..)?.replace(':', "");
+            let hours: i64 = digits.get(..2)?.parse().ok()?;
+            let minutes: i64 = digits.get(2..)?.parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    // days since the epoch of the civil date, from
+    // <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// The names and states of the cross containers selected by `filters`.
+fn get_filtered_containers(
+    filters: &ContainerFilters,
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<Vec<(String, docker::ContainerState)>> {
+    let older_than = filters
+        .older_than
+        .as_deref()
+        .map(cross::config::parse_duration)
+        .transpose()
+        .wrap_err("invalid `--older-than`")?;
+    let mut ps = engine.subcommand("ps");
+    ps.arg("-a")
+        .args(["--format", "{{.Names}}\t{{.State}}\t{{.CreatedAt}}"]);
+    for filter in filters.ps_filters()? {
+        ps.args(["--filter", &filter]);
+    }
+    let stdout = ps.run_and_get_stdout(msg_info)?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let mut containers = vec![];
+    for line in stdout.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.splitn(3, '\t').map(str::trim);
+        let (Some(name), Some(state), created) = (fields.next(), fields.next(), fields.next())
+        else {
+            eyre::bail!("could not parse the container `{line}`");
+        };
+        if let Some(older_than) = older_than {
+            let created = created.unwrap_or_default();
+            let created = parse_created(created).ok_or_else(|| {
+                eyre::eyre!("could not parse the creation time `{created}` of container `{name}`")
+            })?;
+            if now.saturating_sub(created) < older_than.as_secs() as i64 {
+                continue;
+            }
+        }
+        containers.push((name.to_owned(), docker::ContainerState::new(state)?));
+    }
+    containers.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(containers)
+}
+
+/// Stop the running containers selected by `filters`, and remove all of them
+/// if `remove` is set, with whether to force the removal.
+pub fn stop_containers(
+    filters: &ContainerFilters,
+    remove: Option<bool>,
+    dry_run: bool,
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<usize> {
+    let containers = get_filtered_containers(filters, engine, msg_info)?;
+    let (stopped, running): (Vec<_>, Vec<_>) =
+        containers.iter().partition(|(_, state)| state.is_stopped());
+    let running: Vec<_> = running.iter().map(|(name, _)| name).collect();
+    let stopped: Vec<_> = stopped.iter().map(|(name, _)| name).collect();
+
+    let mut commands = vec![];
+    if !running.is_empty() {
+        let mut stop = engine.subcommand("stop");
+        stop.args(&running);
+        commands.push(stop);
+    }
+    if let Some(force) = remove {
+        if !containers.is_empty() {
+            let mut rm = engine.subcommand("rm");
+            if force {
+                rm.arg("--force");
+            }
+            rm.args(&running);
+            rm.args(&stopped);
+            commands.push(rm);
+        }
+    }
+    if commands.is_empty() {
+        msg_info.info("no matching containers were found.")?;
+    } else if dry_run {
+        msg_info.note("this is a dry run. to run the commands, omit the `--dry-run` flag.")?;
+        for command in commands {
+            command.print(msg_info)?;
+        }
+    } else {
+        for mut command in commands {
+            command.run(msg_info, false)?;
+        }
+    }
+
+    Ok(match remove {
+        Some(_) => containers.len(),
+        None => running.len(),
+    })
 }
 
 fn toolchain_or_target(
@@ -471,3 +771,20 @@ fn toolchain_or_target(
 
     Ok(toolchain)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn created_times() {
+        let created = Some(1_716_223_680);
+        assert_eq!(parse_created("2024-05-20 16:48:00 +0000 UTC"), created);
+        assert_eq!(parse_created("2024-05-20T16:48:00.123456789Z"), created);
+        assert_eq!(parse_created("2024-05-20 18:48:00.5 +0200 CEST"), created);
+        assert_eq!(parse_created("2024-05-20T11:18:00-05:30"), created);
+        assert_eq!(parse_created("2000-02-29T00:00:00Z"), Some(951_782_400));
+        assert_eq!(parse_created("2 hours ago"), None);
+        assert_eq!(parse_created(""), None);
+    }
+}
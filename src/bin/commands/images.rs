@@ -1,10 +1,13 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 
 use clap::builder::PossibleValue;
 use clap::{Args, Subcommand};
+use color_eyre::{Section, SectionExt};
 use cross::docker::{self, CROSS_CUSTOM_DOCKERFILE_IMAGE_PREFIX};
 use cross::shell::MessageInfo;
 use cross::{CommandExt, TargetList};
+use eyre::Context;
 
 // known image prefixes, with their registry
 // the docker.io registry can also be implicit
@@ -21,6 +24,9 @@ pub struct ListImages {
     /// Output format
     #[clap(long, default_value = "human")]
     pub format: OutputFormat,
+    /// Check if the image for the running cross version is available upstream.
+    #[clap(long)]
+    pub check_remote: bool,
     /// Only list images for specific target(s). By default, list all targets.
     pub targets: Vec<String>,
 }
@@ -66,24 +72,80 @@ pub struct RemoveImages {
     /// Container engine (such as docker or podman).
     #[clap(long)]
     pub engine: Option<String>,
+    /// Remove the images of every container engine found, such as both
+    /// docker and podman.
+    #[clap(long, conflicts_with = "engine")]
+    pub all_engines: bool,
 }
 
 impl RemoveImages {
     pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        let removed = self.remove(&engine, msg_info)?;
+        if self.all_engines {
+            super::print_engine_totals(&engine, &[(removed, "image")], self.execute, msg_info)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the images, returning how many there were.
+    pub fn remove(
+        &self,
+        engine: &docker::Engine,
+        msg_info: &mut MessageInfo,
+    ) -> cross::Result<usize> {
         if self.targets.is_empty() {
-            remove_all_images(self, &engine, msg_info)
+            remove_all_images(self, engine, msg_info)
         } else {
-            remove_target_images(self, &engine, msg_info)
+            remove_target_images(self, engine, msg_info)
         }
     }
 }
 
+#[derive(Args, Debug)]
+pub struct UpgradeImages {
+    /// If not provided, upgrade the images for all targets.
+    pub targets: Vec<String>,
+    /// Container engine (such as docker or podman).
+    #[clap(long)]
+    pub engine: Option<String>,
+}
+
+impl UpgradeImages {
+    pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        upgrade_images(self, &engine, msg_info)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PullImages {
+    /// Manifest mapping targets to image references, such as `images.toml`.
+    #[clap(long)]
+    pub manifest: PathBuf,
+    /// Times to retry a pull failing with a transient error.
+    #[clap(long, default_value_t = 3)]
+    pub retries: u32,
+    /// Container engine (such as docker or podman).
+    #[clap(long)]
+    pub engine: Option<String>,
+}
+
+impl PullImages {
+    pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        pull_images(self, &engine, msg_info)
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Images {
     /// List cross images in local storage.
     List(ListImages),
     /// Remove cross images in local storage.
     Remove(RemoveImages),
+    /// Pull the images matching this version of cross for all targets with
+    /// images in local storage.
+    Upgrade(UpgradeImages),
+    /// Pull the images pinned in a manifest, verifying their digests.
+    Pull(PullImages),
 }
 
 impl Images {
@@ -91,6 +153,8 @@ impl Images {
         match self {
             Images::List(args) => args.run(engine, msg_info),
             Images::Remove(args) => args.run(engine, msg_info),
+            Images::Upgrade(args) => args.run(engine, msg_info),
+            Images::Pull(args) => args.run(engine, msg_info),
         }
     }
 
@@ -98,6 +162,15 @@ impl Images {
         match self {
             Images::List(l) => l.engine.as_deref(),
             Images::Remove(l) => l.engine.as_deref(),
+            Images::Upgrade(l) => l.engine.as_deref(),
+            Images::Pull(l) => l.engine.as_deref(),
+        }
+    }
+
+    pub fn all_engines(&self) -> bool {
+        match self {
+            Images::Remove(l) => l.all_engines,
+            Images::List(_) | Images::Upgrade(_) | Images::Pull(_) => false,
         }
     }
 }
@@ -126,6 +199,84 @@ impl Image {
     }
 }
 
+/// How the tag of an image relates to the version of cross that is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum VersionStatus {
+    /// The tag matches the image version used by this cross.
+    Current,
+    /// The tag is for another release, or from the legacy `rustembedded` images.
+    Outdated,
+    /// A locally built development image.
+    Local,
+    /// The tag cannot be compared, such as for custom images.
+    Unknown,
+}
+
+impl VersionStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            VersionStatus::Current => "current",
+            VersionStatus::Outdated => "outdated",
+            VersionStatus::Local => "local",
+            VersionStatus::Unknown => "unknown",
+        }
+    }
+}
+
+fn version_status(image: &Image) -> VersionStatus {
+    let tag = image.tag.as_str();
+    if is_local_image(tag) {
+        VersionStatus::Local
+    } else if !image.repository.starts_with(GHCR_IO) {
+        if is_cross_image(&image.repository) {
+            VersionStatus::Outdated
+        } else {
+            VersionStatus::Unknown
+        }
+    } else {
+        match docker::tag_is_current(tag) {
+            Some(true) => VersionStatus::Current,
+            Some(false) => VersionStatus::Outdated,
+            None => VersionStatus::Unknown,
+        }
+    }
+}
+
+/// An image together with its compatibility with the running cross version.
+#[derive(Debug, serde::Serialize)]
+struct ListedImage {
+    #[serde(flatten)]
+    image: Image,
+    version: VersionStatus,
+    /// The upstream tag matching the running cross version, if `--check-remote` found one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_tag: Option<String>,
+}
+
+impl ListedImage {
+    fn status(&self) -> String {
+        match &self.upstream_tag {
+            Some(tag) => format!("{} ({tag} available)", self.version.as_str()),
+            None => self.version.as_str().to_owned(),
+        }
+    }
+}
+
+fn remote_tag_exists(
+    engine: &docker::Engine,
+    repository: &str,
+    tag: &str,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<bool> {
+    Ok(engine
+        .subcommand("manifest")
+        .args(["inspect", &format!("{repository}:{tag}")])
+        .run_and_get_output(msg_info)?
+        .status
+        .success())
+}
+
 fn parse_image(image: &str) -> Image {
     // this cannot panic: we've formatted our image list as `${repo}:${tag} ${id}`
     let (repository, rest) = image.split_once(':').unwrap();
@@ -243,25 +394,56 @@ fn get_image_target(
 
 pub fn list_images(
     ListImages {
-        targets, format, ..
+        targets,
+        format,
+        check_remote,
+        ..
     }: &ListImages,
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
 ) -> cross::Result<()> {
     let cross_images = get_cross_images(engine, msg_info, true)?;
     let target_list = msg_info.as_quiet(cross::rustc::target_list)?;
-    let mut map: BTreeMap<String, Vec<Image>> = BTreeMap::new();
+    let mut map: BTreeMap<String, Vec<ListedImage>> = BTreeMap::new();
+    let mut remote_tags: BTreeMap<String, bool> = BTreeMap::new();
     let mut max_target_len = 0;
     let mut max_image_len = 0;
+    let mut max_status_len = 0;
     for image in cross_images {
         let target = get_image_target(engine, &image, &target_list, msg_info)?;
         if targets.is_empty() || targets.contains(&target) {
-            if !map.contains_key(&target) {
-                map.insert(target.clone(), vec![]);
+            let version = version_status(&image);
+            let mut upstream_tag = None;
+            if *check_remote
+                && version == VersionStatus::Outdated
+                && image.repository.starts_with(GHCR_IO)
+            {
+                let exists = match remote_tags.get(&image.repository) {
+                    Some(exists) => *exists,
+                    None => {
+                        let exists = remote_tag_exists(
+                            engine,
+                            &image.repository,
+                            docker::DEFAULT_IMAGE_VERSION,
+                            msg_info,
+                        )?;
+                        remote_tags.insert(image.repository.clone(), exists);
+                        exists
+                    }
+                };
+                if exists {
+                    upstream_tag = Some(docker::DEFAULT_IMAGE_VERSION.to_owned());
+                }
             }
+            let listed = ListedImage {
+                image,
+                version,
+                upstream_tag,
+            };
             max_target_len = target.len().max(max_target_len);
-            max_image_len = image.name().len().max(max_image_len);
-            map.get_mut(&target).expect("map must have key").push(image);
+            max_image_len = listed.image.name().len().max(max_image_len);
+            max_status_len = listed.status().len().max(max_status_len);
+            map.entry(target).or_default().push(listed);
         }
     }
     let mut keys: Vec<&str> = map.keys().map(|k| k.as_ref()).collect();
@@ -273,47 +455,46 @@ pub fn list_images(
         }
         OutputFormat::Human => {
             let print_string =
-                |col1: &str, col2: &str, fill: char, info: &mut MessageInfo| -> cross::Result<()> {
+                |columns: [&str; 3], fill: char, info: &mut MessageInfo| -> cross::Result<()> {
                     let mut row = String::new();
                     row.push('|');
-                    row.push(fill);
-                    row.push_str(col1);
-                    let spaces = max_target_len.max(col1.len()) + 1 - col1.len();
-                    for _ in 0..spaces {
+                    for (col, max_len) in
+                        columns
+                            .iter()
+                            .zip([max_target_len, max_image_len, max_status_len])
+                    {
                         row.push(fill);
+                        row.push_str(col);
+                        let spaces = max_len.max(col.len()) + 1 - col.len();
+                        for _ in 0..spaces {
+                            row.push(fill);
+                        }
+                        row.push('|');
                     }
-                    row.push('|');
-                    row.push(fill);
-                    row.push_str(col2);
-                    let spaces = max_image_len.max(col2.len()) + 1 - col2.len();
-                    for _ in 0..spaces {
-                        row.push(fill);
-                    }
-                    row.push('|');
                     info.print(row)
                 };
 
             if targets.len() != 1 {
-                print_string("Targets", "Images", ' ', msg_info)?;
-                print_string("-------", "------", '-', msg_info)?;
+                print_string(["Targets", "Images", "Version"], ' ', msg_info)?;
+                print_string(["-------", "------", "-------"], '-', msg_info)?;
             }
 
             let print_single = |_: &str,
-                                image: &Image,
+                                listed: &ListedImage,
                                 info: &mut MessageInfo|
-             -> cross::Result<()> { info.print(image) };
+             -> cross::Result<()> { info.print(&listed.image) };
             let print_table =
-                |target: &str, image: &Image, info: &mut MessageInfo| -> cross::Result<()> {
-                    let name = image.name();
-                    print_string(target, &name, ' ', info)
+                |target: &str, listed: &ListedImage, info: &mut MessageInfo| -> cross::Result<()> {
+                    let name = listed.image.name();
+                    print_string([target, &name, &listed.status()], ' ', info)
                 };
 
             for target in keys {
-                for image in map.get(target).expect("map must have key").iter() {
+                for listed in map.get(target).expect("map must have key").iter() {
                     if targets.len() == 1 {
-                        print_single(target, image, msg_info)?;
+                        print_single(target, listed, msg_info)?;
                     } else {
-                        print_table(target, image, msg_info)?;
+                        print_table(target, listed, msg_info)?;
                     }
                 }
             }
@@ -329,21 +510,21 @@ fn remove_images(
     msg_info: &mut MessageInfo,
     force: bool,
     execute: bool,
-) -> cross::Result<()> {
+) -> cross::Result<usize> {
     let mut command = engine.subcommand("rmi");
     if force {
         command.arg("--force");
     }
     command.args(images.iter().map(|i| &i.id));
     if images.is_empty() {
-        Ok(())
+        return Ok(0);
     } else if execute {
-        command.run(msg_info, false)
+        command.run(msg_info, false)?;
     } else {
         msg_info.note("this is a dry run. to remove the images, pass the `--execute` flag.")?;
         command.print(msg_info)?;
-        Ok(())
     }
+    Ok(images.len())
 }
 
 pub fn remove_all_images(
@@ -355,7 +536,7 @@ pub fn remove_all_images(
     }: &RemoveImages,
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
-) -> cross::Result<()> {
+) -> cross::Result<usize> {
     let images = get_cross_images(engine, msg_info, *local)?;
     remove_images(engine, &images, msg_info, *force, *execute)
 }
@@ -370,7 +551,7 @@ pub fn remove_target_images(
     }: &RemoveImages,
     engine: &docker::Engine,
     msg_info: &mut MessageInfo,
-) -> cross::Result<()> {
+) -> cross::Result<usize> {
     let cross_images = get_cross_images(engine, msg_info, *local)?;
     let target_list = msg_info.as_quiet(cross::rustc::target_list)?;
     let mut images = vec![];
@@ -383,10 +564,245 @@ pub fn remove_target_images(
     remove_images(engine, &images, msg_info, *force, *execute)
 }
 
+/// The tag matching this version of cross for an outdated `tag`, keeping
+/// the sub-target suffix, such as `-centos`.
+fn upgrade_tag(tag: &str) -> Option<String> {
+    if docker::tag_is_current(tag) != Some(false) {
+        return None;
+    }
+    let suffix = tag.split_once('-').map_or("", |(_, sub)| sub);
+    Some(match suffix {
+        "" => docker::DEFAULT_IMAGE_VERSION.to_owned(),
+        sub => format!("{}-{sub}", docker::DEFAULT_IMAGE_VERSION),
+    })
+}
+
+pub fn upgrade_images(
+    UpgradeImages { targets, .. }: &UpgradeImages,
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<()> {
+    let cross_images = get_cross_images(engine, msg_info, false)?;
+    let present: BTreeSet<String> = cross_images.iter().map(Image::name).collect();
+    let target_list = msg_info.as_quiet(cross::rustc::target_list)?;
+    let mut upgrades = BTreeSet::new();
+    for image in &cross_images {
+        if !image.repository.starts_with(GHCR_IO) {
+            continue;
+        }
+        let target = get_image_target(engine, image, &target_list, msg_info)?;
+        if !targets.is_empty() && !targets.contains(&target) {
+            continue;
+        }
+        if let Some(tag) = upgrade_tag(&image.tag) {
+            let name = format!("{}:{tag}", image.repository);
+            if !present.contains(&name) {
+                upgrades.insert(name);
+            }
+        }
+    }
+
+    if upgrades.is_empty() {
+        return msg_info.print("all images are up to date");
+    }
+    for name in upgrades {
+        engine
+            .subcommand("pull")
+            .arg(&name)
+            .run(msg_info, false)
+            .wrap_err_with(|| format!("could not pull image `{name}`"))?;
+    }
+    Ok(())
+}
+
+/// The images pinned by a manifest, such as:
+///
+/// ```toml
+/// [targets]
+/// aarch64-unknown-linux-gnu = "ghcr.io/cross-rs/aarch64-unknown-linux-gnu@sha256:..."
+/// armv7-unknown-linux-gnueabihf = { image = "ghcr.io/cross-rs/armv7-unknown-linux-gnueabihf:0.2.5", digest = "sha256:..." }
+/// ```
+#[derive(Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ImageManifest {
+    targets: BTreeMap<String, PinnedImage>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(untagged)]
+enum PinnedImage {
+    Reference(String),
+    #[serde(rename_all = "kebab-case")]
+    Image {
+        image: String,
+        digest: Option<String>,
+    },
+}
+
+impl PinnedImage {
+    fn reference(&self) -> &str {
+        match self {
+            PinnedImage::Reference(reference) => reference,
+            PinnedImage::Image { image, .. } => image,
+        }
+    }
+
+    /// The digest the pulled image must have, from the `digest` key or the
+    /// `@sha256:...` suffix of the reference.
+    fn digest(&self) -> Option<&str> {
+        match self {
+            PinnedImage::Image {
+                digest: Some(digest),
+                ..
+            } => Some(digest),
+            _ => self
+                .reference()
+                .split_once('@')
+                .map(|(_, digest)| digest)
+                .filter(|digest| !digest.is_empty()),
+        }
+    }
+}
+
+fn read_manifest(path: &Path) -> cross::Result<ImageManifest> {
+    let contents = cross::file::read(path)
+        .wrap_err_with(|| format!("could not read the manifest {path:?}"))?;
+    toml::from_str(&contents).wrap_err_with(|| format!("invalid manifest {path:?}"))
+}
+
+/// Pull `reference`, retrying pulls which failed with a transient error.
+fn pull_reference(
+    engine: &docker::Engine,
+    reference: &str,
+    retries: u32,
+    msg_info: &mut MessageInfo,
+) -> Result<(), String> {
+    let output = engine
+        .pull_with_retries(reference, retries, msg_info, |_| {
+            let mut pull = engine.subcommand("pull");
+            pull.arg(reference);
+            Ok(pull)
+        })
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("the container engine failed")
+        .trim()
+        .to_owned())
+}
+
+/// Verify that the local image `reference` was pulled with `digest`.
+fn verify_digest(
+    engine: &docker::Engine,
+    reference: &str,
+    digest: &str,
+    msg_info: &mut MessageInfo,
+) -> Result<(), String> {
+    let digests = engine
+        .subcommand("image")
+        .args([
+            "inspect",
+            "--format",
+            r#"{{join .RepoDigests "\n"}}"#,
+            reference,
+        ])
+        .run_and_get_stdout(msg_info)
+        .map_err(|e| e.to_string())?;
+    let found: Vec<&str> = digests
+        .lines()
+        .filter_map(|line| line.trim().split_once('@'))
+        .map(|(_, digest)| digest)
+        .collect();
+    if found.contains(&digest) {
+        Ok(())
+    } else if found.is_empty() {
+        Err(format!(
+            "expected digest `{digest}`, but the image has none"
+        ))
+    } else {
+        Err(format!(
+            "expected digest `{digest}`, but the image has `{}`",
+            found.join("`, `")
+        ))
+    }
+}
+
+pub fn pull_images(
+    PullImages {
+        manifest, retries, ..
+    }: &PullImages,
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<()> {
+    let manifest = read_manifest(manifest)?;
+    let mut failures = vec![];
+    for (target, pinned) in &manifest.targets {
+        let reference = pinned.reference();
+        msg_info.info(format_args!("pulling `{reference}` for `{target}`"))?;
+        let result = pull_reference(engine, reference, *retries, msg_info).and_then(|()| {
+            match pinned.digest() {
+                Some(digest) => verify_digest(engine, reference, digest, msg_info),
+                None => Ok(()),
+            }
+        });
+        if let Err(error) = result {
+            failures.push(format!("{target} ({reference}): {error}"));
+        }
+    }
+
+    let total = manifest.targets.len();
+    if failures.is_empty() {
+        return msg_info.print(format_args!("pulled {total} images"));
+    }
+    Err(eyre::eyre!(
+        "could not pull {} of {total} images",
+        failures.len()
+    ))
+    .with_section(|| failures.join("\n").header("Failures:"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn image_manifest() -> cross::Result<()> {
+        let manifest: ImageManifest = toml::from_str(
+            r#"
+            [targets]
+            aarch64-unknown-linux-gnu = "ghcr.io/cross-rs/aarch64-unknown-linux-gnu@sha256:0123"
+            x86_64-unknown-linux-gnu = "ghcr.io/cross-rs/x86_64-unknown-linux-gnu:0.2.5"
+            armv7-unknown-linux-gnueabihf = { image = "ghcr.io/cross-rs/armv7-unknown-linux-gnueabihf:0.2.5", digest = "sha256:4567" }
+            "#,
+        )?;
+        let pinned = |target: &str| &manifest.targets[target];
+        assert_eq!(
+            pinned("aarch64-unknown-linux-gnu").reference(),
+            "ghcr.io/cross-rs/aarch64-unknown-linux-gnu@sha256:0123"
+        );
+        assert_eq!(
+            pinned("aarch64-unknown-linux-gnu").digest(),
+            Some("sha256:0123")
+        );
+        assert_eq!(pinned("x86_64-unknown-linux-gnu").digest(), None);
+        assert_eq!(
+            pinned("armv7-unknown-linux-gnueabihf").reference(),
+            "ghcr.io/cross-rs/armv7-unknown-linux-gnueabihf:0.2.5"
+        );
+        assert_eq!(
+            pinned("armv7-unknown-linux-gnueabihf").digest(),
+            Some("sha256:4567")
+        );
+        assert!(toml::from_str::<ImageManifest>("[images]").is_err());
+        Ok(())
+    }
+
     #[test]
     fn parse_rustembedded_target() {
         let targets = [
@@ -400,4 +816,50 @@ mod tests {
             assert_eq!(rustembedded_target(&versioned), target.to_string());
         }
     }
+
+    #[test]
+    fn image_version_status() {
+        let image = |repository: &str, tag: &str| Image {
+            repository: repository.to_owned(),
+            tag: tag.to_owned(),
+            id: "0123456789ab".to_owned(),
+        };
+        let ghcr = format!("{GHCR_IO}/aarch64-unknown-linux-gnu");
+        let version = docker::DEFAULT_IMAGE_VERSION;
+
+        assert_eq!(
+            version_status(&image(&ghcr, version)),
+            VersionStatus::Current
+        );
+        assert_eq!(
+            version_status(&image(&ghcr, &format!("{version}-centos"))),
+            VersionStatus::Current
+        );
+        assert_eq!(
+            version_status(&image(&ghcr, "0.1.0")),
+            VersionStatus::Outdated
+        );
+        assert_eq!(version_status(&image(&ghcr, "local")), VersionStatus::Local);
+        assert_eq!(
+            version_status(&image(RUST_EMBEDDED, "aarch64-unknown-linux-gnu-0.2.1")),
+            VersionStatus::Outdated
+        );
+        assert_eq!(
+            version_status(&image("localhost/cross-rs/custom", "abc")),
+            VersionStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn image_upgrade_tag() {
+        let version = docker::DEFAULT_IMAGE_VERSION;
+        let old = if version == "0.1.0" { "0.2.0" } else { "0.1.0" };
+        assert_eq!(upgrade_tag(old), Some(version.to_owned()));
+        assert_eq!(
+            upgrade_tag(&format!("{old}-centos")),
+            Some(format!("{version}-centos"))
+        );
+        assert_eq!(upgrade_tag(version), None);
+        assert_eq!(upgrade_tag("local"), None);
+    }
 }
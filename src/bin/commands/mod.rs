@@ -1,9 +1,41 @@
+mod cache;
 mod clean;
 mod containers;
 mod images;
 mod run;
+mod stats;
+mod toolchains;
 
+pub use self::cache::*;
 pub use self::clean::*;
 pub use self::containers::*;
 pub use self::images::*;
 pub use self::run::*;
+pub use self::stats::*;
+pub use self::toolchains::*;
+
+use cross::docker;
+use cross::shell::MessageInfo;
+
+/// Print the totals of the resources removed from `engine`, such as
+/// `docker: removed 2 images, 1 volume`, with `--all-engines`.
+pub fn print_engine_totals(
+    engine: &docker::Engine,
+    totals: &[(usize, &str)],
+    execute: bool,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<()> {
+    let totals = totals
+        .iter()
+        .map(|(count, noun)| match count {
+            1 => format!("1 {noun}"),
+            count => format!("{count} {noun}s"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let verb = match execute {
+        true => "removed",
+        false => "would remove",
+    };
+    msg_info.print(format_args!("{}: {verb} {totals}", engine.name()))
+}
@@ -1,105 +1,198 @@
 use clap::Args as ClapArgs;
-use cross::config::Config;
 use cross::shell::{MessageInfo, Verbosity};
 use cross::{
-    cargo_metadata_with_args, cli::Args, docker, rustc, setup, toml, CommandVariant, CrossSetup,
-    Target,
+    cargo_metadata_with_args,
+    cli::{Args, VersionFormat},
+    configure_cache_dir_with,
+    cross_toml::CargoHomeMode,
+    docker, rustc, rustup, setup, temp, CommandVariant, CrossSetup, Target,
 };
 use eyre::Context;
 
+/// The target and image options shared by `run` and `run-image`.
 #[derive(ClapArgs, Debug)]
-pub struct Run {
+pub struct RunTarget {
     /// Container engine (such as docker or podman).
     #[clap(long)]
     pub engine: Option<String>,
     /// Target
     #[clap(short, long)]
     pub target: String,
-    /// Interactive session
-    #[clap(short, long, default_value = "false")]
-    pub interactive: bool,
-    /// Command to run, will be run in a shell
-    #[clap(last = true)]
-    pub command: String,
+    /// Platform of the image to use, such as `linux/arm64`
+    #[clap(long)]
+    pub platform: Option<String>,
+    /// Named profile of the cross configuration to use
+    #[clap(long)]
+    pub config_profile: Option<String>,
 }
 
-impl Run {
-    pub fn run(
+impl RunTarget {
+    /// Run `args` in a shell in the image of the target, with the same image,
+    /// mounts, user, and environment as a build of the project in the current directory.
+    fn run(
         &self,
+        envs: Vec<String>,
+        interactive: bool,
+        args: &[String],
         cli: &crate::Cli,
-        engine: docker::Engine,
+        engine: Option<docker::Engine>,
         msg_info: &mut MessageInfo,
     ) -> cross::Result<()> {
         let target_list = rustc::target_list(&mut Verbosity::Quiet.into())?;
         let target = Target::from(&self.target, &target_list);
-
         let cwd = std::env::current_dir()?;
         let host_version_meta = rustc::version_meta()?;
+        let cross_args = cross_args(
+            target,
+            self.platform.clone(),
+            self.config_profile.clone(),
+            envs,
+            cli,
+        );
+        let metadata = cargo_metadata_with_args(None, Some(&cross_args), msg_info)?
+            .ok_or_else(|| eyre::eyre!("unable to get project metadata"))?;
+        configure_cache_dir_with(&metadata, Some(&cross_args));
+        let CrossSetup {
+            config,
+            target,
+            toolchain,
+            engine: config_engine,
+            image,
+            ..
+        } = setup(
+            &host_version_meta,
+            &metadata,
+            &cross_args,
+            target_list,
+            msg_info,
+        )?
+        .ok_or_else(|| eyre::eyre!("cannot setup cross environment"))?;
+        let engine = engine.unwrap_or(config_engine);
+        rustup::setup_rustup(&toolchain, msg_info)?;
+        let rustc_version = toolchain.rustc_version()?.map(|(version, _, _)| version);
 
-        let args = Args {
-            cargo_args: vec![],
-            rest_args: vec![],
-            subcommand: None,
-            channel: None,
-            target: Some(target.clone()),
-            features: vec![],
-            target_dir: None,
-            manifest_path: None,
-            version: false,
-            verbose: if cli.verbose { 1 } else { 0 },
-            quiet: cli.quiet,
-            color: cli.color.clone(),
-        };
-
-        if let Some(metadata) = cargo_metadata_with_args(None, Some(&args), msg_info)? {
-            let CrossSetup { toolchain, .. } =
-                match setup(&host_version_meta, &metadata, &args, target_list, msg_info)? {
-                    Some(setup) => setup,
-                    _ => {
-                        eyre::bail!("Error: cannot setup cross environment");
-                    }
-                };
+        let mut paths = docker::DockerPaths::create(&engine, metadata, cwd, toolchain, msg_info)?;
+        if config.mount_cargo_home()? == Some(CargoHomeMode::Project) {
+            let project = docker::path_hash(paths.workspace_root(), docker::PATH_HASH_SHORT)?;
+            paths.set_cargo_home(&temp::cargo_home_dir()?.join(project))?;
+        }
+        let mut options = docker::DockerOptions::new(
+            engine,
+            target,
+            config,
+            image,
+            CommandVariant::Shell,
+            rustc_version,
+            interactive,
+        );
+        options.envs = cross_args.envs;
 
-            let toml = toml(&metadata, msg_info)?;
-            let config = Config::new(Some(toml));
+        docker::run(options, paths, args, None, msg_info).wrap_err("could not run container")?;
 
-            let image = match docker::get_image(&config, &target, false) {
-                Ok(i) => i,
-                Err(docker::GetImageError::NoCompatibleImages(..))
-                    if config.dockerfile(&target).is_some() =>
-                {
-                    "scratch".into()
-                }
-                Err(err) => {
-                    msg_info.warn(&err)?;
-                    eyre::bail!("Error: {}", &err);
-                }
-            };
+        Ok(())
+    }
+}
 
-            let image = image.to_definite_with(&engine, msg_info)?;
+#[derive(ClapArgs, Debug)]
+pub struct Run {
+    #[clap(flatten)]
+    pub run: RunTarget,
+    /// Interactive session
+    #[clap(short, long, default_value = "false")]
+    pub interactive: bool,
+    /// Command to run, will be run in a shell
+    #[clap(last = true)]
+    pub command: String,
+}
 
-            let paths = docker::DockerPaths::create(&engine, metadata, cwd, toolchain, msg_info)?;
-            let options = docker::DockerOptions::new(
-                engine,
-                target,
-                config,
-                image,
-                CommandVariant::Shell,
-                None,
-                self.interactive,
-            );
+impl Run {
+    pub fn run(
+        &self,
+        cli: &crate::Cli,
+        engine: docker::Engine,
+        msg_info: &mut MessageInfo,
+    ) -> cross::Result<()> {
+        let args = ["-c".to_owned(), self.command.clone()];
+        self.run
+            .run(vec![], self.interactive, &args, cli, Some(engine), msg_info)
+    }
 
-            let mut args = vec![String::from("-c")];
-            args.push(self.command.clone());
+    pub fn engine(&self) -> Option<&str> {
+        self.run.engine.as_deref()
+    }
+}
 
-            docker::run(options, paths, &args, None, msg_info)
-                .wrap_err("could not run container")?;
-        }
+/// `run` with environment variables, opening an interactive shell when no
+/// command is given.
+#[derive(ClapArgs, Debug)]
+pub struct RunImage {
+    #[clap(flatten)]
+    pub run: RunTarget,
+    /// Environment variable to pass to the container, as `ENVVAR=value` or `ENVVAR`
+    #[clap(short, long = "env", value_name = "ENVVAR")]
+    pub envs: Vec<String>,
+    /// Command to run instead of an interactive shell, will be run in a shell
+    #[clap(last = true)]
+    pub command: Vec<String>,
+}
 
-        Ok(())
+impl RunImage {
+    pub fn run(
+        &self,
+        cli: &crate::Cli,
+        engine: Option<docker::Engine>,
+        msg_info: &mut MessageInfo,
+    ) -> cross::Result<()> {
+        let args = match self.command.is_empty() {
+            true => vec![],
+            false => vec!["-c".to_owned(), shell_words::join(&self.command)],
+        };
+        self.run
+            .run(self.envs.clone(), true, &args, cli, engine, msg_info)
     }
 
     pub fn engine(&self) -> Option<&str> {
-        self.engine.as_deref()
+        self.run.engine.as_deref()
+    }
+}
+
+/// The arguments of a build of `target`, without a subcommand.
+fn cross_args(
+    target: Target,
+    platform: Option<String>,
+    config_profile: Option<String>,
+    envs: Vec<String>,
+    cli: &crate::Cli,
+) -> Args {
+    Args {
+        cargo_args: vec![],
+        rest_args: vec![],
+        subcommand: None,
+        channel: None,
+        target: Some(target),
+        targets: vec![],
+        extra_targets: vec![],
+        features: vec![],
+        target_dir: None,
+        manifest_path: None,
+        platform,
+        config_profile,
+        build_report: None,
+        version: false,
+        version_format: VersionFormat::Human,
+        print: None,
+        shard: None,
+        quiet_container: false,
+        no_cache_setup: false,
+        in_container: false,
+        skip_image_checks: false,
+        strict_args: false,
+        check_config_only: false,
+        devices: vec![],
+        publish: vec![],
+        envs,
+        verbose: if cli.verbose { 1 } else { 0 },
+        quiet: cli.quiet,
+        color: cli.color.clone(),
     }
 }
@@ -0,0 +1,71 @@
+use std::fs;
+
+use clap::{Args, Subcommand};
+use cross::shell::MessageInfo;
+use cross::stats;
+
+#[derive(Args, Debug)]
+pub struct ShowStats {
+    /// Only show statistics for the target.
+    #[clap(long)]
+    pub target: Option<String>,
+    /// Only show statistics for the cargo profile.
+    #[clap(long)]
+    pub profile: Option<String>,
+    /// Number of recent runs drawn in the trend.
+    #[clap(long, default_value_t = 20)]
+    pub trend: usize,
+}
+
+impl ShowStats {
+    pub fn run(&self, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        stats::show(
+            self.target.as_deref(),
+            self.profile.as_deref(),
+            self.trend,
+            msg_info,
+        )
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ClearStats {
+    /// Remove the statistics. Default is a dry run.
+    #[clap(short, long)]
+    pub execute: bool,
+}
+
+impl ClearStats {
+    pub fn run(&self, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        let path = stats::path()?;
+        match self.execute {
+            true => {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+            false => msg_info.print(format_args!(
+                "fs::remove_file({})",
+                cross::pretty_path(&path, |_| false)
+            ))?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Stats {
+    /// Show the build durations recorded with `CROSS_BUILD_STATS=1`.
+    Show(ShowStats),
+    /// Remove the recorded build statistics.
+    Clear(ClearStats),
+}
+
+impl Stats {
+    pub fn run(&self, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        match self {
+            Stats::Show(args) => args.run(msg_info),
+            Stats::Clear(args) => args.run(msg_info),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use cross::rustc::{self, QualifiedToolchain};
+use cross::shell::MessageInfo;
+use cross::{docker, rustup, temp, CommandExt};
+use serde::Deserialize;
+
+use super::containers::{get_cross_containers, get_cross_volumes};
+
+#[derive(Args, Debug)]
+pub struct Toolchains {
+    /// Remove the containers and volumes of the toolchain with this identifier.
+    #[clap(long, value_name = "ID")]
+    pub remove: Option<String>,
+    /// Force removal of running containers.
+    #[clap(short, long, requires = "remove")]
+    pub force: bool,
+    /// Remove the containers and volumes. Default is a dry run.
+    #[clap(short, long, requires = "remove")]
+    pub execute: bool,
+    /// Container engine (such as docker or podman).
+    #[clap(long)]
+    pub engine: Option<String>,
+}
+
+impl Toolchains {
+    pub fn run(&self, engine: docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+        match &self.remove {
+            Some(id) => remove_toolchain(self, id, &engine, msg_info),
+            None => list_toolchains(&engine, msg_info),
+        }
+    }
+
+    pub fn engine(&self) -> Option<&str> {
+        self.engine.as_deref()
+    }
+}
+
+/// A volume, as reported by `system df --verbose`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DfVolume {
+    name: String,
+    usage_data: Option<DfUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DfUsage {
+    /// `-1` if the engine did not compute the size.
+    size: i64,
+}
+
+/// The size of each volume, if the engine reports them.
+fn volume_sizes(engine: &docker::Engine) -> BTreeMap<String, u64> {
+    // not every engine supports the format, so failures are ignored.
+    engine
+        .subcommand("system")
+        .args(["df", "--verbose", "--format", "{{json .Volumes}}"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| parse_volume_sizes(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+fn parse_volume_sizes(stdout: &str) -> BTreeMap<String, u64> {
+    let volumes: Vec<DfVolume> = serde_json::from_str(stdout.trim()).unwrap_or_default();
+    volumes
+        .into_iter()
+        .filter_map(|v| Some((v.name, u64::try_from(v.usage_data?.size).ok()?)))
+        .collect()
+}
+
+/// Format `bytes` with binary units, such as `1.5 GiB`.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+/// If the volume or container `name` belongs to the toolchain `id`: the data
+/// volume is named after the toolchain, and the mount volumes and containers
+/// start with it.
+fn belongs_to(name: &str, id: &str) -> bool {
+    name.strip_prefix(id)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+}
+
+/// The volumes shared by every toolchain, which are never orphaned.
+const SHARED_VOLUMES: [&str; 3] = [
+    docker::CACHE_PROXY_NAME,
+    docker::CARGO_REGISTRY_VOLUME,
+    docker::SECCOMP_VOLUME,
+];
+
+/// Why the volume `name` is orphaned, if it is: no installed toolchain has the
+/// identifier of the volume, or every workspace copied into it was removed.
+fn orphan_reason(
+    name: &str,
+    toolchain_ids: &[String],
+    sources: &[PathBuf],
+    exists: impl Fn(&Path) -> bool,
+) -> Option<&'static str> {
+    if SHARED_VOLUMES.contains(&name) {
+        None
+    } else if !toolchain_ids.iter().any(|id| belongs_to(name, id)) {
+        Some("toolchain not installed")
+    } else if !sources.is_empty() && !sources.iter().any(|source| exists(source)) {
+        Some("workspace removed")
+    } else {
+        None
+    }
+}
+
+/// The volumes of cross whose toolchain or workspaces no longer exist.
+pub fn orphaned_volumes(
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<Vec<(String, &'static str)>> {
+    let toolchain_ids = installed_toolchains(msg_info)?
+        .iter()
+        .map(QualifiedToolchain::unique_toolchain_identifier)
+        .collect::<cross::Result<Vec<_>>>()?;
+    let mut orphaned = vec![];
+    for volume in get_cross_volumes(engine, msg_info)? {
+        let sources = docker::remote::volume_sources(&volume)?;
+        if let Some(reason) = orphan_reason(&volume, &toolchain_ids, &sources, Path::exists) {
+            orphaned.push((volume, reason));
+        }
+    }
+    Ok(orphaned)
+}
+
+/// The installed rustup toolchains.
+fn installed_toolchains(msg_info: &mut MessageInfo) -> cross::Result<Vec<QualifiedToolchain>> {
+    let config = cross::config::Config::new(None);
+    let sysroot = rustc::sysroot(msg_info)?;
+    let toolchains_dir = sysroot
+        .parent()
+        .ok_or_else(|| eyre::eyre!("could not find the rustup toolchains directory"))?;
+    let mut toolchains = vec![];
+    for name in rustup::installed_toolchains(msg_info)? {
+        match QualifiedToolchain::from_sysroot(toolchains_dir.join(&name), &config, msg_info) {
+            Ok(toolchain) => toolchains.push(toolchain),
+            Err(e) => msg_info.warn(format_args!("could not detect toolchain `{name}`: {e}"))?,
+        }
+    }
+    Ok(toolchains)
+}
+
+pub fn list_toolchains(engine: &docker::Engine, msg_info: &mut MessageInfo) -> cross::Result<()> {
+    let volumes = get_cross_volumes(engine, msg_info)?;
+    let containers = get_cross_containers(engine, msg_info)?;
+    let sizes = volume_sizes(engine);
+
+    let header = [
+        "Identifier",
+        "Channel",
+        "Date",
+        "Host",
+        "Sysroot",
+        "Volumes",
+        "Containers",
+        "Disk usage",
+    ]
+    .map(str::to_owned);
+    let mut rows = vec![header];
+    for toolchain in installed_toolchains(msg_info)? {
+        let id = toolchain.unique_toolchain_identifier()?;
+        let volumes: Vec<_> = volumes.iter().filter(|v| belongs_to(v, &id)).collect();
+        let containers = containers
+            .iter()
+            .filter_map(|c| c.split_once(':'))
+            .filter(|(name, _)| belongs_to(name.trim(), &id))
+            .count();
+        let usage = temp::entry_size(toolchain.get_sysroot())
+            + volumes
+                .iter()
+                .filter_map(|v| sizes.get(v.as_str()))
+                .sum::<u64>();
+        rows.push([
+            id,
+            toolchain.channel.clone(),
+            toolchain.date.clone().unwrap_or_else(|| "-".to_owned()),
+            toolchain.host().target.to_string(),
+            cross::pretty_path(toolchain.get_sysroot(), |_| false),
+            volumes.len().to_string(),
+            containers.to_string(),
+            human_size(usage),
+        ]);
+    }
+    if rows.len() == 1 {
+        return msg_info.print("no toolchains installed");
+    }
+
+    let mut widths = [0; 8];
+    for row in &rows {
+        for (width, col) in widths.iter_mut().zip(row) {
+            *width = col.chars().count().max(*width);
+        }
+    }
+    for row in &rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(col, width)| format!("{col:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        msg_info.print(line.trim_end())?;
+    }
+    let orphaned = orphaned_volumes(engine, msg_info)?;
+    if !orphaned.is_empty() {
+        msg_info.print("\norphaned volumes:")?;
+        for (volume, reason) in &orphaned {
+            let size = sizes
+                .get(volume.as_str())
+                .map(|size| format!(", {}", human_size(*size)))
+                .unwrap_or_default();
+            msg_info.print(format_args!("{volume} ({reason}{size})"))?;
+        }
+    }
+    if sizes.is_empty() && !volumes.is_empty() {
+        msg_info
+            .note("the size of the volumes is not included, since the engine did not report it")?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_toolchain(
+    Toolchains { force, execute, .. }: &Toolchains,
+    id: &str,
+    engine: &docker::Engine,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<()> {
+    if !id.starts_with(docker::VOLUME_PREFIX) {
+        eyre::bail!(
+            "invalid toolchain identifier `{id}`, expected it to start with `{}`",
+            docker::VOLUME_PREFIX
+        );
+    }
+    let mut running = vec![];
+    let mut stopped = vec![];
+    for container in get_cross_containers(engine, msg_info)? {
+        // cannot fail, formatted as {{.Names}}: {{.State}}
+        let (name, state) = container.split_once(':').unwrap();
+        if !belongs_to(name.trim(), id) {
+            continue;
+        }
+        match docker::ContainerState::new(state.trim())?.is_stopped() {
+            true => stopped.push(name.trim().to_owned()),
+            false => running.push(name.trim().to_owned()),
+        }
+    }
+    let volumes: Vec<_> = get_cross_volumes(engine, msg_info)?
+        .into_iter()
+        .filter(|v| belongs_to(v, id))
+        .collect();
+
+    let mut commands = vec![];
+    if !running.is_empty() {
+        let mut stop = engine.subcommand("stop");
+        stop.args(&running);
+        commands.push(stop);
+    }
+    if !(running.is_empty() && stopped.is_empty()) {
+        let mut rm = engine.subcommand("rm");
+        if *force {
+            rm.arg("--force");
+        }
+        rm.args(&running);
+        rm.args(&stopped);
+        commands.push(rm);
+    }
+    if !volumes.is_empty() {
+        let mut rm = engine.subcommand("volume");
+        rm.arg("rm");
+        if *force {
+            rm.arg("--force");
+        }
+        rm.args(&volumes);
+        commands.push(rm);
+    }
+
+    if commands.is_empty() {
+        return msg_info.print(format_args!("no containers or volumes found for `{id}`"));
+    }
+    if *execute {
+        for mut command in commands {
+            command.run(msg_info, false)?;
+        }
+    } else {
+        msg_info.note(
+            "this is a dry run. to remove the containers and volumes, pass the `--execute` flag.",
+        )?;
+        for command in commands {
+            command.print(msg_info)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toolchain_resources() {
+        let id = "cross-stable-x86_64-unknown-linux-gnu-16b8c-fe5b13d68";
+        assert!(belongs_to(id, id));
+        assert!(belongs_to(&format!("{id}-a1b2c3d4e5"), id));
+        assert!(belongs_to(
+            &format!("{id}-aarch64-unknown-linux-gnu-95840-1686000000000"),
+            id
+        ));
+        assert!(!belongs_to(&format!("{id}0"), id));
+        assert!(!belongs_to("cross-nightly-x86_64-unknown-linux-gnu", id));
+
+        let sizes = parse_volume_sizes(
+            r#"[{"Name":"a","UsageData":{"RefCount":0,"Size":2048}},{"Name":"b","UsageData":{"RefCount":0,"Size":-1}},{"Name":"c"}]"#,
+        );
+        assert_eq!(sizes, BTreeMap::from([("a".to_owned(), 2048)]));
+        assert!(parse_volume_sizes("not json").is_empty());
+
+        let ids = [id.to_owned()];
+        let removed = [PathBuf::from("/removed/project")];
+        let kept = [
+            PathBuf::from("/removed/project"),
+            PathBuf::from("/home/user/project"),
+        ];
+        let exists = |path: &Path| path.starts_with("/home");
+        assert_eq!(orphan_reason(id, &ids, &[], exists), None);
+        assert_eq!(orphan_reason(id, &ids, &kept, exists), None);
+        assert_eq!(
+            orphan_reason(id, &ids, &removed, exists),
+            Some("workspace removed")
+        );
+        assert_eq!(
+            orphan_reason(
+                "cross-nightly-x86_64-unknown-linux-gnu-a1b2c-0123456789",
+                &ids,
+                &[],
+                exists
+            ),
+            Some("toolchain not installed")
+        );
+        assert_eq!(
+            orphan_reason(docker::SECCOMP_VOLUME, &ids, &[], exists),
+            None
+        );
+
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 << 30), "3.0 GiB");
+    }
+}
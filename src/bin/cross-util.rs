@@ -48,8 +48,18 @@ enum Commands {
     Containers(commands::Containers),
     /// Run in cross container.
     Run(commands::Run),
+    /// Open a shell in the image of a target, as used by a build of the project.
+    RunImage(commands::RunImage),
     /// Clean all cross data in local storage.
     Clean(commands::Clean),
+    /// Show local build statistics.
+    #[clap(subcommand)]
+    Stats(commands::Stats),
+    /// Work with the cross cache directory.
+    #[clap(subcommand)]
+    Cache(commands::Cache),
+    /// List the toolchains with their volumes and containers, or clean them.
+    Toolchains(commands::Toolchains),
 }
 
 fn is_toolchain(toolchain: &str) -> cross::Result<Toolchain> {
@@ -75,7 +85,41 @@ fn get_container_engine(
         true => Some(true),
         false => None,
     };
-    docker::Engine::from_path(engine, in_docker, None, msg_info)
+    docker::Engine::from_path(engine, in_docker, None, None, true, msg_info)
+}
+
+/// Every container engine found with `--all-engines`, otherwise the
+/// selected one.
+fn get_container_engines(
+    engine: Option<&str>,
+    all_engines: bool,
+    msg_info: &mut MessageInfo,
+) -> cross::Result<Vec<docker::Engine>> {
+    if !all_engines {
+        return Ok(vec![get_container_engine(engine, false, msg_info)?]);
+    }
+    let mut engines: Vec<docker::Engine> = vec![];
+    for path in docker::get_container_engines() {
+        // an engine which cannot be queried doesn't prevent cleaning the others
+        let engine = match docker::Engine::from_path(path.clone(), None, None, None, true, msg_info)
+        {
+            Ok(engine) => engine,
+            Err(err) => {
+                msg_info.warn(format_args!(
+                    "skipping the container engine {path:?}: {err:#}"
+                ))?;
+                continue;
+            }
+        };
+        // wrappers such as `podman-docker` share the storage of the engine
+        if !engines.iter().any(|e| e.kind == engine.kind) {
+            engines.push(engine);
+        }
+    }
+    if engines.is_empty() {
+        eyre::bail!("no usable container engine found, such as docker or podman");
+    }
+    Ok(engines)
 }
 
 macro_rules! get_engine {
@@ -84,31 +128,56 @@ macro_rules! get_engine {
     }};
 }
 
+macro_rules! get_engines {
+    ($args:ident, $msg_info: ident) => {{
+        get_container_engines($args.engine(), $args.all_engines(), &mut $msg_info)
+    }};
+}
+
 pub fn main() -> cross::Result<()> {
     cross::install_panic_hook()?;
     let cli = Cli::parse();
     let mut msg_info = MessageInfo::create(cli.verbose, cli.quiet, cli.color.as_deref())?;
     match &cli.command {
         Commands::Images(args) => {
-            let engine = get_engine!(args, false, msg_info)?;
-            args.run(engine, &mut msg_info)?;
+            for engine in get_engines!(args, msg_info)? {
+                args.run(engine, &mut msg_info)?;
+            }
         }
         Commands::Volumes(args) => {
             let engine = get_engine!(args, args.docker_in_docker(), msg_info)?;
             args.run(engine, cli.toolchain.as_ref(), &mut msg_info)?;
         }
         Commands::Containers(args) => {
-            let engine = get_engine!(args, false, msg_info)?;
-            args.run(engine, &mut msg_info)?;
+            for engine in get_engines!(args, msg_info)? {
+                args.run(engine, &mut msg_info)?;
+            }
         }
         Commands::Clean(args) => {
-            let engine = get_engine!(args, false, msg_info)?;
-            args.run(engine, &mut msg_info)?;
+            let engines = get_engines!(args, msg_info)?;
+            args.run(&engines, &mut msg_info)?;
         }
         Commands::Run(args) => {
             let engine = get_engine!(args, false, msg_info)?;
             args.run(&cli, engine, &mut msg_info)?;
         }
+        Commands::RunImage(args) => {
+            let engine = match args.engine() {
+                Some(_) => Some(get_engine!(args, false, msg_info)?),
+                None => None,
+            };
+            args.run(&cli, engine, &mut msg_info)?;
+        }
+        Commands::Stats(args) => {
+            args.run(&mut msg_info)?;
+        }
+        Commands::Cache(args) => {
+            args.run(&mut msg_info)?;
+        }
+        Commands::Toolchains(args) => {
+            let engine = get_engine!(args, false, msg_info)?;
+            args.run(engine, &mut msg_info)?;
+        }
     }
 
     Ok(())
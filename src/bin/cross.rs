@@ -23,7 +23,7 @@ pub fn main() -> cross::Result<()> {
         Some(status) => status,
         None if !msg_info.should_fail() => {
             // if we fallback to the host cargo, use the same invocation that was made to cross
-            let argv: Vec<String> = env::args().skip(1).collect();
+            let argv = cli::strip_cross_args(cli::expand_argfiles(env::args().skip(1))?);
             msg_info.note("Falling back to `cargo` on the host.")?;
             match subcommand {
                 Some(Subcommand::List) => {
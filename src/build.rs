@@ -24,6 +24,11 @@ fn main() {
         .write_all(commit_info().as_bytes())
         .unwrap();
 
+    println!(
+        "cargo:rustc-env=CROSS_HOST_TRIPLE={}",
+        env::var("TARGET").unwrap()
+    );
+
     if env::var("CROSS_SANDBOXED").is_ok() {
         println!("cargo:rustc-cfg=cross_sandboxed");
     }
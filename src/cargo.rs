@@ -1,10 +1,12 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus};
 
 use crate::cli::Args;
 use crate::errors::*;
 use crate::extensions::CommandExt;
+use crate::setup_cache::SetupCache;
 use crate::shell::{self, MessageInfo};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,22 +20,64 @@ pub enum Subcommand {
     Test,
     Bench,
     Clippy,
+    LlvmCov,
     Metadata,
     List,
     Clean,
+    /// `cross print`, which is handled by cross and never runs cargo.
+    Print,
+    /// `cross flash`, which runs `cargo build` and then flashes the artifact.
+    Flash,
+    Audit,
+    Deny,
+    /// `cross targets`, which lists the targets and never runs cargo.
+    Targets,
+    /// `cross prefetch`, which pulls the images and never runs cargo.
+    Prefetch,
+    /// `cross config migrate`, which rewrites `Cross.toml` and never runs cargo.
+    /// The other `config` commands are cargo's.
+    Config,
+    /// `cross batch`, which runs the builds of a job file.
+    Batch,
+    /// `cross stats`, which shows the recorded build statistics and never runs cargo.
+    Stats,
+    /// `cargo publish`, which runs on the host unless `--in-container` is passed.
+    Publish,
     Other(String),
 }
 
 impl Subcommand {
+    /// If the subcommand runs in the container. `in_container` is only used
+    /// by `cargo publish`, which runs on the host by default, since it needs
+    /// the credentials of the registry.
     #[must_use]
-    pub fn needs_docker(self, is_remote: bool) -> bool {
+    pub fn needs_docker(self, is_remote: bool, in_container: bool) -> bool {
         match self {
+            Subcommand::Publish => in_container,
+            // the artifact is built in the container before it's flashed
+            Subcommand::Flash => true,
             Subcommand::Other(_) | Subcommand::List => false,
+            ref subcommand if subcommand.is_cross_only() => false,
             Subcommand::Clean if !is_remote => false,
             _ => true,
         }
     }
 
+    /// If the subcommand is handled by cross itself, and is not one of cargo.
+    #[must_use]
+    pub fn is_cross_only(&self) -> bool {
+        matches!(
+            self,
+            Subcommand::Print
+                | Subcommand::Flash
+                | Subcommand::Targets
+                | Subcommand::Prefetch
+                | Subcommand::Config
+                | Subcommand::Batch
+                | Subcommand::Stats
+        )
+    }
+
     #[must_use]
     pub fn needs_host(self, is_remote: bool) -> bool {
         self == Subcommand::Clean && is_remote
@@ -41,13 +85,56 @@ impl Subcommand {
 
     #[must_use]
     pub fn needs_interpreter(self) -> bool {
-        matches!(self, Subcommand::Run | Subcommand::Test | Subcommand::Bench)
+        matches!(
+            self,
+            Subcommand::Run | Subcommand::Test | Subcommand::Bench | Subcommand::LlvmCov
+        )
     }
 
     #[must_use]
     pub fn needs_target_in_command(self) -> bool {
         !matches!(self, Subcommand::Metadata)
     }
+
+    /// If the subcommand builds the documentation, with `cargo doc` or
+    /// `cargo rustdoc`, which both support `--open`.
+    #[must_use]
+    pub fn documents(&self) -> bool {
+        matches!(self, Subcommand::Doc | Subcommand::Rustdoc)
+    }
+
+    /// The tool installed into the image to run the subcommand.
+    #[must_use]
+    pub fn tool(&self) -> Option<&'static str> {
+        match self {
+            Subcommand::Audit => Some("cargo-audit"),
+            Subcommand::Deny => Some("cargo-deny"),
+            Subcommand::LlvmCov => Some("cargo-llvm-cov"),
+            _ => None,
+        }
+    }
+
+    /// The flags selecting `triple` for subcommands which do not accept
+    /// `--target` after their own subcommands. These are global flags, so
+    /// they must directly follow the subcommand.
+    #[must_use]
+    pub fn target_flags(&self, triple: &str) -> Option<Vec<String>> {
+        match self {
+            Subcommand::Audit => {
+                let (arch, os) = target_arch_os(triple);
+                let mut flags = vec![];
+                if let Some(arch) = arch {
+                    flags.extend(["--target-arch".to_owned(), arch.to_owned()]);
+                }
+                if let Some(os) = os {
+                    flags.extend(["--target-os".to_owned(), os.to_owned()]);
+                }
+                Some(flags)
+            }
+            Subcommand::Deny => Some(vec!["--target".to_owned(), triple.to_owned()]),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> From<&'a str> for Subcommand {
@@ -63,14 +150,159 @@ impl<'a> From<&'a str> for Subcommand {
             "t" | "test" => Subcommand::Test,
             "bench" => Subcommand::Bench,
             "clippy" => Subcommand::Clippy,
+            "llvm-cov" => Subcommand::LlvmCov,
             "metadata" => Subcommand::Metadata,
+            "print" => Subcommand::Print,
+            "flash" => Subcommand::Flash,
+            "audit" => Subcommand::Audit,
+            "deny" => Subcommand::Deny,
+            "targets" => Subcommand::Targets,
+            "prefetch" => Subcommand::Prefetch,
+            "config" => Subcommand::Config,
+            "batch" => Subcommand::Batch,
+            "stats" => Subcommand::Stats,
+            "publish" => Subcommand::Publish,
             "--list" => Subcommand::List,
             command => Subcommand::Other(command.to_owned()),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// If `name` is an environment variable with the token of a registry, such
+/// as `CARGO_REGISTRY_TOKEN` or `CARGO_REGISTRIES_MY_REGISTRY_TOKEN`.
+#[must_use]
+pub fn is_registry_token_var(name: &str) -> bool {
+    name == "CARGO_REGISTRY_TOKEN"
+        || name
+            .strip_prefix("CARGO_REGISTRIES_")
+            .and_then(|name| name.strip_suffix("_TOKEN"))
+            .is_some_and(|registry| !registry.is_empty())
+}
+
+/// The `[alias]` tables of the cargo configuration, which cargo expands
+/// before running the subcommand.
+#[derive(Debug, Default)]
+pub struct CargoAliases {
+    aliases: BTreeMap<String, Vec<String>>,
+}
+
+impl CargoAliases {
+    /// Read the aliases like cargo does from `cwd`: the configuration of the
+    /// closest directory has precedence, and `CARGO_HOME` comes last.
+    pub fn read(cwd: &Path, cargo_home: Option<&Path>) -> Self {
+        let mut dirs: Vec<PathBuf> = cwd.ancestors().map(|dir| dir.join(".cargo")).collect();
+        if let Some(home) = cargo_home.filter(|home| !dirs.iter().any(|dir| dir == home)) {
+            dirs.push(home.to_owned());
+        }
+
+        let mut aliases = CargoAliases::default();
+        for dir in dirs.iter().rev() {
+            // cargo prefers the legacy `config` if both exist.
+            let contents = ["config", "config.toml"]
+                .iter()
+                .find_map(|name| std::fs::read_to_string(dir.join(name)).ok());
+            if let Some(contents) = contents {
+                aliases.extend(&contents);
+            }
+        }
+        aliases
+    }
+
+    /// Add the aliases of a configuration file, which override the current
+    /// ones. Invalid files are reported by cargo, so they're ignored here.
+    fn extend(&mut self, contents: &str) {
+        let Ok(config) = contents.parse::<toml::Table>() else {
+            return;
+        };
+        let Some(toml::Value::Table(aliases)) = config.get("alias") else {
+            return;
+        };
+        for (name, value) in aliases {
+            let expansion = match value {
+                toml::Value::String(s) => s.split_whitespace().map(str::to_owned).collect(),
+                toml::Value::Array(values) => values
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_owned))
+                    .collect(),
+                _ => continue,
+            };
+            self.aliases.insert(name.clone(), expansion);
+        }
+    }
+
+    /// The expansion of `name`, where `CARGO_ALIAS_{NAME}` overrides the files.
+    fn get(&self, name: &str) -> Option<Vec<String>> {
+        let var = format!("CARGO_ALIAS_{}", name.to_uppercase().replace('-', "_"));
+        match std::env::var(var) {
+            Ok(value) => Some(value.split_whitespace().map(str::to_owned).collect()),
+            Err(_) => self.aliases.get(name).cloned(),
+        }
+    }
+
+    /// The subcommand `name` is an alias of, following aliases of aliases.
+    /// Subcommands handled by cross itself cannot be aliased, since the
+    /// expansion is run by cargo.
+    pub fn resolve(&self, name: &str) -> Option<Subcommand> {
+        let mut seen = vec![name.to_owned()];
+        let mut current = name.to_owned();
+        loop {
+            let expansion = self.get(&current)?;
+            let next = expansion.into_iter().next()?;
+            match Subcommand::from(next.as_str()) {
+                Subcommand::Other(_) if seen.contains(&next) => return None,
+                Subcommand::Other(_) => {
+                    seen.push(next.clone());
+                    current = next;
+                }
+                Subcommand::List => return None,
+                subcommand if subcommand.is_cross_only() => return None,
+                subcommand => return Some(subcommand),
+            }
+        }
+    }
+}
+
+/// The `target_arch` and `target_os` cfg values of `triple`, as far as they
+/// can be derived from the triple itself.
+fn target_arch_os(triple: &str) -> (Option<&'static str>, Option<&'static str>) {
+    let mut parts = triple.split('-');
+    let arch = match parts.next().unwrap_or_default() {
+        "x86_64" => Some("x86_64"),
+        "i386" | "i586" | "i686" => Some("x86"),
+        a if a.starts_with("aarch64") => Some("aarch64"),
+        a if a.starts_with("arm") || a.starts_with("thumb") => Some("arm"),
+        a if a.starts_with("mips64") => Some("mips64"),
+        a if a.starts_with("mips") => Some("mips"),
+        "powerpc" => Some("powerpc"),
+        a if a.starts_with("powerpc64") => Some("powerpc64"),
+        a if a.starts_with("riscv64") => Some("riscv64"),
+        a if a.starts_with("riscv32") => Some("riscv32"),
+        "s390x" => Some("s390x"),
+        "sparc64" | "sparcv9" => Some("sparc64"),
+        "loongarch64" => Some("loongarch64"),
+        "wasm32" => Some("wasm32"),
+        _ => None,
+    };
+    let os = parts.find_map(|part| match part {
+        "linux" if triple.contains("android") => Some("android"),
+        "linux" => Some("linux"),
+        "windows" => Some("windows"),
+        "darwin" => Some("macos"),
+        "ios" => Some("ios"),
+        "freebsd" => Some("freebsd"),
+        "netbsd" => Some("netbsd"),
+        "openbsd" => Some("openbsd"),
+        "dragonfly" => Some("dragonfly"),
+        "illumos" => Some("illumos"),
+        "solaris" => Some("solaris"),
+        "emscripten" => Some("emscripten"),
+        "none" => Some("none"),
+        _ => None,
+    });
+    (arch, os)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct CargoMetadata {
     pub workspace_root: PathBuf,
     pub target_directory: PathBuf,
@@ -91,6 +323,23 @@ impl CargoMetadata {
         self.non_workspace_members().filter_map(|p| p.crate_path())
     }
 
+    /// The highest `rust-version` of the workspace members, with the package
+    /// declaring it.
+    pub fn rust_version(&self) -> Option<(&Package, semver::Version)> {
+        self.packages
+            .iter()
+            .filter(|p| self.workspace_members.contains(&p.id))
+            .filter_map(|p| Some((p, parse_rust_version(p.rust_version.as_deref()?)?)))
+            .max_by(|(_, a), (_, b)| a.cmp(b))
+    }
+
+    /// Build in `cross/<triple>` of the target directory. The builds in the
+    /// container have other fingerprints than the ones of the host, so
+    /// sharing the target directory rebuilds everything when switching.
+    pub fn isolate_target_dir(&mut self, triple: &str) {
+        self.target_directory = self.target_directory.join("cross").join(triple);
+    }
+
     #[cfg(feature = "dev")]
     #[must_use]
     pub fn get_package(&self, package: &str) -> Option<&Package> {
@@ -98,7 +347,27 @@ impl CargoMetadata {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Parse a `rust-version`, which may omit the patch version, such as `1.70`.
+fn parse_rust_version(version: &str) -> Option<semver::Version> {
+    let mut parts = version.trim().split('.').map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    parts
+        .next()
+        .is_none()
+        .then(|| semver::Version::new(major, minor, patch))
+}
+
+/// Returns `true` if rustc `version` satisfies the `rust-version`. Like cargo,
+/// pre-releases such as nightlies satisfy their own version.
+#[must_use]
+pub fn satisfies_rust_version(version: &semver::Version, rust_version: &semver::Version) -> bool {
+    (version.major, version.minor, version.patch)
+        >= (rust_version.major, rust_version.minor, rust_version.patch)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Package {
     pub id: String,
     pub name: String,
@@ -107,6 +376,17 @@ pub struct Package {
     pub version: String,
     pub license: Option<String>,
     pub metadata: Option<Box<serde_json::value::RawValue>>,
+    #[serde(default)]
+    pub targets: Vec<PackageTarget>,
+    #[serde(default)]
+    pub rust_version: Option<String>,
+}
+
+/// A target of a package, such as a library or binary.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PackageTarget {
+    pub name: String,
+    pub kind: Vec<String>,
 }
 
 impl Package {
@@ -121,6 +401,74 @@ impl Package {
     }
 }
 
+/// Cargo settings affecting network access, which must be the same on the
+/// host and inside the container.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkFlags {
+    /// `--offline`, `--frozen` or `CARGO_NET_OFFLINE` was provided.
+    pub offline: bool,
+    /// The sparse protocol is enabled for crates.io.
+    pub sparse_registry: bool,
+}
+
+impl NetworkFlags {
+    /// Detect the network flags from the cargo arguments and the host environment.
+    #[must_use]
+    pub fn detect(cargo_args: &[String]) -> NetworkFlags {
+        Self::detect_with(cargo_args, |var| std::env::var(var).ok())
+    }
+
+    fn detect_with(
+        cargo_args: &[String],
+        get_var: impl Fn(&str) -> Option<String>,
+    ) -> NetworkFlags {
+        let is_true = |var: &str| get_var(var).is_some_and(|v| crate::config::bool_from_envvar(&v));
+        // the arguments after `--` are for the program, not cargo
+        let cargo_args = cargo_args.split(|a| a == "--").next().unwrap_or_default();
+        let offline = cargo_args
+            .iter()
+            .any(|a| a == "--offline" || a == "--frozen")
+            || is_true("CARGO_NET_OFFLINE");
+        let sparse_registry =
+            get_var("CARGO_REGISTRIES_CRATES_IO_PROTOCOL").as_deref() == Some("sparse");
+
+        NetworkFlags {
+            offline,
+            sparse_registry,
+        }
+    }
+
+    /// The environment variables to mirror the flags to any cargo invocation
+    /// in the container, including ones from build scripts or xargo.
+    #[must_use]
+    pub fn envvars(&self) -> Vec<&'static str> {
+        let mut envvars = vec![];
+        if self.offline {
+            envvars.push("CARGO_NET_OFFLINE=true");
+        }
+        if self.sparse_registry {
+            envvars.push("CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse");
+        }
+        envvars
+    }
+}
+
+/// The `--locked`, `--frozen` and `--offline` flags of `cargo_args`, which
+/// must be passed to the cargo commands run by cross, so they don't update
+/// the lockfile or access the network when the build would not.
+#[must_use]
+pub fn lockfile_flags(cargo_args: &[String]) -> Vec<&str> {
+    // the arguments after `--` are for the program, not cargo
+    let cargo_args = cargo_args.split(|a| a == "--").next().unwrap_or_default();
+    let mut flags = vec![];
+    for flag in ["--locked", "--frozen", "--offline"] {
+        if cargo_args.iter().any(|a| a == flag) {
+            flags.push(flag);
+        }
+    }
+    flags
+}
+
 #[must_use]
 pub fn cargo_command() -> Command {
     Command::new("cargo")
@@ -132,6 +480,114 @@ pub fn cargo_metadata_with_args(
     args: Option<&Args>,
     msg_info: &mut MessageInfo,
 ) -> Result<Option<CargoMetadata>> {
+    let cache = match args {
+        Some(args) => {
+            SetupCache::or_disabled(SetupCache::metadata(!args.no_cache_setup), msg_info)?
+        }
+        None => SetupCache::disabled(),
+    };
+    let mut command = metadata_command(cd, args);
+    let key = match metadata_snapshot_key(&command, cd) {
+        Ok(key) => Some(key),
+        Err(err) => {
+            msg_info.debug(format_args!(
+                "not using the setup snapshot of `cargo metadata`: {err:#}"
+            ))?;
+            None
+        }
+    };
+    let snapshot = key
+        .as_deref()
+        .and_then(|key| cache.get::<CargoMetadata>(key));
+    let manifest = match snapshot {
+        Some(metadata) => {
+            msg_info.debug("using the setup snapshot of `cargo metadata`")?;
+            Some(metadata)
+        }
+        None => {
+            let output = command.run_and_get_output(msg_info)?;
+            if !output.status.success() {
+                msg_info.warn("unable to get metadata for package")?;
+                let indented =
+                    shell::indent(&String::from_utf8(output.stderr)?, shell::default_ident());
+                msg_info.debug(indented)?;
+                return Ok(None);
+            }
+            let manifest: Option<CargoMetadata> = serde_json::from_slice(&output.stdout)?;
+            if let (Some(metadata), Some(key)) = (&manifest, &key) {
+                match metadata_snapshot_paths(metadata, cd) {
+                    Ok(paths) => cache.put(key, &paths, metadata, msg_info)?,
+                    Err(err) => msg_info.debug(format_args!(
+                        "unable to take the setup snapshot of `cargo metadata`: {err:#}"
+                    ))?,
+                }
+            }
+            manifest
+        }
+    };
+    manifest
+        .map(|m| -> Result<_> {
+            Ok(CargoMetadata {
+                target_directory: args
+                    .and_then(|a| a.target_dir.clone())
+                    .unwrap_or(m.target_directory),
+                ..m
+            })
+        })
+        .transpose()
+}
+
+/// Everything `cargo metadata` depends on besides the files, which are in
+/// [`metadata_snapshot_paths`].
+fn metadata_snapshot_key(command: &Command, cd: Option<&Path>) -> Result<String> {
+    let cwd = match cd {
+        Some(cd) => cd.to_owned(),
+        None => std::env::current_dir()?,
+    };
+    let vars = [
+        "CARGO_HOME",
+        "CARGO_TARGET_DIR",
+        "CARGO_BUILD_TARGET_DIR",
+        "RUSTUP_TOOLCHAIN",
+    ]
+    .map(std::env::var_os);
+    Ok(format!("cargo-metadata\n{cwd:?}\n{command:?}\n{vars:?}"))
+}
+
+/// The directories of a package where cargo discovers targets, such as
+/// `build.rs` or `src/bin/*.rs`, relative to its manifest.
+const TARGET_DISCOVERY_DIRS: &[&str] = &["", "src", "src/bin", "examples", "tests", "benches"];
+
+/// The manifests of the workspace and its path dependencies, the lockfile,
+/// and the cargo configuration files, which `cargo metadata` reads. Since
+/// adding a file which cargo discovers as a target changes the modification
+/// time of its directory, these directories are included too.
+fn metadata_snapshot_paths(metadata: &CargoMetadata, cd: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let cwd = match cd {
+        Some(cd) => cd.to_owned(),
+        None => std::env::current_dir()?,
+    };
+    let mut paths = vec![];
+    for package in metadata.packages.iter().filter(|p| p.source.is_none()) {
+        paths.push(package.manifest_path.clone());
+        if let Some(dir) = package.manifest_path.parent() {
+            paths.extend(TARGET_DISCOVERY_DIRS.iter().map(|d| dir.join(d)));
+        }
+    }
+    paths.push(metadata.workspace_root.join("Cargo.toml"));
+    paths.push(metadata.workspace_root.join("Cargo.lock"));
+    let config_dirs = cwd
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(home::cargo_home().ok());
+    for dir in config_dirs {
+        paths.push(dir.join("config.toml"));
+        paths.push(dir.join("config"));
+    }
+    Ok(paths)
+}
+
+fn metadata_command(cd: Option<&Path>, args: Option<&Args>) -> Command {
     let mut command = cargo_command();
     if let Some(channel) = args.and_then(|x| x.channel.as_deref()) {
         command.arg(format!("+{channel}"));
@@ -153,24 +609,10 @@ pub fn cargo_metadata_with_args(
     if let Some(features) = args.map(|a| &a.features).filter(|v| !v.is_empty()) {
         command.args([String::from("--features"), features.join(",")]);
     }
-    let output = command.run_and_get_output(msg_info)?;
-    if !output.status.success() {
-        msg_info.warn("unable to get metadata for package")?;
-        let indented = shell::indent(&String::from_utf8(output.stderr)?, shell::default_ident());
-        msg_info.debug(indented)?;
-        return Ok(None);
+    if let Some(args) = args {
+        command.args(lockfile_flags(&args.cargo_args));
     }
-    let manifest: Option<CargoMetadata> = serde_json::from_slice(&output.stdout)?;
-    manifest
-        .map(|m| -> Result<_> {
-            Ok(CargoMetadata {
-                target_directory: args
-                    .and_then(|a| a.target_dir.clone())
-                    .unwrap_or(m.target_directory),
-                ..m
-            })
-        })
-        .transpose()
+    command
 }
 
 /// Pass-through mode
@@ -187,3 +629,205 @@ pub fn run_and_get_output(
 ) -> Result<std::process::Output> {
     cargo_command().args(args).run_and_get_output(msg_info)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&a| a.to_owned()).collect()
+    }
+
+    #[test]
+    fn publish_decisions() {
+        assert_eq!(Subcommand::from("publish"), Subcommand::Publish);
+        // publish runs on the host, unless it's run in the container
+        assert!(!Subcommand::Publish.needs_docker(false, false));
+        assert!(!Subcommand::Publish.needs_docker(true, false));
+        assert!(Subcommand::Publish.needs_docker(false, true));
+        assert!(!Subcommand::Publish.needs_host(false));
+        assert!(!Subcommand::Publish.needs_host(true));
+        // `--in-container` doesn't change the other subcommands
+        assert!(Subcommand::Build.needs_docker(false, false));
+        assert!(!Subcommand::Clean.needs_docker(false, true));
+        assert!(Subcommand::Clean.needs_docker(true, false));
+        assert!(Subcommand::Clean.needs_host(true));
+
+        assert!(is_registry_token_var("CARGO_REGISTRY_TOKEN"));
+        assert!(is_registry_token_var("CARGO_REGISTRIES_MY_REGISTRY_TOKEN"));
+        assert!(!is_registry_token_var("CARGO_REGISTRIES__TOKEN"));
+        assert!(!is_registry_token_var("CARGO_REGISTRIES_MY_REGISTRY_INDEX"));
+        assert!(!is_registry_token_var("CARGO_HOME"));
+    }
+
+    #[test]
+    fn rustc_subcommands() {
+        assert!(Subcommand::Rustdoc.documents());
+        assert!(!Subcommand::Rustc.documents());
+        assert!(Subcommand::Rustc.needs_target_in_command());
+        assert!(Subcommand::Rustc.needs_docker(false, false));
+    }
+
+    #[test]
+    fn rust_versions() {
+        let version = semver::Version::new;
+        assert_eq!(parse_rust_version("1.70"), Some(version(1, 70, 0)));
+        assert_eq!(parse_rust_version("1.70.1"), Some(version(1, 70, 1)));
+        assert_eq!(parse_rust_version("1"), Some(version(1, 0, 0)));
+        assert_eq!(parse_rust_version("1.70.0.0"), None);
+        assert_eq!(parse_rust_version("1.x"), None);
+
+        let msrv = version(1, 70, 0);
+        assert!(satisfies_rust_version(&version(1, 70, 0), &msrv));
+        assert!(satisfies_rust_version(&version(1, 77, 2), &msrv));
+        assert!(satisfies_rust_version(
+            &semver::Version::parse("1.70.0-nightly").unwrap(),
+            &msrv
+        ));
+        assert!(!satisfies_rust_version(&version(1, 69, 0), &msrv));
+    }
+
+    #[test]
+    fn cargo_aliases() {
+        let mut aliases = CargoAliases::default();
+        aliases.extend(
+            r#"
+            [alias]
+            xb = "build --release"
+            xtask = ["run", "-p", "xtask", "--"]
+            ci = ["xb", "--locked"]
+            loop-a = "loop-b"
+            loop-b = "loop-a"
+            unknown = "frobnicate"
+            printer = "print image"
+            numbers = "stats"
+            jobs = "batch jobs.toml"
+        "#,
+        );
+        // closer configurations override the alias
+        aliases.extend("alias.xtest = \"test --workspace\"");
+        aliases.extend("alias.xb = \"check\"");
+
+        assert_eq!(aliases.resolve("xb"), Some(Subcommand::Check));
+        assert_eq!(aliases.resolve("xtask"), Some(Subcommand::Run));
+        assert_eq!(aliases.resolve("ci"), Some(Subcommand::Check));
+        assert_eq!(aliases.resolve("xtest"), Some(Subcommand::Test));
+        assert_eq!(aliases.resolve("loop-a"), None);
+        assert_eq!(aliases.resolve("unknown"), None);
+        assert_eq!(aliases.resolve("printer"), None);
+        assert_eq!(aliases.resolve("numbers"), None);
+        assert_eq!(aliases.resolve("jobs"), None);
+        assert_eq!(aliases.resolve("missing"), None);
+    }
+
+    #[test]
+    fn audit_deny_target_flags() {
+        assert_eq!(
+            Subcommand::Audit.target_flags("armv7-unknown-linux-gnueabihf"),
+            Some(args(&["--target-arch", "arm", "--target-os", "linux"]))
+        );
+        assert_eq!(
+            Subcommand::Audit.target_flags("aarch64-linux-android"),
+            Some(args(&[
+                "--target-arch",
+                "aarch64",
+                "--target-os",
+                "android"
+            ]))
+        );
+        assert_eq!(
+            Subcommand::Audit.target_flags("i686-pc-windows-gnu"),
+            Some(args(&["--target-arch", "x86", "--target-os", "windows"]))
+        );
+        assert_eq!(
+            Subcommand::Deny.target_flags("x86_64-unknown-linux-musl"),
+            Some(args(&["--target", "x86_64-unknown-linux-musl"]))
+        );
+        assert_eq!(
+            Subcommand::Build.target_flags("x86_64-unknown-linux-gnu"),
+            None
+        );
+        assert_eq!(Subcommand::from("deny").tool(), Some("cargo-deny"));
+        assert_eq!(Subcommand::from("llvm-cov").tool(), Some("cargo-llvm-cov"));
+    }
+
+    #[test]
+    fn detect_network_flags() {
+        let no_env = |_: &str| None;
+        assert_eq!(
+            NetworkFlags::detect_with(&args(&["build"]), no_env),
+            NetworkFlags::default()
+        );
+        assert!(NetworkFlags::detect_with(&args(&["build", "--frozen"]), no_env).offline);
+        assert!(!NetworkFlags::detect_with(&args(&["run", "--", "--offline"]), no_env).offline);
+
+        let env = |var: &str| match var {
+            "CARGO_NET_OFFLINE" => Some("true".to_owned()),
+            "CARGO_REGISTRIES_CRATES_IO_PROTOCOL" => Some("sparse".to_owned()),
+            _ => None,
+        };
+        let flags = NetworkFlags::detect_with(&args(&["build"]), env);
+        assert!(flags.offline && flags.sparse_registry);
+        assert_eq!(
+            flags.envvars(),
+            [
+                "CARGO_NET_OFFLINE=true",
+                "CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse"
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_lockfile_flags() -> Result<()> {
+        assert_eq!(
+            lockfile_flags(&args(&["build", "--offline", "--locked", "--", "--frozen"])),
+            ["--locked", "--offline"]
+        );
+
+        let target_list = crate::rustc::TargetList { triples: vec![] };
+        let cross_args = crate::cli::parse_args(
+            args(&["check", "--frozen", "--offline", "--", "--locked"]),
+            &target_list,
+        )?;
+        let command = metadata_command(None, Some(&cross_args));
+        let command_args: Vec<_> = command.get_args().filter_map(|a| a.to_str()).collect();
+        assert_eq!(
+            command_args,
+            ["metadata", "--format-version", "1", "--frozen", "--offline"]
+        );
+
+        // without the arguments, the dependencies are not resolved.
+        let command = metadata_command(None, None);
+        assert!(command.get_args().any(|a| a == "--no-deps"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_snapshot_discovered_targets() -> Result<()> {
+        let metadata: CargoMetadata = serde_json::from_str(
+            r#"{
+                "workspace_root": "/project",
+                "target_directory": "/project/target",
+                "packages": [
+                    {"id": "foo", "name": "foo", "manifest_path": "/project/foo/Cargo.toml", "version": "0.1.0"},
+                    {"id": "bar", "name": "bar", "manifest_path": "/registry/bar/Cargo.toml", "version": "0.1.0", "source": "registry+https://github.com/rust-lang/crates.io-index"}
+                ],
+                "workspace_members": ["foo"]
+            }"#,
+        )?;
+        let paths = metadata_snapshot_paths(&metadata, Some(Path::new("/project")))?;
+        for path in [
+            "/project/foo/Cargo.toml",
+            "/project/foo/",
+            "/project/foo/src/bin",
+            "/project/foo/examples",
+            "/project/Cargo.lock",
+        ] {
+            assert!(paths.contains(&PathBuf::from(path)), "missing {path}");
+        }
+        assert!(!paths.iter().any(|p| p.starts_with("/registry")));
+
+        Ok(())
+    }
+}
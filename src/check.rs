@@ -0,0 +1,236 @@
+//! `cross check --targets`: check the workspace for several targets in one
+//! command, such as for the check command of an IDE.
+//!
+//! The targets sharing an image are checked together, with a single cargo
+//! command in one container using the configuration of the first of them.
+//! Targets building a custom image, with `pre-build` or a `dockerfile`, or
+//! using xargo are checked on their own. The groups are checked one after the
+//! other, each with its own target directory, named after its first target,
+//! so the build scripts and host dependencies are not rebuilt when switching
+//! images, while the registry of the cargo home is shared. With
+//! `--message-format=json`, the diagnostics of every target are remapped to
+//! the paths on the host.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use crate::cargo::{self, Subcommand};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::docker;
+use crate::errors::*;
+use crate::file::ToUtf8;
+use crate::rustc::TargetList;
+use crate::shell::MessageInfo;
+use crate::Target;
+
+/// The target directory of `triple`, inside the target directory of the workspace.
+pub(crate) fn target_dir(base: &Path, triple: &str) -> PathBuf {
+    base.join("check").join(triple)
+}
+
+/// The image `target` is checked with, if it can share a container with
+/// other targets.
+fn shared_image(config: &Config, target: &Target) -> Option<String> {
+    let (uses_xargo, uses_zig) = crate::uses_xargo_and_zig(config, target).ok()?;
+    if uses_xargo || config.dockerfile(target).is_some() || config.pre_build(target).is_some() {
+        return None;
+    }
+    docker::get_image_name(config, target, uses_zig).ok()
+}
+
+/// Group the targets by the image they're checked with, in the order of the
+/// first target of each group.
+fn group_targets(
+    targets: Vec<Target>,
+    image: impl Fn(&Target) -> Option<String>,
+) -> Vec<Vec<Target>> {
+    let mut groups: Vec<(Option<String>, Vec<Target>)> = vec![];
+    for target in targets {
+        let key = image(&target);
+        match groups
+            .iter_mut()
+            .find(|(image, _)| image.is_some() && *image == key)
+        {
+            Some((_, group)) => group.push(target),
+            None => groups.push((key, vec![target])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// The arguments of cargo on the host for `triples`, replacing the target
+/// directory, which was set to the mount point of the container.
+fn host_args(args: &Args, triples: &[&str], target_dir: &Path) -> Result<Vec<String>> {
+    let mut host_args = vec![];
+    let mut cargo_args = args.cargo_args.iter();
+    while let Some(arg) = cargo_args.next() {
+        if arg == "--target-dir" {
+            cargo_args.next();
+        } else if !arg.starts_with("--target-dir=") {
+            host_args.push(arg.clone());
+        }
+    }
+    for triple in triples {
+        host_args.extend(["--target".to_owned(), (*triple).to_owned()]);
+    }
+    host_args.extend(["--target-dir".to_owned(), target_dir.to_utf8()?.to_owned()]);
+    host_args.extend(args.rest_args.iter().cloned());
+    Ok(host_args)
+}
+
+pub(crate) fn run(
+    args: Args,
+    target_list: TargetList,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<ExitStatus>> {
+    if args.subcommand != Some(Subcommand::Check) {
+        eyre::bail!("`--targets` is only supported by `cross check`");
+    }
+    if args.target.is_some() {
+        return Err(eyre::eyre!("`--target` cannot be used with `--targets`"))
+            .with_suggestion(|| "add the target to the list of `--targets`");
+    }
+    let metadata = crate::cargo_metadata_with_args(None, Some(&args), msg_info)?
+        .ok_or_else(|| eyre::eyre!("could not find the workspace of the targets"))?;
+    let base = match &args.target_dir {
+        Some(dir) => dir.clone(),
+        None => metadata.target_directory.clone(),
+    };
+    let config = Config::new(Some(crate::toml_with_profile(&metadata, &args, msg_info)?));
+    let targets = args
+        .targets
+        .iter()
+        .map(|triple| Target::from(triple, &target_list))
+        .collect();
+
+    let mut failure = None;
+    for group in group_targets(targets, |target| shared_image(&config, target)) {
+        let triples: Vec<&str> = group.iter().map(Target::triple).collect();
+        let dir = target_dir(&base, triples[0]);
+        match &triples[..] {
+            [triple] => msg_info.status(format_args!("checking target `{triple}`"))?,
+            _ => msg_info.status(format_args!(
+                "checking targets `{}` in the same container",
+                triples.join("`, `")
+            ))?,
+        }
+        let mut target_args = args.clone();
+        target_args.targets = vec![];
+        target_args.target = Some(group[0].clone());
+        target_args.extra_targets = group[1..].to_vec();
+        target_args.target_dir = Some(dir.clone());
+        let status =
+            match crate::run_build(target_args, target_list.clone(), None, None, None, msg_info)? {
+                Some(status) => status,
+                None if msg_info.should_fail() => return Ok(None),
+                // targets which do not need a container are checked on the host.
+                None => cargo::run(&host_args(&args, &triples, &dir)?, msg_info)?,
+            };
+        if !status.success() && failure.is_none() {
+            failure = Some(status);
+        }
+    }
+    Ok(Some(failure.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_targets() -> Result<()> {
+        let target_list = TargetList { triples: vec![] };
+        let args = crate::cli::parse_args(
+            [
+                "check",
+                "--targets",
+                "aarch64-unknown-linux-gnu,x86_64-pc-windows-gnu",
+                "--message-format=json",
+                "--target-dir",
+                "/tmp/target",
+            ]
+            .map(str::to_owned)
+            .to_vec(),
+            &target_list,
+        )?;
+        assert_eq!(
+            args.targets,
+            ["aarch64-unknown-linux-gnu", "x86_64-pc-windows-gnu"]
+        );
+        assert_eq!(
+            target_dir(Path::new("/tmp/target"), "aarch64-unknown-linux-gnu"),
+            Path::new("/tmp/target/check/aarch64-unknown-linux-gnu")
+        );
+        assert_eq!(
+            host_args(
+                &args,
+                &["x86_64-unknown-linux-gnu"],
+                Path::new("/tmp/target/check/x86_64-unknown-linux-gnu")
+            )?,
+            [
+                "check",
+                "--message-format=json",
+                "--target",
+                "x86_64-unknown-linux-gnu",
+                "--target-dir",
+                "/tmp/target/check/x86_64-unknown-linux-gnu",
+            ]
+        );
+        assert_eq!(
+            host_args(
+                &args,
+                &["x86_64-unknown-linux-gnu", "i686-unknown-linux-gnu"],
+                Path::new("/tmp/target/check/x86_64-unknown-linux-gnu")
+            )?[2..6],
+            [
+                "--target",
+                "x86_64-unknown-linux-gnu",
+                "--target",
+                "i686-unknown-linux-gnu",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn group_by_image() {
+        let target = |triple| Target::new_built_in(triple);
+        let targets = vec![
+            target("aarch64-unknown-linux-gnu"),
+            target("x86_64-unknown-linux-gnu"),
+            target("aarch64-unknown-linux-musl"),
+            target("x86_64-unknown-uefi"),
+            target("x86_64-unknown-none"),
+        ];
+        // the aarch64 targets share an image, the others have none
+        let image = |target: &Target| {
+            target
+                .triple()
+                .strip_prefix("aarch64")
+                .map(|_| "multi-target".to_owned())
+                .or_else(|| {
+                    (target.triple() == "x86_64-unknown-linux-gnu").then(|| "x86_64".to_owned())
+                })
+        };
+        let groups = group_targets(targets, image);
+        let groups: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|group| group.iter().map(Target::triple).collect())
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                vec!["aarch64-unknown-linux-gnu", "aarch64-unknown-linux-musl"],
+                vec!["x86_64-unknown-linux-gnu"],
+                vec!["x86_64-unknown-uefi"],
+                vec!["x86_64-unknown-none"],
+            ]
+        );
+
+        let config = Config::new(None);
+        assert!(shared_image(&config, &target("aarch64-unknown-linux-gnu")).is_some());
+        assert_eq!(shared_image(&config, &target("x86_64-unknown-uefi")), None);
+    }
+}
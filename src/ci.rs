@@ -0,0 +1,110 @@
+//! Integration with GitHub Actions, enabled with `CROSS_CI_ANNOTATIONS=1`.
+//!
+//! Errors and warnings of cross are turned into annotations, a problem
+//! matcher turns the diagnostics of rustc into annotations on the source
+//! files, and a summary of the build is added to the job summary.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::bool_from_envvar;
+use crate::errors::*;
+use crate::file::ToUtf8;
+use crate::report::BuildReport;
+use crate::shell::MessageInfo;
+use crate::temp;
+
+/// The owner of the problem matcher, used to remove it after the build.
+const MATCHER_OWNER: &str = "cross-rustc";
+
+/// Match the diagnostics of rustc, such as:
+///
+/// ```text
+/// error[E0308]: mismatched types
+///  --> src/main.rs:2:18
+/// ```
+const PROBLEM_MATCHER: &str = r#"{
+    "problemMatcher": [
+        {
+            "owner": "cross-rustc",
+            "pattern": [
+                {
+                    "regexp": "^(warning|error)(\\[(\\S*)\\])?: (.*)$",
+                    "severity": 1,
+                    "message": 4,
+                    "code": 3
+                },
+                {
+                    "regexp": "^\\s*-->\\s*(.*?):(\\d+):(\\d+)\\s*$",
+                    "file": 1,
+                    "line": 2,
+                    "column": 3
+                }
+            ]
+        }
+    ]
+}
+"#;
+
+/// If annotations were requested and cross is running in GitHub Actions.
+pub fn annotations_enabled() -> bool {
+    env::var("CROSS_CI_ANNOTATIONS").is_ok_and(|s| bool_from_envvar(&s))
+        && env::var("GITHUB_ACTIONS").as_deref() == Ok("true")
+}
+
+/// Escape the message of a workflow command.
+fn escape_data(message: &str) -> String {
+    message
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// The workflow command for an annotation of `level`, such as `error`.
+pub fn annotation(level: &str, message: &str) -> String {
+    format!("::{level} title=cross::{}", escape_data(message))
+}
+
+pub fn add_problem_matcher(msg_info: &mut MessageInfo) -> Result<()> {
+    let dir = temp::dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("rustc-problem-matcher.json");
+    fs::write(&path, PROBLEM_MATCHER)
+        .wrap_err_with(|| format!("could not write problem matcher to `{path:?}`"))?;
+    msg_info.print(format_args!("::add-matcher::{}", path.to_utf8()?))
+}
+
+pub fn remove_problem_matcher(msg_info: &mut MessageInfo) -> Result<()> {
+    msg_info.print(format_args!("::remove-matcher owner={MATCHER_OWNER}::"))
+}
+
+/// Append the summary of the build to `$GITHUB_STEP_SUMMARY`, if set.
+pub fn write_step_summary(report: &BuildReport) -> Result<()> {
+    let Some(path) = env::var_os("GITHUB_STEP_SUMMARY") else {
+        return Ok(());
+    };
+    let path = Path::new(&path);
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(report.markdown_summary().as_bytes()))
+        .wrap_err_with(|| format!("could not write the job summary to `{path:?}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workflow_commands() {
+        assert_eq!(
+            annotation("error", "could not run container\n100% failed"),
+            "::error title=cross::could not run container%0A100%25 failed"
+        );
+        let matcher: serde_json::Value = serde_json::from_str(PROBLEM_MATCHER).unwrap();
+        assert_eq!(matcher["problemMatcher"][0]["owner"], MATCHER_OWNER);
+    }
+}
@@ -1,29 +1,105 @@
 use std::env;
 use std::path::{Path, PathBuf};
 
-use crate::cargo::Subcommand;
-use crate::errors::Result;
-use crate::file::{absolute_path, PathExt};
+use crate::cargo::{CargoAliases, Subcommand};
+use crate::errors::*;
+use crate::file::{self, absolute_path, PathExt};
 use crate::rustc::TargetList;
+use crate::shard::Shard;
 use crate::shell::{self, MessageInfo};
 use crate::Target;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Args {
     pub cargo_args: Vec<String>,
     pub rest_args: Vec<String>,
     pub subcommand: Option<Subcommand>,
     pub channel: Option<String>,
     pub target: Option<Target>,
+    /// The targets of `cross check --targets`.
+    pub targets: Vec<String>,
+    /// The targets checked along `target` in the same container, since they
+    /// share its image.
+    pub extra_targets: Vec<Target>,
     pub features: Vec<String>,
     pub target_dir: Option<PathBuf>,
     pub manifest_path: Option<PathBuf>,
+    pub platform: Option<String>,
+    pub config_profile: Option<String>,
+    pub build_report: Option<PathBuf>,
+    pub shard: Option<Shard>,
+    pub quiet_container: bool,
+    /// Evaluate the setup again instead of using the setup snapshots.
+    pub no_cache_setup: bool,
+    /// Run `cross publish` in the container instead of on the host.
+    pub in_container: bool,
+    /// Run the image without verifying the capabilities of its labels.
+    pub skip_image_checks: bool,
+    /// Fail instead of noting conflicting arguments, such as several `--target`.
+    pub strict_args: bool,
+    /// Only validate the configuration, without a container engine.
+    pub check_config_only: bool,
+    pub devices: Vec<String>,
+    /// Ports passed with `--publish`, published by the container of `cross run`.
+    pub publish: Vec<String>,
+    /// Environment variables passed with `--env`, as `KEY=VALUE` or `KEY`.
+    pub envs: Vec<String>,
     pub version: bool,
+    pub version_format: VersionFormat,
+    pub print: Option<PrintValue>,
     pub verbose: u8,
     pub quiet: bool,
     pub color: Option<String>,
 }
 
+/// The output format of `cross --version`, `cross targets` and `cross print mounts`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VersionFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+impl std::str::FromStr for VersionFormat {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "human" => Ok(VersionFormat::Human),
+            "json" => Ok(VersionFormat::Json),
+            _ => eyre::bail!("invalid version format `{s}`, expected `human` or `json`"),
+        }
+    }
+}
+
+/// The value resolved and printed by `cross print`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintValue {
+    Image,
+    Sysroot,
+    Runner,
+    DebArch,
+    /// The directories of the host mounted into the container.
+    Mounts,
+}
+
+impl std::str::FromStr for PrintValue {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "image" => Ok(PrintValue::Image),
+            "sysroot" => Ok(PrintValue::Sysroot),
+            "runner" => Ok(PrintValue::Runner),
+            "deb-arch" => Ok(PrintValue::DebArch),
+            "mounts" => Ok(PrintValue::Mounts),
+            _ => eyre::bail!(
+                "invalid value `{s}` to print, expected `image`, `sysroot`, `runner`, `deb-arch`, or `mounts`"
+            ),
+        }
+    }
+}
+
 pub fn is_subcommand_list(stdout: &str) -> bool {
     stdout.starts_with("Installed Commands:")
 }
@@ -62,7 +138,124 @@ pub fn fmt_subcommands(stdout: &str, msg_info: &mut MessageInfo) -> Result<()> {
     Ok(())
 }
 
-fn is_verbose(arg: &str) -> u8 {
+/// Flags only understood by cross, which take a value. These and
+/// `CROSS_FLAG_ARGS` are only used by cross, so they're not passed to cargo.
+const CROSS_VALUE_ARGS: &[&str] = &[
+    "--platform",
+    "--config-profile",
+    "--build-report",
+    "--shard",
+    "--device",
+    "--publish",
+    "--targets",
+    "--env",
+    "-e",
+];
+
+/// Flags only understood by cross, which do not take a value.
+const CROSS_FLAG_ARGS: &[&str] = &[
+    "--quiet-container",
+    "--no-cache-setup",
+    "--in-container",
+    "--skip-image-checks",
+    "--strict-args",
+    "--check-config-only",
+];
+
+/// Remove the flags only understood by cross, for falling back to cargo on the host.
+pub fn strip_cross_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut stripped = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            stripped.push(arg);
+            stripped.extend(args.by_ref());
+        } else if CROSS_FLAG_ARGS.contains(&arg.as_str()) {
+            continue;
+        } else if let Some(kind) = CROSS_VALUE_ARGS
+            .iter()
+            .find_map(|field| is_value_arg(&arg, field))
+        {
+            if let ArgKind::Next = kind {
+                args.next();
+            }
+        } else {
+            stripped.push(arg);
+        }
+    }
+    stripped
+}
+
+/// The flags kept in the cargo arguments whose value is the next argument.
+const CARGO_VALUE_FLAGS: &[&str] = &[
+    "--color",
+    "--manifest-path",
+    "--target",
+    "--features",
+    "--target-dir",
+    "--format",
+];
+
+/// The index of the subcommand in the cargo arguments, which is not the value
+/// of a flag such as `--target <TRIPLE>` before it.
+pub(crate) fn subcommand_index(cargo_args: &[String]) -> Option<usize> {
+    let mut index = 0;
+    while let Some(arg) = cargo_args.get(index) {
+        if !arg.starts_with('-') {
+            return Some(index);
+        }
+        index += match CARGO_VALUE_FLAGS.contains(&arg.as_str()) {
+            true => 2,
+            false => 1,
+        };
+    }
+    None
+}
+
+/// Request the JSON messages of cargo after its subcommand, with the
+/// diagnostics still rendered as text, if they were not requested already.
+/// Returns if the messages were only requested by cross, and so should not
+/// be printed.
+pub(crate) fn request_json_messages(cargo_args: &mut Vec<String>) -> bool {
+    if crate::docker::uses_json_messages(cargo_args) {
+        return false;
+    }
+    match subcommand_index(cargo_args) {
+        Some(index) => {
+            cargo_args.insert(
+                index + 1,
+                "--message-format=json-render-diagnostics".to_owned(),
+            );
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove the `--format` flag used by `--version` from the cargo arguments,
+/// since cargo's own `--version` does not accept it.
+fn take_version_format(cargo_args: &mut Vec<String>) -> Result<VersionFormat> {
+    let mut format = VersionFormat::default();
+    let mut args = std::mem::take(cargo_args).into_iter();
+    while let Some(arg) = args.next() {
+        match is_value_arg(&arg, "--format") {
+            Some(ArgKind::Next) => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| eyre::eyre!("`--format` requires a value"))?;
+                format = value.parse()?;
+            }
+            Some(ArgKind::Equal) => {
+                let (_, value) = arg.split_once('=').expect("argument should contain `=`");
+                format = value.parse()?;
+            }
+            None => cargo_args.push(arg),
+        }
+    }
+    Ok(format)
+}
+
+pub(crate) fn is_verbose(arg: &str) -> u8 {
     match arg {
         "--verbose" => 1,
         // cargo can handle any number of "v"s
@@ -132,6 +325,19 @@ fn parse_equal_arg<T>(
     Ok(result)
 }
 
+/// The value of a flag of `CROSS_VALUE_ARGS`, which is required.
+fn cross_value_arg(
+    kind: ArgKind,
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String> {
+    let value = match kind {
+        ArgKind::Next => args.next(),
+        ArgKind::Equal => arg.split_once('=').map(|(_, v)| v.to_owned()),
+    };
+    value.ok_or_else(|| eyre::eyre!("`{arg}` requires a value"))
+}
+
 fn parse_manifest_path(path: &str) -> Result<Option<PathBuf>> {
     let p = PathBuf::from(path);
     Ok(absolute_path(p).ok())
@@ -141,6 +347,21 @@ fn parse_target_dir(path: &str) -> Result<PathBuf> {
     absolute_path(PathBuf::from(path))
 }
 
+fn parse_build_report(path: &str) -> Result<PathBuf> {
+    if path.is_empty() {
+        eyre::bail!("`--build-report` requires the path of the report file");
+    }
+    let path = absolute_path(PathBuf::from(path))?;
+    if path.is_dir() {
+        return Err(eyre::eyre!(
+            "the build report `{}` is a directory",
+            path.to_string_lossy()
+        ))
+        .with_suggestion(|| "pass the path of the JSON file to write, such as `report.json`");
+    }
+    Ok(path)
+}
+
 fn identity(arg: String) -> Result<String> {
     Ok(arg)
 }
@@ -150,29 +371,88 @@ fn str_to_owned(arg: &str) -> Result<String> {
 }
 
 fn store_manifest_path(path: String) -> Result<String> {
-    Path::new(&path).as_posix_relative()
+    // the container may not work from the current directory, see
+    // `PackageDirectories::assemble`
+    Path::new(&path).as_posix_absolute()
 }
 
 fn store_target_dir(_: String) -> Result<String> {
     Ok("/target".to_owned())
 }
 
+/// Expand the `@path` argfiles before the `--` separator, which contain one
+/// argument per line, like the argfiles of rustc. Arguments after the
+/// separator are left for the program receiving them.
+pub fn expand_argfiles(args: impl IntoIterator<Item = String>) -> Result<Vec<String>> {
+    expand_argfiles_with(args, |path| {
+        file::read(path).wrap_err_with(|| format!("could not read argfile `{path}`"))
+    })
+}
+
+fn expand_argfiles_with(
+    args: impl IntoIterator<Item = String>,
+    read: impl Fn(&str) -> Result<String>,
+) -> Result<Vec<String>> {
+    let mut expanded = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            expanded.push(arg);
+            expanded.extend(args.by_ref());
+        } else if let Some(path) = arg.strip_prefix('@').filter(|p| !p.is_empty()) {
+            expanded.extend(read(path)?.lines().map(ToOwned::to_owned));
+        } else {
+            expanded.push(arg);
+        }
+    }
+    Ok(expanded)
+}
+
 pub fn parse(target_list: &TargetList) -> Result<Args> {
+    let mut args = parse_args(expand_argfiles(env::args().skip(1))?, target_list)?;
+    // aliases are expanded by cargo, but cross must know the subcommand to
+    // decide if it runs in the container and needs a target.
+    if let Some(Subcommand::Other(name)) = &args.subcommand {
+        let aliases = CargoAliases::read(&env::current_dir()?, home::cargo_home().ok().as_deref());
+        if let Some(subcommand) = aliases.resolve(name) {
+            args.subcommand = Some(subcommand);
+        }
+    }
+    Ok(args)
+}
+
+pub(crate) fn parse_args(args: Vec<String>, target_list: &TargetList) -> Result<Args> {
     let mut channel = None;
     let mut target = None;
+    let mut targets = vec![];
     let mut features = Vec::new();
     let mut manifest_path: Option<PathBuf> = None;
     let mut target_dir = None;
+    let mut platform = None;
+    let mut config_profile = None;
+    let mut build_report = None;
+    let mut shard = None;
+    let mut quiet_container = false;
+    let mut no_cache_setup = false;
+    let mut in_container = false;
+    let mut skip_image_checks = false;
+    let mut strict_args = false;
+    let mut check_config_only = false;
+    let mut devices = vec![];
+    let mut publish = vec![];
+    let mut envs = vec![];
     let mut sc = None;
     let mut cargo_args: Vec<String> = Vec::new();
     let mut rest_args: Vec<String> = Vec::new();
     let mut version = false;
+    let mut version_format = VersionFormat::default();
+    let mut print = None;
     let mut quiet = false;
     let mut verbose = 0;
     let mut color = None;
 
     {
-        let mut args = env::args().skip(1);
+        let mut args = args.into_iter();
         while let Some(arg) = args.next() {
             if arg.is_empty() {
                 continue;
@@ -185,6 +465,18 @@ pub fn parse(target_list: &TargetList) -> Result<Args> {
                 cargo_args.push(arg);
             } else if matches!(arg.as_str(), "--version" | "-V") {
                 version = true;
+            } else if arg == "--quiet-container" {
+                quiet_container = true;
+            } else if arg == "--no-cache-setup" {
+                no_cache_setup = true;
+            } else if arg == "--in-container" {
+                in_container = true;
+            } else if arg == "--skip-image-checks" {
+                skip_image_checks = true;
+            } else if arg == "--strict-args" {
+                strict_args = true;
+            } else if arg == "--check-config-only" {
+                check_config_only = true;
             } else if matches!(arg.as_str(), "--quiet" | "-q") {
                 quiet = true;
                 cargo_args.push(arg);
@@ -241,6 +533,14 @@ pub fn parse(target_list: &TargetList) -> Result<Args> {
                         identity,
                     )?),
                 };
+            } else if let Some(kind) = is_value_arg(&arg, "--targets") {
+                let value = cross_value_arg(kind, &arg, &mut args)?;
+                targets.extend(
+                    value
+                        .split(',')
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_owned),
+                );
             } else if let Some(kind) = is_value_arg(&arg, "--features") {
                 match kind {
                     ArgKind::Next => {
@@ -284,9 +584,36 @@ pub fn parse(target_list: &TargetList) -> Result<Args> {
                         )?);
                     }
                 }
+            } else if let Some(kind) = is_value_arg(&arg, "--platform") {
+                platform = Some(cross_value_arg(kind, &arg, &mut args)?);
+            } else if let Some(kind) = is_value_arg(&arg, "--build-report") {
+                let path = cross_value_arg(kind, &arg, &mut args)?;
+                build_report = Some(parse_build_report(&path)?);
+            } else if let Some(kind) = is_value_arg(&arg, "--shard") {
+                shard = Some(cross_value_arg(kind, &arg, &mut args)?.parse()?);
+            } else if let Some(kind) = is_value_arg(&arg, "--device") {
+                devices.push(cross_value_arg(kind, &arg, &mut args)?);
+            } else if let Some(kind) = is_value_arg(&arg, "--publish") {
+                publish.push(cross_value_arg(kind, &arg, &mut args)?);
+            } else if let Some(kind) =
+                is_value_arg(&arg, "--env").or_else(|| is_value_arg(&arg, "-e"))
+            {
+                envs.push(cross_value_arg(kind, &arg, &mut args)?);
+            } else if let Some(kind) = is_value_arg(&arg, "--format") {
+                // the value must not be mistaken for the subcommand. it's
+                // removed from the cargo args if it's the `--version` format.
+                cargo_args.push(arg);
+                if let ArgKind::Next = kind {
+                    cargo_args.extend(args.next());
+                }
+            } else if let Some(kind) = is_value_arg(&arg, "--config-profile") {
+                config_profile = Some(cross_value_arg(kind, &arg, &mut args)?);
             } else {
                 if (!arg.starts_with('-') || arg == "--list") && sc.is_none() {
                     sc = Some(Subcommand::from(arg.as_ref()));
+                } else if sc == Some(Subcommand::Print) && !arg.starts_with('-') && print.is_none()
+                {
+                    print = Some(arg.parse()?);
                 }
 
                 cargo_args.push(arg.clone());
@@ -294,16 +621,40 @@ pub fn parse(target_list: &TargetList) -> Result<Args> {
         }
     }
 
+    if (version && sc.is_none())
+        || check_config_only
+        || matches!(sc, Some(Subcommand::Targets | Subcommand::Print))
+    {
+        version_format = take_version_format(&mut cargo_args)?;
+    }
+
     Ok(Args {
         cargo_args,
         rest_args,
         subcommand: sc,
         channel,
         target,
+        targets,
+        extra_targets: vec![],
         features,
         target_dir,
         manifest_path,
+        platform,
+        config_profile,
+        build_report,
+        shard,
+        quiet_container,
+        no_cache_setup,
+        in_container,
+        skip_image_checks,
+        strict_args,
+        check_config_only,
+        devices,
+        publish,
+        envs,
         version,
+        version_format,
+        print,
         verbose,
         quiet,
         color,
@@ -325,4 +676,184 @@ mod tests {
         assert!(is_verbose("-vvvv") == 4);
         assert!(is_verbose("-version") == 0);
     }
+
+    #[test]
+    fn subcommand_index_test() {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert_eq!(subcommand_index(&args(&["build", "--release"])), Some(0));
+        assert_eq!(
+            subcommand_index(&args(&["--target", "thumbv7em-none-eabihf", "flash"])),
+            Some(2)
+        );
+        assert_eq!(
+            subcommand_index(&args(&["-v", "--target=x86_64-unknown-linux-gnu", "test"])),
+            Some(2)
+        );
+        assert_eq!(subcommand_index(&args(&["--color", "never"])), None);
+    }
+
+    #[test]
+    fn take_version_format_test() -> Result<()> {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+
+        let mut cargo_args = args(&["-v", "--format", "json"]);
+        assert_eq!(take_version_format(&mut cargo_args)?, VersionFormat::Json);
+        assert_eq!(cargo_args, args(&["-v"]));
+
+        let mut cargo_args = args(&["--format=human"]);
+        assert_eq!(take_version_format(&mut cargo_args)?, VersionFormat::Human);
+        assert!(cargo_args.is_empty());
+
+        assert!(take_version_format(&mut args(&["--format=yaml"])).is_err());
+        assert!(take_version_format(&mut args(&["--format"])).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn expand_argfiles_test() -> Result<()> {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        let read = |path: &str| match path {
+            "release.args" => Ok("--release\n--features\nfoo bar\n".to_owned()),
+            _ => eyre::bail!("missing"),
+        };
+
+        assert_eq!(
+            expand_argfiles_with(
+                args(&["build", "@release.args", "--", "@release.args"]),
+                read
+            )?,
+            args(&[
+                "build",
+                "--release",
+                "--features",
+                "foo bar",
+                "--",
+                "@release.args"
+            ])
+        );
+        assert_eq!(expand_argfiles_with(args(&["@"]), read)?, args(&["@"]));
+        assert!(expand_argfiles_with(args(&["@missing.args"]), read).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_separator_test() -> Result<()> {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        let target_list = TargetList {
+            triples: vec!["aarch64-unknown-linux-gnu".to_owned()],
+        };
+
+        // the flags after `--` are for the program, not cargo
+        let parsed = parse_args(
+            args(&["run", "--release", "--", "--target", "foo", "-v"]),
+            &target_list,
+        )?;
+        assert_eq!(parsed.target, None);
+        assert_eq!(parsed.verbose, 0);
+        assert_eq!(parsed.subcommand, Some(Subcommand::Run));
+        assert_eq!(parsed.cargo_args, args(&["run", "--release"]));
+        assert_eq!(parsed.rest_args, args(&["--", "--target", "foo", "-v"]));
+
+        let parsed = parse_args(
+            args(&[
+                "test",
+                "--target=aarch64-unknown-linux-gnu",
+                "--",
+                "--",
+                "x",
+            ]),
+            &target_list,
+        )?;
+        assert_eq!(
+            parsed.target.as_ref().map(Target::triple),
+            Some("aarch64-unknown-linux-gnu")
+        );
+        assert_eq!(parsed.rest_args, args(&["--", "--", "x"]));
+
+        // environment variables are for the container, not cargo
+        let parsed = parse_args(
+            args(&["build", "-e", "RUST_LOG=debug", "--env=CI", "--", "-e", "x"]),
+            &target_list,
+        )?;
+        assert_eq!(parsed.envs, args(&["RUST_LOG=debug", "CI"]));
+        assert_eq!(parsed.cargo_args, args(&["build"]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_build_report_test() -> Result<()> {
+        let cwd = env::current_dir()?;
+        assert_eq!(parse_build_report("report.json")?, cwd.join("report.json"));
+        assert!(parse_build_report("").is_err());
+        assert!(parse_build_report(&cwd.to_string_lossy()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_cross_value_test() -> Result<()> {
+        let target_list = TargetList { triples: vec![] };
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        for flag in CROSS_VALUE_ARGS {
+            let err = parse_args(args(&["build", flag]), &target_list)
+                .expect_err("a missing value should fail");
+            assert_eq!(err.to_string(), format!("`{flag}` requires a value"));
+        }
+        let parsed = parse_args(args(&["build", "--platform=linux/arm64"]), &target_list)?;
+        assert_eq!(parsed.platform.as_deref(), Some("linux/arm64"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn request_json_messages_test() {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        let mut cargo_args = args(&["-v", "build", "--release"]);
+        assert!(request_json_messages(&mut cargo_args));
+        assert_eq!(
+            cargo_args,
+            args(&[
+                "-v",
+                "build",
+                "--message-format=json-render-diagnostics",
+                "--release"
+            ])
+        );
+
+        let mut cargo_args = args(&["build", "--message-format", "json"]);
+        assert!(!request_json_messages(&mut cargo_args));
+        assert_eq!(cargo_args, args(&["build", "--message-format", "json"]));
+    }
+
+    #[test]
+    fn strip_cross_args_test() {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert_eq!(
+            strip_cross_args(args(&[
+                "build",
+                "--platform",
+                "linux/arm64",
+                "--build-report=report.json",
+                "--quiet-container",
+                "--no-cache-setup",
+                "--in-container",
+                "--skip-image-checks",
+                "--strict-args",
+                "--check-config-only",
+                "--device",
+                "/dev/ttyUSB0",
+                "--publish=8080:8080",
+                "-e",
+                "RUST_LOG=debug",
+                "--env=CI",
+                "--release",
+                "--",
+                "--platform",
+            ])),
+            args(&["build", "--release", "--", "--platform"])
+        );
+    }
 }
@@ -1,13 +1,20 @@
-use crate::cross_toml::BuildStd;
+use crate::cross_toml::{
+    AutoTarget, Backend, BuildStd, CargoHomeMode, CrossContainerConfig, CrossContainerUserConfig,
+    CrossNativeConfig, CrossSdkConfig, LintLevel,
+};
 use crate::docker::custom::PreBuild;
+use crate::docker::tools::Tool;
 use crate::docker::{ImagePlatform, PossibleImage};
 use crate::shell::MessageInfo;
 use crate::{CrossToml, Result, Target, TargetList};
+use color_eyre::{Section, SectionExt};
+use eyre::WrapErr;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct ConfVal<T> {
@@ -105,6 +112,16 @@ impl Environment {
         })
     }
 
+    fn auto_target(&self, target: &Target) -> ConfVal<AutoTarget> {
+        self.get_values_for("AUTO_TARGET", target, |v| {
+            if let Some(value) = try_bool_from_envvar(v) {
+                AutoTarget::Bool(value)
+            } else {
+                AutoTarget::Skip(v.split(',').map(str::to_owned).collect())
+            }
+        })
+    }
+
     fn zig(&self, target: &Target) -> ConfVal<bool> {
         self.get_values_for("ZIG", target, bool_from_envvar)
     }
@@ -147,6 +164,14 @@ impl Environment {
         self.get_values_for("DOCKERFILE_CONTEXT", target, ToOwned::to_owned)
     }
 
+    fn dockerfile_network(&self, target: &Target) -> ConfVal<String> {
+        self.get_values_for("DOCKERFILE_NETWORK", target, ToOwned::to_owned)
+    }
+
+    fn dockerfile_forward_proxy(&self, target: &Target) -> ConfVal<bool> {
+        self.get_values_for("DOCKERFILE_FORWARD_PROXY", target, bool_from_envvar)
+    }
+
     fn pre_build(&self, target: &Target) -> ConfVal<PreBuild> {
         self.get_values_for("PRE_BUILD", target, |v| {
             let v: Vec<_> = v.split('\n').map(String::from).collect();
@@ -161,10 +186,58 @@ impl Environment {
         })
     }
 
+    fn pre_build_host(&self, target: &Target) -> ConfVal<Vec<String>> {
+        self.get_values_for("PRE_BUILD_HOST", target, split_to_cloned_by_lines)
+    }
+
     fn runner(&self, target: &Target) -> Option<String> {
         self.get_target_var(target, "RUNNER")
     }
 
+    fn platform(&self, target: &Target) -> ConfVal<String> {
+        self.get_values_for("PLATFORM", target, ToOwned::to_owned)
+    }
+
+    fn network(&self, target: &Target) -> ConfVal<String> {
+        self.get_values_for("NETWORK", target, ToOwned::to_owned)
+    }
+
+    fn ssh_agent(&self, target: &Target) -> ConfVal<bool> {
+        self.get_values_for("SSH_AGENT", target, bool_from_envvar)
+    }
+
+    fn strict_env(&self, target: &Target) -> ConfVal<bool> {
+        self.get_values_for("STRICT_ENV", target, bool_from_envvar)
+    }
+
+    fn enforce_msrv(&self, target: &Target) -> ConfVal<bool> {
+        self.get_values_for("ENFORCE_MSRV", target, bool_from_envvar)
+    }
+
+    fn verify_artifacts(&self, target: &Target) -> ConfVal<bool> {
+        self.get_values_for("VERIFY_ARTIFACTS", target, bool_from_envvar)
+    }
+
+    fn flasher(&self, target: &Target) -> ConfVal<String> {
+        self.get_values_for("FLASHER", target, ToOwned::to_owned)
+    }
+
+    fn timeout(&self, target: &Target) -> Option<String> {
+        self.get_target_var(target, "TIMEOUT")
+    }
+
+    fn engine(&self, target: &Target) -> Option<String> {
+        self.get_target_var(target, "ENGINE")
+    }
+
+    fn seccomp(&self, target: &Target) -> Option<String> {
+        self.get_target_var(target, "SECCOMP")
+    }
+
+    fn backend(&self, target: &Target) -> Option<String> {
+        self.get_target_var(target, "BACKEND")
+    }
+
     fn passthrough(&self, target: &Target) -> ConfVal<Vec<String>> {
         self.get_values_for("ENV_PASSTHROUGH", target, split_to_cloned_by_ws)
     }
@@ -173,6 +246,22 @@ impl Environment {
         self.get_values_for("ENV_VOLUMES", target, split_to_cloned_by_ws)
     }
 
+    fn devices(&self, target: &Target) -> ConfVal<Vec<String>> {
+        self.get_values_for("DEVICES", target, split_to_cloned_by_ws)
+    }
+
+    fn publish(&self, target: &Target) -> ConfVal<Vec<String>> {
+        self.get_values_for("PUBLISH", target, split_to_cloned_by_ws)
+    }
+
+    fn hooks_pre(&self, target: &Target) -> ConfVal<Vec<String>> {
+        self.get_values_for("HOOKS_PRE", target, split_to_cloned_by_lines)
+    }
+
+    fn hooks_post(&self, target: &Target) -> ConfVal<Vec<String>> {
+        self.get_values_for("HOOKS_POST", target, split_to_cloned_by_lines)
+    }
+
     fn target(&self) -> Option<String> {
         self.get_build_var("TARGET")
             .or_else(|| std::env::var("CARGO_BUILD_TARGET").ok())
@@ -192,6 +281,61 @@ impl Environment {
         self.get_var("CUSTOM_TOOLCHAIN_COMPAT")
     }
 
+    fn registry_mirror(&self) -> Option<String> {
+        self.get_build_var("REGISTRY_MIRROR")
+    }
+
+    fn mount_cargo_home(&self) -> Option<String> {
+        self.get_build_var("MOUNT_CARGO_HOME")
+    }
+
+    fn mount_cargo_bin(&self) -> Option<bool> {
+        self.get_build_var("MOUNT_CARGO_BIN")
+            .map(|v| bool_from_envvar(&v))
+    }
+
+    fn isolate_target_dir(&self) -> Option<bool> {
+        self.get_build_var("ISOLATE_TARGET_DIR")
+            .map(|v| bool_from_envvar(&v))
+    }
+
+    fn mount_path_dependencies(&self) -> Option<bool> {
+        self.get_build_var("MOUNT_PATH_DEPENDENCIES")
+            .map(|v| bool_from_envvar(&v))
+    }
+
+    fn cache_proxy(&self) -> Option<bool> {
+        self.get_build_var("CACHE_PROXY")
+            .map(|v| bool_from_envvar(&v))
+    }
+
+    fn shared_pre_build_cache(&self) -> Option<bool> {
+        self.get_build_var("SHARED_PRE_BUILD_CACHE")
+            .map(|v| bool_from_envvar(&v))
+    }
+
+    fn host_toolchain(&self) -> Option<bool> {
+        self.get_build_var("HOST_TOOLCHAIN")
+            .map(|v| bool_from_envvar(&v))
+    }
+
+    fn config_lints(&self) -> Option<String> {
+        self.get_build_var("CONFIG_LINTS")
+    }
+
+    fn podman_connection(&self) -> Option<String> {
+        self.get_build_var("PODMAN_CONNECTION")
+    }
+
+    fn copy_exclude(&self) -> Option<Vec<String>> {
+        self.get_build_var("COPY_EXCLUDE")
+            .map(|v| split_to_cloned_by_ws(&v))
+    }
+
+    fn cache_max_size(&self) -> Option<String> {
+        self.get_var("CROSS_CACHE_MAX_SIZE")
+    }
+
     fn build_opts(&self) -> Option<String> {
         self.get_var("CROSS_BUILD_OPTS")
     }
@@ -224,12 +368,64 @@ fn split_to_cloned_by_ws(string: &str) -> Vec<String> {
     string.split_whitespace().map(String::from).collect()
 }
 
+fn split_to_cloned_by_lines(string: &str) -> Vec<String> {
+    string
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
 /// this takes the value of the environment variable,
 /// so you should call `bool_from_envvar(env::var("FOO"))`
 pub fn bool_from_envvar(envvar: &str) -> bool {
     try_bool_from_envvar(envvar).unwrap_or(!envvar.is_empty())
 }
 
+/// Parses a duration such as `90`, `45s`, `30m`, or `1h30m`.
+///
+/// a value without a unit is interpreted as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    if value.is_empty() {
+        eyre::bail!("empty duration");
+    }
+    if let Ok(secs) = u64::from_str(value) {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            eyre::bail!("invalid duration `{value}`: expected a number");
+        }
+        let (number, tail) = rest.split_at(digits);
+        let number = u64::from_str(number)?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let duration = match unit {
+            "ms" => Some(Duration::from_millis(number)),
+            "s" | "" => Some(Duration::from_secs(number)),
+            "m" => number.checked_mul(60).map(Duration::from_secs),
+            "h" => number.checked_mul(60 * 60).map(Duration::from_secs),
+            _ => eyre::bail!("invalid duration `{value}`: unknown unit `{unit}`"),
+        };
+        total = duration
+            .and_then(|duration| total.checked_add(duration))
+            .ok_or_else(|| eyre::eyre!("invalid duration `{value}`: duration is too large"))?;
+        rest = tail;
+    }
+
+    Ok(total)
+}
+
 pub fn try_bool_from_envvar(envvar: &str) -> Option<bool> {
     if let Ok(value) = bool::from_str(envvar) {
         Some(value)
@@ -364,6 +560,13 @@ impl Config {
         self.get_from_ref(target, Environment::build_std, CrossToml::build_std)
     }
 
+    /// Append `--target` to the cargo command for the `subcommand`.
+    pub fn auto_target(&self, target: &Target, subcommand: Option<&str>) -> bool {
+        self.get_from_ref(target, Environment::auto_target, CrossToml::auto_target)
+            .unwrap_or_default()
+            .enabled_for(subcommand)
+    }
+
     pub fn zig(&self, target: &Target) -> Option<bool> {
         self.get_from_value(target, Environment::zig, CrossToml::zig)
     }
@@ -386,14 +589,117 @@ impl Config {
         ))
     }
 
-    pub fn runner(&self, target: &Target) -> Option<String> {
-        self.get_from_ref(
+    /// The runners of `cross run` and `cross test`, the first one working on
+    /// the host being used.
+    pub fn runner(&self, target: &Target) -> Result<Option<Vec<String>>> {
+        // the runners are split like a shell, so a quoted runner is a single one
+        let env = self
+            .env
+            .runner(target)
+            .map(|runner| {
+                shell_words::split(&runner)
+                    .wrap_err_with(|| format!("invalid runners `{runner}` in the environment"))
+            })
+            .transpose()?;
+        Ok(self.get_from_ref(
             target,
-            |env, target| ConfVal::new(None, env.runner(target)),
+            |_, _| ConfVal::new(None, env),
             |toml, target| ConfVal::new(None, toml.runner(target)),
+        ))
+    }
+
+    /// The platform of the image to use, overriding the default selection.
+    pub fn platform(&self, target: &Target) -> Result<Option<ImagePlatform>> {
+        self.get_from_ref(target, Environment::platform, CrossToml::platform)
+            .map(|platform| ImagePlatform::from_override(&platform))
+            .transpose()
+    }
+
+    /// The network the build container and custom images are built in.
+    pub fn network(&self, target: &Target) -> Option<String> {
+        self.get_from_ref(target, Environment::network, CrossToml::network)
+    }
+
+    /// Forward the SSH agent of the host into the container.
+    pub fn ssh_agent(&self, target: &Target) -> bool {
+        self.get_from_value(target, Environment::ssh_agent, CrossToml::ssh_agent)
+            .unwrap_or_default()
+    }
+
+    /// Only pass through the host variables which are explicitly requested.
+    pub fn strict_env(&self, target: &Target) -> bool {
+        self.get_from_value(target, Environment::strict_env, CrossToml::strict_env)
+            .unwrap_or_default()
+    }
+
+    /// Fail instead of warning if the toolchain is older than the `rust-version`.
+    pub fn enforce_msrv(&self, target: &Target) -> bool {
+        self.get_from_value(target, Environment::enforce_msrv, CrossToml::enforce_msrv)
+            .unwrap_or_default()
+    }
+
+    /// Fail if the binaries built are not for the architecture of the target.
+    pub fn verify_artifacts(&self, target: &Target) -> bool {
+        self.get_from_value(
+            target,
+            Environment::verify_artifacts,
+            CrossToml::verify_artifacts,
+        )
+        .unwrap_or_default()
+    }
+
+    /// The host command `cross flash` runs with the built artifact.
+    pub fn flasher(&self, target: &Target) -> Option<String> {
+        self.get_from_ref(target, Environment::flasher, CrossToml::flasher)
+    }
+
+    /// Maximum duration for the container command, before it is killed.
+    pub fn timeout(&self, target: &Target) -> Result<Option<Duration>> {
+        self.get_from_ref(
+            target,
+            |env, target| ConfVal::new(None, env.timeout(target)),
+            |toml, target| ConfVal::new(None, toml.timeout(target)),
+        )
+        .map(|timeout| parse_duration(&timeout))
+        .transpose()
+    }
+
+    /// The container engine to use for `target`, overriding `CROSS_CONTAINER_ENGINE`.
+    pub fn engine(&self, target: &Target) -> Option<String> {
+        self.get_from_ref(
+            target,
+            |env, target| ConfVal::new(None, env.engine(target)),
+            |toml, target| ConfVal::new(None, toml.engine(target)),
+        )
+    }
+
+    /// The seccomp profile for `target`: a path, `unconfined`, or `default`.
+    pub fn seccomp(&self, target: &Target) -> Option<String> {
+        self.get_from_ref(
+            target,
+            |env, target| ConfVal::new(None, env.seccomp(target)),
+            |toml, target| ConfVal::new(None, toml.seccomp(target)),
         )
     }
 
+    /// Where the build of `target` runs: in a container, or on the host.
+    pub fn backend(&self, target: &Target) -> Result<Backend> {
+        Ok(match self.env.backend(target) {
+            Some(value) => value.parse()?,
+            None => self
+                .toml
+                .as_ref()
+                .and_then(|toml| toml.backend(target))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The C toolchain on the host used by the native backend, from
+    /// `Cross.toml` only.
+    pub fn native(&self, target: &Target) -> Option<CrossNativeConfig> {
+        self.toml.as_ref()?.native(target).cloned()
+    }
+
     pub fn doctests(&self) -> Option<bool> {
         self.env.doctests()
     }
@@ -423,6 +729,201 @@ impl Config {
         self.get_from_ref(target, Environment::volumes, CrossToml::env_volumes)
     }
 
+    /// Host devices passed through to the container, such as `/dev/ttyUSB0`.
+    pub fn devices(&self, target: &Target) -> Option<Vec<String>> {
+        self.get_from_ref(target, Environment::devices, CrossToml::devices)
+    }
+
+    /// Ports published by the container of `cross run`, such as `8080:8080`.
+    pub fn publish(&self, target: &Target) -> Option<Vec<String>> {
+        self.get_from_ref(target, Environment::publish, CrossToml::publish)
+    }
+
+    /// The directory for temporary files and caches, from `Cross.toml` only:
+    /// `CROSS_CACHE_DIR` is handled by [`temp`](crate::temp).
+    pub fn cache_dir(&self) -> Option<String> {
+        self.toml.as_ref()?.cache_dir().cloned()
+    }
+
+    /// The registry the images provided by cross are pulled from instead.
+    pub fn registry_mirror(&self) -> Option<String> {
+        self.env
+            .registry_mirror()
+            .or_else(|| self.toml.as_ref()?.registry_mirror().cloned())
+    }
+
+    /// How the cargo home is mounted into the container, or `None` if it isn't.
+    pub fn mount_cargo_home(&self) -> Result<Option<CargoHomeMode>> {
+        let mount = match self.env.mount_cargo_home() {
+            Some(value) => Some(value.parse()?),
+            None => self.toml.as_ref().and_then(CrossToml::mount_cargo_home),
+        };
+        Ok(mount.map_or(Some(CargoHomeMode::Rw), |mount| mount.mode()))
+    }
+
+    /// Mount `bin` of the cargo home, which is hidden from the container by default.
+    pub fn mount_cargo_bin(&self) -> bool {
+        self.env
+            .mount_cargo_bin()
+            .or_else(|| self.toml.as_ref()?.mount_cargo_bin())
+            .unwrap_or_default()
+    }
+
+    /// Build in `cross/<triple>` of the target directory, apart from the
+    /// builds of the host.
+    pub fn isolate_target_dir(&self) -> bool {
+        self.env
+            .isolate_target_dir()
+            .or_else(|| self.toml.as_ref()?.isolate_target_dir())
+            .unwrap_or_default()
+    }
+
+    /// Mount the path dependencies outside of the workspace root, instead of
+    /// failing the build.
+    pub fn mount_path_dependencies(&self) -> bool {
+        self.env
+            .mount_path_dependencies()
+            .or_else(|| self.toml.as_ref()?.mount_path_dependencies())
+            .unwrap_or(true)
+    }
+
+    /// Run the build container behind the shared caching proxy.
+    pub fn cache_proxy(&self) -> bool {
+        self.env
+            .cache_proxy()
+            .or_else(|| self.toml.as_ref()?.cache_proxy())
+            .unwrap_or_default()
+    }
+
+    /// Share the pre-build images between projects, keyed by their content.
+    pub fn shared_pre_build_cache(&self) -> bool {
+        self.env
+            .shared_pre_build_cache()
+            .or_else(|| self.toml.as_ref()?.shared_pre_build_cache())
+            .unwrap_or_default()
+    }
+
+    /// Mount a toolchain for the architecture of the image when the toolchain
+    /// doesn't run on it.
+    pub fn host_toolchain(&self) -> bool {
+        self.env
+            .host_toolchain()
+            .or_else(|| self.toml.as_ref()?.host_toolchain())
+            .unwrap_or_default()
+    }
+
+    /// How conflicting or ineffective combinations of options are reported.
+    pub fn config_lints(&self) -> Result<LintLevel> {
+        Ok(match self.env.config_lints() {
+            Some(value) => value.parse()?,
+            None => self
+                .toml
+                .as_ref()
+                .and_then(CrossToml::config_lints)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The combinations of options for `target` that conflict, where one of
+    /// them is ignored, or that have no effect.
+    pub fn lints(&self, target: &Target, is_remote: bool) -> Result<Vec<String>> {
+        let mut lints = vec![];
+        if self.zig(target).unwrap_or_default() {
+            if self.image(target)?.is_some() && self.zig_image(target)?.is_none() {
+                lints.push(
+                    "`image` is ignored with `zig`, set `zig.image` to use a custom image"
+                        .to_owned(),
+                );
+            }
+        } else {
+            if self.zig_version(target).is_some() {
+                lints.push("`zig.version` has no effect without `zig = true`".to_owned());
+            }
+            if self.zig_image(target)?.is_some() {
+                lints.push("`zig.image` has no effect without `zig = true`".to_owned());
+            }
+        }
+        if self.build_std(target).is_some_and(|b| b.enabled()) && self.xargo(target) == Some(true) {
+            lints.push("`xargo` is ignored with `build-std`, which takes precedence".to_owned());
+        }
+        if self.shared_pre_build_cache() && self.pre_build(target).is_none() {
+            lints.push(
+                "`build.shared-pre-build-cache` has no effect without `pre-build`".to_owned(),
+            );
+        }
+        if self.host_toolchain() && is_remote {
+            lints.push("`build.host-toolchain` is not supported with remote engines".to_owned());
+        }
+        Ok(lints)
+    }
+
+    /// Report the lints for `target` according to `config-lints`.
+    pub fn check_lints(
+        &self,
+        target: &Target,
+        is_remote: bool,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
+        let level = self.config_lints()?;
+        if level == LintLevel::Allow {
+            return Ok(());
+        }
+        let lints = self.lints(target, is_remote)?;
+        if lints.is_empty() {
+            return Ok(());
+        }
+        if level == LintLevel::Deny {
+            return Err(eyre::eyre!(
+                "the configuration for `{target}` has {} conflicting or ineffective options",
+                lints.len()
+            ))
+            .with_section(|| lints.join("\n").header("Lints:"))
+            .with_suggestion(|| "set `build.config-lints = \"warn\"` to only warn about them");
+        }
+        for lint in lints {
+            msg_info.warn(format_args!("{lint} for `{target}`"))?;
+        }
+        Ok(())
+    }
+
+    /// The `podman system connection` to build with, as a remote engine.
+    pub fn podman_connection(&self) -> Option<String> {
+        self.env
+            .podman_connection()
+            .or_else(|| self.toml.as_ref()?.podman_connection().cloned())
+            .filter(|c| !c.is_empty())
+    }
+
+    /// Gitignore-style patterns of the files not copied to remote engines,
+    /// in addition to the `.crossignore` of the workspace.
+    pub fn copy_exclude(&self) -> Vec<String> {
+        self.env
+            .copy_exclude()
+            .or_else(|| Some(self.toml.as_ref()?.copy_exclude()?.to_vec()))
+            .unwrap_or_default()
+    }
+
+    /// The maximum size of the cache directory, in bytes.
+    pub fn cache_max_size(&self) -> Result<Option<u64>> {
+        self.env
+            .cache_max_size()
+            .or_else(|| self.toml.as_ref()?.cache_max_size().cloned())
+            .map(|size| crate::temp::parse_size(&size))
+            .transpose()
+    }
+
+    /// Host commands to run before the build, `build` hooks first and then `target` hooks.
+    pub fn hooks_pre(&self, target: &Target) -> Vec<String> {
+        self.vec_from_config(target, Environment::hooks_pre, CrossToml::hooks_pre, true)
+            .unwrap_or_default()
+    }
+
+    /// Host commands to run after a successful build, `build` hooks first and then `target` hooks.
+    pub fn hooks_post(&self, target: &Target) -> Vec<String> {
+        self.vec_from_config(target, Environment::hooks_post, CrossToml::hooks_post, true)
+            .unwrap_or_default()
+    }
+
     pub fn target(&self, target_list: &TargetList) -> Option<Target> {
         if let Some(env_value) = self.env.target() {
             return Some(Target::from(&env_value, target_list));
@@ -432,6 +933,14 @@ impl Config {
             .and_then(|t| t.default_target(target_list))
     }
 
+    /// The targets with a `target.<triple>` table in the config file.
+    pub fn configured_targets(&self) -> Vec<Target> {
+        self.toml
+            .as_ref()
+            .map(|t| t.targets.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
     pub fn dockerfile(&self, target: &Target) -> Option<String> {
         self.get_from_ref(target, Environment::dockerfile, CrossToml::dockerfile)
     }
@@ -451,10 +960,89 @@ impl Config {
             .and_then(|t| t.dockerfile_build_args(target))
     }
 
+    /// The network used when building custom images, overriding `network`.
+    pub fn dockerfile_network(&self, target: &Target) -> Option<String> {
+        self.get_from_ref(
+            target,
+            Environment::dockerfile_network,
+            CrossToml::dockerfile_network,
+        )
+    }
+
+    /// If the proxy environment variables are passed as build arguments
+    /// to custom images, which is the default.
+    pub fn dockerfile_forward_proxy(&self, target: &Target) -> bool {
+        self.get_from_value(
+            target,
+            Environment::dockerfile_forward_proxy,
+            CrossToml::dockerfile_forward_proxy,
+        )
+        .unwrap_or(true)
+    }
+
     pub fn pre_build(&self, target: &Target) -> Option<PreBuild> {
         self.get_from_ref(target, Environment::pre_build, CrossToml::pre_build)
     }
 
+    /// Prebuilt tools to install into the custom image, from `Cross.toml` only.
+    pub fn tools(&self, target: &Target) -> Option<Vec<Tool>> {
+        let tools = self.toml.as_ref()?.tools(target);
+        tools.target.or(tools.build).map(<[Tool]>::to_vec)
+    }
+
+    /// System packages to install into the custom image, from `Cross.toml`
+    /// only. The packages of `[build]` and of the target are combined.
+    pub fn packages(&self, target: &Target) -> Option<Vec<String>> {
+        let packages = self.toml.as_ref()?.packages(target);
+        let combined: Vec<String> = packages
+            .build
+            .into_iter()
+            .chain(packages.target)
+            .flatten()
+            .cloned()
+            .collect();
+        (packages.build.is_some() || packages.target.is_some()).then_some(combined)
+    }
+
+    /// Environment variables set in the container for the target, with
+    /// their values not yet templated, from `Cross.toml` only.
+    pub fn build_env(&self, target: &Target) -> Option<BTreeMap<String, String>> {
+        self.toml.as_ref()?.build_env(target).cloned()
+    }
+
+    /// The proprietary SDK mounted into the container, from `Cross.toml` only.
+    pub fn sdk(&self, target: &Target) -> Option<CrossSdkConfig> {
+        self.toml.as_ref()?.sdk(target).cloned()
+    }
+
+    /// How the build command is run in the container, from `Cross.toml` only.
+    pub fn container(&self, target: &Target) -> Option<CrossContainerConfig> {
+        self.toml.as_ref()?.container(target).cloned()
+    }
+
+    /// The user created in the container at startup, from `Cross.toml` only.
+    pub fn container_user(&self, target: &Target) -> Option<CrossContainerUserConfig> {
+        let user = self.toml.as_ref()?.container_user(target);
+        user.target.or(user.build).cloned()
+    }
+
+    /// The docker context or podman connection, from `Cross.toml` only:
+    /// `CROSS_CONTAINER_CONTEXT` is handled by the engine.
+    pub fn container_context(&self, target: &Target) -> Option<String> {
+        let context = self.toml.as_ref()?.container_context(target);
+        context.target.or(context.build).cloned()
+    }
+
+    /// Host commands to run before building the custom image.
+    pub fn pre_build_host(&self, target: &Target) -> Option<Vec<String>> {
+        self.vec_from_config(
+            target,
+            Environment::pre_build_host,
+            CrossToml::pre_build_host,
+            false,
+        )
+    }
+
     // FIXME: remove when we disable sums in 0.3.0.
     fn sum_of_env_toml_values<'a>(
         &'a self,
@@ -577,6 +1165,16 @@ mod tests {
             );
         }
 
+        #[test]
+        pub fn target_timeout() {
+            let mut map = std::collections::HashMap::new();
+            map.insert("CROSS_TARGET_AARCH64_UNKNOWN_LINUX_GNU_TIMEOUT", "30m");
+
+            let env = Environment::new(Some(map));
+            assert_eq!(env.timeout(&target()), Some("30m".to_owned()));
+            assert_eq!(env.timeout(&target2()), None);
+        }
+
         #[test]
         pub fn collect_passthrough() {
             let mut map = std::collections::HashMap::new();
@@ -638,6 +1236,27 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        pub fn env_runners_are_split_like_a_shell() -> Result<()> {
+            let mut map = HashMap::new();
+            map.insert(
+                "CROSS_TARGET_AARCH64_UNKNOWN_LINUX_GNU_RUNNER",
+                "native '/opt/my sim/run'",
+            );
+            let config = Config::new_with(None, Environment::new(Some(map)));
+            assert_eq!(
+                config.runner(&target())?,
+                Some(vec![s!("native"), s!("/opt/my sim/run")])
+            );
+
+            let mut map = HashMap::new();
+            map.insert("CROSS_TARGET_AARCH64_UNKNOWN_LINUX_GNU_RUNNER", "'native");
+            let config = Config::new_with(None, Environment::new(Some(map)));
+            assert!(config.runner(&target()).is_err());
+
+            Ok(())
+        }
+
         #[test]
         pub fn env_target_and_toml_target_xargo_target_then_use_env() -> Result<()> {
             let mut map = HashMap::new();
@@ -656,6 +1275,53 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        pub fn config_lints() -> Result<()> {
+            let toml_str = r#"
+            [build]
+            shared-pre-build-cache = true
+            host-toolchain = true
+
+            [target.aarch64-unknown-linux-gnu]
+            image = "custom:latest"
+            xargo = true
+            build-std = true
+            zig = true
+            "#;
+            let config = Config::new_with(Some(toml(toml_str)?), Environment::new(None));
+            assert_eq!(config.config_lints()?, LintLevel::Warn);
+            assert_eq!(config.lints(&target(), false)?.len(), 3);
+            assert_eq!(config.lints(&target(), true)?.len(), 4);
+            assert!(config.lints(&target(), false)?[0].starts_with("`image` is ignored"));
+
+            // the zig options only apply to zig
+            let toml_str = r#"
+            [build]
+            config-lints = "deny"
+
+            [target.aarch64-unknown-linux-gnu]
+            zig.version = "2.17"
+            "#;
+            let config = Config::new_with(Some(toml(toml_str)?), Environment::new(None));
+            assert_eq!(config.config_lints()?, LintLevel::Deny);
+            assert_eq!(
+                config.lints(&target(), false)?,
+                ["`zig.version` has no effect without `zig = true`"]
+            );
+            assert!(config
+                .check_lints(&target(), false, &mut MessageInfo::default())
+                .is_err());
+            assert!(config.lints(&target2(), false)?.is_empty());
+
+            let mut map = HashMap::new();
+            map.insert("CROSS_BUILD_CONFIG_LINTS", "allow");
+            let config = Config::new_with(Some(toml(toml_str)?), Environment::new(Some(map)));
+            assert_eq!(config.config_lints()?, LintLevel::Allow);
+            config.check_lints(&target(), false, &mut MessageInfo::default())?;
+
+            Ok(())
+        }
+
         #[test]
         pub fn env_target_and_toml_build_xargo_then_use_toml() -> Result<()> {
             let mut map = HashMap::new();
@@ -831,6 +1497,176 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        pub fn env_target_timeout_then_toml_target_timeout() -> Result<()> {
+            let mut map = HashMap::new();
+            map.insert("CROSS_TARGET_AARCH64_UNKNOWN_LINUX_GNU_TIMEOUT", "90");
+            let env = Environment::new(Some(map));
+            let config = Config::new_with(Some(toml(TOML_TARGET_TIMEOUT)?), env);
+            assert_eq!(config.timeout(&target())?, Some(Duration::from_secs(90)));
+
+            let config = Config::new_with(Some(toml(TOML_TARGET_TIMEOUT)?), Environment::new(None));
+            assert_eq!(
+                config.timeout(&target())?,
+                Some(Duration::from_secs(90 * 60))
+            );
+            assert_eq!(config.timeout(&target2())?, None);
+
+            Ok(())
+        }
+
+        #[test]
+        pub fn parse_durations() -> Result<()> {
+            assert_eq!(parse_duration("45")?, Duration::from_secs(45));
+            assert_eq!(parse_duration("45s")?, Duration::from_secs(45));
+            assert_eq!(parse_duration("30m")?, Duration::from_secs(30 * 60));
+            assert_eq!(parse_duration("1h30m")?, Duration::from_secs(90 * 60));
+            assert_eq!(parse_duration("500ms")?, Duration::from_millis(500));
+            assert!(parse_duration("").is_err());
+            assert!(parse_duration("m").is_err());
+            assert!(parse_duration("10d").is_err());
+            assert!(parse_duration("9999999999999999999h").is_err());
+            assert!(parse_duration("18446744073709551615s1s").is_err());
+
+            Ok(())
+        }
+
+        #[test]
+        pub fn env_build_platform_then_toml_target_platform() -> Result<()> {
+            let mut map = HashMap::new();
+            map.insert("CROSS_BUILD_PLATFORM", "linux/amd64");
+            let env = Environment::new(Some(map));
+            let config = Config::new_with(Some(toml(TOML_TARGET_PLATFORM)?), env);
+            assert_eq!(
+                config.platform(&target())?,
+                Some(ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU)
+            );
+            assert_eq!(
+                config.platform(&target2())?,
+                Some(ImagePlatform::X86_64_UNKNOWN_LINUX_GNU)
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        pub fn env_target_network_then_toml_build_network() -> Result<()> {
+            let mut map = HashMap::new();
+            map.insert("CROSS_TARGET_AARCH64_UNKNOWN_LINUX_GNU_NETWORK", "none");
+            let env = Environment::new(Some(map));
+            let config = Config::new_with(Some(toml(TOML_BUILD_NETWORK)?), env);
+            assert_eq!(config.network(&target()), Some(s!("none")));
+            assert_eq!(config.network(&target2()), Some(s!("proxy")));
+
+            Ok(())
+        }
+
+        #[test]
+        pub fn env_build_flasher_then_toml_target_flasher() -> Result<()> {
+            let toml_str = r#"
+            [target.aarch64-unknown-linux-gnu]
+            flasher = "probe-rs run --chip STM32F411RETx"
+            "#;
+            let mut map = HashMap::new();
+            map.insert("CROSS_BUILD_FLASHER", "openocd");
+            let env = Environment::new(Some(map));
+            let config = Config::new_with(Some(toml(toml_str)?), env);
+            assert_eq!(
+                config.flasher(&target()),
+                Some(s!("probe-rs run --chip STM32F411RETx"))
+            );
+            assert_eq!(config.flasher(&target2()), Some(s!("openocd")));
+
+            Ok(())
+        }
+
+        #[test]
+        pub fn toml_build_hooks_then_target_hooks() -> Result<()> {
+            let config = Config::new_with(Some(toml(TOML_HOOKS)?), Environment::new(None));
+            assert_eq!(
+                config.hooks_pre(&target()),
+                vec![s!("./scripts/gen.sh"), s!("./scripts/gen-aarch64.sh")]
+            );
+            assert_eq!(config.hooks_pre(&target2()), vec![s!("./scripts/gen.sh")]);
+            assert_eq!(config.hooks_post(&target()), vec![s!("./scripts/strip.sh")]);
+            assert!(config.hooks_post(&target2()).is_empty());
+
+            let mut map = HashMap::new();
+            map.insert("CROSS_BUILD_HOOKS_PRE", "./a.sh\n./b.sh --flag");
+            let env = Environment::new(Some(map));
+            let config = Config::new_with(Some(toml(TOML_HOOKS)?), env);
+            assert_eq!(
+                config.hooks_pre(&target()),
+                vec![s!("./a.sh"), s!("./b.sh --flag")]
+            );
+
+            Ok(())
+        }
+
+        static TOML_HOOKS: &str = r#"
+    [build.hooks]
+    pre = ["./scripts/gen.sh"]
+
+    [target.aarch64-unknown-linux-gnu.hooks]
+    pre = ["./scripts/gen-aarch64.sh"]
+    post = ["./scripts/strip.sh"]
+    "#;
+
+        #[test]
+        pub fn toml_target_pre_build_host_over_build() -> Result<()> {
+            let toml_str = r#"
+    [build]
+    pre-build-host = ["./scripts/bindgen.sh"]
+
+    [target.aarch64-unknown-linux-gnu]
+    pre-build-host = ["./scripts/bindgen-aarch64.sh"]
+    "#;
+            let config = Config::new_with(Some(toml(toml_str)?), Environment::new(None));
+            assert_eq!(
+                config.pre_build_host(&target()),
+                Some(vec![s!("./scripts/bindgen-aarch64.sh")])
+            );
+            assert_eq!(
+                config.pre_build_host(&target2()),
+                Some(vec![s!("./scripts/bindgen.sh")])
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        pub fn env_target_engine_then_toml_target_engine() -> Result<()> {
+            let toml_str = r#"
+    [target.aarch64-unknown-linux-gnu]
+    engine = "podman"
+    "#;
+            let config = Config::new_with(Some(toml(toml_str)?), Environment::new(None));
+            assert_eq!(config.engine(&target()), Some(s!("podman")));
+            assert_eq!(config.engine(&target2()), None);
+
+            let mut map = HashMap::new();
+            map.insert("CROSS_TARGET_AARCH64_UNKNOWN_LINUX_GNU_ENGINE", "docker");
+            let config = Config::new_with(Some(toml(toml_str)?), Environment::new(Some(map)));
+            assert_eq!(config.engine(&target()), Some(s!("docker")));
+
+            Ok(())
+        }
+
+        static TOML_TARGET_PLATFORM: &str = r#"
+    [target.aarch64-unknown-linux-gnu]
+    platform = "linux/arm64"
+    "#;
+
+        static TOML_BUILD_NETWORK: &str = r#"
+    [build]
+    network = "proxy"
+    "#;
+
+        static TOML_TARGET_TIMEOUT: &str = r#"
+    [target.aarch64-unknown-linux-gnu]
+    timeout = "1h30m"
+    "#;
+
         static TOML_BUILD_XARGO_FALSE: &str = r#"
     [build]
     xargo = false
@@ -0,0 +1,354 @@
+//! `--check-config-only`: validate the configuration of the workspace without
+//! a container engine, such as in the checks of a pull request.
+//!
+//! `Cross.toml`, the environment variables overriding it, and the toolchain,
+//! image and platform of each target are resolved like for a build. The
+//! checks which need the engine, such as pulling the image and verifying its
+//! labels, are reported as skipped.
+
+use std::process::ExitStatus;
+
+use color_eyre::SectionExt;
+use serde::Serialize;
+
+use crate::cli::{Args, VersionFormat};
+use crate::config::Config;
+use crate::cross_toml::{Backend, LintLevel};
+use crate::errors::*;
+use crate::rustc::{TargetList, VersionMetaExt};
+use crate::shell::MessageInfo;
+use crate::{docker, Target};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Status {
+    Ok,
+    Warning,
+    Error,
+    /// The check needs a container engine.
+    Skipped,
+}
+
+impl Status {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+            Status::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Diagnostic {
+    /// The target checked, or `None` for the checks of the workspace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    check: &'static str,
+    status: Status,
+    message: String,
+}
+
+impl Diagnostic {
+    fn to_human(&self) -> String {
+        let status = self.status.as_str();
+        match &self.target {
+            Some(target) => format!("{status:<8} {target}: {}: {}", self.check, self.message),
+            None => format!("{status:<8} {}: {}", self.check, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    fn push(
+        &mut self,
+        target: Option<&Target>,
+        check: &'static str,
+        status: Status,
+        message: impl Into<String>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            target: target.map(|t| t.triple().to_owned()),
+            check,
+            status,
+            message: message.into(),
+        });
+    }
+
+    /// Record the result of `check`, returning its value if it succeeded.
+    fn record<T>(
+        &mut self,
+        target: Option<&Target>,
+        check: &'static str,
+        result: Result<T>,
+        message: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.push(target, check, Status::Ok, message(&value));
+                Some(value)
+            }
+            Err(err) => {
+                self.push(target, check, Status::Error, format!("{err:#}"));
+                None
+            }
+        }
+    }
+
+    fn errors(&self) -> Vec<String> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.status == Status::Error)
+            .map(Diagnostic::to_human)
+            .collect()
+    }
+}
+
+/// The name of a variant in the configuration, such as `native`.
+fn config_name(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default()
+}
+
+/// The status of the conflicting or ineffective options of a target.
+fn lint_status(level: LintLevel, lints: &[String]) -> Status {
+    match level {
+        _ if lints.is_empty() => Status::Ok,
+        LintLevel::Allow => Status::Ok,
+        LintLevel::Warn => Status::Warning,
+        LintLevel::Deny => Status::Error,
+    }
+}
+
+fn check_target(
+    diagnostics: &mut Diagnostics,
+    config: &Config,
+    target: &Target,
+    args: &Args,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    let t = Some(target);
+    diagnostics.push(
+        t,
+        "target",
+        Status::Ok,
+        match target.is_builtin() {
+            true => "built-in target",
+            false => "custom target",
+        },
+    );
+    diagnostics.record(
+        t,
+        "toolchain",
+        crate::select_toolchain(config, target, args, msg_info),
+        |toolchain| format!("`{toolchain}`"),
+    );
+    diagnostics.record(
+        t,
+        "timeout",
+        config.timeout(target),
+        |timeout| match timeout {
+            Some(timeout) => format!("{}s", timeout.as_secs()),
+            None => "none".to_owned(),
+        },
+    );
+
+    let Some(backend) = diagnostics.record(t, "backend", config.backend(target), config_name)
+    else {
+        return Ok(());
+    };
+    if backend == Backend::Container {
+        if let Some((_, uses_zig)) = diagnostics.record(
+            t,
+            "zig",
+            crate::uses_xargo_and_zig(config, target),
+            |(_, uses_zig)| match uses_zig {
+                true => "builds with zig".to_owned(),
+                false => "builds without zig".to_owned(),
+            },
+        ) {
+            let image = match docker::get_image(config, target, uses_zig) {
+                Ok(image) => Ok(format!("`{}`", image.reference.get())),
+                Err(docker::GetImageError::NoCompatibleImages(..))
+                    if config.dockerfile(target).is_some() =>
+                {
+                    Ok("built from the dockerfile".to_owned())
+                }
+                Err(err) => Err(eyre::Report::new(err)),
+            };
+            diagnostics.record(t, "image", image, Clone::clone);
+        }
+        let platform = match &args.platform {
+            Some(platform) => docker::ImagePlatform::from_override(platform).map(Some),
+            None => config.platform(target),
+        };
+        diagnostics.record(t, "platform", platform, |platform| match platform {
+            Some(platform) => format!("`{}`", platform.docker_platform()),
+            None => "the platform of the container engine".to_owned(),
+        });
+    }
+
+    // remote engines are only known from the environment without an engine
+    let lints = config
+        .config_lints()
+        .and_then(|level| Ok((level, config.lints(target, docker::Engine::is_remote())?)));
+    match lints {
+        Ok((level, lints)) if lints.is_empty() => diagnostics.push(
+            t,
+            "lints",
+            lint_status(level, &lints),
+            "no conflicting or ineffective options",
+        ),
+        Ok((level, lints)) => {
+            diagnostics.push(t, "lints", lint_status(level, &lints), lints.join("; "));
+        }
+        Err(err) => diagnostics.push(t, "lints", Status::Error, format!("{err:#}")),
+    }
+
+    if backend == Backend::Container {
+        diagnostics.push(
+            t,
+            "engine",
+            Status::Skipped,
+            "detecting the container engine, and pulling and verifying the image, need an engine",
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn run(
+    args: &Args,
+    target_list: TargetList,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<ExitStatus>> {
+    let metadata = crate::cargo_metadata_with_args(None, Some(args), msg_info)?
+        .ok_or_else(|| eyre::eyre!("could not find the cargo workspace to check"))?;
+    let host = crate::rustc::version_meta()?.host();
+    let mut diagnostics = Diagnostics::default();
+
+    let paths = crate::cross_config_paths(&metadata.workspace_root)?;
+    let config = diagnostics.record(
+        None,
+        "config",
+        crate::toml_with_profile(&metadata, args, msg_info),
+        |_| {
+            let found: Vec<_> = paths
+                .iter()
+                .filter(|path| path.exists())
+                .map(|path| format!("{path:?}"))
+                .collect();
+            match found.is_empty() {
+                true => "no configuration file, using the defaults".to_owned(),
+                false => format!("read {}", found.join(", ")),
+            }
+        },
+    );
+    if let Some(toml) = config {
+        let config = Config::new(Some(toml));
+        diagnostics.record(
+            None,
+            "cache-max-size",
+            config.cache_max_size(),
+            |size| match size {
+                Some(size) => format!("{size} bytes"),
+                None => "unlimited".to_owned(),
+            },
+        );
+        diagnostics.record(
+            None,
+            "mount-cargo-home",
+            config.mount_cargo_home(),
+            |mode| config_name(&mode.unwrap_or_default()),
+        );
+
+        let targets = match args.targets.is_empty() {
+            true => vec![crate::select_target(&config, args, &target_list, &host)],
+            false => args
+                .targets
+                .iter()
+                .map(|triple| Target::from(triple, &target_list))
+                .collect(),
+        };
+        for target in &targets {
+            check_target(&mut diagnostics, &config, target, args, msg_info)?;
+        }
+    }
+
+    match args.version_format {
+        VersionFormat::Human => {
+            for diagnostic in &diagnostics.diagnostics {
+                msg_info.print(diagnostic.to_human())?;
+            }
+        }
+        VersionFormat::Json => msg_info.print(serde_json::to_string_pretty(&diagnostics)?)?,
+    }
+
+    let errors = diagnostics.errors();
+    if !errors.is_empty() {
+        return Err(eyre::eyre!(
+            "{} of {} checks of the configuration failed",
+            errors.len(),
+            diagnostics.diagnostics.len()
+        ))
+        .with_section(|| errors.join("\n").header("Failures:"));
+    }
+    Ok(Some(ExitStatus::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_diagnostics() {
+        let target = Target::new_built_in("aarch64-unknown-linux-gnu");
+        let mut diagnostics = Diagnostics::default();
+        assert_eq!(
+            diagnostics.record(None, "config", Ok(()), |_| "read Cross.toml".to_owned()),
+            Some(())
+        );
+        assert_eq!(
+            diagnostics.record::<()>(
+                Some(&target),
+                "image",
+                Err(eyre::eyre!("no image")),
+                |_| unreachable!(),
+            ),
+            None
+        );
+        diagnostics.push(Some(&target), "engine", Status::Skipped, "needs an engine");
+
+        let human: Vec<_> = diagnostics
+            .diagnostics
+            .iter()
+            .map(Diagnostic::to_human)
+            .collect();
+        assert_eq!(
+            human,
+            [
+                "ok       config: read Cross.toml",
+                "error    aarch64-unknown-linux-gnu: image: no image",
+                "skipped  aarch64-unknown-linux-gnu: engine: needs an engine",
+            ]
+        );
+        assert_eq!(diagnostics.errors(), human[1..2]);
+        assert_eq!(
+            serde_json::to_value(&diagnostics.diagnostics[0]).unwrap(),
+            serde_json::json!({"check": "config", "status": "ok", "message": "read Cross.toml"})
+        );
+
+        let lints = ["`zig.version` has no effect without `zig = true`".to_owned()];
+        assert_eq!(lint_status(LintLevel::Deny, &[]), Status::Ok);
+        assert_eq!(lint_status(LintLevel::Allow, &lints), Status::Ok);
+        assert_eq!(lint_status(LintLevel::Warn, &lints), Status::Warning);
+        assert_eq!(lint_status(LintLevel::Deny, &lints), Status::Error);
+    }
+}
@@ -5,15 +5,18 @@
 //!
 //! [1]: https://github.com/cross-rs/cross/blob/main/docs/config_file.md
 
+use crate::cargo::Subcommand;
 use crate::config::ConfVal;
 use crate::docker::custom::PreBuild;
+use crate::docker::tools::Tool;
 use crate::docker::PossibleImage;
+use crate::migrate;
 use crate::shell::MessageInfo;
 use crate::{config, errors::*};
 use crate::{Target, TargetList};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::str::FromStr;
 
 /// Environment configuration
@@ -23,6 +26,73 @@ pub struct CrossEnvConfig {
     passthrough: Option<Vec<String>>,
 }
 
+/// Host-side hooks configuration
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct CrossHooksConfig {
+    pre: Option<Vec<String>>,
+    post: Option<Vec<String>>,
+}
+
+/// User created in the container at startup, matching the host user
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrossContainerUserConfig {
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+impl CrossContainerUserConfig {
+    /// The supplementary groups, by name or GID on the host.
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+}
+
+/// A proprietary SDK on the host, such as the QNX or VxWorks SDKs, mounted
+/// read-only at the same path in the container.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrossSdkConfig {
+    path: String,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+impl CrossSdkConfig {
+    /// The directory of the SDK on the host.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The environment variables set in the container, where `{sdk}` is
+    /// replaced with the path of the SDK in the container.
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+}
+
+/// How the build command is run in the container, such as for vendored
+/// toolchains of custom images.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrossContainerConfig {
+    #[serde(default)]
+    path: Vec<String>,
+    profile_script: Option<String>,
+}
+
+impl CrossContainerConfig {
+    /// The directories prepended to `PATH` in the container.
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// The script sourced in the container before the build command.
+    pub fn profile_script(&self) -> Option<&str> {
+        self.profile_script.as_deref()
+    }
+}
+
 /// Build configuration
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -34,10 +104,40 @@ pub struct CrossBuildConfig {
     #[serde(default, deserialize_with = "opt_string_bool_or_struct")]
     zig: Option<CrossZigConfig>,
     default_target: Option<String>,
+    cache_dir: Option<String>,
+    cache_max_size: Option<String>,
+    registry_mirror: Option<String>,
+    mount_cargo_home: Option<MountCargoHome>,
+    mount_cargo_bin: Option<bool>,
+    isolate_target_dir: Option<bool>,
+    copy_exclude: Option<Vec<String>>,
+    podman_connection: Option<String>,
+    mount_path_dependencies: Option<bool>,
+    cache_proxy: Option<bool>,
+    shared_pre_build_cache: Option<bool>,
+    host_toolchain: Option<bool>,
+    config_lints: Option<LintLevel>,
     #[serde(default, deserialize_with = "opt_string_or_string_vec")]
     pre_build: Option<PreBuild>,
+    pre_build_host: Option<Vec<String>>,
+    tools: Option<Vec<Tool>>,
+    packages: Option<Vec<String>>,
     #[serde(default, deserialize_with = "opt_string_or_struct")]
     dockerfile: Option<CrossTargetDockerfileConfig>,
+    platform: Option<String>,
+    network: Option<String>,
+    container_user: Option<CrossContainerUserConfig>,
+    flasher: Option<String>,
+    ssh_agent: Option<bool>,
+    strict_env: Option<bool>,
+    container_context: Option<String>,
+    devices: Option<Vec<String>>,
+    publish: Option<Vec<String>>,
+    enforce_msrv: Option<bool>,
+    verify_artifacts: Option<bool>,
+    auto_target: Option<AutoTarget>,
+    #[serde(default)]
+    hooks: CrossHooksConfig,
 }
 
 /// Target configuration
@@ -54,11 +154,54 @@ pub struct CrossTargetConfig {
     dockerfile: Option<CrossTargetDockerfileConfig>,
     #[serde(default, deserialize_with = "opt_string_or_string_vec")]
     pre_build: Option<PreBuild>,
-    runner: Option<String>,
+    pre_build_host: Option<Vec<String>>,
+    tools: Option<Vec<Tool>>,
+    packages: Option<Vec<String>>,
+    runner: Option<Runners>,
+    timeout: Option<String>,
+    platform: Option<String>,
+    network: Option<String>,
+    engine: Option<String>,
+    seccomp: Option<String>,
+    container_user: Option<CrossContainerUserConfig>,
+    flasher: Option<String>,
+    ssh_agent: Option<bool>,
+    strict_env: Option<bool>,
+    container_context: Option<String>,
+    devices: Option<Vec<String>>,
+    publish: Option<Vec<String>>,
+    enforce_msrv: Option<bool>,
+    verify_artifacts: Option<bool>,
+    auto_target: Option<AutoTarget>,
+    build_env: Option<BTreeMap<String, String>>,
+    sdk: Option<CrossSdkConfig>,
+    container: Option<CrossContainerConfig>,
+    backend: Option<Backend>,
+    native: Option<CrossNativeConfig>,
+    #[serde(default)]
+    hooks: CrossHooksConfig,
     #[serde(default)]
     env: CrossEnvConfig,
 }
 
+/// The runners of `cross run` and `cross test`: either a single runner, or a
+/// list of runners where the first one working on the host is used.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum Runners {
+    Single(String),
+    Fallback(Vec<String>),
+}
+
+impl Runners {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Self::Single(runner) => std::slice::from_ref(runner),
+            Self::Fallback(runners) => runners,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(untagged, rename_all = "kebab-case")]
 pub enum BuildStd {
@@ -81,6 +224,176 @@ impl BuildStd {
     }
 }
 
+/// Whether `--target` is appended to the cargo command: either for all
+/// subcommands, or for all subcommands except the ones listed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged, rename_all = "kebab-case")]
+pub enum AutoTarget {
+    Bool(bool),
+    Skip(Vec<String>),
+}
+
+impl Default for AutoTarget {
+    fn default() -> Self {
+        Self::Bool(true)
+    }
+}
+
+impl AutoTarget {
+    /// Returns `true` if `--target` is appended for the cargo `subcommand`,
+    /// such as `build` or `b`.
+    pub fn enabled_for(&self, subcommand: Option<&str>) -> bool {
+        match self {
+            Self::Bool(enabled) => *enabled,
+            Self::Skip(skip) => subcommand.map_or(true, |subcommand| {
+                let subcommand = Subcommand::from(subcommand);
+                !skip
+                    .iter()
+                    .any(|s| Subcommand::from(s.as_str()) == subcommand)
+            }),
+        }
+    }
+}
+
+/// How the cargo home is mounted into the container: `true` or a mode, or
+/// `false` to not mount it at all.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(untagged)]
+pub enum MountCargoHome {
+    Bool(bool),
+    Mode(CargoHomeMode),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CargoHomeMode {
+    /// The cargo home of the host, read-write.
+    #[default]
+    Rw,
+    /// The cargo home of the host, read-only.
+    Ro,
+    /// A cargo home dedicated to the project, in the cache directory.
+    Project,
+}
+
+impl MountCargoHome {
+    /// The mode the cargo home is mounted with, or `None` if it isn't.
+    pub fn mode(self) -> Option<CargoHomeMode> {
+        match self {
+            Self::Bool(true) => Some(CargoHomeMode::Rw),
+            Self::Bool(false) => None,
+            Self::Mode(mode) => Some(mode),
+        }
+    }
+}
+
+impl FromStr for MountCargoHome {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rw" => Ok(Self::Mode(CargoHomeMode::Rw)),
+            "ro" => Ok(Self::Mode(CargoHomeMode::Ro)),
+            "project" => Ok(Self::Mode(CargoHomeMode::Project)),
+            _ => config::try_bool_from_envvar(s)
+                .map(Self::Bool)
+                .ok_or_else(|| {
+                    eyre::eyre!(
+                    "invalid cargo home mount `{s}`, expected `rw`, `ro`, `project`, or a boolean"
+                )
+                }),
+        }
+    }
+}
+
+/// How conflicting or ineffective combinations of options are reported.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintLevel {
+    /// Don't report them.
+    Allow,
+    /// Warn about each of them.
+    #[default]
+    Warn,
+    /// Fail before building.
+    Deny,
+}
+
+impl FromStr for LintLevel {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "warn" => Ok(Self::Warn),
+            "deny" => Ok(Self::Deny),
+            _ => eyre::bail!("invalid lint level `{s}`, expected `allow`, `warn` or `deny`"),
+        }
+    }
+}
+
+/// Where the build of a target runs.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// In the image of the target, with the container engine.
+    #[default]
+    Container,
+    /// On the host, with cross toolchains installed on the host.
+    Native,
+}
+
+impl FromStr for Backend {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "container" => Ok(Self::Container),
+            "native" => Ok(Self::Native),
+            _ => eyre::bail!("invalid backend `{s}`, expected `container` or `native`"),
+        }
+    }
+}
+
+/// The C toolchain on the host used by the native backend, such as a
+/// crosstool-NG toolchain.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrossNativeConfig {
+    cc: Option<String>,
+    cxx: Option<String>,
+    ar: Option<String>,
+    linker: Option<String>,
+    sysroot: Option<String>,
+}
+
+impl CrossNativeConfig {
+    /// The C compiler, such as `aarch64-unknown-linux-gnu-gcc`.
+    pub fn cc(&self) -> Option<&str> {
+        self.cc.as_deref()
+    }
+
+    /// The C++ compiler.
+    pub fn cxx(&self) -> Option<&str> {
+        self.cxx.as_deref()
+    }
+
+    /// The archiver.
+    pub fn ar(&self) -> Option<&str> {
+        self.ar.as_deref()
+    }
+
+    /// The linker, by default the C compiler.
+    pub fn linker(&self) -> Option<&str> {
+        self.linker.as_deref().or(self.cc.as_deref())
+    }
+
+    /// The sysroot of the toolchain, with the libraries `qemu-user` loads.
+    pub fn sysroot(&self) -> Option<&str> {
+        self.sysroot.as_deref()
+    }
+}
+
 /// Dockerfile configuration
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
@@ -88,6 +401,8 @@ pub struct CrossTargetDockerfileConfig {
     file: String,
     context: Option<String>,
     build_args: Option<HashMap<String, String>>,
+    network: Option<String>,
+    forward_proxy: Option<bool>,
 }
 
 impl FromStr for CrossTargetDockerfileConfig {
@@ -98,6 +413,8 @@ impl FromStr for CrossTargetDockerfileConfig {
             file: s.to_owned(),
             context: None,
             build_args: None,
+            network: None,
+            forward_proxy: None,
         })
     }
 }
@@ -140,13 +457,32 @@ impl FromStr for CrossZigConfig {
     }
 }
 
+/// Named configuration profile, merged over the base configuration when selected
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct CrossProfileConfig {
+    #[serde(default, rename = "target")]
+    pub targets: HashMap<Target, CrossTargetConfig>,
+    #[serde(default)]
+    pub build: CrossBuildConfig,
+}
+
 /// Cross configuration
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
 pub struct CrossToml {
+    /// The version of the schema the file was written for, see
+    /// [`migrate::SCHEMA_VERSION`](crate::migrate::SCHEMA_VERSION).
+    #[serde(
+        default,
+        rename = "schema-version",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub schema_version: Option<u32>,
     #[serde(default, rename = "target")]
     pub targets: HashMap<Target, CrossTargetConfig>,
     #[serde(default)]
     pub build: CrossBuildConfig,
+    #[serde(default, rename = "profile", skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, CrossProfileConfig>,
 }
 
 impl CrossToml {
@@ -157,7 +493,15 @@ impl CrossToml {
         msg_info: &mut MessageInfo,
     ) -> Result<(Self, BTreeSet<String>)> {
         let tomld = toml::Deserializer::new(toml_str);
-        Self::parse_from_deserializer(tomld, source, msg_info)
+        let (mut cfg, mut unused) = Self::deserialize_with_unused(tomld)?;
+        // older configurations may use deprecated keys, which are replaced.
+        if !unused.is_empty() && migrate::schema_version(&cfg) < migrate::SCHEMA_VERSION {
+            if let Some(migrated) = migrate::migrate_str(toml_str, &unused, source, msg_info)? {
+                (cfg, unused) = Self::deserialize_with_unused(toml::Deserializer::new(&migrated))?;
+            }
+        }
+        Self::warn_unused(&unused, source, msg_info)?;
+        Ok((cfg, unused))
     }
 
     /// Parses the [`CrossToml`] from a string containing the Cargo.toml contents
@@ -188,6 +532,19 @@ impl CrossToml {
         source: Option<&str>,
         msg_info: &mut MessageInfo,
     ) -> Result<(Self, BTreeSet<String>)>
+    where
+        D: Deserializer<'de>,
+        D::Error: Send + Sync + 'static,
+    {
+        let (cfg, unused) = Self::deserialize_with_unused(deserializer)?;
+        Self::warn_unused(&unused, source, msg_info)?;
+        Ok((cfg, unused))
+    }
+
+    /// Deserializes the [`CrossToml`], returning the keys which were not used
+    pub(crate) fn deserialize_with_unused<'de, D>(
+        deserializer: D,
+    ) -> Result<(Self, BTreeSet<String>)>
     where
         D: Deserializer<'de>,
         D::Error: Send + Sync + 'static,
@@ -196,7 +553,14 @@ impl CrossToml {
         let cfg = serde_ignored::deserialize(deserializer, |path| {
             unused.insert(path.to_string());
         })?;
+        Ok((cfg, unused))
+    }
 
+    fn warn_unused(
+        unused: &BTreeSet<String>,
+        source: Option<&str>,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
         if !unused.is_empty() {
             msg_info.warn(format_args!(
                 "found unused key(s) in Cross configuration{}:\n > {}",
@@ -204,8 +568,7 @@ impl CrossToml {
                 unused.clone().into_iter().collect::<Vec<_>>().join(", ")
             ))?;
         }
-
-        Ok((cfg, unused))
+        Ok(())
     }
 
     /// Merges another [`CrossToml`] into `self` and returns a new merged one
@@ -274,6 +637,32 @@ impl CrossToml {
         from_map(self_map)
     }
 
+    /// Merges the `profile.{name}` section over the base configuration and returns the result
+    pub fn with_profile(mut self, name: &str) -> Result<CrossToml> {
+        let mut profiles = std::mem::take(&mut self.profiles);
+        let profile = match profiles.remove(name) {
+            Some(profile) => profile,
+            None if profiles.is_empty() => {
+                eyre::bail!("config profile `{name}` not found: no profiles are defined")
+            }
+            None => {
+                let mut available: Vec<_> = profiles.into_keys().collect();
+                available.sort();
+                eyre::bail!(
+                    "config profile `{name}` not found, available profiles are: {}",
+                    available.join(", ")
+                );
+            }
+        };
+
+        self.merge(CrossToml {
+            schema_version: None,
+            targets: profile.targets,
+            build: profile.build,
+            profiles: HashMap::new(),
+        })
+    }
+
     /// Returns the `target.{}.image` part of `Cross.toml`
     pub fn image(&self, target: &Target) -> Option<&PossibleImage> {
         self.get_target(target).and_then(|t| t.image.as_ref())
@@ -313,14 +702,215 @@ impl CrossToml {
         config::opt_merge(target.cloned(), build.cloned())
     }
 
+    /// Returns the `build.dockerfile.network` or the `target.{}.dockerfile.network` part of `Cross.toml`
+    pub fn dockerfile_network(&self, target: &Target) -> ConfVal<&String> {
+        self.get_ref(
+            target,
+            |b| b.dockerfile.as_ref().and_then(|c| c.network.as_ref()),
+            |t| t.dockerfile.as_ref().and_then(|c| c.network.as_ref()),
+        )
+    }
+
+    /// Returns the `build.dockerfile.forward-proxy` or the `target.{}.dockerfile.forward-proxy` part of `Cross.toml`
+    pub fn dockerfile_forward_proxy(&self, target: &Target) -> ConfVal<bool> {
+        self.get_value(
+            target,
+            |b| b.dockerfile.as_ref().and_then(|c| c.forward_proxy),
+            |t| t.dockerfile.as_ref().and_then(|c| c.forward_proxy),
+        )
+    }
+
     /// Returns the `build.dockerfile.pre-build` and `target.{}.dockerfile.pre-build` part of `Cross.toml`
     pub fn pre_build(&self, target: &Target) -> ConfVal<&PreBuild> {
         self.get_ref(target, |b| b.pre_build.as_ref(), |t| t.pre_build.as_ref())
     }
 
+    /// Returns the `build.tools` or the `target.{}.tools` part of `Cross.toml`
+    pub fn tools(&self, target: &Target) -> ConfVal<&[Tool]> {
+        self.get_ref(target, |b| b.tools.as_deref(), |t| t.tools.as_deref())
+    }
+
+    /// Returns the `build.packages` or the `target.{}.packages` part of `Cross.toml`
+    pub fn packages(&self, target: &Target) -> ConfVal<&[String]> {
+        self.get_ref(target, |b| b.packages.as_deref(), |t| t.packages.as_deref())
+    }
+
+    /// Returns the `build.pre-build-host` or the `target.{}.pre-build-host` part of `Cross.toml`
+    pub fn pre_build_host(&self, target: &Target) -> ConfVal<&[String]> {
+        self.get_ref(
+            target,
+            |b| b.pre_build_host.as_deref(),
+            |t| t.pre_build_host.as_deref(),
+        )
+    }
+
     /// Returns the `target.{}.runner` part of `Cross.toml`
-    pub fn runner(&self, target: &Target) -> Option<&String> {
-        self.get_target(target).and_then(|t| t.runner.as_ref())
+    pub fn runner(&self, target: &Target) -> Option<&[String]> {
+        self.get_target(target)
+            .and_then(|t| t.runner.as_ref())
+            .map(Runners::as_slice)
+    }
+
+    /// Returns the `build.platform` or the `target.{}.platform` part of `Cross.toml`
+    pub fn platform(&self, target: &Target) -> ConfVal<&String> {
+        self.get_ref(target, |b| b.platform.as_ref(), |t| t.platform.as_ref())
+    }
+
+    /// Returns the `build.network` or the `target.{}.network` part of `Cross.toml`
+    pub fn network(&self, target: &Target) -> ConfVal<&String> {
+        self.get_ref(target, |b| b.network.as_ref(), |t| t.network.as_ref())
+    }
+
+    /// Returns the `build.container-user` or the `target.{}.container-user` part of `Cross.toml`
+    pub fn container_user(&self, target: &Target) -> ConfVal<&CrossContainerUserConfig> {
+        self.get_ref(
+            target,
+            |b| b.container_user.as_ref(),
+            |t| t.container_user.as_ref(),
+        )
+    }
+
+    /// Returns the `build.flasher` or the `target.{}.flasher` part of `Cross.toml`
+    pub fn flasher(&self, target: &Target) -> ConfVal<&String> {
+        self.get_ref(target, |b| b.flasher.as_ref(), |t| t.flasher.as_ref())
+    }
+
+    /// Returns the `build.ssh-agent` or the `target.{}.ssh-agent` part of `Cross.toml`
+    pub fn ssh_agent(&self, target: &Target) -> ConfVal<bool> {
+        self.get_value(target, |b| b.ssh_agent, |t| t.ssh_agent)
+    }
+
+    /// Returns the `build.strict-env` or the `target.{}.strict-env` part of `Cross.toml`
+    pub fn strict_env(&self, target: &Target) -> ConfVal<bool> {
+        self.get_value(target, |b| b.strict_env, |t| t.strict_env)
+    }
+
+    /// Returns the `build.container-context` or the `target.{}.container-context` part of `Cross.toml`
+    pub fn container_context(&self, target: &Target) -> ConfVal<&String> {
+        self.get_ref(
+            target,
+            |b| b.container_context.as_ref(),
+            |t| t.container_context.as_ref(),
+        )
+    }
+
+    /// Returns the `build.devices` or the `target.{}.devices` part of `Cross.toml`
+    pub fn devices(&self, target: &Target) -> ConfVal<&[String]> {
+        self.get_ref(target, |b| b.devices.as_deref(), |t| t.devices.as_deref())
+    }
+
+    /// Returns the `build.publish` or the `target.{}.publish` part of `Cross.toml`
+    pub fn publish(&self, target: &Target) -> ConfVal<&[String]> {
+        self.get_ref(target, |b| b.publish.as_deref(), |t| t.publish.as_deref())
+    }
+
+    /// Returns the `build.enforce-msrv` or the `target.{}.enforce-msrv` part of `Cross.toml`
+    pub fn enforce_msrv(&self, target: &Target) -> ConfVal<bool> {
+        self.get_value(target, |b| b.enforce_msrv, |t| t.enforce_msrv)
+    }
+
+    /// Returns the `build.verify-artifacts` or the `target.{}.verify-artifacts` part of `Cross.toml`
+    pub fn verify_artifacts(&self, target: &Target) -> ConfVal<bool> {
+        self.get_value(target, |b| b.verify_artifacts, |t| t.verify_artifacts)
+    }
+
+    /// Returns the `build.auto-target` or the `target.{}.auto-target` part of `Cross.toml`
+    pub fn auto_target(&self, target: &Target) -> ConfVal<&AutoTarget> {
+        self.get_ref(
+            target,
+            |b| b.auto_target.as_ref(),
+            |t| t.auto_target.as_ref(),
+        )
+    }
+
+    /// Returns the `build.mount-cargo-home` part of `Cross.toml`
+    pub fn mount_cargo_home(&self) -> Option<MountCargoHome> {
+        self.build.mount_cargo_home
+    }
+
+    /// Returns the `build.mount-cargo-bin` part of `Cross.toml`
+    pub fn mount_cargo_bin(&self) -> Option<bool> {
+        self.build.mount_cargo_bin
+    }
+
+    /// Returns the `build.isolate-target-dir` part of `Cross.toml`
+    pub fn isolate_target_dir(&self) -> Option<bool> {
+        self.build.isolate_target_dir
+    }
+
+    /// Returns the `build.copy-exclude` part of `Cross.toml`
+    pub fn copy_exclude(&self) -> Option<&[String]> {
+        self.build.copy_exclude.as_deref()
+    }
+
+    /// Returns the `build.podman-connection` part of `Cross.toml`
+    pub fn podman_connection(&self) -> Option<&String> {
+        self.build.podman_connection.as_ref()
+    }
+
+    /// Returns the `build.mount-path-dependencies` part of `Cross.toml`
+    pub fn mount_path_dependencies(&self) -> Option<bool> {
+        self.build.mount_path_dependencies
+    }
+
+    /// Returns the `build.cache-proxy` part of `Cross.toml`
+    pub fn cache_proxy(&self) -> Option<bool> {
+        self.build.cache_proxy
+    }
+
+    /// Returns the `build.shared-pre-build-cache` part of `Cross.toml`
+    pub fn shared_pre_build_cache(&self) -> Option<bool> {
+        self.build.shared_pre_build_cache
+    }
+
+    /// Returns the `build.host-toolchain` part of `Cross.toml`
+    pub fn host_toolchain(&self) -> Option<bool> {
+        self.build.host_toolchain
+    }
+
+    /// Returns the `build.config-lints` part of `Cross.toml`
+    pub fn config_lints(&self) -> Option<LintLevel> {
+        self.build.config_lints
+    }
+
+    /// Returns the `target.{}.build-env` part of `Cross.toml`
+    pub fn build_env(&self, target: &Target) -> Option<&BTreeMap<String, String>> {
+        self.get_target(target).and_then(|t| t.build_env.as_ref())
+    }
+
+    /// Returns the `target.{}.sdk` part of `Cross.toml`
+    pub fn sdk(&self, target: &Target) -> Option<&CrossSdkConfig> {
+        self.get_target(target).and_then(|t| t.sdk.as_ref())
+    }
+
+    /// Returns the `target.{}.container` part of `Cross.toml`
+    pub fn container(&self, target: &Target) -> Option<&CrossContainerConfig> {
+        self.get_target(target).and_then(|t| t.container.as_ref())
+    }
+
+    /// Returns the `target.{}.backend` part of `Cross.toml`
+    pub fn backend(&self, target: &Target) -> Option<Backend> {
+        self.get_target(target).and_then(|t| t.backend)
+    }
+
+    /// Returns the `target.{}.native` part of `Cross.toml`
+    pub fn native(&self, target: &Target) -> Option<&CrossNativeConfig> {
+        self.get_target(target).and_then(|t| t.native.as_ref())
+    }
+
+    /// Returns the `target.{}.engine` part of `Cross.toml`
+    pub fn engine(&self, target: &Target) -> Option<&String> {
+        self.get_target(target).and_then(|t| t.engine.as_ref())
+    }
+
+    /// Returns the `target.{}.seccomp` part of `Cross.toml`
+    pub fn seccomp(&self, target: &Target) -> Option<&String> {
+        self.get_target(target).and_then(|t| t.seccomp.as_ref())
+    }
+
+    /// Returns the `target.{}.timeout` part of `Cross.toml`
+    pub fn timeout(&self, target: &Target) -> Option<&String> {
+        self.get_target(target).and_then(|t| t.timeout.as_ref())
     }
 
     /// Returns the `build.xargo` or the `target.{}.xargo` part of `Cross.toml`
@@ -378,6 +968,24 @@ impl CrossToml {
         )
     }
 
+    /// Returns the list of host commands to run before the build for `build` and `target`
+    pub fn hooks_pre(&self, target: &Target) -> ConfVal<&[String]> {
+        self.get_ref(
+            target,
+            |build| build.hooks.pre.as_deref(),
+            |t| t.hooks.pre.as_deref(),
+        )
+    }
+
+    /// Returns the list of host commands to run after the build for `build` and `target`
+    pub fn hooks_post(&self, target: &Target) -> ConfVal<&[String]> {
+        self.get_ref(
+            target,
+            |build| build.hooks.post.as_deref(),
+            |t| t.hooks.post.as_deref(),
+        )
+    }
+
     /// Returns the default target to build,
     pub fn default_target(&self, target_list: &TargetList) -> Option<Target> {
         self.build
@@ -386,6 +994,21 @@ impl CrossToml {
             .map(|t| Target::from(t, target_list))
     }
 
+    /// Returns the `build.cache-dir` part of `Cross.toml`
+    pub fn cache_dir(&self) -> Option<&String> {
+        self.build.cache_dir.as_ref()
+    }
+
+    /// Returns the `build.cache-max-size` part of `Cross.toml`
+    pub fn cache_max_size(&self) -> Option<&String> {
+        self.build.cache_max_size.as_ref()
+    }
+
+    /// Returns the `build.registry-mirror` part of `Cross.toml`
+    pub fn registry_mirror(&self) -> Option<&String> {
+        self.build.registry_mirror.as_ref()
+    }
+
     /// Returns a reference to the [`CrossTargetConfig`] of a specific `target`
     fn get_target(&self, target: &Target) -> Option<&CrossTargetConfig> {
         self.targets.get(target)
@@ -611,8 +1234,10 @@ mod tests {
     #[test]
     pub fn parse_empty_toml() -> Result<()> {
         let cfg = CrossToml {
+            schema_version: None,
             targets: HashMap::new(),
             build: CrossBuildConfig::default(),
+            profiles: HashMap::new(),
         };
         let (parsed_cfg, unused) = CrossToml::parse_from_cross_str("", None, &mut m!())?;
 
@@ -625,6 +1250,7 @@ mod tests {
     #[test]
     pub fn parse_build_toml() -> Result<()> {
         let cfg = CrossToml {
+            schema_version: None,
             targets: HashMap::new(),
             build: CrossBuildConfig {
                 env: CrossEnvConfig {
@@ -635,9 +1261,42 @@ mod tests {
                 build_std: None,
                 zig: None,
                 default_target: None,
+                cache_dir: None,
+                cache_max_size: None,
+                registry_mirror: None,
+                mount_cargo_home: None,
+                mount_cargo_bin: None,
+                isolate_target_dir: None,
+                copy_exclude: None,
+                podman_connection: None,
+                mount_path_dependencies: None,
+                cache_proxy: None,
+                shared_pre_build_cache: None,
+                host_toolchain: None,
+                config_lints: None,
                 pre_build: Some(PreBuild::Lines(vec![p!("echo 'Hello World!'")])),
+                pre_build_host: None,
+                tools: None,
+                packages: None,
                 dockerfile: None,
+                platform: None,
+                network: None,
+                container_user: None,
+                flasher: None,
+                ssh_agent: None,
+                strict_env: None,
+                container_context: None,
+                devices: None,
+                publish: None,
+                enforce_msrv: None,
+                verify_artifacts: None,
+                auto_target: None,
+                hooks: CrossHooksConfig {
+                    pre: Some(vec![p!("./scripts/gen.sh")]),
+                    post: None,
+                },
             },
+            profiles: HashMap::new(),
         };
 
         let test_str = r#"
@@ -645,6 +1304,9 @@ mod tests {
           xargo = true
           pre-build = ["echo 'Hello World!'"]
 
+          [build.hooks]
+          pre = ["./scripts/gen.sh"]
+
           [build.env]
           volumes = ["VOL1_ARG", "VOL2_ARG"]
           passthrough = ["VAR1", "VAR2"]
@@ -674,8 +1336,32 @@ mod tests {
                 zig: None,
                 image: Some("test-image".into()),
                 runner: None,
+                timeout: Some(p!("30m")),
+                engine: None,
+                seccomp: None,
+                container_user: None,
+                flasher: None,
+                ssh_agent: None,
+                strict_env: None,
+                container_context: None,
+                devices: None,
+                publish: None,
+                enforce_msrv: None,
+                verify_artifacts: None,
+                auto_target: None,
+                build_env: None,
+                sdk: None,
+                container: None,
+                backend: None,
+                native: None,
+                platform: None,
+                network: None,
+                hooks: CrossHooksConfig::default(),
                 dockerfile: None,
                 pre_build: Some(PreBuild::Lines(vec![])),
+                pre_build_host: None,
+                tools: None,
+                packages: None,
             },
         );
         target_map.insert(
@@ -696,14 +1382,40 @@ mod tests {
                 }),
                 image: None,
                 runner: None,
+                timeout: None,
+                engine: None,
+                seccomp: None,
+                container_user: None,
+                flasher: None,
+                ssh_agent: None,
+                strict_env: None,
+                container_context: None,
+                devices: None,
+                publish: None,
+                enforce_msrv: None,
+                verify_artifacts: None,
+                auto_target: None,
+                build_env: None,
+                sdk: None,
+                container: None,
+                backend: None,
+                native: None,
+                platform: None,
+                network: None,
+                hooks: CrossHooksConfig::default(),
                 dockerfile: None,
                 pre_build: None,
+                pre_build_host: None,
+                tools: None,
+                packages: None,
             },
         );
 
         let cfg = CrossToml {
+            schema_version: None,
             targets: target_map,
             build: CrossBuildConfig::default(),
+            profiles: HashMap::new(),
         };
 
         let test_str = r#"
@@ -715,6 +1427,7 @@ mod tests {
             build-std = true
             image = "test-image"
             pre-build = []
+            timeout = "30m"
 
             [target.aarch64-unknown-linux-musl.zig]
             enable = true
@@ -750,9 +1463,35 @@ mod tests {
                     file: p!("Dockerfile.test"),
                     context: None,
                     build_args: None,
+                    network: None,
+                    forward_proxy: None,
                 }),
                 pre_build: Some(PreBuild::Lines(vec![p!("echo 'Hello'")])),
+                pre_build_host: None,
+                tools: None,
+                packages: None,
                 runner: None,
+                timeout: None,
+                engine: None,
+                seccomp: None,
+                container_user: None,
+                flasher: None,
+                ssh_agent: None,
+                strict_env: None,
+                container_context: None,
+                devices: None,
+                publish: None,
+                enforce_msrv: None,
+                verify_artifacts: None,
+                auto_target: None,
+                build_env: None,
+                sdk: None,
+                container: None,
+                backend: None,
+                native: None,
+                platform: None,
+                network: None,
+                hooks: CrossHooksConfig::default(),
                 env: CrossEnvConfig {
                     passthrough: None,
                     volumes: Some(vec![p!("VOL")]),
@@ -761,6 +1500,7 @@ mod tests {
         );
 
         let cfg = CrossToml {
+            schema_version: None,
             targets: target_map,
             build: CrossBuildConfig {
                 env: CrossEnvConfig {
@@ -780,9 +1520,39 @@ mod tests {
                     }),
                 }),
                 default_target: None,
+                cache_dir: None,
+                cache_max_size: None,
+                registry_mirror: None,
+                mount_cargo_home: None,
+                mount_cargo_bin: None,
+                isolate_target_dir: None,
+                copy_exclude: None,
+                podman_connection: None,
+                mount_path_dependencies: None,
+                cache_proxy: None,
+                shared_pre_build_cache: None,
+                host_toolchain: None,
+                config_lints: None,
                 pre_build: Some(PreBuild::Lines(vec![])),
+                pre_build_host: None,
+                tools: None,
+                packages: None,
                 dockerfile: None,
+                platform: None,
+                network: None,
+                container_user: None,
+                flasher: None,
+                ssh_agent: None,
+                strict_env: None,
+                container_context: None,
+                devices: None,
+                publish: None,
+                enforce_msrv: None,
+                verify_artifacts: None,
+                auto_target: None,
+                hooks: CrossHooksConfig::default(),
             },
+            profiles: HashMap::new(),
         };
 
         let test_str = r#"
@@ -835,6 +1605,7 @@ mod tests {
     #[test]
     pub fn parse_from_cargo_toml() -> Result<()> {
         let cfg = CrossToml {
+            schema_version: None,
             targets: HashMap::new(),
             build: CrossBuildConfig {
                 env: CrossEnvConfig {
@@ -845,9 +1616,39 @@ mod tests {
                 xargo: Some(true),
                 zig: None,
                 default_target: None,
+                cache_dir: None,
+                cache_max_size: None,
+                registry_mirror: None,
+                mount_cargo_home: None,
+                mount_cargo_bin: None,
+                isolate_target_dir: None,
+                copy_exclude: None,
+                podman_connection: None,
+                mount_path_dependencies: None,
+                cache_proxy: None,
+                shared_pre_build_cache: None,
+                host_toolchain: None,
+                config_lints: None,
                 pre_build: None,
+                pre_build_host: None,
+                tools: None,
+                packages: None,
                 dockerfile: None,
+                platform: None,
+                network: None,
+                container_user: None,
+                flasher: None,
+                ssh_agent: None,
+                strict_env: None,
+                container_context: None,
+                devices: None,
+                publish: None,
+                enforce_msrv: None,
+                verify_artifacts: None,
+                auto_target: None,
+                hooks: CrossHooksConfig::default(),
             },
+            profiles: HashMap::new(),
         };
 
         let test_str = r#"
@@ -1051,6 +1852,217 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn profile_merges_over_base() -> Result<()> {
+        let toml_str = r#"
+            [build]
+            xargo = true
+
+            [target.aarch64-unknown-linux-gnu]
+            image = "base-image"
+            runner = "qemu-user"
+
+            [profile.ci.build]
+            xargo = false
+
+            [profile.ci.target.aarch64-unknown-linux-gnu]
+            image = "ci-image"
+
+            [profile.local.build]
+            zig = true
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+        assert_eq!(toml.profiles.len(), 2);
+
+        let target = Target::new_built_in("aarch64-unknown-linux-gnu");
+        let ci = toml.with_profile("ci")?;
+        assert!(ci.profiles.is_empty());
+        assert_eq!(ci.build.xargo, Some(false));
+        assert_eq!(ci.image(&target), Some(&p!("ci-image")));
+        assert_eq!(ci.runner(&target), Some(&[p!("qemu-user")][..]));
+
+        let (toml, _) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        let err = toml.with_profile("release").unwrap_err();
+        assert!(err.to_string().contains("ci, local"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_tools() -> Result<()> {
+        let toml_str = r#"
+            [build]
+            tools = ["sccache@0.8.1"]
+
+            [target.aarch64-unknown-linux-gnu]
+            tools = ["cargo-nextest", { name = "just", url = "https://example.com/just.tar.gz" }]
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+        // tools must survive the round-trip used when merging
+        let toml = toml.merge(CrossToml::default())?;
+
+        let target = Target::new_built_in("aarch64-unknown-linux-gnu");
+        let tools = toml.tools(&target);
+        assert_eq!(tools.build, Some(&[Tool::Known(p!("sccache@0.8.1"))][..]));
+        let tools = tools.target.expect("target tools should be set");
+        assert_eq!(tools.len(), 2);
+        assert_eq!(tools[0], Tool::Known(p!("cargo-nextest")));
+        assert!(matches!(tools[1], Tool::Custom(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_packages() -> Result<()> {
+        let toml_str = r#"
+            [build]
+            packages = ["cmake"]
+
+            [target.aarch64-unknown-linux-gnu]
+            packages = ["libssl-dev:{deb_arch}", "cmake"]
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+        let toml = toml.merge(CrossToml::default())?;
+
+        let packages = toml.packages(&Target::new_built_in("aarch64-unknown-linux-gnu"));
+        assert_eq!(packages.build, Some(&[p!("cmake")][..]));
+        assert_eq!(
+            packages.target,
+            Some(&[p!("libssl-dev:{deb_arch}"), p!("cmake")][..])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_auto_target() -> Result<()> {
+        let toml_str = r#"
+            [build]
+            auto-target = false
+
+            [target.aarch64-unknown-linux-gnu]
+            auto-target = ["llvm-cov", "b"]
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+
+        let target = Target::new_built_in("aarch64-unknown-linux-gnu");
+        let auto_target = toml.auto_target(&target);
+        assert_eq!(auto_target.build, Some(&AutoTarget::Bool(false)));
+        let auto_target = auto_target
+            .target
+            .expect("target auto-target should be set");
+        assert!(!auto_target.enabled_for(Some("llvm-cov")));
+        assert!(!auto_target.enabled_for(Some("build")));
+        assert!(auto_target.enabled_for(Some("test")));
+        assert!(auto_target.enabled_for(None));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_mount_cargo_home() -> Result<()> {
+        let mode = |toml_str: &str| -> Result<Option<CargoHomeMode>> {
+            let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+            assert!(unused.is_empty());
+            Ok(toml.mount_cargo_home().and_then(MountCargoHome::mode))
+        };
+        assert_eq!(
+            mode("[build]\nmount-cargo-home = \"ro\"")?,
+            Some(CargoHomeMode::Ro)
+        );
+        assert_eq!(
+            mode("[build]\nmount-cargo-home = \"project\"")?,
+            Some(CargoHomeMode::Project)
+        );
+        assert_eq!(mode("[build]\nmount-cargo-home = false")?, None);
+        assert!(mode("[build]\nmount-cargo-home = \"none\"").is_err());
+
+        assert_eq!(
+            "rw".parse::<MountCargoHome>()?.mode(),
+            Some(CargoHomeMode::Rw)
+        );
+        assert_eq!("0".parse::<MountCargoHome>()?.mode(), None);
+        assert!("yes please".parse::<MountCargoHome>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_sdk() -> Result<()> {
+        let toml_str = r#"
+            [target.aarch64-unknown-nto-qnx710]
+            image = "my/qnx-image"
+            sdk = { path = "/opt/qnx", env = { QNX_HOST = "{sdk}/host/linux/x86_64", QNX_TARGET = "{sdk}/target/qnx7" } }
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+
+        let sdk = toml
+            .sdk(&Target::new_built_in("aarch64-unknown-nto-qnx710"))
+            .expect("the sdk should be set");
+        assert_eq!(sdk.path(), "/opt/qnx");
+        assert_eq!(
+            sdk.env().get("QNX_TARGET").map(String::as_str),
+            Some("{sdk}/target/qnx7")
+        );
+        assert!(toml
+            .sdk(&Target::new_built_in("aarch64-unknown-linux-gnu"))
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_container() -> Result<()> {
+        let toml_str = r#"
+            [target.aarch64-unknown-linux-gnu.container]
+            path = ["/opt/vendor/bin"]
+            profile-script = "/opt/vendor/env.sh"
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+
+        let container = toml
+            .container(&Target::new_built_in("aarch64-unknown-linux-gnu"))
+            .expect("the container should be set");
+        assert_eq!(container.path(), ["/opt/vendor/bin"]);
+        assert_eq!(container.profile_script(), Some("/opt/vendor/env.sh"));
+        assert!(toml
+            .container(&Target::new_built_in("x86_64-unknown-linux-gnu"))
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parse_native_backend() -> Result<()> {
+        let toml_str = r#"
+            [target.aarch64-unknown-linux-gnu]
+            backend = "native"
+            native = { cc = "aarch64-unknown-linux-gnu-gcc", ar = "aarch64-unknown-linux-gnu-ar" }
+        "#;
+        let (toml, unused) = CrossToml::parse_from_cross_str(toml_str, None, &mut m!())?;
+        assert!(unused.is_empty());
+
+        let target = Target::new_built_in("aarch64-unknown-linux-gnu");
+        assert_eq!(toml.backend(&target), Some(Backend::Native));
+        let native = toml.native(&target).expect("native should be set");
+        assert_eq!(native.cc(), Some("aarch64-unknown-linux-gnu-gcc"));
+        assert_eq!(native.linker(), Some("aarch64-unknown-linux-gnu-gcc"));
+        assert_eq!(native.cxx(), None);
+        assert_eq!(
+            toml.backend(&Target::new_built_in("x86_64-unknown-linux-gnu")),
+            None
+        );
+        assert!("docker".parse::<Backend>().is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn pre_build_script() -> Result<()> {
         let toml_str = r#"
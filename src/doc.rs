@@ -0,0 +1,140 @@
+//! `cross doc --open`: build the documentation in the container, then open it
+//! from the host, since a browser cannot be opened inside the container.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cargo::CargoMetadata;
+use crate::errors::*;
+use crate::extensions::CommandExt;
+use crate::file::ToUtf8;
+use crate::flash::flag_value;
+use crate::shell::MessageInfo;
+
+/// Remove `--open` from the cargo arguments, returning whether it was passed.
+pub(crate) fn take_open(cargo_args: &mut Vec<String>) -> bool {
+    let end = cargo_args
+        .iter()
+        .position(|a| a == "--")
+        .unwrap_or(cargo_args.len());
+    match cargo_args[..end].iter().position(|a| a == "--open") {
+        Some(index) => {
+            cargo_args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The index page of the crate cargo would open: the package selected with
+/// `--package`, the package containing `cwd`, or the first workspace member.
+pub(crate) fn index(
+    metadata: &CargoMetadata,
+    cargo_args: &[String],
+    cwd: &Path,
+    doc_dir: &Path,
+) -> Result<PathBuf> {
+    let members: Vec<_> = metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .collect();
+    let package = match flag_value(cargo_args, &["--package", "-p"]) {
+        Some(name) => members.iter().find(|p| p.name == name),
+        None => members
+            .iter()
+            .filter(|p| p.manifest_path.parent().is_some_and(|d| cwd.starts_with(d)))
+            .max_by_key(|p| p.manifest_path.components().count())
+            .or_else(|| members.first()),
+    }
+    .ok_or_else(|| eyre::eyre!("could not determine which documentation to open"))?;
+    let target = package
+        .targets
+        .iter()
+        .find(|t| t.kind.iter().any(|k| k == "lib" || k == "proc-macro"))
+        .or_else(|| {
+            package
+                .targets
+                .iter()
+                .find(|t| t.kind.iter().any(|k| k == "bin"))
+        })
+        .ok_or_else(|| eyre::eyre!("package `{}` has no documentation", package.name))?;
+    Ok(doc_dir
+        .join(target.name.replace('-', "_"))
+        .join("index.html"))
+}
+
+/// Open the documentation with `BROWSER`, or the default browser of the host.
+pub(crate) fn open(path: &Path, msg_info: &mut MessageInfo) -> Result<()> {
+    if !path.exists() {
+        eyre::bail!("the documentation was not found at `{}`", path.to_utf8()?);
+    }
+    let mut command = match env::var_os("BROWSER") {
+        Some(browser) => Command::new(browser),
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        None => Command::new("xdg-open"),
+    };
+    msg_info.status(format_args!("     Opening {}", path.to_utf8()?))?;
+    command.arg(path).run(msg_info, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! s {
+        ($x:literal) => {
+            $x.to_owned()
+        };
+    }
+
+    #[test]
+    fn take_open_flag() {
+        let mut args = vec![s!("doc"), s!("--open"), s!("--no-deps")];
+        assert!(take_open(&mut args));
+        assert_eq!(args, [s!("doc"), s!("--no-deps")]);
+
+        let mut args = vec![s!("doc"), s!("--"), s!("--open")];
+        assert!(!take_open(&mut args));
+        assert_eq!(args.len(), 3);
+    }
+
+    #[test]
+    fn doc_index() -> Result<()> {
+        let json = r#"{"workspace_root": "/ws", "target_directory": "/ws/target",
+            "workspace_members": ["my-lib", "tool"], "metadata": null,
+            "packages": [
+                {"id": "my-lib", "name": "my-lib", "manifest_path": "/ws/Cargo.toml",
+                "source": null, "version": "0.1.0", "license": null, "metadata": null,
+                "targets": [{"name": "my-lib", "kind": ["lib"]}]},
+                {"id": "tool", "name": "tool", "manifest_path": "/ws/tool/Cargo.toml",
+                "source": null, "version": "0.1.0", "license": null, "metadata": null,
+                "targets": [{"name": "my-tool", "kind": ["bin"]}]}
+            ]}"#;
+        let metadata: CargoMetadata = serde_json::from_str(json)?;
+        let doc_dir = Path::new("/ws/target/aarch64-unknown-linux-gnu/doc");
+
+        let index = |args: &[String], cwd: &str| index(&metadata, args, Path::new(cwd), doc_dir);
+        assert_eq!(
+            index(&[s!("doc")], "/ws")?,
+            doc_dir.join("my_lib").join("index.html")
+        );
+        assert_eq!(
+            index(&[s!("doc")], "/ws/tool/src")?,
+            doc_dir.join("my_tool").join("index.html")
+        );
+        assert_eq!(
+            index(&[s!("doc"), s!("-p"), s!("my-lib")], "/ws/tool")?,
+            doc_dir.join("my_lib").join("index.html")
+        );
+        assert!(index(&[s!("doc"), s!("-p"), s!("missing")], "/ws").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! The caching proxy enabled with `build.cache-proxy`.
+//!
+//! A squid container is started by cross the first time it's needed and is
+//! reused by later builds: it runs on its own network, which the build
+//! container joins, and keeps its cache in a persistent volume. Only the HTTP
+//! proxy variables of the build container point at it, so plain HTTP
+//! downloads such as apt packages are cached, since squid can't cache HTTPS.
+//!
+//! The crates are cached by cargo instead: the registry of the cargo home in
+//! the container is a persistent volume shared by the builds, unless it's
+//! already the registry of the host.
+
+use std::env;
+
+use super::engine::Engine;
+use crate::errors::*;
+use crate::extensions::CommandExt;
+use crate::shell::MessageInfo;
+
+/// The name of the proxy container, of its network, and of its cache volume.
+pub const CACHE_PROXY_NAME: &str = "cross-cache-proxy";
+pub const CACHE_PROXY_IMAGE_ENV: &str = "CROSS_CACHE_PROXY_IMAGE";
+/// The volume shared as the cargo registry of the build containers.
+pub const CARGO_REGISTRY_VOLUME: &str = "cross-cargo-registry";
+const DEFAULT_CACHE_PROXY_IMAGE: &str = "docker.io/ubuntu/squid:5.2-22.04_beta";
+const CACHE_PROXY_PORT: u16 = 3128;
+const CACHE_DIR: &str = "/var/spool/squid";
+/// The hosts reached without the proxy, in addition to those of the host.
+const NO_PROXY: &str = "localhost,127.0.0.1,::1";
+
+/// The proxy environment variables of the build container, keeping the
+/// hosts of `NO_PROXY` on the host.
+pub(crate) fn envvars(host_no_proxy: Option<&str>) -> Vec<String> {
+    let url = format!("http://{CACHE_PROXY_NAME}:{CACHE_PROXY_PORT}");
+    let no_proxy = match host_no_proxy.filter(|hosts| !hosts.is_empty()) {
+        Some(hosts) => format!("{NO_PROXY},{hosts}"),
+        None => NO_PROXY.to_owned(),
+    };
+    ["http_proxy", "HTTP_PROXY"]
+        .iter()
+        .map(|var| format!("{var}={url}"))
+        .chain(
+            ["no_proxy", "NO_PROXY"]
+                .iter()
+                .map(|var| format!("{var}={no_proxy}")),
+        )
+        .collect()
+}
+
+/// The `NO_PROXY` hosts of the host environment.
+pub(crate) fn host_no_proxy() -> Option<String> {
+    env::var("NO_PROXY").or_else(|_| env::var("no_proxy")).ok()
+}
+
+/// The volume argument mounting the shared registry into the cargo home.
+pub(crate) fn registry_volume(cargo_home: &str) -> String {
+    format!(
+        "{CARGO_REGISTRY_VOLUME}:{}/registry",
+        cargo_home.trim_end_matches('/')
+    )
+}
+
+fn image() -> String {
+    env::var(CACHE_PROXY_IMAGE_ENV).unwrap_or_else(|_| DEFAULT_CACHE_PROXY_IMAGE.to_owned())
+}
+
+fn exists(engine: &Engine, kind: &str, name: &str, msg_info: &mut MessageInfo) -> Result<bool> {
+    Ok(engine
+        .subcommand(kind)
+        .args(["inspect", name])
+        .run_and_get_output(msg_info)?
+        .status
+        .success())
+}
+
+/// Create the shared registry volume, writable by the user of any build.
+pub(crate) fn create_registry(engine: &Engine, msg_info: &mut MessageInfo) -> Result<()> {
+    if exists(engine, "volume", CARGO_REGISTRY_VOLUME, msg_info)? {
+        return Ok(());
+    }
+    engine
+        .subcommand("run")
+        .args(["--rm", "--entrypoint", "chmod"])
+        .args(["--volume", &format!("{CARGO_REGISTRY_VOLUME}:/registry")])
+        .arg(image())
+        .args(["0777", "/registry"])
+        .run(msg_info, true)
+        .wrap_err_with(|| format!("could not create the volume `{CARGO_REGISTRY_VOLUME}`"))
+}
+
+/// The state of the proxy container, `None` if it doesn't exist.
+fn state(engine: &Engine, msg_info: &mut MessageInfo) -> Result<Option<String>> {
+    let output = engine
+        .subcommand("container")
+        .args(["inspect", "--format", "{{.State.Status}}", CACHE_PROXY_NAME])
+        .run_and_get_output(msg_info)?;
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned()))
+}
+
+/// Start the proxy if it's not running, and connect it to `network` if the
+/// build container runs in a user network.
+pub(crate) fn start(
+    engine: &Engine,
+    network: Option<&str>,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    if !exists(engine, "network", CACHE_PROXY_NAME, msg_info)? {
+        let created = engine
+            .subcommand("network")
+            .args(["create", CACHE_PROXY_NAME])
+            .run_and_get_output(msg_info)?;
+        // another build may have created it in the meantime.
+        if !created.status.success() && !exists(engine, "network", CACHE_PROXY_NAME, msg_info)? {
+            eyre::bail!("could not create the network `{CACHE_PROXY_NAME}` of the cache proxy");
+        }
+    }
+
+    match state(engine, msg_info)?.as_deref() {
+        Some("running") => (),
+        Some(_) => engine
+            .subcommand("start")
+            .arg(CACHE_PROXY_NAME)
+            .run(msg_info, true)
+            .wrap_err("could not start the cache proxy")?,
+        None => {
+            let image = image();
+            msg_info.note(format_args!(
+                "starting the cache proxy `{CACHE_PROXY_NAME}` from `{image}`"
+            ))?;
+            let started = engine
+                .subcommand("run")
+                .args(["--detach", "--name", CACHE_PROXY_NAME])
+                .args(["--network", CACHE_PROXY_NAME])
+                .args(["--restart", "unless-stopped"])
+                .args([
+                    "--label",
+                    &format!("{}.cache-proxy", crate::CROSS_LABEL_DOMAIN),
+                ])
+                .args(["--volume", &format!("{CACHE_PROXY_NAME}:{CACHE_DIR}")])
+                .arg(&image)
+                .run_and_get_output(msg_info)?;
+            if !started.status.success() && state(engine, msg_info)?.is_none() {
+                return Err(eyre::eyre!(
+                    "could not start the cache proxy from `{image}`"
+                ))
+                .with_suggestion(|| {
+                    format!("set `{CACHE_PROXY_IMAGE_ENV}` to a squid image which can be pulled")
+                });
+            }
+        }
+    }
+
+    if let Some(network) = network {
+        let networks = engine
+            .subcommand("container")
+            .args([
+                "inspect",
+                "--format",
+                "{{range $name, $_ := .NetworkSettings.Networks}}{{$name}} {{end}}",
+                CACHE_PROXY_NAME,
+            ])
+            .run_and_get_stdout(msg_info)?;
+        if !networks.split_whitespace().any(|n| n == network) {
+            engine
+                .subcommand("network")
+                .args(["connect", network, CACHE_PROXY_NAME])
+                .run(msg_info, true)
+                .wrap_err_with(|| {
+                    format!("could not connect the cache proxy to the network `{network}`")
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_envvars() {
+        let url = "http://cross-cache-proxy:3128";
+        assert_eq!(
+            envvars(None),
+            [
+                format!("http_proxy={url}"),
+                format!("HTTP_PROXY={url}"),
+                format!("no_proxy={NO_PROXY}"),
+                format!("NO_PROXY={NO_PROXY}"),
+            ]
+        );
+        let vars = envvars(Some(".internal,10.0.0.1"));
+        assert!(vars.contains(&format!("NO_PROXY={NO_PROXY},.internal,10.0.0.1")));
+        // https can't be cached, so it doesn't go through the proxy
+        assert!(!vars
+            .iter()
+            .any(|var| var.to_lowercase().starts_with("https_proxy")));
+        assert_eq!(envvars(Some("")), envvars(None));
+    }
+
+    #[test]
+    fn shared_registry() {
+        assert_eq!(
+            registry_volume("/tmp/cargo-home"),
+            "cross-cargo-registry:/tmp/cargo-home/registry"
+        );
+        assert_eq!(
+            registry_volume("/cross/cargo/"),
+            "cross-cargo-registry:/cross/cargo/registry"
+        );
+        // the image is pinned, so the proxy doesn't change between builds
+        assert!(!DEFAULT_CACHE_PROXY_IMAGE.ends_with(":latest"));
+    }
+}
@@ -0,0 +1,213 @@
+//! Capabilities advertised by the labels of an image, verified before the
+//! image is run so missing toolchains fail early with a clear error.
+//!
+//! The labels are optional, and only the capabilities an image advertises
+//! are verified, so images without them are used as is.
+
+use std::collections::BTreeMap;
+
+use rustc_version::Version;
+
+use super::shared::DockerOptions;
+use crate::errors::*;
+use crate::interpreter;
+use crate::shell::MessageInfo;
+
+/// The targets whose sysroot the image provides, separated by commas.
+pub const TARGETS_LABEL: &str = "org.cross-rs.targets";
+/// The runners the image provides, such as `qemu-user`, separated by commas.
+pub const RUNNERS_LABEL: &str = "org.cross-rs.runners";
+/// The oldest rustc version the image supports, such as `1.64`.
+pub const MIN_RUSTC_LABEL: &str = "org.cross-rs.min-rustc";
+/// The newest rustc version the image supports, such as `1.63`.
+pub const MAX_RUSTC_LABEL: &str = "org.cross-rs.max-rustc";
+
+fn label_values<'a>(labels: &'a BTreeMap<String, String>, label: &str) -> Option<Vec<&'a str>> {
+    labels.get(label).map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect()
+    })
+}
+
+fn quoted(values: &[&str]) -> String {
+    values
+        .iter()
+        .map(|v| format!("`{v}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Verify that the `labels` of `image` provide `target` and `runner`.
+fn check_labels(
+    image: &str,
+    labels: &BTreeMap<String, String>,
+    target: &str,
+    runner: Option<&str>,
+) -> Result<()> {
+    if let Some(targets) = label_values(labels, TARGETS_LABEL) {
+        if !targets.contains(&target) {
+            return Err(eyre::eyre!(
+                "image `{image}` does not provide the sysroot for `{target}`"
+            ))
+            .with_note(|| {
+                format!(
+                    "the label `{TARGETS_LABEL}` of the image only lists {}",
+                    quoted(&targets)
+                )
+            })
+            .with_suggestion(|| {
+                format!("use an image for `{target}`, or pass `--skip-image-checks`")
+            });
+        }
+    }
+
+    if let (Some(runners), Some(runner)) = (label_values(labels, RUNNERS_LABEL), runner) {
+        if !runners.contains(&runner) {
+            return Err(eyre::eyre!(
+                "image `{image}` does not provide the runner `{runner}` for `{target}`"
+            ))
+            .with_note(|| {
+                format!(
+                    "the label `{RUNNERS_LABEL}` of the image only lists {}",
+                    quoted(&runners)
+                )
+            })
+            .with_suggestion(|| {
+                "configure one of the runners of the image, or pass `--skip-image-checks`"
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a `major.minor` rustc version of a label, the patch being ignored.
+fn label_version(labels: &BTreeMap<String, String>, label: &str) -> Result<Option<(u64, u64)>> {
+    let Some(value) = labels.get(label) else {
+        return Ok(None);
+    };
+    let mut parts = value.trim().split('.').map(str::parse::<u64>);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Ok(Some((major, minor))),
+        _ => Err(eyre::eyre!(
+            "invalid rustc version `{value}` in the label `{label}`"
+        )),
+    }
+}
+
+/// The range of rustc versions supported by the image of `labels`, such as
+/// `1.64 and later`, if `version` is outside of it.
+fn unsupported_rustc(
+    labels: &BTreeMap<String, String>,
+    version: &Version,
+) -> Result<Option<String>> {
+    let min = label_version(labels, MIN_RUSTC_LABEL)?;
+    let max = label_version(labels, MAX_RUSTC_LABEL)?;
+    let version = (version.major, version.minor);
+    if min.map_or(true, |min| version >= min) && max.map_or(true, |max| version <= max) {
+        return Ok(None);
+    }
+    Ok(Some(match (min, max) {
+        (Some((a, b)), Some((c, d))) => format!("{a}.{b} to {c}.{d}"),
+        (Some((a, b)), None) => format!("{a}.{b} and later"),
+        (None, Some((c, d))) => format!("{c}.{d} and earlier"),
+        (None, None) => unreachable!("every version is supported without labels"),
+    }))
+}
+
+/// Verify the capabilities advertised by the labels of `image`, which must
+/// be present locally, unless `--skip-image-checks` was passed.
+pub(crate) fn verify(
+    options: &DockerOptions,
+    image: &str,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    if options.skip_image_checks {
+        return Ok(());
+    }
+    let labels = options.engine.image_labels(image, msg_info)?;
+    let target = &options.target;
+    let runner = match options.config.runner(target)? {
+        Some(runners) if options.runs_binaries => {
+            interpreter::select_runner(&runners, target, &options.engine, msg_info)?
+        }
+        _ => None,
+    };
+    msg_info.debug(format_args!("verifying the labels of image `{image}`"))?;
+    if let Some(version) = &options.rustc_version {
+        if let Some(range) = unsupported_rustc(&labels, version)? {
+            msg_info.warn(format_args!(
+                "rustc {version} is outside of the versions supported by image `{image}`, which supports rustc {range}. the build may fail."
+            ))?;
+        }
+    }
+    check_labels(image, &labels, target.triple(), runner.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn capability_labels() {
+        let target = "aarch64-unknown-linux-gnu";
+        // images without the labels are not verified
+        assert!(check_labels("image", &labels(&[]), target, Some("native")).is_ok());
+
+        let image = labels(&[
+            (
+                TARGETS_LABEL,
+                "aarch64-unknown-linux-gnu, aarch64-unknown-linux-musl",
+            ),
+            (RUNNERS_LABEL, "qemu-user,native"),
+        ]);
+        assert!(check_labels("image", &image, target, None).is_ok());
+        assert!(check_labels("image", &image, target, Some("qemu-user")).is_ok());
+        assert!(check_labels("image", &image, "aarch64-unknown-linux-musl", None).is_ok());
+        assert!(check_labels("image", &image, "x86_64-unknown-linux-gnu", None).is_err());
+        assert!(check_labels("image", &image, target, Some("qemu-system")).is_err());
+
+        let no_runners = labels(&[(RUNNERS_LABEL, "")]);
+        assert!(check_labels("image", &no_runners, target, None).is_ok());
+        assert!(check_labels("image", &no_runners, target, Some("qemu-user")).is_err());
+    }
+
+    #[test]
+    fn rustc_labels() -> Result<()> {
+        let version = |s: &str| Version::parse(s).unwrap();
+        assert_eq!(unsupported_rustc(&labels(&[]), &version("1.50.0"))?, None);
+
+        let current = labels(&[(MIN_RUSTC_LABEL, "1.64")]);
+        assert_eq!(unsupported_rustc(&current, &version("1.70.0"))?, None);
+        assert_eq!(
+            unsupported_rustc(&current, &version("1.64.0-nightly"))?,
+            None
+        );
+        assert_eq!(
+            unsupported_rustc(&current, &version("1.63.0"))?.as_deref(),
+            Some("1.64 and later")
+        );
+
+        let range = labels(&[(MIN_RUSTC_LABEL, "1.40.0"), (MAX_RUSTC_LABEL, "1.63")]);
+        assert_eq!(unsupported_rustc(&range, &version("1.63.1"))?, None);
+        assert_eq!(
+            unsupported_rustc(&range, &version("1.75.0"))?.as_deref(),
+            Some("1.40 to 1.63")
+        );
+        assert!(
+            unsupported_rustc(&labels(&[(MAX_RUSTC_LABEL, "latest")]), &version("1.0.0")).is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use std::env;
 use std::io::Write;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -8,11 +9,39 @@ use crate::{errors::*, file, CommandExt, ToUtf8};
 use crate::{CargoMetadata, TargetTriple};
 
 use super::{
-    create_target_dir, get_image_name, path_hash, BuildCommandExt, BuildResultExt, Engine,
-    ImagePlatform,
+    create_target_dir, get_image_name, path_hash, BuildCommandExt, BuildResultExt,
+    DockerCommandExt, Engine, ImagePlatform,
 };
 
 pub const CROSS_CUSTOM_DOCKERFILE_IMAGE_PREFIX: &str = "localhost/cross-rs/cross-custom-";
+/// The repository of the pre-build images shared between projects.
+pub const CROSS_SHARED_PRE_BUILD_IMAGE: &str = "localhost/cross-rs/cross-custom-shared";
+
+/// The proxy variables predefined as build arguments by docker and podman.
+const PROXY_ENVVARS: [&str; 8] = [
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "FTP_PROXY",
+    "ftp_proxy",
+    "NO_PROXY",
+    "no_proxy",
+];
+
+/// The proxy variables set on the host and not provided in `build-args`.
+///
+/// Only the names are passed, so the engine reads the values from the
+/// environment and credentials in the proxy URL don't show in the command.
+fn proxy_build_args(
+    is_set: impl Fn(&str) -> bool,
+    build_args: &[(String, String)],
+) -> Vec<&'static str> {
+    PROXY_ENVVARS
+        .into_iter()
+        .filter(|var| is_set(var) && !build_args.iter().any(|(key, _)| key == var))
+        .collect()
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Dockerfile<'a> {
@@ -24,6 +53,7 @@ pub enum Dockerfile<'a> {
     },
     Custom {
         content: String,
+        name: Option<&'a str>,
         runs_with: &'a ImagePlatform,
     },
 }
@@ -99,20 +129,32 @@ impl<'a> Dockerfile<'a> {
         docker_build.verbose(msg_info.verbosity);
         docker_build.cross_labels(options.target.triple(), self.runs_with().target.triple());
 
-        docker_build.args([
-            "--label",
-            &format!(
-                "{}.workspace_root={}",
-                crate::CROSS_LABEL_DOMAIN,
-                paths.workspace_root().to_utf8()?
-            ),
-        ]);
+        // shared images do not belong to a single workspace
+        if !matches!(self, Dockerfile::Custom { name: Some(_), .. }) {
+            docker_build.args([
+                "--label",
+                &format!(
+                    "{}.workspace_root={}",
+                    crate::CROSS_LABEL_DOMAIN,
+                    paths.workspace_root().to_utf8()?
+                ),
+            ]);
+        }
 
         let image_name = self.image_name(options.target.target(), &paths.metadata)?;
         docker_build.args(["--tag", &image_name]);
 
-        for (key, arg) in build_args {
-            docker_build.args(["--build-arg", &format!("{}={}", key.as_ref(), arg.as_ref())]);
+        let build_args: Vec<(String, String)> = build_args
+            .into_iter()
+            .map(|(key, arg)| (key.as_ref().to_owned(), arg.as_ref().to_owned()))
+            .collect();
+        for (key, arg) in &build_args {
+            docker_build.args(["--build-arg", &format!("{key}={arg}")]);
+        }
+        if options.config.dockerfile_forward_proxy(&options.target) {
+            for var in proxy_build_args(|var| env::var_os(var).is_some(), &build_args) {
+                docker_build.args(["--build-arg", var]);
+            }
         }
 
         if let Some(arch) = options.target.target().deb_arch() {
@@ -150,6 +192,12 @@ impl<'a> Dockerfile<'a> {
         }
 
         docker_build.args(["--file".into(), path]);
+        match options.config.dockerfile_network(&options.target) {
+            Some(network) => {
+                docker_build.args(["--network", &network]);
+            }
+            None => docker_build.add_network(options),
+        }
 
         if let Some(build_opts) = options.config.build_opts() {
             docker_build.args(Engine::parse_opts(&build_opts)?);
@@ -172,6 +220,7 @@ impl<'a> Dockerfile<'a> {
         // conditionally apply this suggestion and note. This could then inspect if a help string is emitted,
         // if the daemon is not running, etc.
         docker_build
+            .container_output(msg_info)?
             .run(msg_info, true)
             .engine_warning(&options.engine)
             .buildkit_warning()?;
@@ -186,17 +235,23 @@ impl<'a> Dockerfile<'a> {
         match self {
             Dockerfile::File {
                 name: Some(name), ..
+            }
+            | Dockerfile::Custom {
+                name: Some(name), ..
             } => Ok((*name).to_owned()),
             _ => Ok(format!(
-                "{}{package_name}:{target_triple}-{path_hash}{custom}",
+                "{}{package_name}:{tag}",
                 CROSS_CUSTOM_DOCKERFILE_IMAGE_PREFIX,
                 package_name = docker_package_name(metadata),
-                path_hash = path_hash(&metadata.workspace_root, docker::PATH_HASH_SHORT)?,
-                custom = if matches!(self, Self::File { .. }) {
-                    ""
-                } else {
-                    "-pre-build"
-                }
+                tag = docker_tag(&format!(
+                    "{target_triple}-{path_hash}{custom}",
+                    path_hash = path_hash(&metadata.workspace_root, docker::PATH_HASH_SHORT)?,
+                    custom = if matches!(self, Self::File { .. }) {
+                        ""
+                    } else {
+                        "-pre-build"
+                    }
+                )),
             )),
         }
     }
@@ -218,6 +273,33 @@ impl<'a> Dockerfile<'a> {
     }
 }
 
+/// A digest of `parts`. `const_sha1` hashes small buffers only, so the
+/// digest is chained over chunks of each part, prefixed by its length.
+pub(crate) fn content_digest<'b>(parts: impl IntoIterator<Item = &'b [u8]>) -> String {
+    let mut digest = String::new();
+    let mut update = |chunk: &[u8]| {
+        let mut buffer = digest.as_bytes().to_vec();
+        buffer.extend_from_slice(chunk);
+        digest = const_sha1::sha1(&const_sha1::ConstBuffer::from_slice(&buffer)).to_string();
+    };
+    for part in parts {
+        update(part.len().to_string().as_bytes());
+        for chunk in part.chunks(512) {
+            update(chunk);
+        }
+    }
+    digest
+}
+
+/// The name of the pre-build image shared between projects, from the ID of
+/// the base image and everything else the layer depends on.
+pub(crate) fn shared_pre_build_image<'b>(parts: impl IntoIterator<Item = &'b [u8]>) -> String {
+    format!(
+        "{CROSS_SHARED_PRE_BUILD_IMAGE}:pre-build-{}",
+        &content_digest(parts)[..16]
+    )
+}
+
 fn docker_package_name(metadata: &CargoMetadata) -> String {
     // a valid image name consists of the following:
     // - lowercase ASCII letters
@@ -234,49 +316,69 @@ fn docker_package_name(metadata: &CargoMetadata) -> String {
     )
 }
 
+/// Normalize a separator run of an image name component: `.`, `_`, `__`,
+/// or any number of `-` are valid, and anything else is collapsed.
+fn normalize_separator(separator: &str) -> &str {
+    if separator.chars().all(|c| c == '-') {
+        separator
+    } else if separator.chars().all(|c| c == '.') {
+        "."
+    } else if separator.chars().all(|c| c == '_') {
+        &"__"[..separator.len().min(2)]
+    } else {
+        "-"
+    }
+}
+
 fn docker_tag_name(file_name: &str) -> String {
-    // a valid image name consists of the following:
-    // - lowercase ASCII letters
-    // - digits
-    // - a period
-    // - 1-2 underscores
-    // - 1 or more hyphens (dashes)
+    // a valid image name component matches `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*`:
+    // lowercase ASCII letters and digits, separated by a period, 1-2
+    // underscores, or 1 or more hyphens (dashes).
     let mut result = String::new();
-    let mut consecutive_underscores = 0;
+    let mut separator = String::new();
     for c in file_name.chars() {
         match c {
-            'a'..='z' | '0'..='9' | '.' | '-' => {
-                consecutive_underscores = 0;
-                result.push(c);
-            }
-            'A'..='Z' => {
-                consecutive_underscores = 0;
-                result.push(c.to_ascii_lowercase());
-            }
-            '_' => {
-                consecutive_underscores += 1;
-                if consecutive_underscores <= 2 {
-                    result.push(c);
+            'a'..='z' | 'A'..='Z' | '0'..='9' => {
+                // separators can't lead or trail the component
+                if !result.is_empty() {
+                    result.push_str(normalize_separator(&separator));
                 }
+                separator.clear();
+                result.push(c.to_ascii_lowercase());
             }
+            '.' | '_' | '-' => separator.push(c),
             // ignore any non-ascii characters
             _ => (),
         }
     }
 
-    // in case our result ends in an invalid last char `-` or `.`
-    // we remove
-    result = result.trim_end_matches(&['.', '-']).to_owned();
-
-    // in case all characters were invalid or we had all non-ASCII
-    // characters followed by a `-` or `.`, we use a non-empty filename
+    // in case all characters were invalid, use a hash of the name so distinct
+    // names still produce distinct images.
     if result.is_empty() {
-        result = "empty".to_owned();
+        let digest = const_sha1::sha1(&const_sha1::ConstBuffer::from_slice(file_name.as_bytes()));
+        result = format!("empty-{}", &digest.to_string()[..docker::PATH_HASH_SHORT]);
     }
 
     result
 }
 
+/// Normalize an image tag to `[A-Za-z0-9_][A-Za-z0-9_.-]{0,127}`, replacing
+/// any invalid characters, such as in the names of custom targets.
+fn docker_tag(tag: &str) -> String {
+    let mut result: String = tag
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '-' => c,
+            _ => '-',
+        })
+        .take(128)
+        .collect();
+    if result.starts_with(['.', '-']) {
+        result.replace_range(..1, "_");
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -287,6 +389,39 @@ mod tests {
         };
     }
 
+    #[test]
+    fn proxy_build_args_test() {
+        let is_set = |var: &str| matches!(var, "HTTPS_PROXY" | "https_proxy" | "NO_PROXY");
+        assert_eq!(
+            proxy_build_args(is_set, &[]),
+            ["HTTPS_PROXY", "https_proxy", "NO_PROXY"]
+        );
+        let build_args = [(s!("NO_PROXY"), s!("localhost"))];
+        assert_eq!(
+            proxy_build_args(is_set, &build_args),
+            ["HTTPS_PROXY", "https_proxy"]
+        );
+    }
+
+    #[test]
+    fn shared_pre_build_image_test() {
+        let script = "apt-get install libpq-dev\n".repeat(100);
+        let image = |id: &str, script: &str| {
+            shared_pre_build_image([id.as_bytes(), script.as_bytes(), b"arm64"])
+        };
+        let name = image("sha256:1234", &script);
+        assert!(name.starts_with("localhost/cross-rs/cross-custom-shared:pre-build-"));
+        assert_eq!(name.len(), CROSS_SHARED_PRE_BUILD_IMAGE.len() + 27);
+        assert_eq!(name, image("sha256:1234", &script));
+        assert_ne!(name, image("sha256:5678", &script));
+        assert_ne!(name, image("sha256:1234", &script[1..]));
+        // the parts are delimited
+        assert_ne!(
+            shared_pre_build_image([&b"ab"[..], b"c"]),
+            shared_pre_build_image([&b"a"[..], b"bc"])
+        );
+    }
+
     #[test]
     fn docker_tag_name_test() {
         assert_eq!(docker_tag_name("package"), s!("package"));
@@ -306,5 +441,69 @@ mod tests {
 
         assert_eq!(docker_tag_name("foo-123"), s!("foo-123"));
         assert_eq!(docker_tag_name("foo-123-"), s!("foo-123"));
+        assert_eq!(docker_tag_name("-_foo_-bar.._baz__"), s!("foo-bar-baz"));
+        assert_eq!(docker_tag_name("foo..bar"), s!("foo.bar"));
+        assert!(docker_tag_name("안녕하세요").starts_with("empty-"));
+        assert_ne!(docker_tag_name("안녕"), docker_tag_name("하세요"));
+    }
+
+    /// `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*`, from the OCI distribution spec.
+    fn is_valid_component(name: &str) -> bool {
+        let is_alnum = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+        let mut chars = name.chars().peekable();
+        loop {
+            let mut alnum = 0;
+            while chars.next_if(|&c| is_alnum(c)).is_some() {
+                alnum += 1;
+            }
+            if alnum == 0 {
+                return false;
+            }
+            let mut separator = String::new();
+            while let Some(c) = chars.next_if(|&c| !is_alnum(c)) {
+                separator.push(c);
+            }
+            match separator.as_str() {
+                "" => return chars.peek().is_none(),
+                "." | "_" | "__" => (),
+                s if s.chars().all(|c| c == '-') => (),
+                _ => return false,
+            }
+        }
+    }
+
+    /// `[\w][\w.-]{0,127}`, from the OCI distribution spec.
+    fn is_valid_tag(tag: &str) -> bool {
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        tag.len() <= 128
+            && tag.starts_with(is_word)
+            && tag.chars().all(|c| is_word(c) || c == '.' || c == '-')
+    }
+
+    #[test]
+    fn docker_names_match_reference_grammar() {
+        assert!(is_valid_component("a__b-c--d.e"));
+        assert!(!is_valid_component("a_-b") && !is_valid_component("-a"));
+        assert!(!is_valid_component("a.") && !is_valid_component("a___b"));
+
+        let alphabet: Vec<char> = "aZ09._-_-.:/@+ é안".chars().collect();
+        // a small linear congruential generator, so the cases are reproducible
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) as usize % bound
+        };
+        for _ in 0..2000 {
+            let len = next(24);
+            let name: String = (0..len).map(|_| alphabet[next(alphabet.len())]).collect();
+            let component = docker_tag_name(&name);
+            assert!(is_valid_component(&component), "{name:?} -> {component:?}");
+            let tag = docker_tag(&name.repeat(8));
+            assert!(name.is_empty() || is_valid_tag(&tag), "{name:?} -> {tag:?}");
+        }
+        assert!(is_valid_tag(&docker_tag("my-target.json-abcde-pre-build")));
+        assert_eq!(docker_tag("-custom/target"), s!("_custom-target"));
     }
 }
@@ -1,23 +1,38 @@
+use std::collections::BTreeMap;
 use std::env;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+
+use color_eyre::SectionExt;
 
 use crate::config::bool_from_envvar;
 use crate::extensions::CommandExt;
+use crate::file;
+use crate::setup_cache::SetupCache;
 use crate::shell::MessageInfo;
 use crate::{errors::*, OutputExt};
 
-use super::{Architecture, ContainerOs};
+use super::shared::{is_transient_engine_error, RETRY_DELAY};
+use super::{Architecture, ContainerOs, Image};
 
 pub const DOCKER: &str = "docker";
 pub const PODMAN: &str = "podman";
+pub const KUBECTL: &str = "kubectl";
+pub const NERDCTL: &str = "nerdctl";
+/// The engine name selecting the experimental Kubernetes backend.
+pub const KUBERNETES: &str = "kubernetes";
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum EngineType {
     Docker,
     Podman,
     PodmanRemote,
     Nerdctl,
+    /// `kubectl`, running the build in a pod with the experimental Kubernetes backend.
+    Kubernetes,
     Other,
 }
 
@@ -37,13 +52,13 @@ impl EngineType {
     /// Returns `true` if the build command supports the `--output` flag.
     #[must_use]
     pub const fn supports_output_flag(&self) -> bool {
-        !matches!(self, Self::Other)
+        !matches!(self, Self::Kubernetes | Self::Other)
     }
 
     /// Returns `true` if the build command supports the `--pull` flag.
     #[must_use]
     pub const fn supports_pull_flag(&self) -> bool {
-        !matches!(self, Self::Nerdctl | Self::Other)
+        !matches!(self, Self::Nerdctl | Self::Kubernetes | Self::Other)
     }
 
     /// Returns `true` if the build command supports the `--cache-from type=` key.
@@ -67,37 +82,142 @@ pub struct Engine {
     pub os: Option<ContainerOs>,
     pub is_remote: bool,
     pub is_rootless: bool,
+    /// The engine runs nested inside another container with cgroup v2,
+    /// such as a docker-in-docker CI runner.
+    pub is_nested: bool,
+    /// The docker context or podman connection the engine talks to.
+    pub context: Option<String>,
+}
+
+/// Errors of registries for images which require authentication, or which
+/// don't exist, in lowercase.
+const IMAGE_ACCESS_ERRORS: &[&str] = &[
+    "unauthorized",
+    "authentication required",
+    "no basic auth credentials",
+    "pull access denied",
+    "requested access to the resource is denied",
+    "denied:",
+    "manifest unknown",
+    "name unknown",
+    "repository does not exist",
+];
+
+/// Returns `true` if the output of a failed pull means that the registry
+/// denied access to the image, or that the image does not exist.
+pub(crate) fn is_image_access_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    IMAGE_ACCESS_ERRORS
+        .iter()
+        .any(|error| stderr.contains(error))
+}
+
+/// The registry of the image reference `image`, `docker.io` if it has none.
+fn image_registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+        _ => "docker.io",
+    }
 }
 
 impl Engine {
     pub const CROSS_CONTAINER_ENGINE_NO_BUILDKIT_ENV: &'static str =
         "CROSS_CONTAINER_ENGINE_NO_BUILDKIT";
+    pub const CROSS_CONTAINER_CONTEXT_ENV: &'static str = "CROSS_CONTAINER_CONTEXT";
     pub fn new(
         in_docker: Option<bool>,
         is_remote: Option<bool>,
+        context: Option<String>,
+        cache_setup: bool,
         msg_info: &mut MessageInfo,
     ) -> Result<Engine> {
         #[allow(clippy::map_err_ignore)]
         let path = get_container_engine()
             .map_err(|_| eyre::eyre!("no container engine found"))
             .with_suggestion(|| "is docker or podman installed?")?;
-        Self::from_path(path, in_docker, is_remote, msg_info)
+        Self::from_path(path, in_docker, is_remote, context, cache_setup, msg_info)
     }
 
+    /// Creates the container engine `name`, such as `docker` or `podman`,
+    /// falling back to the default engine if no name is provided.
+    pub fn from_name(
+        name: Option<&str>,
+        in_docker: Option<bool>,
+        is_remote: Option<bool>,
+        context: Option<String>,
+        cache_setup: bool,
+        msg_info: &mut MessageInfo,
+    ) -> Result<Engine> {
+        match name {
+            Some(name) => {
+                let path = which::which(engine_binary(name))
+                    .wrap_err_with(|| format!("container engine `{name}` not found"))?;
+                Self::from_path(path, in_docker, is_remote, context, cache_setup, msg_info)
+            }
+            None => Self::new(in_docker, is_remote, context, cache_setup, msg_info),
+        }
+    }
+
+    /// Creates the container engine at `path`. `CROSS_CONTAINER_CONTEXT`
+    /// has precedence over the configured `context`. Without `cache_setup`,
+    /// such as with `--no-cache-setup`, the info of the engine is queried
+    /// again instead of using its setup snapshot.
     pub fn from_path(
         path: PathBuf,
         in_docker: Option<bool>,
         is_remote: Option<bool>,
+        context: Option<String>,
+        cache_setup: bool,
         msg_info: &mut MessageInfo,
     ) -> Result<Engine> {
-        let in_docker = match in_docker {
-            Some(v) => v,
+        let explicit_in_docker = match in_docker {
+            Some(v) => Some(v),
             None => Self::in_docker(msg_info)?,
         };
-        let (kind, arch, os) = get_engine_info(&path, msg_info)?;
-        let is_rootless = is_rootless(kind).unwrap_or_else(|| is_docker_rootless(&path, msg_info));
+        let in_docker = explicit_in_docker.unwrap_or_default();
+        let context = env::var(Self::CROSS_CONTAINER_CONTEXT_ENV)
+            .ok()
+            .or(context)
+            .filter(|c| !c.is_empty());
+        let cache = EngineInfoCache::new(&path, context.as_deref(), cache_setup, msg_info)?;
+        let mut info = match cache.get() {
+            Some(info) => {
+                msg_info.debug(format_args!("using the cached info of engine {path:?}"))?;
+                info
+            }
+            None => EngineInfo::new(get_engine_info(&path, context.as_deref(), msg_info)?),
+        };
+        let EngineInfo { kind, arch, os, .. } = info.clone();
+        if let Some(context) = &context {
+            if context_flag(kind).is_none() {
+                msg_info.warn(format_args!(
+                    "container context `{context}` is ignored, since it's only supported by docker and podman."
+                ))?;
+            }
+            msg_info.debug(format_args!("using container context `{context}`"))?;
+        }
+        if kind == EngineType::Kubernetes {
+            // the build runs in a pod, so there is no local daemon to inspect
+            cache.put(&info, msg_info)?;
+            return Ok(Engine {
+                path,
+                kind,
+                in_docker,
+                arch,
+                os,
+                is_remote: true,
+                is_rootless: true,
+                is_nested: false,
+                context,
+            });
+        }
+        let is_rootless = is_rootless(kind).unwrap_or_else(|| {
+            *info
+                .is_rootless
+                .get_or_insert_with(|| is_docker_rootless(&path, context.as_deref(), msg_info))
+        });
         let is_remote = is_remote.unwrap_or_else(Self::is_remote);
-        Ok(Engine {
+        let mut engine = Engine {
             path,
             kind,
             in_docker,
@@ -105,7 +225,127 @@ impl Engine {
             os,
             is_remote,
             is_rootless,
-        })
+            is_nested: false,
+            context,
+        };
+        if explicit_in_docker.is_none() && !is_remote && in_container(Path::exists) {
+            engine.detect_container_setup(msg_info)?;
+        }
+        // only checked once the setup is known, since a sibling container
+        // uses the engine of the host, which is not nested.
+        engine.is_nested = is_nested(engine.in_docker, Path::exists).unwrap_or_else(|| {
+            *info.uses_cgroup_v2.get_or_insert_with(|| {
+                uses_cgroup_v2(&engine.path, kind, engine.context.as_deref(), msg_info)
+            })
+        });
+        cache.put(&info, msg_info)?;
+        Ok(engine)
+    }
+
+    /// The name of the binary of the engine, such as `docker` or `podman`.
+    pub fn name(&self) -> std::borrow::Cow<'_, str> {
+        self.path
+            .file_stem()
+            .map_or_else(|| DOCKER.into(), |s| s.to_string_lossy())
+    }
+
+    /// The error for `image`, which could not be pulled with `stderr`. If the
+    /// registry denied access, the suggestions point at logging in to the
+    /// registry and at the name of the image.
+    pub(crate) fn pull_error(&self, image: &str, stderr: &str) -> eyre::Report {
+        let stderr = stderr.trim().to_owned();
+        if !is_image_access_error(&stderr) {
+            return eyre::eyre!("could not pull image `{image}`")
+                .with_section(|| stderr.header("Stderr:"));
+        }
+        let registry = image_registry(image);
+        let binary = self.name();
+        let login = format!("{binary} login {registry}");
+        eyre::eyre!("access to image `{image}` was denied, or the image does not exist")
+            .with_section(|| stderr.header("Registry response:"))
+            .with_suggestion(|| {
+                format!("if the image is private, log in to the registry with `{login}`")
+            })
+            .with_suggestion(|| {
+                "otherwise, check the name and tag of the image, such as `image` in `Cross.toml`"
+            })
+    }
+
+    /// Pull `image` according to the [`PullPolicy`], before the container is
+    /// run. The progress is written to the terminal, or to the container log
+    /// with a spinner, while the errors are turned into a
+    /// [`pull_error`](Self::pull_error).
+    ///
+    /// Pulls failing with a transient error are retried: the engine keeps the
+    /// layers it already downloaded, so the retry resumes the pull.
+    pub(crate) fn pull_image(&self, image: &Image, msg_info: &mut MessageInfo) -> Result<()> {
+        let policy = PullPolicy::from_env()?;
+        if policy != PullPolicy::Always {
+            let exists = self
+                .subcommand("image")
+                .args(["inspect", &image.name])
+                .run_and_get_output(msg_info)?
+                .status
+                .success();
+            match (exists, policy) {
+                (true, _) => return Ok(()),
+                (false, PullPolicy::Never) => {
+                    return Err(eyre::eyre!("image `{image}` is not in local storage"))
+                        .with_suggestion(|| {
+                            format!(
+                                "pull the image with `{} pull {image}`, or unset `{}`",
+                                self.name(),
+                                PullPolicy::CROSS_PULL_POLICY_ENV
+                            )
+                        })
+                }
+                (false, _) => (),
+            }
+        }
+
+        let retries = pull_retries()?;
+        let _spinner = match msg_info.has_container_log() {
+            true => Some(msg_info.spinner(format_args!("pulling image `{image}`"))?),
+            false => None,
+        };
+        let output = self.pull_with_retries(&image.name, retries, msg_info, |msg_info| {
+            let mut pull = self.subcommand("pull");
+            image.platform.specify_platform(self, &mut pull);
+            // the progress must not mix with the output of cargo on stdout.
+            pull.arg(&image.name).stdout(Stdio::from(io::stderr()));
+            pull.container_output(msg_info)?.stderr(Stdio::piped());
+            Ok(pull)
+        })?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(self.pull_error(&image.name, &String::from_utf8_lossy(&output.stderr))),
+        }
+    }
+
+    /// Run the pull of `image` created by `pull`, retried up to `retries`
+    /// times while it fails with a transient error. Returns the output of the
+    /// last attempt, which may have failed.
+    pub fn pull_with_retries(
+        &self,
+        image: &str,
+        retries: u32,
+        msg_info: &mut MessageInfo,
+        mut pull: impl FnMut(&mut MessageInfo) -> Result<Command>,
+    ) -> Result<Output> {
+        let mut attempt = 0;
+        loop {
+            let output = pull(msg_info)?.run_and_get_output(msg_info)?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if output.status.success() || attempt == retries || !is_transient_engine_error(&stderr)
+            {
+                return Ok(output);
+            }
+            attempt += 1;
+            msg_info.note(format_args!(
+                "pulling image `{image}` failed with a transient error, retrying ({attempt}/{retries})."
+            ))?;
+            std::thread::sleep(RETRY_DELAY * attempt);
+        }
     }
 
     #[must_use]
@@ -113,7 +353,10 @@ impl Engine {
         self.is_remote && self.kind == EngineType::Podman
     }
 
-    pub fn in_docker(msg_info: &mut MessageInfo) -> Result<bool> {
+    /// If cross runs in a container sharing the engine of its host, or
+    /// `None` if neither `CROSS_CONTAINER_IN_CONTAINER` nor
+    /// `CROSS_DOCKER_IN_DOCKER` is set, to detect it instead.
+    pub fn in_docker(msg_info: &mut MessageInfo) -> Result<Option<bool>> {
         Ok(
             if let Ok(value) = env::var("CROSS_CONTAINER_IN_CONTAINER") {
                 if env::var("CROSS_DOCKER_IN_DOCKER").is_ok() {
@@ -121,12 +364,12 @@ impl Engine {
                         "using both `CROSS_CONTAINER_IN_CONTAINER` and `CROSS_DOCKER_IN_DOCKER`.",
                     )?;
                 }
-                bool_from_envvar(&value)
+                Some(bool_from_envvar(&value))
             } else if let Ok(value) = env::var("CROSS_DOCKER_IN_DOCKER") {
                 // FIXME: remove this when we deprecate CROSS_DOCKER_IN_DOCKER.
-                bool_from_envvar(&value)
+                Some(bool_from_envvar(&value))
             } else {
-                false
+                None
             },
         )
     }
@@ -138,6 +381,32 @@ impl Engine {
             .unwrap_or_default()
     }
 
+    /// Check that the engine is podman, and that its connection was set up
+    /// with `podman system connection add`.
+    pub fn check_podman_connection(&self, msg_info: &mut MessageInfo) -> Result<()> {
+        if !matches!(self.kind, EngineType::Podman | EngineType::PodmanRemote) {
+            eyre::bail!(
+                "`build.podman-connection` requires podman, but the container engine is {:?}",
+                self.path
+            );
+        }
+        let Some(connection) = &self.context else {
+            return Ok(());
+        };
+        let stdout = engine_command(&self.path, self.kind, None)
+            .args(["system", "connection", "list", "--format", "json"])
+            .run_and_get_stdout(msg_info)?;
+        if !podman_connection_names(&stdout)?.contains(connection) {
+            return Err(eyre::eyre!(
+                "podman connection `{connection}` does not exist"
+            ))
+            .with_suggestion(|| {
+                format!("add it with `podman system connection add {connection} <destination>`")
+            });
+        }
+        Ok(())
+    }
+
     #[must_use]
     pub fn has_buildkit() -> bool {
         !env::var(Self::CROSS_CONTAINER_ENGINE_NO_BUILDKIT_ENV)
@@ -156,9 +425,44 @@ fn is_rootless(kind: EngineType) -> Option<bool> {
         .or_else(|| (!kind.is_docker()).then_some(true))
 }
 
+/// The names in the output of `podman system connection list --format json`.
+fn podman_connection_names(json: &str) -> Result<Vec<String>> {
+    #[derive(serde::Deserialize)]
+    struct Connection {
+        #[serde(rename = "Name")]
+        name: String,
+    }
+
+    // older versions of podman print nothing without connections.
+    if json.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let connections: Vec<Connection> =
+        serde_json::from_str(json).wrap_err("could not parse the podman connections")?;
+    Ok(connections.into_iter().map(|c| c.name).collect())
+}
+
+/// The global flag selecting the docker context or podman connection.
+fn context_flag(kind: EngineType) -> Option<&'static str> {
+    match kind {
+        EngineType::Docker => Some("--context"),
+        EngineType::Podman | EngineType::PodmanRemote => Some("--connection"),
+        _ => None,
+    }
+}
+
+/// A command for the engine `ce`, talking to the daemon of `context`.
+pub(crate) fn engine_command(ce: &Path, kind: EngineType, context: Option<&str>) -> Command {
+    let mut command = Command::new(ce);
+    if let (Some(flag), Some(context)) = (context_flag(kind), context) {
+        command.args([flag, context]);
+    }
+    command
+}
+
 #[must_use]
-fn is_docker_rootless(ce: &Path, msg_info: &mut MessageInfo) -> bool {
-    let mut cmd = Command::new(ce);
+fn is_docker_rootless(ce: &Path, context: Option<&str>, msg_info: &mut MessageInfo) -> bool {
+    let mut cmd = engine_command(ce, EngineType::Docker, context);
     cmd.args(["info", "-f", "{{.SecurityOptions}}"])
         .run_and_get_output(msg_info)
         .ok()
@@ -171,6 +475,104 @@ fn is_docker_rootless(ce: &Path, msg_info: &mut MessageInfo) -> bool {
         .unwrap_or_default()
 }
 
+/// Files created by container engines in the root of a container.
+const CONTAINER_MARKERS: &[&str] = &["/.dockerenv", "/run/.containerenv"];
+
+/// If cross runs in a container, from the files created by the engines.
+pub(crate) fn in_container(exists: impl Fn(&Path) -> bool) -> bool {
+    CONTAINER_MARKERS.iter().any(|p| exists(Path::new(p)))
+}
+
+/// If the daemon of the engine is reached over the network, such as a
+/// docker-in-docker service, from `DOCKER_HOST` or `CONTAINER_HOST`.
+fn is_network_host(host: &str) -> bool {
+    ["tcp://", "ssh://", "http://", "https://"]
+        .iter()
+        .any(|scheme| host.starts_with(scheme))
+}
+
+pub(crate) fn uses_network_host() -> bool {
+    ["DOCKER_HOST", "CONTAINER_HOST"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .any(|host| is_network_host(&host))
+}
+
+/// The default sockets of the engines, which are mounted into the container
+/// running cross to share the engine of the host.
+const ENGINE_SOCKETS: &[&str] = &[
+    "/var/run/docker.sock",
+    "/run/docker.sock",
+    "/run/podman/podman.sock",
+];
+
+/// The sockets the engine is reached through, from the `unix://` hosts of
+/// `DOCKER_HOST` or `CONTAINER_HOST`, or the default ones.
+pub(crate) fn engine_sockets() -> Vec<PathBuf> {
+    let hosts: Vec<PathBuf> = ["DOCKER_HOST", "CONTAINER_HOST"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .filter_map(|host| host.strip_prefix("unix://").map(PathBuf::from))
+        .collect();
+    match hosts.is_empty() {
+        true => ENGINE_SOCKETS.iter().map(PathBuf::from).collect(),
+        false => hosts,
+    }
+}
+
+/// If one of `sockets` is a mount point in `mountinfo`, such as the socket of
+/// the engine of the host mounted with `-v /var/run/docker.sock:/var/run/docker.sock`.
+pub(crate) fn mounts_engine_socket(mountinfo: &str, sockets: &[PathBuf]) -> bool {
+    // `/var/run` is usually a link to `/run`, where the socket is mounted
+    let sockets: Vec<PathBuf> = sockets
+        .iter()
+        .flat_map(|socket| {
+            [
+                socket.clone(),
+                file::canonicalize(socket).unwrap_or_default(),
+            ]
+        })
+        .collect();
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .any(|mount| sockets.iter().any(|socket| socket == Path::new(mount)))
+}
+
+/// If the engine runs nested in the container running cross, such as with
+/// docker-in-docker, or `None` if it depends on the cgroup version. A sibling
+/// container, with `in_docker`, shares the engine of the host instead.
+fn is_nested(in_docker: bool, exists: impl Fn(&Path) -> bool) -> Option<bool> {
+    env::var("CROSS_CONTAINER_NESTED")
+        .ok()
+        .and_then(|s| match s.as_ref() {
+            "auto" => None,
+            b => Some(bool_from_envvar(b)),
+        })
+        .or_else(|| (in_docker || !in_container(exists)).then_some(false))
+}
+
+#[must_use]
+fn uses_cgroup_v2(
+    ce: &Path,
+    kind: EngineType,
+    context: Option<&str>,
+    msg_info: &mut MessageInfo,
+) -> bool {
+    let format = if kind.is_podman() {
+        "{{.Host.CgroupsVersion}}"
+    } else {
+        "{{.CgroupVersion}}"
+    };
+    let mut cmd = engine_command(ce, kind, context);
+    cmd.args(["info", "-f", format])
+        .run_and_get_output(msg_info)
+        .ok()
+        .and_then(|cmd| cmd.stdout().ok())
+        .map(|out| matches!(out.trim(), "2" | "v2"))
+        .unwrap_or_default()
+}
+
 #[test]
 fn various_is_rootless_configs() {
     let var = "CROSS_ROOTLESS_CONTAINER_ENGINE";
@@ -209,10 +611,215 @@ fn various_is_rootless_configs() {
     }
 }
 
+#[test]
+fn podman_connections() -> Result<()> {
+    let json = r#"[
+        {"Name": "builder", "URI": "ssh://core@10.0.0.2:22/run/podman/podman.sock", "Default": true},
+        {"Name": "arm64", "URI": "ssh://core@10.0.0.3:22/run/podman/podman.sock", "Default": false}
+    ]"#;
+    assert_eq!(podman_connection_names(json)?, ["builder", "arm64"]);
+    assert!(podman_connection_names("")?.is_empty());
+    assert!(podman_connection_names("builder").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn engine_command_context() {
+    let args = |kind, context| {
+        let cmd = engine_command(Path::new("engine"), kind, context);
+        cmd.get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        args(EngineType::Docker, Some("colima")),
+        ["--context", "colima"]
+    );
+    assert_eq!(
+        args(EngineType::PodmanRemote, Some("remote")),
+        ["--connection", "remote"]
+    );
+    assert!(args(EngineType::Docker, None).is_empty());
+    assert!(args(EngineType::Nerdctl, Some("colima")).is_empty());
+}
+
+#[test]
+fn various_is_nested_configs() {
+    let var = "CROSS_CONTAINER_NESTED";
+    let old = env::var(var);
+    env::remove_var(var);
+
+    let host = |_: &Path| false;
+    let container = |p: &Path| p == Path::new("/.dockerenv");
+
+    assert_eq!(is_nested(false, host), Some(false));
+    assert_eq!(is_nested(true, host), Some(false));
+    assert_eq!(is_nested(true, container), Some(false));
+    assert_eq!(is_nested(false, container), None);
+
+    env::set_var(var, "0");
+    assert_eq!(is_nested(true, container), Some(false));
+
+    env::set_var(var, "1");
+    assert_eq!(is_nested(false, host), Some(true));
+
+    env::set_var(var, "auto");
+    assert_eq!(is_nested(false, host), Some(false));
+    assert_eq!(is_nested(false, container), None);
+
+    match old {
+        Ok(v) => env::set_var(var, v),
+        Err(_) => env::remove_var(var),
+    }
+}
+
+#[test]
+fn engine_socket_mounts() {
+    let mountinfo = "\
+1425 1297 0:94 / / rw,relatime master:392 - overlay overlay rw
+1437 1425 0:22 /docker.sock /run/docker.sock rw,nosuid,nodev - tmpfs tmpfs rw
+1438 1425 259:2 /home/user/project /project rw,relatime - ext4 /dev/nvme0n1p2 rw";
+    let sockets = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+    assert!(mounts_engine_socket(
+        mountinfo,
+        &sockets(&["/run/docker.sock"])
+    ));
+    assert!(!mounts_engine_socket(
+        mountinfo,
+        &sockets(&["/run/podman/podman.sock"])
+    ));
+    // without the socket, the engine cannot be the one of the host
+    let without = mountinfo.replace("/run/docker.sock", "/run/other");
+    assert!(!mounts_engine_socket(&without, &sockets(ENGINE_SOCKETS)));
+}
+
+#[test]
+fn network_hosts() {
+    assert!(is_network_host("tcp://docker:2375"));
+    assert!(is_network_host("ssh://user@builder"));
+    assert!(!is_network_host("unix:///var/run/docker.sock"));
+    assert!(!is_network_host(""));
+}
+
+pub const CROSS_ENGINE_INFO_TTL_ENV: &str = "CROSS_ENGINE_INFO_TTL";
+
+/// What the engine reported about itself and its daemon. The daemon is only
+/// queried when it isn't configured, so those fields are `None` until then.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct EngineInfo {
+    kind: EngineType,
+    arch: Option<Architecture>,
+    os: Option<ContainerOs>,
+    is_rootless: Option<bool>,
+    uses_cgroup_v2: Option<bool>,
+}
+
+impl EngineInfo {
+    fn new((kind, arch, os): (EngineType, Option<Architecture>, Option<ContainerOs>)) -> Self {
+        Self {
+            kind,
+            arch,
+            os,
+            is_rootless: None,
+            uses_cgroup_v2: None,
+        }
+    }
+}
+
+/// The info of the engines created during this invocation.
+static ENGINE_INFO: Mutex<BTreeMap<String, EngineInfo>> = Mutex::new(BTreeMap::new());
+
+/// The variables selecting the daemon the engines talk to.
+const DAEMON_ENVVARS: &[&str] = &[
+    "DOCKER_HOST",
+    "DOCKER_CONTEXT",
+    "CONTAINER_HOST",
+    "CONTAINER_CONNECTION",
+];
+
+/// The key of the info of the engine `ce`, with everything selecting the
+/// daemon it talks to.
+pub(crate) fn engine_info_key(ce: &Path, context: Option<&str>) -> String {
+    let mut key = format!("engine\n{ce:?}\n{context:?}\n");
+    for var in DAEMON_ENVVARS {
+        key.push_str(&format!("{var}={:?}\n", env::var(var).ok()));
+    }
+    key
+}
+
+/// The files the info of the engine `ce` is evaluated from: the engine, and
+/// the configurations with the current docker context and podman connection.
+fn engine_info_paths(ce: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![ce.to_owned()];
+    let docker_config = env::var_os("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| home::home_dir().map(|home| home.join(".docker")));
+    paths.extend(docker_config.map(|dir| dir.join("config.json")));
+    if let Some(dirs) = directories::BaseDirs::new() {
+        let containers = dirs.config_dir().join("containers");
+        paths.push(containers.join("containers.conf"));
+        paths.push(containers.join("podman-connections.json"));
+    }
+    paths
+}
+
+/// The info of an engine, cached in memory during this invocation, and in a
+/// setup snapshot between invocations for `CROSS_ENGINE_INFO_TTL`. The
+/// snapshots are best-effort, and never fail creating the engine.
+struct EngineInfoCache {
+    key: String,
+    paths: Vec<PathBuf>,
+    snapshots: SetupCache,
+}
+
+impl EngineInfoCache {
+    fn new(
+        ce: &Path,
+        context: Option<&str>,
+        enabled: bool,
+        msg_info: &mut MessageInfo,
+    ) -> Result<Self> {
+        let snapshots = SetupCache::with_ttl_env(enabled, CROSS_ENGINE_INFO_TTL_ENV);
+        Ok(Self {
+            key: engine_info_key(ce, context),
+            paths: engine_info_paths(ce),
+            snapshots: SetupCache::or_disabled(snapshots, msg_info)?,
+        })
+    }
+
+    fn get(&self) -> Option<EngineInfo> {
+        let cached = ENGINE_INFO.lock().ok()?.get(&self.key).cloned();
+        if cached.is_some() {
+            return cached;
+        }
+        let info = self.snapshots.get::<EngineInfo>(&self.key)?;
+        ENGINE_INFO
+            .lock()
+            .ok()?
+            .insert(self.key.clone(), info.clone());
+        Some(info)
+    }
+
+    /// Cache `info`, unless it's unchanged.
+    fn put(&self, info: &EngineInfo, msg_info: &mut MessageInfo) -> Result<()> {
+        if let Ok(mut memory) = ENGINE_INFO.lock() {
+            if memory.get(&self.key) == Some(info) {
+                return Ok(());
+            }
+            memory.insert(self.key.clone(), info.clone());
+        }
+        self.snapshots.put(&self.key, &self.paths, info, msg_info)
+    }
+}
+
 // determine if the container engine is docker. this fixes issues with
 // any aliases (#530), and doesn't fail if an executable suffix exists.
 fn get_engine_info(
     ce: &Path,
+    context: Option<&str>,
     msg_info: &mut MessageInfo,
 ) -> Result<(EngineType, Option<Architecture>, Option<ContainerOs>)> {
     let stdout_help = Command::new(ce)
@@ -220,6 +827,11 @@ fn get_engine_info(
         .run_and_get_stdout(msg_info)?
         .to_lowercase();
 
+    // the pods are scheduled by the cluster, so the platform is unknown
+    if stdout_help.contains("kubectl") {
+        return Ok((EngineType::Kubernetes, None, None));
+    }
+
     let kind = if stdout_help.contains("podman-remote") {
         EngineType::PodmanRemote
     } else if stdout_help.contains("podman") {
@@ -237,6 +849,8 @@ fn get_engine_info(
     //   can't evaluate field Arch in type *define.Version
     let os_arch_server = engine_info(
         ce,
+        kind,
+        context,
         &["version", "-f", "{{ .Server.Os }},,,{{ .Server.Arch }}"],
         ",,,",
         msg_info,
@@ -246,9 +860,9 @@ fn get_engine_info(
         Ok(Some(os_arch)) => (Ok(Some(os_arch)), None),
         result => {
             if kind.is_podman() {
-                (get_podman_info(ce, msg_info), result.err())
+                (get_podman_info(ce, kind, context, msg_info), result.err())
             } else {
-                (get_custom_info(ce, msg_info), result.err())
+                (get_custom_info(ce, kind, context, msg_info), result.err())
             }
         }
     };
@@ -285,11 +899,13 @@ impl EngineInfoError {
 /// Get engine info
 fn engine_info(
     ce: &Path,
+    kind: EngineType,
+    context: Option<&str>,
     args: &[&str],
     sep: &str,
     msg_info: &mut MessageInfo,
 ) -> Result<Option<(ContainerOs, Architecture)>, EngineInfoError> {
-    let mut cmd = Command::new(ce);
+    let mut cmd = engine_command(ce, kind, context);
     cmd.args(args);
     let out = cmd
         .run_and_get_output(msg_info)
@@ -308,27 +924,192 @@ fn engine_info(
 
 fn get_podman_info(
     ce: &Path,
+    kind: EngineType,
+    context: Option<&str>,
     msg_info: &mut MessageInfo,
 ) -> Result<Option<(ContainerOs, Architecture)>, EngineInfoError> {
-    engine_info(ce, &["info", "-f", "{{ .Version.OsArch }}"], "/", msg_info)
+    engine_info(
+        ce,
+        kind,
+        context,
+        &["info", "-f", "{{ .Version.OsArch }}"],
+        "/",
+        msg_info,
+    )
 }
 
 fn get_custom_info(
     ce: &Path,
+    kind: EngineType,
+    context: Option<&str>,
     msg_info: &mut MessageInfo,
 ) -> Result<Option<(ContainerOs, Architecture)>, EngineInfoError> {
     engine_info(
         ce,
+        kind,
+        context,
         &["version", "-f", "{{ .Client.Os }},,,{{ .Client.Arch }}"],
         ",,,",
         msg_info,
     )
 }
 
+/// When images are pulled before the container is run, from
+/// `CROSS_PULL_POLICY`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PullPolicy {
+    /// Always pull the image, to update its tag.
+    Always,
+    /// Pull the image if it's not in local storage.
+    #[default]
+    Missing,
+    /// Never pull the image, failing if it's not in local storage.
+    Never,
+}
+
+impl PullPolicy {
+    pub const CROSS_PULL_POLICY_ENV: &'static str = "CROSS_PULL_POLICY";
+
+    pub fn from_env() -> Result<Self> {
+        match env::var(Self::CROSS_PULL_POLICY_ENV) {
+            Ok(policy) if !policy.is_empty() => policy.parse(),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+impl std::str::FromStr for PullPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(Self::Always),
+            "missing" => Ok(Self::Missing),
+            "never" => Ok(Self::Never),
+            _ => eyre::bail!(
+                "invalid `{}` `{s}`, expected `always`, `missing` or `never`",
+                Self::CROSS_PULL_POLICY_ENV
+            ),
+        }
+    }
+}
+
+/// The number of times to retry a pull which failed with a transient error,
+/// from `CROSS_PULL_RETRIES`.
+fn pull_retries() -> Result<u32> {
+    match env::var("CROSS_PULL_RETRIES") {
+        Ok(retries) => retries
+            .parse()
+            .wrap_err_with(|| format!("invalid CROSS_PULL_RETRIES `{retries}`")),
+        Err(_) => Ok(3),
+    }
+}
+
+/// The binary for the engine `name`, mapping `kubernetes` to `kubectl`.
+fn engine_binary(name: &str) -> &str {
+    match name {
+        KUBERNETES => KUBECTL,
+        name => name,
+    }
+}
+
 pub fn get_container_engine() -> Result<PathBuf, which::Error> {
     if let Ok(ce) = env::var("CROSS_CONTAINER_ENGINE") {
-        which::which(ce)
+        which::which(engine_binary(&ce))
     } else {
         which::which(DOCKER).or_else(|_| which::which(PODMAN))
     }
 }
+
+/// The paths of every container engine found on the `PATH`, such as both
+/// docker and podman, without the ones linking to the same binary.
+pub fn get_container_engines() -> Vec<PathBuf> {
+    let mut engines: Vec<PathBuf> = vec![];
+    for path in [DOCKER, PODMAN, NERDCTL]
+        .into_iter()
+        .filter_map(|name| which::which(name).ok())
+    {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if !engines
+            .iter()
+            .any(|e| e.canonicalize().is_ok_and(|e| e == canonical))
+        {
+            engines.push(path);
+        }
+    }
+    engines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pull_policy() {
+        assert_eq!(
+            "always".parse::<PullPolicy>().ok(),
+            Some(PullPolicy::Always)
+        );
+        assert_eq!(
+            "missing".parse::<PullPolicy>().ok(),
+            Some(PullPolicy::Missing)
+        );
+        assert_eq!("never".parse::<PullPolicy>().ok(), Some(PullPolicy::Never));
+        assert!("if-not-present".parse::<PullPolicy>().is_err());
+        assert_eq!(PullPolicy::default(), PullPolicy::Missing);
+    }
+
+    #[test]
+    fn image_access_errors() {
+        assert!(is_image_access_error(
+            "Error response from daemon: Head \"https://ghcr.io/v2/org/private/manifests/latest\": unauthorized"
+        ));
+        assert!(is_image_access_error(
+            "Error response from daemon: pull access denied for my/image, repository does not exist or may require 'docker login'"
+        ));
+        assert!(is_image_access_error(
+            "Error: initializing source docker://quay.io/org/image:v1: reading manifest v1 in quay.io/org/image: manifest unknown"
+        ));
+        assert!(!is_image_access_error(
+            "Error response from daemon: Get \"https://ghcr.io/v2/\": net/http: TLS handshake timeout"
+        ));
+
+        assert_eq!(
+            image_registry("ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main"),
+            "ghcr.io"
+        );
+        assert_eq!(image_registry("localhost:5000/image"), "localhost:5000");
+        assert_eq!(image_registry("my/image"), "docker.io");
+        assert_eq!(image_registry("ubuntu:22.04"), "docker.io");
+    }
+
+    #[test]
+    fn engine_info_cache() -> Result<()> {
+        let ce = Path::new("/nonexistent/engine-info-cache/docker");
+        let cache = |context| EngineInfoCache {
+            key: engine_info_key(ce, context),
+            paths: vec![],
+            snapshots: SetupCache::disabled(),
+        };
+        let msg_info = &mut MessageInfo::default();
+        let default = cache(None);
+        let remote = cache(Some("remote"));
+        assert_ne!(default.key, remote.key);
+        assert_eq!(default.get(), None);
+
+        let mut info = EngineInfo::new((EngineType::Docker, Some(Architecture::Arm64), None));
+        default.put(&info, msg_info)?;
+        assert_eq!(default.get(), Some(info.clone()));
+        // another context talks to another daemon
+        assert_eq!(remote.get(), None);
+
+        // the daemon is queried later, updating the cached info
+        info.is_rootless = Some(true);
+        default.put(&info, msg_info)?;
+        assert_eq!(default.get().and_then(|i| i.is_rootless), Some(true));
+
+        let json = serde_json::to_string(&info)?;
+        assert_eq!(serde_json::from_str::<EngineInfo>(&json)?, info);
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! The `.crossignore` file and `build.copy-exclude`: patterns of the files
+//! which are not copied into the data volume of remote engines.
+//!
+//! The patterns use the syntax of `.gitignore`, and are matched with the
+//! `ignore` crate, so they behave like they do for git.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::errors::*;
+
+pub(crate) const IGNORE_FILE: &str = ".crossignore";
+
+/// The patterns of `.crossignore` and `build.copy-exclude`, the last
+/// matching pattern deciding if a path is ignored.
+#[derive(Debug)]
+pub(crate) struct IgnoreRules {
+    gitignore: Gitignore,
+}
+
+impl Default for IgnoreRules {
+    fn default() -> Self {
+        IgnoreRules {
+            gitignore: Gitignore::empty(),
+        }
+    }
+}
+
+impl IgnoreRules {
+    /// The patterns of `lines`, relative to `root`.
+    pub(crate) fn new<'a>(root: &Path, lines: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(root);
+        for line in lines {
+            builder
+                .add_line(None, line)
+                .wrap_err_with(|| format!("invalid ignore pattern `{line}`"))?;
+        }
+        Ok(IgnoreRules {
+            gitignore: builder.build()?,
+        })
+    }
+
+    /// Read the `.crossignore` file of `root`, if any, followed by `extra`.
+    pub(crate) fn read(root: &Path, extra: &[String]) -> Result<Self> {
+        let path = root.join(IGNORE_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).wrap_err_with(|| format!("could not read {path:?}")),
+        };
+        IgnoreRules::new(
+            root,
+            contents.lines().chain(extra.iter().map(String::as_str)),
+        )
+        .wrap_err_with(|| format!("when reading {path:?} and `build.copy-exclude`"))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.gitignore.is_empty()
+    }
+
+    /// `relpath` is relative to the root.
+    pub(crate) fn is_ignored(&self, relpath: &Path, is_dir: bool) -> bool {
+        self.gitignore.matched(relpath, is_dir).is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_patterns() -> Result<()> {
+        let rules = IgnoreRules::new(
+            Path::new("/project"),
+            [
+                "# comment",
+                "node_modules/",
+                "*.bin",
+                "!keep.bin",
+                "/datasets",
+                "docs/**/*.png",
+                "fixtures/**",
+                "[a-c]x.log",
+                "\\#literal",
+            ],
+        )?;
+        let ignored = |path: &str, is_dir: bool| rules.is_ignored(Path::new(path), is_dir);
+
+        // unanchored patterns match at any depth
+        assert!(ignored("node_modules", true));
+        assert!(ignored("web/app/node_modules", true));
+        assert!(!ignored("web/node_modules", false));
+        assert!(ignored("assets/model.bin", false));
+        assert!(!ignored("assets/keep.bin", false));
+        assert!(ignored("logs/bx.log", false));
+        assert!(!ignored("logs/dx.log", false));
+        assert!(ignored("#literal", false));
+
+        // anchored patterns are relative to the root
+        assert!(ignored("datasets", true));
+        assert!(!ignored("crates/datasets", true));
+        assert!(ignored("docs/logo.png", false));
+        assert!(ignored("docs/guide/img/logo.png", false));
+        assert!(!ignored("src/logo.png", false));
+        assert!(ignored("fixtures/a/b.json", false));
+        assert!(!ignored("fixtures", true));
+
+        assert!(!ignored("src/main.rs", false));
+        assert!(IgnoreRules::new(Path::new("/project"), ["", "# only comments"])?.is_empty());
+        assert!(IgnoreRules::new(Path::new("/project"), ["{a"]).is_err());
+
+        Ok(())
+    }
+}
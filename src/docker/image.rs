@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     docker::{CROSS_IMAGE, DEFAULT_IMAGE_VERSION},
     errors::*,
+    extensions::CommandExt,
     shell::MessageInfo,
     TargetTriple,
 };
@@ -33,16 +34,26 @@ pub struct PossibleImage {
 }
 
 impl PossibleImage {
-    pub fn to_definite_with(&self, engine: &Engine, msg_info: &mut MessageInfo) -> Result<Image> {
+    /// Make a definite [`Image`], picking the platform best matching the
+    /// container engine, or `platform` if it was requested. If the image
+    /// is not available for the variant of a requested platform, another
+    /// variant of the same architecture is used.
+    pub fn to_definite_with(
+        &self,
+        engine: &Engine,
+        platform: Option<&ImagePlatform>,
+        msg_info: &mut MessageInfo,
+    ) -> Result<Image> {
         let ImageReference::Name(name) = self.reference.clone() else {
             eyre::bail!("cannot make definite Image from unqualified PossibleImage");
         };
 
-        if self.toolchain.is_empty() {
-            Ok(Image {
-                name,
-                platform: ImagePlatform::DEFAULT,
-            })
+        let platform = if let Some(platform) = platform {
+            // only inspect the manifest for requested platforms, since it
+            // needs the registry, which local images and offline builds lack.
+            resolve_platform(engine, &name, platform, msg_info)?
+        } else if self.toolchain.is_empty() {
+            ImagePlatform::DEFAULT
         } else {
             let platform = if self.toolchain.len() == 1 {
                 self.toolchain.first().expect("should contain at least one")
@@ -81,14 +92,125 @@ impl PossibleImage {
                     platform
                 }
             };
-            Ok(Image {
-                platform: platform.clone(),
-                name,
-            })
+            platform.clone()
+        };
+        Ok(Image { name, platform })
+    }
+}
+
+/// A platform available in the manifest list of an image.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ManifestPlatform {
+    pub architecture: String,
+    pub os: String,
+    pub variant: Option<String>,
+}
+
+impl ManifestPlatform {
+    fn matches(&self, platform: &ImagePlatform, check_variant: bool) -> bool {
+        self.os == platform.os.to_string()
+            && self.architecture == platform.architecture.to_string()
+            && (!check_variant || platform.variant.is_none() || self.variant == platform.variant)
+    }
+}
+
+impl std::fmt::Display for ManifestPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(variant) = &self.variant {
+            write!(f, "{}/{}/{variant}", self.os, self.architecture)
+        } else {
+            write!(f, "{}/{}", self.os, self.architecture)
         }
     }
 }
 
+/// Get the platforms available for a multi-arch image.
+///
+/// Returns `None` if the manifest could not be inspected, such as for
+/// local images, or if the image is not a manifest list.
+pub fn manifest_platforms(
+    engine: &Engine,
+    name: &str,
+    msg_info: &mut MessageInfo,
+) -> Option<Vec<ManifestPlatform>> {
+    #[derive(Deserialize)]
+    struct ManifestList {
+        manifests: Option<Vec<ManifestEntry>>,
+    }
+
+    #[derive(Deserialize)]
+    struct ManifestEntry {
+        platform: Option<ManifestPlatform>,
+    }
+
+    let output = engine
+        .subcommand("manifest")
+        .args(["inspect", name])
+        .run_and_get_output(msg_info)
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let list: ManifestList = serde_json::from_slice(&output.stdout).ok()?;
+    Some(
+        list.manifests?
+            .into_iter()
+            .filter_map(|m| m.platform)
+            // attestation manifests use an `unknown/unknown` platform
+            .filter(|p| p.os != "unknown")
+            .collect(),
+    )
+}
+
+/// The platform of `available` to use for `platform`: itself, or another
+/// variant of the same architecture, or `None` if there is none.
+fn select_variant(
+    available: &[ManifestPlatform],
+    platform: &ImagePlatform,
+) -> Option<ImagePlatform> {
+    if available.iter().any(|p| p.matches(platform, true)) {
+        return Some(platform.clone());
+    }
+    let fallback = available.iter().find(|p| p.matches(platform, false))?;
+    Some(ImagePlatform {
+        variant: fallback.variant.clone(),
+        ..platform.clone()
+    })
+}
+
+// check the requested platform is available for the image, falling back to
+// another variant of the same architecture if the exact platform is missing.
+fn resolve_platform(
+    engine: &Engine,
+    name: &str,
+    platform: &ImagePlatform,
+    msg_info: &mut MessageInfo,
+) -> Result<ImagePlatform> {
+    let Some(available) = manifest_platforms(engine, name, msg_info) else {
+        return Ok(platform.clone());
+    };
+    if let Some(selected) = select_variant(&available, platform) {
+        if selected != *platform {
+            msg_info.note(format_args!(
+                "image `{name}` is not available for `{}`, using `{}` instead.",
+                platform.docker_platform(),
+                selected.docker_platform()
+            ))?;
+        }
+        return Ok(selected);
+    }
+
+    eyre::bail!(
+        "image `{name}` is not available for platform `{}`, available platforms are: {}",
+        platform.docker_platform(),
+        available
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
 impl<T: AsRef<str>> From<T> for PossibleImage {
     fn from(s: T) -> Self {
         PossibleImage {
@@ -193,6 +315,38 @@ impl ImagePlatform {
     }
 }
 
+impl ImagePlatform {
+    /// Parse a platform override, such as `linux/arm64`, `linux/arm64=aarch64-unknown-linux-gnu`,
+    /// or a target triple.
+    ///
+    /// Without a toolchain, the toolchain is inferred for `linux/amd64` and `linux/arm64`.
+    pub fn from_override(s: &str) -> Result<Self> {
+        let Some((os, rest)) = s.split_once('/').filter(|_| !s.contains('=')) else {
+            return s.parse();
+        };
+        let (arch, variant) = match rest.split_once('/') {
+            Some((arch, variant)) => (arch, Some(variant.to_owned())),
+            None => (rest, None),
+        };
+        let os = Os::new(os)?;
+        let architecture = Architecture::new(arch)?;
+        let target = match (&os, &architecture) {
+            (Os::Linux, Architecture::Amd64) => TargetTriple::X86_64UnknownLinuxGnu,
+            (Os::Linux, Architecture::Arm64) => TargetTriple::Aarch64UnknownLinuxGnu,
+            _ => eyre::bail!(
+                "cannot determine the toolchain for platform `{s}`, use `{s}=<toolchain>` instead"
+            ),
+        };
+
+        Ok(ImagePlatform {
+            architecture,
+            os,
+            variant,
+            target,
+        })
+    }
+}
+
 impl Default for ImagePlatform {
     fn default() -> ImagePlatform {
         ImagePlatform::DEFAULT
@@ -485,6 +639,60 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn platform_from_override() -> Result<()> {
+        assert_eq!(
+            ImagePlatform::from_override("linux/arm64")?,
+            ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU
+        );
+        assert_eq!(
+            ImagePlatform::from_override("linux/amd64")?,
+            ImagePlatform::X86_64_UNKNOWN_LINUX_GNU
+        );
+        assert_eq!(
+            ImagePlatform::from_override("x86_64-unknown-linux-gnu")?,
+            ImagePlatform::X86_64_UNKNOWN_LINUX_GNU
+        );
+        let platform = ImagePlatform::from_override("linux/arm/v7=armv7-unknown-linux-gnueabihf")?;
+        assert_eq!(platform.architecture, Architecture::Arm);
+        assert_eq!(platform.variant.as_deref(), Some("v7"));
+        assert_eq!(platform.target, t!("armv7-unknown-linux-gnueabihf"));
+        assert!(ImagePlatform::from_override("linux/s390x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_platform_matches() {
+        let platform = ManifestPlatform {
+            architecture: "arm64".to_owned(),
+            os: "linux".to_owned(),
+            variant: Some("v8".to_owned()),
+        };
+        assert_eq!(platform.to_string(), "linux/arm64/v8");
+        assert!(platform.matches(&ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU, true));
+        assert!(!platform.matches(&ImagePlatform::X86_64_UNKNOWN_LINUX_GNU, false));
+
+        let mut other = ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU;
+        other.variant = Some("v9".to_owned());
+        assert!(!platform.matches(&other, true));
+        assert!(platform.matches(&other, false));
+
+        // a missing variant falls back to another of the same architecture
+        let available = [platform.clone()];
+        assert_eq!(
+            select_variant(&available, &other).and_then(|p| p.variant),
+            Some("v8".to_owned())
+        );
+        assert_eq!(
+            select_variant(&available, &ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU),
+            Some(ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU)
+        );
+        assert_eq!(
+            select_variant(&available, &ImagePlatform::X86_64_UNKNOWN_LINUX_GNU),
+            None
+        );
+    }
+
     #[test]
     fn os_from_target() -> Result<()> {
         assert_eq!(Os::from_target(&t!("x86_64-apple-darwin"))?, Os::Darwin);
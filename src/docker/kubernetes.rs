@@ -0,0 +1,370 @@
+//! Experimental backend running the build in a Kubernetes pod.
+//!
+//! This drives `kubectl`, so any cluster reachable with the current
+//! kubeconfig can be used: a pod is created from the target image, the
+//! toolchain and sources are copied into it, cargo runs through `kubectl
+//! exec`, and the target directory is copied back before the pod is deleted.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::remote::posix_parent;
+use super::shared::*;
+use crate::config::bool_from_envvar;
+use crate::errors::*;
+use crate::extensions::CommandExt;
+use crate::shell::{MessageInfo, Stream};
+
+/// Kubernetes resource names must be DNS labels.
+const MAX_POD_NAME_LEN: usize = 63;
+const POD_READY_TIMEOUT: &str = "5m";
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by=cross";
+
+/// Where the credentials for the cluster come from.
+#[derive(Debug, PartialEq, Eq)]
+enum Kubeconfig {
+    /// The first existing file in `KUBECONFIG` or `~/.kube/config`.
+    File(PathBuf),
+    /// The service account of the pod cross itself runs in.
+    InCluster,
+}
+
+fn detect_kubeconfig(
+    kubeconfig: Option<OsString>,
+    home: Option<&Path>,
+    in_cluster: bool,
+    exists: impl Fn(&Path) -> bool,
+) -> Option<Kubeconfig> {
+    let candidates: Vec<PathBuf> = match kubeconfig {
+        Some(paths) => env::split_paths(&paths).collect(),
+        None => home
+            .map(|h| h.join(".kube").join("config"))
+            .into_iter()
+            .collect(),
+    };
+    candidates
+        .into_iter()
+        .find(|path| exists(path))
+        .map(Kubeconfig::File)
+        .or_else(|| in_cluster.then_some(Kubeconfig::InCluster))
+}
+
+/// Turn the `-e` flags for the container engine into `KEY=VALUE` pairs,
+/// resolving the variables which are forwarded from the host.
+fn pod_envvars<'a>(
+    args: impl IntoIterator<Item = &'a OsStr>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    let mut envvars = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg != "-e" {
+            continue;
+        }
+        let Some(var) = args.next().and_then(OsStr::to_str) else {
+            continue;
+        };
+        if var.contains('=') {
+            envvars.push(var.to_owned());
+        } else if let Some(value) = lookup(var) {
+            envvars.push(format!("{var}={value}"));
+        }
+    }
+    envvars
+}
+
+fn pod_name(container_id: &str) -> String {
+    let name: String = container_id
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            _ => '-',
+        })
+        .take(MAX_POD_NAME_LEN)
+        .collect();
+    name.trim_matches('-').to_owned()
+}
+
+struct Kubectl {
+    path: PathBuf,
+    namespace: Option<String>,
+}
+
+impl Kubectl {
+    fn command(&self, subcommand: &str) -> Command {
+        let mut command = Command::new(&self.path);
+        if let Some(namespace) = &self.namespace {
+            command.args(["--namespace", namespace]);
+        }
+        command.arg(subcommand);
+        command
+    }
+
+    fn mkdir(&self, pod: &str, dir: &str, msg_info: &mut MessageInfo) -> Result<()> {
+        self.command("exec")
+            .args([pod, "--", "mkdir", "-p", dir])
+            .run(msg_info, true)
+    }
+
+    fn copy_to(&self, pod: &str, src: &Path, dst: &str, msg_info: &mut MessageInfo) -> Result<()> {
+        // `kubectl cp` copies into the destination if it already exists
+        if let Some(parent) = posix_parent(dst).filter(|p| !p.is_empty()) {
+            self.mkdir(pod, parent, msg_info)?;
+        }
+        self.command("cp")
+            .arg(src)
+            .arg(format!("{pod}:{dst}"))
+            .run(msg_info, true)
+            .wrap_err_with(|| format!("when copying `{src:?}` to the pod"))
+    }
+}
+
+/// The build pod, deleted once it goes out of scope.
+struct Pod<'a> {
+    kubectl: &'a Kubectl,
+    name: String,
+}
+
+impl Drop for Pod<'_> {
+    fn drop(&mut self) {
+        // cleanup is best-effort: the pod is labeled, so it can be found later
+        self.kubectl
+            .command("delete")
+            .args(["pod", &self.name, "--wait=false", "--ignore-not-found"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok();
+    }
+}
+
+pub(crate) fn run(
+    options: DockerOptions,
+    paths: DockerPaths,
+    args: &[String],
+    msg_info: &mut MessageInfo,
+) -> Result<Option<ContainerRun>> {
+    let target = &options.target;
+    let toolchain_dirs = paths.directories.toolchain_directories();
+    let package_dirs = paths.directories.package_directories();
+
+    let kubeconfig = detect_kubeconfig(
+        env::var_os("KUBECONFIG"),
+        directories::BaseDirs::new().as_ref().map(|d| d.home_dir()),
+        env::var_os("KUBERNETES_SERVICE_HOST").is_some(),
+        Path::exists,
+    )
+    .ok_or_else(|| eyre::eyre!("no kubeconfig found for the kubernetes backend"))
+    .with_suggestion(|| "set `KUBECONFIG` or create `~/.kube/config` to select a cluster")?;
+    msg_info.debug(format_args!("using kubeconfig {kubeconfig:?}"))?;
+
+    if options.needs_custom_image() {
+        eyre::bail!("custom images cannot be built with the kubernetes backend");
+    }
+    if !options.devices().is_empty() {
+        eyre::bail!("devices cannot be passed through with the kubernetes backend");
+    }
+
+    let kubectl = Kubectl {
+        path: options.engine.path.clone(),
+        namespace: env::var("CROSS_KUBERNETES_NAMESPACE").ok(),
+    };
+    let pod = Pod {
+        kubectl: &kubectl,
+        name: pod_name(&toolchain_dirs.unique_container_identifier(target.target())?),
+    };
+
+    // 1. create the pod from the image, with all envvars set, and wait for it
+    let mut envvars = Command::new("");
+    envvars.add_envvars(&options, toolchain_dirs, msg_info)?;
+    envvars.add_network_envvars(args);
+    let envvars = pod_envvars(envvars.get_args(), |var| env::var(var).ok());
+
+    let overrides = serde_json::json!({
+        "apiVersion": "v1",
+        "spec": {
+            "nodeSelector": {
+                "kubernetes.io/arch": options.image.platform.architecture.to_string(),
+            },
+        },
+    });
+    let mut kubectl_run = kubectl.command("run");
+    kubectl_run.args([
+        &pod.name,
+        &format!("--image={}", options.image.name),
+        "--restart=Never",
+        &format!("--labels={MANAGED_BY_LABEL}"),
+        &format!("--overrides={overrides}"),
+    ]);
+    for envvar in &envvars {
+        kubectl_run.arg(format!("--env={envvar}"));
+    }
+    kubectl_run.args(["--command", "--", "sh", "-c", "sleep infinity"]);
+    kubectl_run
+        .run(msg_info, true)
+        .wrap_err("when creating the build pod")?;
+    kubectl
+        .command("wait")
+        .args([
+            "--for=condition=Ready",
+            &format!("pod/{}", pod.name),
+            &format!("--timeout={POD_READY_TIMEOUT}"),
+        ])
+        .run(msg_info, true)
+        .wrap_err("when waiting for the build pod")?;
+
+    // 2. copy the toolchain, cargo caches, project, and extra volumes
+    if toolchain_dirs.xargo().exists() {
+        kubectl.copy_to(
+            &pod.name,
+            toolchain_dirs.xargo(),
+            toolchain_dirs.xargo_mount_path(),
+            msg_info,
+        )?;
+    }
+    let mount_cargo_home = options.config.mount_cargo_home()?.is_some();
+    for dir in ["registry", "git"] {
+        let src = toolchain_dirs.cargo().join(dir);
+        if mount_cargo_home && src.exists() {
+            let dst = format!("{}/{dir}", toolchain_dirs.cargo_mount_path());
+            kubectl.copy_to(&pod.name, &src, &dst, msg_info)?;
+        }
+    }
+    kubectl.copy_to(
+        &pod.name,
+        toolchain_dirs.get_sysroot(),
+        toolchain_dirs.sysroot_mount_path(),
+        msg_info,
+    )?;
+    kubectl.copy_to(
+        &pod.name,
+        package_dirs.host_root(),
+        package_dirs.mount_root(),
+        msg_info,
+    )?;
+    let mut volumes = vec![];
+    Command::new("")
+        .add_mounts(
+            &options,
+            &paths,
+            |_, _, _, _| Ok(()),
+            |(src, dst)| volumes.push((src, dst)),
+            msg_info,
+        )
+        .wrap_err("could not determine mount points")?;
+    for (src, dst) in &volumes {
+        if !dst.starts_with(package_dirs.mount_root()) {
+            kubectl.copy_to(&pod.name, Path::new(src), dst, msg_info)?;
+        }
+    }
+    let copy_cache = env::var("CROSS_REMOTE_COPY_CACHE")
+        .map(|s| bool_from_envvar(&s))
+        .unwrap_or_default();
+    if copy_cache && package_dirs.target().exists() {
+        kubectl.copy_to(&pod.name, package_dirs.target(), "/target", msg_info)?;
+    }
+
+    if msg_info.should_fail() {
+        return Ok(None);
+    }
+
+    // 3. execute our cargo command inside the pod
+    let cmd = options.container_command(args);
+    let script = format!(
+        "mkdir -p /target && cd {} && PATH=\"$PATH\":{}/bin {:?}",
+        shell_escape::unix::escape(paths.mount_cwd().into()),
+        shell_escape::unix::escape(toolchain_dirs.sysroot_mount_path().into()),
+        cmd
+    );
+    let mut exec = kubectl.command("exec");
+    if options.interactive {
+        exec.arg("-i");
+    }
+    if io::Stdin::is_atty() && io::Stdout::is_atty() && io::Stderr::is_atty() {
+        exec.arg("-t");
+    }
+    exec.args([&pod.name, "--", "sh", "-c", &script]);
+    let remap = uses_json_messages(args)
+        .then(|| PathRemap::new(&paths, "/target").hide_messages(options.hide_messages));
+    let status = match &remap {
+        None => exec.run_and_get_status(msg_info, false)?,
+        Some(remap) => run_and_get_status_with_retries(
+            &mut exec,
+            &options.engine,
+            &pod.name,
+            None,
+            0,
+            Some(remap),
+            msg_info,
+        )?
+        .unwrap_or_else(timeout_exit_status),
+    };
+
+    // 4. copy the target directory back, even if the build failed
+    if !options.command_variant.is_shell() {
+        kubectl
+            .command("cp")
+            .arg(format!("{}:/target", pod.name))
+            .arg(package_dirs.target())
+            .run(msg_info, true)
+            .wrap_err("when copying the target directory from the pod")?;
+    }
+
+    Ok(Some(ContainerRun::new(status, remap.as_ref())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kubeconfig_detection() {
+        let home = Path::new("/home/user");
+        let default = home.join(".kube").join("config");
+        let exists = |path: &Path| path == Path::new("/etc/kube.yaml") || path == default;
+
+        assert_eq!(
+            detect_kubeconfig(None, Some(home), false, exists),
+            Some(Kubeconfig::File(default.clone()))
+        );
+        let paths = env::join_paths(["/missing.yaml", "/etc/kube.yaml"]).unwrap();
+        assert_eq!(
+            detect_kubeconfig(Some(paths), Some(home), false, exists),
+            Some(Kubeconfig::File("/etc/kube.yaml".into()))
+        );
+        assert_eq!(
+            detect_kubeconfig(Some("/missing.yaml".into()), Some(home), true, exists),
+            Some(Kubeconfig::InCluster)
+        );
+        assert_eq!(detect_kubeconfig(None, None, false, exists), None);
+    }
+
+    #[test]
+    fn pod_environment() {
+        let args = [
+            "-e",
+            "CARGO_TARGET_DIR=/target",
+            "-e",
+            "TERM",
+            "-e",
+            "UNSET",
+        ];
+        let envvars = pod_envvars(args.iter().map(OsStr::new), |var| {
+            (var == "TERM").then(|| "xterm".to_owned())
+        });
+        assert_eq!(envvars, vec!["CARGO_TARGET_DIR=/target", "TERM=xterm"]);
+    }
+
+    #[test]
+    fn pod_names() {
+        assert_eq!(
+            pod_name("cross-stable-x86_64-unknown-linux-gnu-95840-f89e5d4"),
+            "cross-stable-x86-64-unknown-linux-gnu-95840-f89e5d4"
+        );
+        assert_eq!(pod_name(&"a_".repeat(40)).len(), MAX_POD_NAME_LEN);
+    }
+}
@@ -1,9 +1,13 @@
+use std::cell::RefCell;
 use std::io;
 use std::path::Path;
-use std::process::{Command, ExitStatus};
+use std::process::Command;
 use std::sync::atomic::Ordering;
+use std::time;
 
+use super::capabilities;
 use super::shared::*;
+use crate::cross_toml::CargoHomeMode;
 use crate::errors::Result;
 use crate::extensions::CommandExt;
 use crate::file::{PathExt, ToUtf8};
@@ -26,21 +30,110 @@ fn mount(
     Ok(())
 }
 
+/// The directories of the toolchain, the cargo home and the package mounted
+/// into the container, besides the configured volumes.
+fn package_mounts(options: &DockerOptions, paths: &DockerPaths) -> Result<Vec<Mount>> {
+    let toolchain_dirs = paths.directories.toolchain_directories();
+    let package_dirs = paths.directories.package_directories();
+
+    let mut mounts = vec![Mount::new(
+        toolchain_dirs.xargo_host_path()?,
+        toolchain_dirs.xargo_mount_path(),
+        false,
+    )];
+    if let Some(mode) = options.config.mount_cargo_home()? {
+        mounts.push(Mount::new(
+            toolchain_dirs.cargo_host_path()?,
+            toolchain_dirs.cargo_mount_path(),
+            mode == CargoHomeMode::Ro,
+        ));
+    }
+
+    let host_root = paths.mount_finder.find_mount_path(package_dirs.host_root());
+    mounts.push(Mount::new(
+        host_root.to_utf8()?,
+        package_dirs.mount_root(),
+        false,
+    ));
+
+    let sysroot = paths
+        .mount_finder
+        .find_mount_path(toolchain_dirs.get_sysroot());
+    mounts.push(Mount::new(
+        sysroot.to_utf8()?,
+        toolchain_dirs.sysroot_mount_path(),
+        true,
+    ));
+    mounts.push(Mount::new(
+        package_dirs.target().to_utf8()?,
+        "/target",
+        false,
+    ));
+
+    // When running inside NixOS or using Nix packaging we need to add the Nix
+    // Store to the running container so it can load the needed binaries.
+    if let Some(nix_store) = toolchain_dirs.nix_store() {
+        mounts.push(Mount::new(
+            nix_store.to_utf8()?,
+            &nix_store.as_posix_absolute()?,
+            false,
+        ));
+    }
+
+    Ok(mounts)
+}
+
+/// All the paths of the host mounted into the container, resolved the same
+/// way as [`run`] without running anything.
+pub(crate) fn mounts(
+    options: &DockerOptions,
+    paths: &DockerPaths,
+    msg_info: &mut MessageInfo,
+) -> Result<Vec<Mount>> {
+    let volumes = RefCell::new(vec![]);
+    let mount_cb = |_: &mut Command, host: &Path, absolute: &Path, readonly: bool| {
+        let container = absolute.as_posix_absolute()?;
+        volumes
+            .borrow_mut()
+            .push(Mount::new(host.to_utf8()?, &container, readonly));
+        Ok(())
+    };
+    // the arguments of the command are discarded
+    let mut docker = options.engine.subcommand("run");
+    let mut stored = vec![];
+    docker.add_mounts(
+        options,
+        paths,
+        mount_cb,
+        |mount| stored.push(mount),
+        msg_info,
+    )?;
+    // the context manifest is written to a new directory for every container
+    let _context_dir =
+        docker.add_context(options, paths, &[], &stored, "/target", mount_cb, |_| {})?;
+    let mut mounts = volumes.into_inner();
+    mounts.extend(ssh_agent_mounts(options, paths, msg_info)?);
+    mounts.extend(package_mounts(options, paths)?);
+    Ok(mounts)
+}
+
 pub(crate) fn run(
     options: DockerOptions,
     paths: DockerPaths,
     args: &[String],
     msg_info: &mut MessageInfo,
-) -> Result<Option<ExitStatus>> {
+) -> Result<Option<ContainerRun>> {
     let engine = &options.engine;
     let toolchain_dirs = paths.directories.toolchain_directories();
-    let package_dirs = paths.directories.package_directories();
 
-    let mut cmd = options.command_variant.safe_command();
-    cmd.args(args);
+    let cmd = options.container_command(args);
 
     let mut docker = engine.subcommand("run");
-    docker.add_userns();
+    docker.add_userns(engine.is_nested);
+    docker.add_network(&options);
+    docker.add_cache_proxy(&options, &paths, msg_info)?;
+    docker.add_devices(&options, msg_info)?;
+    docker.add_publish(&options, msg_info)?;
 
     // Podman on macOS doesn't support selinux labels, see issue #756
     #[cfg(target_os = "macos")]
@@ -57,84 +150,49 @@ pub(crate) fn run(
         .platform
         .specify_platform(&options.engine, &mut docker);
     docker.add_envvars(&options, toolchain_dirs, msg_info)?;
+    docker.add_network_envvars(args);
 
+    let mount_cb = |docker: &mut Command, host: &Path, absolute: &Path, readonly: bool| {
+        let selinux = if readonly { selinux_ro } else { selinux };
+        mount(docker, host, absolute, "", selinux)
+    };
+    let mut mounts = vec![];
     docker.add_mounts(
         &options,
         &paths,
-        |docker, host, absolute| mount(docker, host, absolute, "", selinux),
-        |_| {},
+        mount_cb,
+        |mount| mounts.push(mount),
         msg_info,
     )?;
+    let _context_dir =
+        docker.add_context(&options, &paths, args, &mounts, "/target", mount_cb, |_| {})?;
+    docker.add_ssh_agent(&options, &paths, msg_info)?;
 
     let container_id = toolchain_dirs.unique_container_identifier(options.target.target())?;
     docker.args(["--name", &container_id]);
     docker.arg("--rm");
+    docker.add_container_labels(&options, &paths)?;
 
     docker
-        .add_seccomp(engine.kind, &options.target, &paths.metadata)
+        .add_seccomp(&options, &paths, msg_info)
         .wrap_err("when copying seccomp profile")?;
-    docker.add_user_id(engine.is_rootless);
-
-    docker
-        .args([
-            "-v",
-            &format!(
-                "{}:{}{selinux}",
-                toolchain_dirs.xargo_host_path()?,
-                toolchain_dirs.xargo_mount_path()
-            ),
-        ])
-        .args([
-            "-v",
-            &format!(
-                "{}:{}{selinux}",
-                toolchain_dirs.cargo_host_path()?,
-                toolchain_dirs.cargo_mount_path()
-            ),
-        ])
-        // Prevent `bin` from being mounted inside the Docker container.
-        .args(["-v", &format!("{}/bin", toolchain_dirs.cargo_mount_path())]);
-
-    let host_root = paths.mount_finder.find_mount_path(package_dirs.host_root());
-    docker.args([
-        "-v",
-        &format!(
-            "{}:{}{selinux}",
-            host_root.to_utf8()?,
-            package_dirs.mount_root()
-        ),
-    ]);
-
-    let sysroot = paths
-        .mount_finder
-        .find_mount_path(toolchain_dirs.get_sysroot());
-    docker
-        .args([
-            "-v",
-            &format!(
-                "{}:{}{selinux_ro}",
-                sysroot.to_utf8()?,
-                toolchain_dirs.sysroot_mount_path()
-            ),
-        ])
-        .args([
-            "-v",
-            &format!("{}:/target{selinux}", package_dirs.target().to_utf8()?),
-        ]);
-    docker.add_cwd(&paths)?;
+    let container_user = ContainerUser::new(&options, msg_info)?;
+    if container_user.is_none() {
+        docker.add_user_id(engine.is_rootless);
+    }
 
-    // When running inside NixOS or using Nix packaging we need to add the Nix
-    // Store to the running container so it can load the needed binaries.
-    if let Some(nix_store) = toolchain_dirs.nix_store() {
+    for mount in package_mounts(&options, &paths)? {
+        let selinux = if mount.readonly { selinux_ro } else { selinux };
         docker.args([
             "-v",
-            &format!(
-                "{}:{}{selinux}",
-                nix_store.to_utf8()?,
-                nix_store.as_posix_absolute()?
-            ),
+            &format!("{}:{}{selinux}", mount.host, mount.container),
         ]);
     }
+    if options.config.mount_cargo_home()?.is_some() && !options.config.mount_cargo_bin() {
+        // Prevent `bin` from being mounted inside the Docker container.
+        docker.args(["-v", &format!("{}/bin", toolchain_dirs.cargo_mount_path())]);
+    }
+    docker.add_cwd(&paths)?;
 
     if io::Stdin::is_atty() && io::Stdout::is_atty() && io::Stderr::is_atty() {
         docker.arg("-t");
@@ -149,16 +207,39 @@ pub(crate) fn run(
         image_name = options
             .custom_image_build(&paths, msg_info)
             .wrap_err("when building custom image")?;
+    } else {
+        // pulling before the run writes the progress of the pull to the
+        // container log, and turns authentication failures into clear errors.
+        engine.pull_image(&options.image, msg_info)?;
     }
+    capabilities::verify(&options, &image_name, msg_info)?;
 
-    ChildContainer::create(engine.clone(), container_id)?;
+    let timeout = options.config.timeout(&options.target)?;
+    let retries = container_retries()?;
+    ChildContainer::create(engine.clone(), container_id.clone())?;
     if msg_info.should_fail() {
         return Ok(None);
     }
-    let status = docker
+    docker
         .arg(&image_name)
-        .add_build_command(toolchain_dirs, &cmd)
-        .run_and_get_status(msg_info, false);
+        .add_container_user(container_user.as_ref())
+        .add_build_command(toolchain_dirs, &options, &cmd);
+    let started = time::SystemTime::now();
+    let remap = uses_json_messages(args)
+        .then(|| PathRemap::new(&paths, "/target").hide_messages(options.hide_messages));
+    let status = match (timeout, retries, &remap) {
+        (None, 0, None) => docker.run_and_get_status(msg_info, false),
+        (timeout, retries, remap) => run_and_get_status_with_retries(
+            &mut docker,
+            engine,
+            &container_id,
+            timeout,
+            retries,
+            remap.as_ref(),
+            msg_info,
+        )
+        .map(|status| status.unwrap_or_else(timeout_exit_status)),
+    };
 
     // `cargo` generally returns 0 or 101 on completion, but isn't guaranteed
     // to. `ExitStatus::code()` may be None if a signal caused the process to
@@ -166,9 +247,15 @@ pub(crate) fn run(
     // simpler: just test if the program termination handler was called.
     // SAFETY: an atomic load.
     let is_terminated = unsafe { crate::errors::TERMINATED.load(Ordering::SeqCst) };
+    let mut termination = None;
     if !is_terminated {
-        ChildContainer::exit_static();
+        ChildContainer::finish_static(false, msg_info);
+        if let Ok(status) = &status {
+            termination =
+                Termination::from_events(engine, &container_id, started, status, msg_info)?;
+        }
     }
 
-    status.map(Some)
+    status
+        .map(|status| Some(ContainerRun::new(status, remap.as_ref()).with_termination(termination)))
 }
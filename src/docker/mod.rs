@@ -1,23 +1,34 @@
 mod build;
+mod cache_proxy;
+mod capabilities;
 pub(crate) mod custom;
 mod engine;
+mod ignore;
 mod image;
+#[cfg(feature = "kubernetes")]
+mod kubernetes;
 mod local;
+mod packages;
 mod provided_images;
 pub mod remote;
 mod shared;
+pub(crate) mod tools;
+mod upgrade;
 
 pub use self::build::{BuildCommandExt, BuildResultExt, Progress};
+pub use self::cache_proxy::{CACHE_PROXY_IMAGE_ENV, CACHE_PROXY_NAME, CARGO_REGISTRY_VOLUME};
 pub use self::engine::*;
+pub(crate) use self::local::mounts;
 pub use self::provided_images::PROVIDED_IMAGES;
 pub use self::shared::*;
+pub(crate) use self::upgrade::notify_outdated;
+pub use self::upgrade::tag_is_current;
 
 pub use image::{
-    Architecture, Image, ImagePlatform, ImageReference, Os as ContainerOs, PossibleImage,
+    manifest_platforms, Architecture, Image, ImagePlatform, ImageReference, ManifestPlatform,
+    Os as ContainerOs, PossibleImage,
 };
 
-use std::process::ExitStatus;
-
 use crate::errors::*;
 use crate::shell::MessageInfo;
 
@@ -27,6 +38,9 @@ pub struct ProvidedImage {
     pub name: &'static str,
     pub platforms: &'static [ImagePlatform],
     pub sub: Option<&'static str>,
+    /// The runner the image uses when `runner` is not configured, from the
+    /// `CROSS_TARGET_RUNNER` of its Dockerfile.
+    pub runner: Option<&'static str>,
 }
 
 impl ProvidedImage {
@@ -56,17 +70,27 @@ pub fn run(
     args: &[String],
     subcommand: Option<crate::Subcommand>,
     msg_info: &mut MessageInfo,
-) -> Result<Option<ExitStatus>> {
+) -> Result<Option<ContainerRun>> {
     if cfg!(target_os = "windows") && options.in_docker() {
         msg_info.fatal(
             "running cross insider a container running windows is currently unsupported",
             1,
         );
     }
+    if options.engine.kind == EngineType::Kubernetes {
+        #[cfg(feature = "kubernetes")]
+        return kubernetes::run(options, paths, args, msg_info)
+            .wrap_err("could not complete kubernetes run");
+        #[cfg(not(feature = "kubernetes"))]
+        eyre::bail!("the kubernetes backend requires building cross with `--features kubernetes`");
+    }
     if options.is_remote() {
         remote::run(options, paths, args, subcommand, msg_info)
             .wrap_err("could not complete remote run")
     } else {
+        if options.engine.is_nested {
+            msg_info.note("detected a nested container engine with cgroup v2, disabling user namespaces and the custom seccomp profile. if builds still fail, consider `CROSS_REMOTE=1`, or set `CROSS_CONTAINER_NESTED=0` to disable this detection.")?;
+        }
         local::run(options, paths, args, msg_info)
     }
 }
@@ -0,0 +1,154 @@
+//! System packages installed into the custom image from `packages`.
+//!
+//! The package manager of the image is detected when the image is built, so
+//! the same configuration works for images based on Debian, Fedora, CentOS,
+//! or Alpine. Packages with an architecture, such as `libssl-dev:arm64`, are
+//! only supported by `apt`, which enables the architecture first.
+
+use crate::errors::*;
+
+fn is_shell_safe(package: &str) -> bool {
+    !package.is_empty()
+        && !package.starts_with('-')
+        && package
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '+' | ':' | '='))
+}
+
+/// Replace `{deb_arch}` with the debian architecture of the target, then sort
+/// and deduplicate the packages, so the same set of packages always produces
+/// the same cached layer.
+fn resolve(packages: &[String], deb_arch: Option<&str>) -> Result<Vec<String>> {
+    let mut resolved = vec![];
+    for package in packages {
+        let package = if package.contains("{deb_arch}") {
+            let arch = deb_arch.ok_or_else(|| {
+                eyre::eyre!("package `{package}` uses `{{deb_arch}}`, but the target has no debian architecture")
+            })?;
+            package.replace("{deb_arch}", arch)
+        } else {
+            package.clone()
+        };
+        if !is_shell_safe(&package) {
+            eyre::bail!("invalid package `{package}`");
+        }
+        resolved.push(package);
+    }
+    resolved.sort();
+    resolved.dedup();
+    Ok(resolved)
+}
+
+/// The foreign architectures of `packages`, such as `arm64` for `libssl-dev:arm64`.
+fn architectures(packages: &[String]) -> Vec<&str> {
+    let mut arches: Vec<&str> = packages
+        .iter()
+        .filter_map(|p| p.split('=').next()?.split_once(':').map(|(_, arch)| arch))
+        .collect();
+    arches.sort_unstable();
+    arches.dedup();
+    arches
+}
+
+/// The `RUN` instruction installing `packages` with the package manager of
+/// the image, or `None` if there are no packages.
+pub fn layer(packages: &[String], deb_arch: Option<&str>) -> Result<Option<String>> {
+    let packages = resolve(packages, deb_arch)?;
+    if packages.is_empty() {
+        return Ok(None);
+    }
+    let arches = architectures(&packages);
+    let list = packages.join(" ");
+    let others = if arches.is_empty() {
+        format!(
+            "elif command -v dnf >/dev/null; then dnf install -y {list}; dnf clean all; \
+            elif command -v yum >/dev/null; then yum install -y {list}; yum clean all; \
+            elif command -v apk >/dev/null; then apk add --no-cache {list}; "
+        )
+    } else {
+        String::new()
+    };
+    Ok(Some(format!(
+        "RUN set -e; \
+        if command -v apt-get >/dev/null; then \
+        for arch in {arches}; do \
+        [ \"$(dpkg --print-architecture)\" = \"$arch\" ] || dpkg --add-architecture \"$arch\"; \
+        done; \
+        apt-get update; \
+        DEBIAN_FRONTEND=noninteractive apt-get install --assume-yes --no-install-recommends {list}; \
+        rm -rf /var/lib/apt/lists/*; \
+        {others}\
+        else echo 'cross: no supported package manager was found to install `packages`' >&2; exit 1; \
+        fi",
+        arches = arches.join(" "),
+    )))
+}
+
+/// The `RUN` instruction installing the packages of the `commands` missing
+/// in the image, such as the downloaders used by `tools`. The commands are
+/// named like their packages.
+pub fn missing_commands_layer(commands: &[&str]) -> String {
+    let commands = commands.join(" ");
+    format!(
+        "RUN set -e; missing=''; \
+        for cmd in {commands}; do command -v \"$cmd\" >/dev/null || missing=\"$missing $cmd\"; done; \
+        if [ -z \"$missing\" ]; then :; \
+        elif command -v apt-get >/dev/null; then \
+        apt-get update; \
+        DEBIAN_FRONTEND=noninteractive apt-get install --assume-yes --no-install-recommends ca-certificates $missing; \
+        rm -rf /var/lib/apt/lists/*; \
+        elif command -v dnf >/dev/null; then dnf install -y $missing; dnf clean all; \
+        elif command -v yum >/dev/null; then yum install -y $missing; yum clean all; \
+        elif command -v apk >/dev/null; then apk add --no-cache ca-certificates $missing; \
+        else echo \"cross: no supported package manager was found to install$missing\" >&2; exit 1; \
+        fi"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_layers() -> Result<()> {
+        let packages = |list: &[&str]| list.iter().map(|&p| p.to_owned()).collect::<Vec<_>>();
+
+        assert_eq!(
+            resolve(
+                &packages(&[
+                    "cmake",
+                    "libssl-dev:{deb_arch}",
+                    "cmake",
+                    "libssl-dev:arm64"
+                ]),
+                Some("arm64")
+            )?,
+            packages(&["cmake", "libssl-dev:arm64"])
+        );
+        assert!(resolve(&packages(&["libssl-dev:{deb_arch}"]), None).is_err());
+        assert!(resolve(&packages(&["cmake; rm -rf /"]), None).is_err());
+        assert!(resolve(&packages(&["--allow-unauthenticated"]), None).is_err());
+        assert_eq!(layer(&[], None)?, None);
+
+        let native = layer(&packages(&["protobuf-compiler", "cmake"]), None)?.unwrap();
+        assert!(native.contains(
+            "apt-get install --assume-yes --no-install-recommends cmake protobuf-compiler;"
+        ));
+        assert!(native.contains("dnf install -y cmake protobuf-compiler;"));
+        assert!(native.contains("apk add --no-cache cmake protobuf-compiler;"));
+
+        let foreign = layer(
+            &packages(&["libssl-dev:arm64", "zlib1g-dev:armhf=1:1.2.13"]),
+            None,
+        )?
+        .unwrap();
+        assert!(foreign.contains("for arch in arm64 armhf; do"));
+        assert!(!foreign.contains("dnf"));
+
+        let missing = missing_commands_layer(&["curl", "unzip"]);
+        assert!(missing.contains("for cmd in curl unzip; do"));
+        assert!(missing.contains("apk add --no-cache ca-certificates $missing;"));
+
+        Ok(())
+    }
+}
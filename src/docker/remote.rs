@@ -1,18 +1,23 @@
 use std::collections::BTreeMap;
 use std::io::{self, BufRead, Read, Write};
-use std::path::Path;
-use std::process::{Command, ExitStatus};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str::FromStr;
 use std::{env, fs, time};
 
+use color_eyre::Section;
 use eyre::Context;
 
+use super::capabilities;
 use super::engine::Engine;
+use super::ignore::IgnoreRules;
 use super::shared::*;
 use crate::config::bool_from_envvar;
 use crate::errors::Result;
 use crate::extensions::CommandExt;
 use crate::file::{self, PathExt, ToUtf8};
 use crate::rustc::{self, QualifiedToolchain, VersionMetaExt};
+use crate::rustup;
 use crate::shell::{MessageInfo, Stream};
 use crate::temp;
 use crate::TargetTriple;
@@ -35,8 +40,184 @@ fn subcommand_or_exit(engine: &Engine, cmd: &str) -> Result<Command> {
     Ok(engine.subcommand(cmd))
 }
 
+/// The parent of a POSIX path in the container. Unlike [`Path::parent`],
+/// this only splits at `/`, so it's the same on Windows hosts.
 pub fn posix_parent(path: &str) -> Option<&str> {
-    Path::new(path).parent()?.to_str()
+    let path = path.trim_end_matches('/');
+    match path.rsplit_once('/') {
+        Some(("", _)) => Some("/"),
+        Some((parent, _)) => Some(parent),
+        None if path.is_empty() => None,
+        None => Some(""),
+    }
+}
+
+/// If `src` is the contents of a directory, such as `dir/.`, which is copied
+/// into the destination instead of as a subdirectory of it.
+fn is_dir_contents(src: &Path) -> Result<bool> {
+    let path = src.to_utf8()?;
+    let is_contents = path.strip_suffix('.').is_some_and(|parent| {
+        parent.ends_with('/') || cfg!(target_family = "windows") && parent.ends_with('\\')
+    });
+    Ok(is_contents && src.is_dir())
+}
+
+/// The compression of the directories copied to and from the container,
+/// set with `CROSS_REMOTE_COMPRESSION=zstd[:level]`.
+///
+/// The directory is streamed as a tarball through `docker exec`, which needs
+/// `tar` and `zstd` both on the host and in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteCompression {
+    Zstd { level: u8 },
+}
+
+impl RemoteCompression {
+    const DEFAULT_ZSTD_LEVEL: u8 = 3;
+
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var("CROSS_REMOTE_COMPRESSION") {
+            Ok(value) if !value.is_empty() && value != "none" => value
+                .parse()
+                .map(Some)
+                .wrap_err("invalid `CROSS_REMOTE_COMPRESSION`"),
+            _ => Ok(None),
+        }
+    }
+
+    fn compress(self) -> Command {
+        let Self::Zstd { level } = self;
+        let mut command = Command::new("zstd");
+        command.args(["-q", "-c", &format!("-{level}")]);
+        command
+    }
+
+    fn decompress(self) -> Command {
+        let mut command = Command::new("zstd");
+        command.args(["-q", "-d", "-c"]);
+        command
+    }
+
+    fn container_compress(self) -> String {
+        let Self::Zstd { level } = self;
+        format!("zstd -q -c -{level}")
+    }
+
+    /// Copy the contents of the directory `src` of the host into `dst` in the container.
+    fn copy_to(
+        self,
+        engine: &Engine,
+        container: &str,
+        src: &Path,
+        dst: &str,
+        msg_info: &mut MessageInfo,
+    ) -> Result<ExitStatus> {
+        let mut tar = Command::new("tar");
+        tar.arg("-C").arg(src).args(["-cf", "-", "."]);
+        let mut tar = spawn_piped(tar, None, msg_info)?;
+        let mut zstd = spawn_piped(self.compress(), Some(&mut tar), msg_info)?;
+        let dst = shell_escape::unix::escape(dst.into());
+        let status = subcommand_or_exit(engine, "exec")?
+            .args(["-i", container, "sh", "-c"])
+            .arg(format!(
+                "mkdir -p {dst} && zstd -q -d -c | tar -C {dst} -xf -"
+            ))
+            .stdin(piped_stdout(&mut zstd)?)
+            .run_and_get_status(msg_info, false)?;
+        wait_piped(tar, "tar")?;
+        wait_piped(zstd, "zstd")?;
+        Ok(status)
+    }
+
+    /// Copy the contents of the directory `src` in the container into `dst` of the host.
+    fn copy_from(
+        self,
+        engine: &Engine,
+        container: &str,
+        src: &str,
+        dst: &Path,
+        msg_info: &mut MessageInfo,
+    ) -> Result<ExitStatus> {
+        let mut docker = subcommand_or_exit(engine, "exec")?;
+        docker.args([container, "sh", "-c"]).arg(format!(
+            "tar -C {} -cf - . | {}",
+            shell_escape::unix::escape(src.into()),
+            self.container_compress()
+        ));
+        let mut docker = spawn_piped(docker, None, msg_info)?;
+        let mut zstd = spawn_piped(self.decompress(), Some(&mut docker), msg_info)?;
+        file::create_dir_all(dst)?;
+        let status = Command::new("tar")
+            .arg("-C")
+            .arg(dst)
+            .args(["-xf", "-"])
+            .stdin(piped_stdout(&mut zstd)?)
+            .run_and_get_status(msg_info, false)?;
+        wait_piped(docker, "the container engine")?;
+        wait_piped(zstd, "zstd")?;
+        Ok(status)
+    }
+}
+
+impl FromStr for RemoteCompression {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (algorithm, level) = match s.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level)),
+            None => (s, None),
+        };
+        match algorithm {
+            "zstd" => {
+                let level = match level {
+                    Some(level) => level
+                        .parse()
+                        .ok()
+                        .filter(|level| (1..=19).contains(level))
+                        .ok_or_else(|| {
+                            eyre::eyre!("invalid zstd level `{level}`, expected 1 to 19")
+                        })?,
+                    None => Self::DEFAULT_ZSTD_LEVEL,
+                };
+                Ok(Self::Zstd { level })
+            }
+            _ => eyre::bail!("unsupported compression `{s}`, expected `zstd` or `zstd:<level>`"),
+        }
+    }
+}
+
+/// Spawn `command` with its output piped, reading the output of `input`.
+fn spawn_piped(
+    mut command: Command,
+    input: Option<&mut Child>,
+    msg_info: &mut MessageInfo,
+) -> Result<Child> {
+    if let Some(input) = input {
+        command.stdin(piped_stdout(input)?);
+    }
+    command.stdout(Stdio::piped());
+    command.debug(msg_info)?;
+    let program = command.get_program().to_owned();
+    command
+        .spawn()
+        .wrap_err_with(|| format!("could not run {program:?} on the host"))
+        .with_suggestion(|| "install `tar` and `zstd`, or unset `CROSS_REMOTE_COMPRESSION`")
+}
+
+fn piped_stdout(child: &mut Child) -> Result<Stdio> {
+    child
+        .stdout
+        .take()
+        .map(Stdio::from)
+        .ok_or_else(|| eyre::eyre!("the output of the process is not piped"))
+}
+
+fn wait_piped(mut child: Child, name: &str) -> Result<()> {
+    let status = child.wait()?;
+    if !status.success() {
+        eyre::bail!("{name} failed with {status} when copying with compression");
+    }
+    Ok(())
 }
 
 impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
@@ -54,6 +235,7 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
         subcommand_or_exit(self.engine, "exec")?
             .arg(self.container)
             .args(["sh", "-c", &format!("mkdir -p '{mount_prefix}/{reldir}'")])
+            .container_output(msg_info)?
             .run_and_get_status(msg_info, false)
     }
 
@@ -73,10 +255,12 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
         mount_prefix: &str,
         msg_info: &mut MessageInfo,
     ) -> Result<ExitStatus> {
+        // only the contents of directories are streamed through tar
+        let is_contents = is_dir_contents(src)?;
         if let Some((_, rel)) = reldst.rsplit_once('/') {
             if msg_info.cross_debug
                 && src.is_dir()
-                && !src.to_string_lossy().ends_with("/.")
+                && !is_contents
                 && rel
                     == src
                         .file_name()
@@ -84,16 +268,26 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
             {
                 msg_info.warn(format_args!(
                     "source is pointing to a directory instead of its contents: {} -> {}\nThis might be a bug. {}",
-                    src.as_posix_relative()?,
+                    src.to_string_lossy(),
                     reldst,
                     std::panic::Location::caller()
                 ))?;
             }
         }
+        if let Some(compression) = self.compression.filter(|_| is_contents) {
+            return compression.copy_to(
+                self.engine,
+                self.container,
+                src,
+                &format!("{mount_prefix}/{reldst}"),
+                msg_info,
+            );
+        }
         subcommand_or_exit(self.engine, "cp")?
             .arg("-a")
             .arg(src.to_utf8()?)
             .arg(format!("{}:{mount_prefix}/{reldst}", self.container))
+            .container_output(msg_info)?
             .run_and_get_status(msg_info, false)
     }
 
@@ -110,6 +304,7 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
         reldst: &str,
         mount_prefix: &str,
         copy_symlinks: bool,
+        filter: &CopyFilter<'_>,
         msg_info: &mut MessageInfo,
     ) -> Result<ExitStatus> {
         // avoid any cached directories when copying
@@ -117,7 +312,9 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
         // SAFETY: safe, single-threaded execution.
         let tempdir = unsafe { temp::TempDir::new()? };
         let temppath = tempdir.path();
-        let had_symlinks = copy_dir(src, temppath, copy_symlinks, 0, |e, _| is_cachedir(e))?;
+        let had_symlinks = copy_dir(src, temppath, copy_symlinks, 0, |e, _| {
+            filter.is_excluded(e)
+        })?;
         warn_symlinks(had_symlinks, msg_info)?;
         self.copy_files(&temppath.join("."), reldst, mount_prefix, msg_info)
     }
@@ -181,11 +378,13 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
         subcommand_or_exit(self.engine, "cp")?
             .arg(tempfile.path())
             .arg(format!("{}:{PATH}", self.container))
+            .container_output(msg_info)?
             .run_and_get_status(msg_info, true)?;
 
         subcommand_or_exit(self.engine, "exec")?
             .arg(self.container)
             .args(["sh", "-c", &script.join("\n")])
+            .container_output(msg_info)?
             .run_and_get_status(msg_info, true)
     }
 
@@ -207,6 +406,14 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
             .success())
     }
 
+    fn has_compression_tools(&self, msg_info: &mut MessageInfo) -> Result<bool> {
+        Ok(subcommand_or_exit(self.engine, "exec")?
+            .arg(self.container)
+            .args(["sh", "-c", "command -v tar && command -v zstd"])
+            .run_and_get_status(msg_info, true)?
+            .success())
+    }
+
     #[track_caller]
     pub fn copy_xargo(&self, mount_prefix: &str, msg_info: &mut MessageInfo) -> Result<()> {
         let dirs = &self.toolchain_dirs;
@@ -396,31 +603,37 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
     }
 
     #[track_caller]
+    #[allow(clippy::too_many_arguments)]
     fn copy_mount(
         &self,
         src: &Path,
         reldst: &str,
         mount_prefix: &str,
         volume: &VolumeId,
-        copy_cache: bool,
+        filter: &CopyFilter<'_>,
         msg_info: &mut MessageInfo,
     ) -> Result<()> {
         let copy_all = |info: &mut MessageInfo| {
-            if copy_cache {
+            if filter.copy_cache && filter.ignore.is_empty() {
                 self.copy_files(&src.join("."), reldst, mount_prefix, info)
             } else {
-                self.copy_files_nocache(&src.join("."), reldst, mount_prefix, true, info)
+                self.copy_files_nocache(&src.join("."), reldst, mount_prefix, true, filter, info)
             }
         };
         match volume {
             VolumeId::Keep(_) => {
-                let parent = temp::dir()?;
+                let parent = temp::fingerprint_dir()?;
                 file::create_dir_all(&parent)?;
 
                 let toolchain = &self.toolchain_dirs.toolchain();
                 let filename = toolchain.unique_mount_identifier(src)?;
+                // `cross-util` finds the volumes of removed workspaces by it
+                fs::write(
+                    parent.join(format!("{filename}.{SOURCE_EXTENSION}")),
+                    src.to_utf8()?,
+                )?;
                 let fingerprint = parent.join(filename);
-                let current = Fingerprint::read_dir(src, copy_cache)?;
+                let current = Fingerprint::read_dir(src, filter)?;
                 // need to check if the container path exists, otherwise we might
                 // have stale data: the persistent volume was deleted & recreated.
                 if fingerprint.exists()
@@ -450,6 +663,147 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
 
         Ok(())
     }
+
+    /// Copy the toolchain, cargo home, and xargo home of `snapshot`, instead
+    /// of the local ones.
+    #[track_caller]
+    pub fn copy_snapshot(
+        &self,
+        snapshot: &ToolchainSnapshot,
+        mount_prefix: &str,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
+        let dirs = &self.toolchain_dirs;
+        let xargo = snapshot.root.join("xargo");
+        if xargo.is_dir() {
+            let reldst = dirs.xargo_mount_path_relative()?;
+            self.create_dir(&reldst, mount_prefix, msg_info)?;
+            self.copy_files(&xargo.join("."), &reldst, mount_prefix, msg_info)?;
+        }
+        let cargo = snapshot.root.join("cargo");
+        let reldst = dirs.cargo_mount_path_relative()?;
+        self.create_dir(&reldst, mount_prefix, msg_info)?;
+        if cargo.is_dir() {
+            self.copy_files(&cargo.join("."), &reldst, mount_prefix, msg_info)?;
+        }
+        let reldst = dirs.sysroot_mount_path_relative()?;
+        self.create_dir(&reldst, mount_prefix, msg_info)?;
+        self.copy_files(
+            &snapshot.sysroot().join("."),
+            &reldst,
+            mount_prefix,
+            msg_info,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// A snapshot of the data of a persistent data volume, such as one made on
+/// another machine, used to seed a new volume without copying the local
+/// toolchain.
+///
+/// The snapshot is a directory, or a tarball of it, containing the rust
+/// toolchain in `sysroot`, and optionally the cargo home in `cargo` and the
+/// xargo home in `xargo`.
+#[derive(Debug)]
+pub struct ToolchainSnapshot {
+    root: PathBuf,
+    // the extracted tarball, removed once the snapshot is dropped.
+    _tempdir: Option<temp::TempDir>,
+}
+
+impl ToolchainSnapshot {
+    /// Open the snapshot at `path`, extracting it if it's a tarball.
+    pub fn open(path: &Path, msg_info: &mut MessageInfo) -> Result<Self> {
+        if path.is_dir() {
+            return Ok(ToolchainSnapshot {
+                root: path.to_owned(),
+                _tempdir: None,
+            });
+        }
+        if !path.is_file() {
+            eyre::bail!("toolchain snapshot {path:?} does not exist");
+        }
+
+        // SAFETY: safe, single-threaded execution.
+        let tempdir = unsafe { temp::TempDir::new()? };
+        Command::new("tar")
+            .arg("-xf")
+            .arg(path)
+            .arg("-C")
+            .arg(tempdir.path())
+            .run(msg_info, false)
+            .wrap_err_with(|| format!("when extracting toolchain snapshot {path:?}"))?;
+        Ok(ToolchainSnapshot {
+            root: tempdir.path().to_owned(),
+            _tempdir: Some(tempdir),
+        })
+    }
+
+    fn sysroot(&self) -> PathBuf {
+        self.root.join("sysroot")
+    }
+
+    /// Check that the snapshot contains the same toolchain as `toolchain`:
+    /// the same rustc commit, with the standard library for the host.
+    pub fn verify(&self, toolchain: &QualifiedToolchain) -> Result<()> {
+        let sysroot = self.sysroot();
+        let version = rustup::sysroot_rustc_version_string(&sysroot)?.ok_or_else(|| {
+            eyre::eyre!("toolchain snapshot does not contain a rust toolchain in `sysroot`")
+        })?;
+        let expected = toolchain.rustc_version_string()?.ok_or_else(|| {
+            eyre::eyre!(
+                "the version of the toolchain {} is unknown, so the snapshot cannot be verified",
+                toolchain
+            )
+        })?;
+        verify_snapshot_version(&version, &expected)?;
+
+        let host = toolchain.host().target.triple();
+        if !sysroot.join("lib/rustlib").join(host).is_dir() {
+            eyre::bail!("toolchain snapshot does not contain the standard library for `{host}`");
+        }
+
+        Ok(())
+    }
+}
+
+fn verify_snapshot_version(version: &str, expected: &str) -> Result<()> {
+    if rustc::hash_from_version_string(version, 1) != rustc::hash_from_version_string(expected, 1) {
+        eyre::bail!(
+            "toolchain snapshot is for rust {version}, but the toolchain is rust {expected}"
+        );
+    }
+    Ok(())
+}
+
+/// The extension of the files recording the host path of a fingerprint.
+const SOURCE_EXTENSION: &str = "source";
+
+/// The host directories copied into the persistent data volume `volume_id`.
+pub fn volume_sources(volume_id: &str) -> Result<Vec<PathBuf>> {
+    let dir = temp::fingerprint_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).wrap_err_with(|| format!("when reading directory {dir:?}")),
+    };
+    let mut sources = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let is_source = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(SOURCE_EXTENSION)?.strip_suffix('.'))
+            .and_then(|name| name.strip_prefix(volume_id))
+            .is_some_and(|rest| rest.starts_with('-'));
+        if is_source {
+            sources.push(PathBuf::from(file::read(&path)?));
+        }
+    }
+    sources.sort();
+    Ok(sources)
 }
 
 fn is_cachedir_tag(path: &Path) -> Result<bool> {
@@ -471,6 +825,32 @@ fn is_cachedir(entry: &fs::DirEntry) -> bool {
     }
 }
 
+/// The files which are not copied into the data volume.
+struct CopyFilter<'a> {
+    /// Also copy cache directories and the target directory.
+    copy_cache: bool,
+    /// The target directory, skipped even if cargo has not tagged it as a
+    /// cache directory yet.
+    target_dir: Option<&'a Path>,
+    /// The directory the paths of `ignore` are relative to.
+    root: &'a Path,
+    ignore: IgnoreRules,
+}
+
+impl CopyFilter<'_> {
+    fn is_excluded(&self, entry: &fs::DirEntry) -> bool {
+        let path = entry.path();
+        if !self.copy_cache
+            && (is_cachedir(entry) || self.target_dir.is_some_and(|dir| path == dir))
+        {
+            return true;
+        }
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        path.strip_prefix(self.root)
+            .is_ok_and(|relpath| self.ignore.is_ignored(relpath, is_dir))
+    }
+}
+
 // recursively copy a directory into another
 fn copy_dir<Skip>(
     src: &Path,
@@ -580,15 +960,16 @@ impl Fingerprint {
         Ok(())
     }
 
-    fn _read_dir(&mut self, home: &Path, path: &Path, copy_cache: bool) -> Result<()> {
+    fn _read_dir(&mut self, home: &Path, path: &Path, filter: &CopyFilter<'_>) -> Result<()> {
         for entry in fs::read_dir(path)? {
             let file = entry?;
             let file_type = file.file_type()?;
             // only parse known files types: 0 or 1 of these tests can pass.
+            if filter.is_excluded(&file) {
+                continue;
+            }
             if file_type.is_dir() {
-                if copy_cache || !is_cachedir(&file) {
-                    self._read_dir(home, &path.join(file.file_name()), copy_cache)?;
-                }
+                self._read_dir(home, &path.join(file.file_name()), filter)?;
             } else if file_type.is_file() || file_type.is_symlink() {
                 // we're mounting to the same location, so this should fine
                 // we need to round the modified date to millis.
@@ -602,9 +983,9 @@ impl Fingerprint {
         Ok(())
     }
 
-    fn read_dir(home: &Path, copy_cache: bool) -> Result<Fingerprint> {
+    fn read_dir(home: &Path, filter: &CopyFilter<'_>) -> Result<Fingerprint> {
         let mut result = Fingerprint::new();
-        result._read_dir(home, home, copy_cache)?;
+        result._read_dir(home, home, filter)?;
         Ok(result)
     }
 
@@ -626,6 +1007,43 @@ impl Fingerprint {
     }
 }
 
+/// The location of the target directory of the host in the container.
+#[derive(Debug, PartialEq, Eq)]
+struct RemoteTargetDir {
+    /// The path relative to the mount prefix in the data volume.
+    reldst: String,
+    /// The absolute path passed to cargo as `--target-dir`.
+    container: String,
+    /// The target directory is outside the project, so it's copied on its own.
+    external: bool,
+}
+
+impl RemoteTargetDir {
+    /// Locate the canonicalized `target` directory, as reported by cargo
+    /// metadata, relative to the canonicalized project `host_root`.
+    fn new(target: &Path, host_root: &Path, mount_root: &str) -> Result<Self> {
+        let mount_root = mount_root.trim_end_matches('/');
+        if let Ok(relpath) = target.strip_prefix(host_root) {
+            let relpath = relpath.as_posix_relative()?;
+            let container = format!("{mount_root}/{relpath}");
+            Ok(RemoteTargetDir {
+                reldst: container
+                    .strip_prefix('/')
+                    .expect("mount root should be absolute")
+                    .to_owned(),
+                container,
+                external: false,
+            })
+        } else {
+            Ok(RemoteTargetDir {
+                reldst: "target".to_owned(),
+                container: "/target".to_owned(),
+                external: true,
+            })
+        }
+    }
+}
+
 impl QualifiedToolchain {
     pub fn unique_toolchain_identifier(&self) -> Result<String> {
         // try to get the commit hash for the currently toolchain, if possible
@@ -670,14 +1088,20 @@ pub(crate) fn run(
     args: &[String],
     subcommand: Option<crate::Subcommand>,
     msg_info: &mut MessageInfo,
-) -> Result<Option<ExitStatus>> {
+) -> Result<Option<ContainerRun>> {
     let engine = &options.engine;
     let target = &options.target;
     let toolchain_dirs = paths.directories.toolchain_directories();
     let package_dirs = paths.directories.package_directories();
 
     let mount_prefix = MOUNT_PREFIX;
+    let is_doc = subcommand
+        .as_ref()
+        .is_some_and(crate::Subcommand::documents);
 
+    if options.config.ssh_agent(target) {
+        msg_info.warn("`ssh-agent` cannot be forwarded to a remote container engine, since sockets cannot be copied to data volumes.")?;
+    }
     if options.in_docker() {
         msg_info.warn("remote and docker-in-docker are unlikely to work together when using cross. remote cross uses data volumes, so docker-in-docker should not be required.")?;
     }
@@ -739,13 +1163,18 @@ pub(crate) fn run(
 
     // 3. create our start container command here
     let mut docker = engine.subcommand("run");
-    docker.add_userns();
+    docker.add_userns(engine.is_nested);
+    docker.add_network(&options);
+    docker.add_cache_proxy(&options, &paths, msg_info)?;
+    docker.add_devices(&options, msg_info)?;
+    docker.add_publish(&options, msg_info)?;
     options
         .image
         .platform
         .specify_platform(&options.engine, &mut docker);
     docker.args(["--name", &container_id]);
     docker.arg("--rm");
+    docker.add_container_labels(&options, &paths)?;
     docker.args(["-v", &volume.mount(mount_prefix)]);
 
     let mut volumes = vec![];
@@ -753,18 +1182,35 @@ pub(crate) fn run(
         .add_mounts(
             &options,
             &paths,
-            |_, _, _| Ok(()),
+            |_, _, _, _| Ok(()),
             |(src, dst)| volumes.push((src, dst)),
             msg_info,
         )
         .wrap_err("could not determine mount points")?;
+    let mounts = volumes.clone();
+    let target_dir = RemoteTargetDir::new(
+        &file::canonicalize(package_dirs.target())?,
+        &file::canonicalize(package_dirs.host_root())?,
+        package_dirs.mount_root(),
+    )?;
+    let _context_dir = docker.add_context(
+        &options,
+        &paths,
+        args,
+        &mounts,
+        &target_dir.container,
+        |_, _, _, _| Ok(()),
+        |(src, dst)| volumes.push((src, dst)),
+    )?;
 
     docker
-        .add_seccomp(engine.kind, target, &paths.metadata)
+        .add_seccomp(&options, &paths, msg_info)
         .wrap_err("when copying seccomp profile")?;
 
-    // Prevent `bin` from being mounted inside the Docker container.
-    docker.args(["-v", &format!("{mount_prefix}/cargo/bin")]);
+    if !options.config.mount_cargo_bin() {
+        // Prevent `bin` from being mounted inside the Docker container.
+        docker.args(["-v", &format!("{mount_prefix}/cargo/bin")]);
+    }
 
     // When running inside NixOS or using Nix packaging we need to add the Nix
     // Store to the running container so it can load the needed binaries.
@@ -785,7 +1231,10 @@ pub(crate) fn run(
         image_name = options
             .custom_image_build(&paths, msg_info)
             .wrap_err("when building custom image")?;
+    } else {
+        engine.pull_image(&options.image, msg_info)?;
     }
+    capabilities::verify(&options, &image_name, msg_info)?;
 
     docker.arg(&image_name);
 
@@ -800,23 +1249,39 @@ pub(crate) fn run(
 
     // store first, since failing to non-existing container is fine
     ChildContainer::create(engine.clone(), container_id.clone())?;
-    docker.run_and_get_status(msg_info, true)?;
+    docker
+        .container_output(msg_info)?
+        .run_and_get_status(msg_info, true)?;
 
     // 4. copy all mounted volumes over
-    let data_volume = ContainerDataVolume::new(engine, &container_id, toolchain_dirs);
+    let mut data_volume = ContainerDataVolume::new(engine, &container_id, toolchain_dirs);
+    data_volume.compression = RemoteCompression::from_env()?;
+    if data_volume.compression.is_some() && !data_volume.has_compression_tools(msg_info)? {
+        msg_info.warn("`tar` or `zstd` is missing from the image, copying without compression")?;
+        data_volume.compression = None;
+    }
     let copy_cache = env::var("CROSS_REMOTE_COPY_CACHE")
         .map(|s| bool_from_envvar(&s))
         .unwrap_or_default();
+    // the target directory is only part of the fingerprint if it's copied
+    let filter = CopyFilter {
+        copy_cache,
+        target_dir: Some(package_dirs.target()),
+        root: paths.workspace_root(),
+        ignore: IgnoreRules::read(paths.workspace_root(), &options.config.copy_exclude())?,
+    };
     let copy = |src, reldst: &str, info: &mut MessageInfo| {
-        data_volume.copy_mount(src, reldst, mount_prefix, &volume, copy_cache, info)
+        data_volume.copy_mount(src, reldst, mount_prefix, &volume, &filter, info)
     };
     if let VolumeId::Discard = volume {
         data_volume
             .copy_xargo(mount_prefix, msg_info)
             .wrap_err("when copying xargo")?;
-        data_volume
-            .copy_cargo(mount_prefix, false, msg_info)
-            .wrap_err("when copying cargo")?;
+        if options.config.mount_cargo_home()?.is_some() {
+            data_volume
+                .copy_cargo(mount_prefix, false, msg_info)
+                .wrap_err("when copying cargo")?;
+        }
         data_volume
             .copy_rust(Some(target.target()), mount_prefix, msg_info)
             .wrap_err("when copying rust")?;
@@ -855,22 +1320,17 @@ pub(crate) fn run(
         (package_dirs.host_root(), rel_mount_root.to_owned()),
     ];
     let mut to_symlink = vec![];
-    let target_dir = file::canonicalize(package_dirs.target())?;
-    let target_dir = if let Ok(relpath) = target_dir.strip_prefix(package_dirs.host_root()) {
-        relpath.as_posix_relative()?
-    } else {
+    if target_dir.external {
         // outside project, need to copy the target data over
         // only do if we're copying over cached files.
-        let target_dir = "target".to_owned();
         if copy_cache {
-            copy(package_dirs.target(), &target_dir, msg_info)?;
+            copy(package_dirs.target(), &target_dir.reldst, msg_info)?;
         } else {
-            data_volume.create_dir(&target_dir, mount_prefix, msg_info)?;
+            data_volume.create_dir(&target_dir.reldst, mount_prefix, msg_info)?;
         }
 
-        copied.push((package_dirs.target(), target_dir.clone()));
-        target_dir
-    };
+        copied.push((package_dirs.target(), target_dir.reldst.clone()));
+    }
     for (src, dst) in &volumes {
         let src: &Path = src.as_ref();
         if let Some((psrc, pdst)) = copied.iter().find(|(p, _)| src.starts_with(p)) {
@@ -891,17 +1351,20 @@ pub(crate) fn run(
                     msg_info,
                 )?;
             }
-            copy(src, reldst, msg_info)?;
+            if src.is_file() {
+                // such as the `.git` file of a worktree above the workspace
+                data_volume.copy_files(src, reldst, mount_prefix, msg_info)?;
+            } else {
+                copy(src, reldst, msg_info)?;
+            }
         }
     }
 
-    let mut cmd = options.command_variant.safe_command();
-
     if msg_info.should_fail() {
         return Ok(None);
     }
 
-    if !options.command_variant.is_shell() {
+    let cmd = if !options.command_variant.is_shell() {
         // `clean` doesn't handle symlinks: it will just unlink the target
         // directory, so we should just substitute it our target directory
         // for it. we'll still have the same end behavior
@@ -913,12 +1376,12 @@ pub(crate) fn run(
                 has_target_dir = true;
                 final_args.push(arg);
                 if iter.next().is_some() {
-                    final_args.push(target_dir.clone());
+                    final_args.push(target_dir.container.clone());
                 }
             } else if arg.starts_with("--target-dir=") {
                 has_target_dir = true;
                 if arg.split_once('=').is_some() {
-                    final_args.push(format!("--target-dir={target_dir}"));
+                    final_args.push(format!("--target-dir={}", target_dir.container));
                 }
             } else {
                 final_args.push(arg);
@@ -926,13 +1389,13 @@ pub(crate) fn run(
         }
         if !has_target_dir && subcommand.map_or(true, |s| s.needs_target_in_command()) {
             final_args.push("--target-dir".to_owned());
-            final_args.push(target_dir.clone());
+            final_args.push(target_dir.container.clone());
         }
 
-        cmd.args(final_args);
+        options.container_command(&final_args)
     } else {
-        cmd.args(args);
-    }
+        options.container_command(args)
+    };
 
     // 5. create symlinks for copied data
     let mut symlink = vec!["set -e pipefail".to_owned()];
@@ -971,23 +1434,58 @@ symlink_recurse \"${{prefix}}\"
     subcommand_or_exit(engine, "exec")?
         .arg(&container_id)
         .args(["sh", "-c", &symlink.join("\n")])
+        .container_output(msg_info)?
         .run_and_get_status(msg_info, false)
         .wrap_err("when creating symlinks to provide consistent host/mount paths")?;
 
     // 6. execute our cargo command inside the container
     let mut docker = engine.subcommand("exec");
-    docker.add_user_id(engine.is_rootless);
+    let container_user = ContainerUser::new(&options, msg_info)?;
+    if container_user.is_none() {
+        docker.add_user_id(engine.is_rootless);
+    }
     docker.add_envvars(&options, toolchain_dirs, msg_info)?;
+    docker.add_network_envvars(args);
     docker.add_cwd(&paths)?;
     docker.arg(&container_id);
-    docker.add_build_command(toolchain_dirs, &cmd);
+    docker
+        .add_container_user(container_user.as_ref())
+        .add_build_command(toolchain_dirs, &options, &cmd);
 
     if options.interactive {
         docker.arg("-i");
     }
 
     bail_container_exited!();
-    let status = docker.run_and_get_status(msg_info, false);
+    let remap = uses_json_messages(args).then(|| {
+        PathRemap::new(&paths, &target_dir.container).hide_messages(options.hide_messages)
+    });
+    let status = match (options.config.timeout(target)?, &remap) {
+        (None, None) => docker.run_and_get_status(msg_info, false),
+        (timeout, remap) => {
+            match run_and_get_status_with_retries(
+                &mut docker,
+                engine,
+                &container_id,
+                timeout,
+                0,
+                remap.as_ref(),
+                msg_info,
+            )? {
+                Some(status) => Ok(status),
+                None => {
+                    // the container was killed, so there are no artifacts to copy.
+                    ChildContainer::exit_static();
+                    return Ok(Some(ContainerRun::new(timeout_exit_status(), None)));
+                }
+            }
+        }
+    };
+
+    let termination = match &status {
+        Ok(status) => Termination::inspect(engine, &container_id, status, msg_info)?,
+        Err(_) => None,
+    };
 
     // 7. copy data from our target dir back to host
     // this might not exist if we ran `clean`.
@@ -995,24 +1493,208 @@ symlink_recurse \"${{prefix}}\"
         .map(|s| bool_from_envvar(&s))
         .unwrap_or_default();
     bail_container_exited!();
-    let mount_target_dir = format!("{}/{}", package_dirs.mount_root(), target_dir);
     if !skip_artifacts
-        && data_volume.container_path_exists(&mount_target_dir, mount_prefix, msg_info)?
+        && data_volume.container_path_exists(&target_dir.reldst, mount_prefix, msg_info)?
+    {
+        // copy the contents, since the target directory can have any name
+        if let Some(compression) = data_volume.compression {
+            compression.copy_from(
+                engine,
+                &container_id,
+                &target_dir.container,
+                package_dirs.target(),
+                msg_info,
+            )?;
+        } else {
+            subcommand_or_exit(engine, "cp")?
+                .arg("-a")
+                .arg(format!("{container_id}:{}/.", target_dir.container))
+                .arg(package_dirs.target())
+                .container_output(msg_info)?
+                .run_and_get_status(msg_info, false)
+                .map_err::<eyre::ErrReport, _>(Into::into)?;
+        }
+    }
+    // the documentation is always synced, so it can be opened on the host
+    let doc_dir = format!("{}/{}/doc", target_dir.reldst, target.triple());
+    if skip_artifacts
+        && is_doc
+        && data_volume.container_path_exists(&doc_dir, mount_prefix, msg_info)?
     {
+        let host_doc_dir = package_dirs.target().join(target.triple()).join("doc");
+        file::create_dir_all(&host_doc_dir)?;
         subcommand_or_exit(engine, "cp")?
             .arg("-a")
-            .arg(&format!("{container_id}:{mount_target_dir}",))
-            .arg(
-                package_dirs
-                    .target()
-                    .parent()
-                    .expect("target directory should have a parent"),
-            )
-            .run_and_get_status(msg_info, false)
-            .map_err::<eyre::ErrReport, _>(Into::into)?;
+            .arg(format!(
+                "{container_id}:{}/{}/doc/.",
+                target_dir.container,
+                target.triple()
+            ))
+            .arg(host_doc_dir)
+            .container_output(msg_info)?
+            .run_and_get_status(msg_info, false)?;
     }
 
     ChildContainer::finish_static(is_tty, msg_info);
 
-    status.map(Some)
+    status
+        .map(|status| Some(ContainerRun::new(status, remap.as_ref()).with_termination(termination)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ignore::IGNORE_FILE;
+    use super::*;
+
+    #[test]
+    fn remote_compression() -> Result<()> {
+        assert_eq!(
+            "zstd".parse::<RemoteCompression>()?,
+            RemoteCompression::Zstd { level: 3 }
+        );
+        assert_eq!(
+            "zstd:19".parse::<RemoteCompression>()?,
+            RemoteCompression::Zstd { level: 19 }
+        );
+        assert!("zstd:0".parse::<RemoteCompression>().is_err());
+        assert!("zstd:fast".parse::<RemoteCompression>().is_err());
+        assert!("gzip".parse::<RemoteCompression>().is_err());
+        assert_eq!(
+            RemoteCompression::Zstd { level: 7 }.container_compress(),
+            "zstd -q -c -7"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn remote_paths() -> Result<()> {
+        assert_eq!(posix_parent("/mnt/c/Users/cross"), Some("/mnt/c/Users"));
+        assert_eq!(posix_parent("mnt/c/project/"), Some("mnt/c"));
+        assert_eq!(posix_parent("/project"), Some("/"));
+        assert_eq!(posix_parent("project"), Some(""));
+        assert_eq!(posix_parent("/"), None);
+
+        let tmp = tempfile::tempdir()?;
+        assert!(is_dir_contents(&tmp.path().join("."))?);
+        assert!(!is_dir_contents(tmp.path())?);
+        let dotted = tmp.path().join("file.");
+        fs::create_dir(&dotted)?;
+        assert!(!is_dir_contents(&dotted)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_version() {
+        let version = "1.77.0 (aedd173a2 2024-03-17)";
+        assert!(verify_snapshot_version(version, "1.77.0 (aedd173a2 2024-03-17)").is_ok());
+        assert!(verify_snapshot_version(version, "1.77.1 (7cf61ebde 2024-03-27)").is_err());
+        assert!(verify_snapshot_version("1.78.0-nightly", "1.78.0-nightly").is_ok());
+    }
+
+    #[test]
+    fn target_dir_in_project() -> Result<()> {
+        let root = Path::new("/home/user/project");
+        assert_eq!(
+            RemoteTargetDir::new(&root.join("target"), root, "/home/user/project")?,
+            RemoteTargetDir {
+                reldst: "home/user/project/target".to_owned(),
+                container: "/home/user/project/target".to_owned(),
+                external: false,
+            }
+        );
+        // custom layouts, such as `CARGO_TARGET_DIR=build/cargo`
+        let target = root.join("build").join("cargo");
+        assert_eq!(
+            RemoteTargetDir::new(&target, root, "/home/user/project")?.container,
+            "/home/user/project/build/cargo"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn target_dir_out_of_tree() -> Result<()> {
+        let root = Path::new("/home/user/project");
+        let target = Path::new("/tmp/cargo-target");
+        assert_eq!(
+            RemoteTargetDir::new(target, root, "/home/user/project")?,
+            RemoteTargetDir {
+                reldst: "target".to_owned(),
+                container: "/target".to_owned(),
+                external: true,
+            }
+        );
+        // a sibling directory sharing the prefix is not inside the project
+        let sibling = Path::new("/home/user/project-target");
+        assert!(RemoteTargetDir::new(sibling, root, "/home/user/project")?.external);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fingerprint_excludes_target_dir() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let root = tmp.path();
+        let target = root.join("out");
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(target.join("debug"))?;
+        fs::write(root.join("src").join("main.rs"), "fn main() {}")?;
+        fs::write(target.join("debug").join("app"), "")?;
+
+        let files = |fingerprint: Fingerprint| fingerprint.map.into_keys().collect::<Vec<_>>();
+        let filter = |copy_cache: bool| CopyFilter {
+            copy_cache,
+            target_dir: Some(&target),
+            root,
+            ignore: IgnoreRules::default(),
+        };
+        assert_eq!(
+            files(Fingerprint::read_dir(root, &filter(false))?),
+            ["src/main.rs"]
+        );
+        assert_eq!(
+            files(Fingerprint::read_dir(root, &filter(true))?),
+            ["out/debug/app", "src/main.rs"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fingerprint_applies_crossignore() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let root = tmp.path();
+        for dir in ["src", "web/node_modules/pkg", "data/raw"] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+        for file in [
+            "src/main.rs",
+            "src/model.bin",
+            "web/node_modules/pkg/index.js",
+            "data/raw/a.csv",
+            "data/README.md",
+        ] {
+            fs::write(root.join(file), "")?;
+        }
+        fs::write(
+            root.join(IGNORE_FILE),
+            "node_modules/\n/data/**\n!/data/README.md\n",
+        )?;
+
+        let filter = CopyFilter {
+            copy_cache: false,
+            target_dir: None,
+            root,
+            ignore: IgnoreRules::read(root, &["*.bin".to_owned()])?,
+        };
+        let fingerprint = Fingerprint::read_dir(root, &filter)?;
+        assert_eq!(
+            fingerprint.map.into_keys().collect::<Vec<_>>(),
+            [IGNORE_FILE, "data/README.md", "src/main.rs"]
+        );
+
+        Ok(())
+    }
 }
@@ -1,22 +1,31 @@
-use std::io::Write;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus, Output};
+use std::process::{Command, ExitStatus, Output, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::{env, fs, time};
+use std::sync::{Arc, Mutex};
+use std::{env, fs, thread, time};
 
+use super::cache_proxy::{self, CACHE_PROXY_NAME};
 use super::custom::{Dockerfile, PreBuild};
-use super::image::PossibleImage;
+use super::image::{ImageReference, PossibleImage};
+use super::remote::RemoteCompression;
+use super::tools::Tool;
 use super::Image;
 use super::PROVIDED_IMAGES;
 use super::{engine::*, ProvidedImage};
-use crate::cargo::CargoMetadata;
+use crate::cargo::{CargoMetadata, NetworkFlags};
 use crate::config::Config;
+use crate::cross_toml::CrossContainerConfig;
 use crate::errors::*;
 use crate::extensions::{CommandExt, SafeCommand};
 use crate::file::{self, write_file, PathExt, ToUtf8};
 use crate::id;
+use crate::interpreter;
 use crate::rustc::QualifiedToolchain;
+use crate::shard::Shard;
 use crate::shell::{ColorChoice, MessageInfo, Verbosity};
+use crate::temp::TempDir;
 use crate::{CommandVariant, OutputExt, Target, TargetTriple};
 
 use rustc_version::Version as RustcVersion;
@@ -24,6 +33,9 @@ use rustc_version::Version as RustcVersion;
 pub use super::custom::CROSS_CUSTOM_DOCKERFILE_IMAGE_PREFIX;
 
 pub const CROSS_IMAGE: &str = "ghcr.io/cross-rs";
+/// The directory in the target directory where `cargo llvm-cov` writes the
+/// coverage data.
+pub const COVERAGE_DIR: &str = "llvm-cov-target";
 // note: this is the most common base image for our images
 pub const UBUNTU_BASE: &str = "ubuntu:20.04";
 pub const DEFAULT_IMAGE_VERSION: &str = if crate::commit_info().is_empty() {
@@ -42,6 +54,46 @@ pub struct DockerOptions {
     // not all toolchains will provide this
     pub rustc_version: Option<RustcVersion>,
     pub interactive: bool,
+    /// Devices passed with `--device`, in addition to the configured ones.
+    pub devices: Vec<String>,
+    /// Ports published by the container, only set for `cross run`.
+    pub publish: Vec<String>,
+    /// Environment variables passed with `--env`, which override the
+    /// configured ones.
+    pub envs: Vec<String>,
+    /// Tools the subcommand runs, such as `cargo-deny`, installed unless
+    /// they are configured in `tools`.
+    pub subcommand_tools: Vec<&'static str>,
+    /// If the subcommand runs the binaries of the target, with the runner.
+    pub runs_binaries: bool,
+    /// Skip verifying the capabilities advertised by the labels of the image.
+    pub skip_image_checks: bool,
+    /// The JSON messages of cargo were only requested by cross to find the
+    /// executables built, for `cross flash`, so they're not printed.
+    pub hide_messages: bool,
+    /// Pass the target with `CARGO_BUILD_TARGET`, since `auto-target` kept
+    /// cross from appending `--target` to the command.
+    pub target_in_env: bool,
+    /// Only run the tests of the shard, with `cross test --shard`.
+    pub shard: Option<Shard>,
+}
+
+/// The cargo home in the container when the one of the host isn't mounted.
+const UNMOUNTED_CARGO_HOME: &str = "/tmp/cargo-home";
+
+/// The directory of the context manifest in the container.
+pub const CONTEXT_MOUNT_DIR: &str = "/run/cross";
+/// The context manifest, whose path in the container is in `CROSS_CONTEXT`.
+pub const CONTEXT_FILE: &str = "context.json";
+/// The version of the format of the context manifest.
+pub const CONTEXT_VERSION: u32 = 1;
+
+/// The digest of an image reference pinned with `name@digest`.
+fn reference_digest(reference: &str) -> Option<&str> {
+    reference
+        .split_once('@')
+        .map(|(_, digest)| digest)
+        .filter(|digest| !digest.is_empty())
 }
 
 impl DockerOptions {
@@ -62,6 +114,33 @@ impl DockerOptions {
             command_variant: cargo_variant,
             rustc_version,
             interactive,
+            devices: vec![],
+            publish: vec![],
+            envs: vec![],
+            subcommand_tools: vec![],
+            runs_binaries: false,
+            skip_image_checks: false,
+            hide_messages: false,
+            target_in_env: false,
+            shard: None,
+        }
+    }
+
+    /// The command run in the container with the cargo `args`, which is a
+    /// script listing and running the tests of the shard with `shard`.
+    pub(crate) fn container_command(&self, args: &[String]) -> SafeCommand {
+        match self.shard {
+            Some(shard) => {
+                let script = shard.script(self.command_variant.to_str(), args);
+                let mut cmd = CommandVariant::Shell.safe_command();
+                cmd.args(["-c".to_owned(), script]);
+                cmd
+            }
+            None => {
+                let mut cmd = self.command_variant.safe_command();
+                cmd.args(args);
+                cmd
+            }
         }
     }
 
@@ -79,6 +158,132 @@ impl DockerOptions {
     pub fn needs_custom_image(&self) -> bool {
         self.config.dockerfile(&self.target).is_some()
             || self.config.pre_build(&self.target).is_some()
+            || self.config.packages(&self.target).is_some()
+            || self.config.tools(&self.target).is_some()
+            || !self.subcommand_tools.is_empty()
+    }
+
+    /// The configured tools, followed by the tools the subcommand runs.
+    #[must_use]
+    pub fn tools(&self) -> Vec<Tool> {
+        let mut tools = self.config.tools(&self.target).unwrap_or_default();
+        for &name in &self.subcommand_tools {
+            if !tools.iter().any(|tool| tool.name() == name) {
+                tools.push(Tool::Known(name.to_owned()));
+            }
+        }
+        tools
+    }
+
+    /// The cargo home in the container, which is only the one of the host
+    /// if `mount-cargo-home` isn't disabled.
+    pub(crate) fn cargo_home<'a>(&self, dirs: &'a ToolchainDirectories) -> Result<&'a str> {
+        Ok(match self.config.mount_cargo_home()? {
+            Some(_) => dirs.cargo_mount_path(),
+            None => UNMOUNTED_CARGO_HOME,
+        })
+    }
+
+    /// The manifest describing the build to scripts in the container, so
+    /// they don't have to guess where the project and the target directory
+    /// are mounted. `mounts` are the additional host and container paths, and
+    /// `target_dir` is the target directory in the container.
+    pub(crate) fn context_manifest(
+        &self,
+        paths: &DockerPaths,
+        args: &[String],
+        mounts: &[(String, String)],
+        target_dir: &str,
+    ) -> Result<serde_json::Value> {
+        let toolchain_dirs = paths.directories.toolchain_directories();
+        let package_dirs = paths.directories.package_directories();
+        let mut all_mounts = vec![
+            (
+                package_dirs.host_root().to_utf8()?,
+                package_dirs.mount_root(),
+            ),
+            (package_dirs.target().to_utf8()?, target_dir),
+            (
+                toolchain_dirs.get_sysroot().to_utf8()?,
+                toolchain_dirs.sysroot_mount_path(),
+            ),
+            (
+                toolchain_dirs.xargo_host_path()?,
+                toolchain_dirs.xargo_mount_path(),
+            ),
+        ];
+        if self.config.mount_cargo_home()?.is_some() {
+            all_mounts.push((
+                toolchain_dirs.cargo_host_path()?,
+                toolchain_dirs.cargo_mount_path(),
+            ));
+        }
+        all_mounts.extend(
+            mounts
+                .iter()
+                .map(|(host, container)| (&**host, &**container)),
+        );
+
+        Ok(serde_json::json!({
+            "version": CONTEXT_VERSION,
+            "target": self.target.triple(),
+            "host": toolchain_dirs.host_target().triple(),
+            "toolchain": toolchain_dirs.toolchain().full,
+            "cargo-args": args,
+            "workspace-root": package_dirs.mount_root(),
+            "cwd": paths.mount_cwd(),
+            "target-dir": target_dir,
+            "cargo-home": self.cargo_home(toolchain_dirs)?,
+            "sysroot": toolchain_dirs.sysroot_mount_path(),
+            "mounts": all_mounts
+                .iter()
+                .map(|(host, container)| serde_json::json!({ "host": host, "container": container }))
+                .collect::<Vec<_>>(),
+        }))
+    }
+
+    /// The configured devices, followed by the ones from the command line.
+    #[must_use]
+    pub fn devices(&self) -> Vec<String> {
+        let mut devices = self.config.devices(&self.target).unwrap_or_default();
+        devices.extend(self.devices.iter().cloned());
+        devices
+    }
+
+    /// The digest of the image the container runs, if it's known without
+    /// pulling it. Custom images are built locally, so they have none.
+    fn image_digest(&self, msg_info: &mut MessageInfo) -> Option<String> {
+        let name = &self.image.name;
+        if let Some(digest) = reference_digest(name) {
+            return Some(digest.to_owned());
+        }
+        if self.needs_custom_image() || self.engine.kind == EngineType::Kubernetes {
+            return None;
+        }
+        let reference = self.engine.image_digest(name, msg_info)?;
+        reference_digest(&reference).map(ToOwned::to_owned)
+    }
+
+    /// The image shared between projects for a pre-build layer on `image`,
+    /// with `shared-pre-build-cache`, keyed by the ID of `image` and `parts`.
+    /// Returns `None` if the ID of the image is unknown.
+    fn shared_pre_build_image(
+        &self,
+        image: &Image,
+        parts: &[&[u8]],
+        msg_info: &mut MessageInfo,
+    ) -> Result<Option<String>> {
+        self.engine.pull_image(image, msg_info)?;
+        let Some(id) = self.engine.image_id(&image.name, msg_info) else {
+            msg_info.warn(format_args!(
+                "could not get the ID of image `{image}`, the pre-build image will not be shared"
+            ))?;
+            return Ok(None);
+        };
+        let deb_arch = self.target.target().deb_arch().unwrap_or_default();
+        let mut key = vec![id.as_bytes(), deb_arch.as_bytes()];
+        key.extend_from_slice(parts);
+        Ok(Some(super::custom::shared_pre_build_image(key)))
     }
 
     pub(crate) fn custom_image_build(
@@ -118,6 +323,34 @@ impl DockerOptions {
                 )
                 .wrap_err("when building dockerfile")?;
         }
+
+        let packages = self.config.packages(&self.target).unwrap_or_default();
+        let deb_arch = self.target.target().deb_arch();
+        if let Some(layer) = super::packages::layer(&packages, deb_arch)? {
+            let custom = Dockerfile::Custom {
+                content: format!("\nFROM {image}\n{layer}"),
+                name: None,
+                runs_with: &image.platform,
+            };
+            image.name = custom
+                .build(self, paths, None::<(&str, &str)>, msg_info)
+                .wrap_err("when installing packages")
+                .with_note(|| format!("packages: {}", packages.join(" ")))?;
+        }
+
+        let tools = self.tools();
+        if !tools.is_empty() {
+            let layers = super::tools::layers(&tools, &image.platform.architecture)?;
+            let custom = Dockerfile::Custom {
+                content: format!("\nFROM {image}\n{layers}"),
+                name: None,
+                runs_with: &image.platform,
+            };
+            image.name = custom
+                .build(self, paths, None::<(&str, &str)>, msg_info)
+                .wrap_err("when installing tools")?;
+        }
+
         let pre_build = self.config.pre_build(&self.target);
 
         if let Some(pre_build) = pre_build {
@@ -129,59 +362,93 @@ impl DockerOptions {
                     || !pre_build_script.contains('\n')
                         && paths.host_root().join(&pre_build_script).is_file() =>
                 {
-                    let custom = Dockerfile::Custom {
-                        content: format!(
-                            r#"
-                FROM {image}
+                    let body = r#"
                 ARG CROSS_DEB_ARCH=
                 ARG CROSS_SCRIPT
                 ARG CROSS_TARGET
                 COPY $CROSS_SCRIPT /pre-build-script
                 RUN chmod +x /pre-build-script
-                RUN ./pre-build-script $CROSS_TARGET"#
-                        ),
-                        runs_with: &image.platform,
-                    };
-
-                    image.name = custom
-                        .build(
-                            self,
-                            paths,
-                            vec![
-                                ("CROSS_SCRIPT", &*pre_build_script),
-                                ("CROSS_TARGET", self.target.triple()),
-                            ],
+                RUN ./pre-build-script $CROSS_TARGET"#;
+                    let shared = if self.config.shared_pre_build_cache() {
+                        let script = paths.host_root().join(&pre_build_script);
+                        let script = fs::read(&script).wrap_err_with(|| {
+                            format!("could not read pre-build script `{pre_build_script}`")
+                        })?;
+                        let triple = self.target.triple().as_bytes();
+                        self.shared_pre_build_image(
+                            &image,
+                            &[body.as_bytes(), &script, triple],
                             msg_info,
-                        )
-                        .wrap_err("when pre-building")
-                        .with_note(|| format!("CROSS_SCRIPT={pre_build_script}"))
-                        .with_note(|| format!("CROSS_TARGET={}", self.target))?;
-                }
-                this => {
-                    let pre_build = match this {
-                        PreBuild::Single { line, .. } => vec![line],
-                        PreBuild::Lines(lines) => lines,
+                        )?
+                    } else {
+                        None
                     };
-                    if !pre_build.is_empty() {
+                    if let Some(name) = shared
+                        .as_ref()
+                        .filter(|name| self.engine.image_id(name, msg_info).is_some())
+                    {
+                        msg_info.info(format_args!("using the shared pre-build image `{name}`"))?;
+                        image.name = name.clone();
+                    } else {
                         let custom = Dockerfile::Custom {
-                            content: format!(
-                                r#"
-                FROM {image}
-                ARG CROSS_DEB_ARCH=
-                ARG CROSS_CMD
-                RUN eval "${{CROSS_CMD}}""#
-                            ),
+                            content: format!("\n                FROM {image}{body}"),
+                            name: shared.as_deref(),
                             runs_with: &image.platform,
                         };
+
                         image.name = custom
                             .build(
                                 self,
                                 paths,
-                                Some(("CROSS_CMD", pre_build.join("\n"))),
+                                vec![
+                                    ("CROSS_SCRIPT", &*pre_build_script),
+                                    ("CROSS_TARGET", self.target.triple()),
+                                ],
                                 msg_info,
                             )
                             .wrap_err("when pre-building")
-                            .with_note(|| format!("CROSS_CMD={}", pre_build.join("\n")))?;
+                            .with_note(|| format!("CROSS_SCRIPT={pre_build_script}"))
+                            .with_note(|| format!("CROSS_TARGET={}", self.target))?;
+                    }
+                }
+                this => {
+                    let pre_build = match this {
+                        PreBuild::Single { line, .. } => vec![line],
+                        PreBuild::Lines(lines) => lines,
+                    };
+                    if !pre_build.is_empty() {
+                        let body = r#"
+                ARG CROSS_DEB_ARCH=
+                ARG CROSS_CMD
+                RUN eval "${CROSS_CMD}""#;
+                        let cmd = pre_build.join("\n");
+                        let shared = if self.config.shared_pre_build_cache() {
+                            self.shared_pre_build_image(
+                                &image,
+                                &[body.as_bytes(), cmd.as_bytes()],
+                                msg_info,
+                            )?
+                        } else {
+                            None
+                        };
+                        if let Some(name) = shared
+                            .as_ref()
+                            .filter(|name| self.engine.image_id(name, msg_info).is_some())
+                        {
+                            msg_info
+                                .info(format_args!("using the shared pre-build image `{name}`"))?;
+                            image.name = name.clone();
+                        } else {
+                            let custom = Dockerfile::Custom {
+                                content: format!("\n                FROM {image}{body}"),
+                                name: shared.as_deref(),
+                                runs_with: &image.platform,
+                            };
+                            image.name = custom
+                                .build(self, paths, Some(("CROSS_CMD", &cmd)), msg_info)
+                                .wrap_err("when pre-building")
+                                .with_note(|| format!("CROSS_CMD={cmd}"))?;
+                        }
                     }
                 }
             }
@@ -208,7 +475,17 @@ impl DockerPaths {
     ) -> Result<Self> {
         let mount_finder = MountFinder::create(engine, msg_info)?;
         let (directories, metadata) =
-            Directories::assemble(&mount_finder, metadata, &cwd, toolchain)?;
+            Directories::assemble(&mount_finder, metadata, &cwd, toolchain, msg_info)?;
+        if !engine.is_remote {
+            let toolchain = directories.toolchain_directories();
+            mount_finder.verify_mounted(&[
+                ("the workspace", &metadata.workspace_root),
+                ("the target directory", &metadata.target_directory),
+                ("the cargo home", Path::new(toolchain.cargo_mount_path())),
+                ("the xargo home", Path::new(toolchain.xargo_mount_path())),
+                ("the toolchain", toolchain.get_sysroot()),
+            ])?;
+        }
         Ok(Self {
             mount_finder,
             metadata,
@@ -217,6 +494,15 @@ impl DockerPaths {
         })
     }
 
+    /// Use `cargo` as the cargo home instead of the one of the host, keeping
+    /// the path of the cargo home in the container.
+    pub fn set_cargo_home(&mut self, cargo: &Path) -> Result<()> {
+        file::create_dir_all(cargo)?;
+        let cargo = file::canonicalize(cargo)?;
+        self.directories.toolchain.cargo = self.mount_finder.find_mount_path(cargo);
+        Ok(())
+    }
+
     pub fn get_sysroot(&self) -> &Path {
         self.directories.toolchain_directories().get_sysroot()
     }
@@ -229,6 +515,19 @@ impl DockerPaths {
         self.metadata.path_dependencies()
     }
 
+    /// The path dependencies outside of the mounted root, such as
+    /// `../shared`, which need their own mounts.
+    pub fn out_of_root_dependencies(&self) -> Vec<&Path> {
+        let root = self.host_root();
+        // cargo metadata always canonicalizes paths, the root may not be.
+        let canonical_root = file::canonicalize(root).ok();
+        let roots: Vec<&Path> = [Some(root), canonical_root.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        out_of_root_paths(&roots, self.workspace_dependencies())
+    }
+
     pub fn workspace_from_cwd(&self) -> Result<&Path> {
         self.cwd
             .strip_prefix(self.workspace_root())
@@ -247,6 +546,88 @@ impl DockerPaths {
     pub fn host_root(&self) -> &Path {
         self.directories.package_directories().host_root()
     }
+
+    /// Git directories outside of the mounted root that are required when
+    /// building from a worktree or submodule.
+    pub fn git_linked_directories(&self, msg_info: &mut MessageInfo) -> Result<Vec<PathBuf>> {
+        git_linked_directories(self.host_root(), msg_info)
+    }
+}
+
+/// The `paths` outside of all `roots`, without the paths inside another one
+/// of them, since those are covered by its mount.
+fn out_of_root_paths<'a>(roots: &[&Path], paths: impl Iterator<Item = &'a Path>) -> Vec<&'a Path> {
+    let mut paths: Vec<&Path> = paths
+        .filter(|path| !roots.iter().any(|root| path.starts_with(root)))
+        .collect();
+    paths.sort();
+    paths.dedup();
+    let mut outer: Vec<&Path> = vec![];
+    for path in paths {
+        // parents are sorted before their children
+        if !outer.iter().any(|parent| path.starts_with(parent)) {
+            outer.push(path);
+        }
+    }
+    outer
+}
+
+/// Find the paths referenced by the `.git` file of a git worktree or submodule,
+/// which are outside of `root` and need to be mounted.
+///
+/// In a worktree or submodule, `.git` is a file containing `gitdir: <path>`,
+/// and for worktrees, `<path>/commondir` points to the main `.git` directory.
+/// Stale paths, such as of a moved worktree, are skipped with a warning.
+fn git_linked_directories(root: &Path, msg_info: &mut MessageInfo) -> Result<Vec<PathBuf>> {
+    let dot_git = match root
+        .ancestors()
+        .map(|p| p.join(".git"))
+        .find(|p| p.exists())
+    {
+        Some(dot_git) if dot_git.is_file() => dot_git,
+        _ => return Ok(vec![]),
+    };
+    let contents = file::read(&dot_git)?;
+    let gitdir = match contents
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))
+    {
+        Some(gitdir) => gitdir.trim(),
+        None => return Ok(vec![]),
+    };
+    let parent = dot_git.parent().expect(".git file must have a parent");
+    let gitdir = match file::canonicalize(parent.join(gitdir)) {
+        Ok(gitdir) => gitdir,
+        Err(err) => {
+            msg_info.warn(format_args!(
+                "could not find the git directory of `{dot_git:?}`, it will not be mounted: {err}"
+            ))?;
+            return Ok(vec![]);
+        }
+    };
+
+    let mut candidates = vec![dot_git, gitdir.clone()];
+    let commondir = gitdir.join("commondir");
+    if commondir.is_file() {
+        let common = file::read(&commondir)?;
+        match file::canonicalize(gitdir.join(common.trim())) {
+            Ok(common) => candidates.push(common),
+            Err(err) => msg_info.warn(format_args!(
+                "could not find the common git directory of `{gitdir:?}`, it will not be mounted: {err}"
+            ))?,
+        }
+    }
+
+    let mut paths: Vec<PathBuf> = vec![];
+    for candidate in candidates {
+        let covered = |p: &PathBuf| candidate.starts_with(p);
+        if !candidate.starts_with(root) && !paths.iter().any(covered) {
+            paths.retain(|p| !p.starts_with(&candidate));
+            paths.push(candidate);
+        }
+    }
+
+    Ok(paths)
 }
 
 #[derive(Debug)]
@@ -258,15 +639,51 @@ pub struct ToolchainDirectories {
     cargo_mount_path: String,
     xargo_mount_path: String,
     sysroot_mount_path: String,
+    /// The temporary directories used instead of the cargo or xargo home,
+    /// removed when the directories are dropped.
+    _fallback_dirs: Vec<tempfile::TempDir>,
+}
+
+/// Create the directory `path` to mount, or a directory for this run in the
+/// temporary directory of the host if it cannot be created, such as with a
+/// read-only home directory. The temporary directory is removed when its
+/// guard is dropped.
+fn create_mount_dir(
+    path: PathBuf,
+    name: &str,
+    var: &str,
+    msg_info: &mut MessageInfo,
+) -> Result<(PathBuf, Option<tempfile::TempDir>)> {
+    let err = match file::create_dir_all(&path) {
+        Ok(()) => return Ok((path, None)),
+        Err(err) => err,
+    };
+    let fallback = tempfile::Builder::new()
+        .prefix(&format!("cross-{name}-"))
+        .tempdir()
+        .wrap_err_with(|| format!("could not create `{path:?}`"))?;
+    msg_info.warn(format_args!(
+        "could not create `{}`: {err}. using `{}` for this run instead, set `{var}` to a writable directory to keep it between runs.",
+        path.to_utf8()?,
+        fallback.path().to_utf8()?,
+    ))?;
+    Ok((fallback.path().to_owned(), Some(fallback)))
 }
 
 impl ToolchainDirectories {
-    pub fn assemble(mount_finder: &MountFinder, toolchain: QualifiedToolchain) -> Result<Self> {
-        let home_dir =
-            home::home_dir().ok_or_else(|| eyre::eyre!("could not find home directory"))?;
+    pub fn assemble(
+        mount_finder: &MountFinder,
+        toolchain: QualifiedToolchain,
+        msg_info: &mut MessageInfo,
+    ) -> Result<Self> {
         let cargo = home::cargo_home()?;
-        let xargo =
-            env::var_os("XARGO_HOME").map_or_else(|| home_dir.join(".xargo"), PathBuf::from);
+        let xargo = match env::var_os("CROSS_XARGO_HOME").or_else(|| env::var_os("XARGO_HOME")) {
+            Some(xargo) => PathBuf::from(xargo),
+            None => home::home_dir()
+                .ok_or_else(|| eyre::eyre!("could not find home directory"))
+                .with_suggestion(|| "set `CROSS_XARGO_HOME` to the directory to use for xargo")?
+                .join(".xargo"),
+        };
         // NIX_STORE_DIR is an override of NIX_STORE, which is the path in derivations.
         let nix_store = env::var_os("NIX_STORE_DIR")
             .or_else(|| env::var_os("NIX_STORE"))
@@ -276,8 +693,10 @@ impl ToolchainDirectories {
         // otherwise `docker` will create them but they will be owned by `root`
         // cargo builds all intermediate directories, but fails
         // if it has other issues (such as permission errors).
-        file::create_dir_all(&cargo)?;
-        file::create_dir_all(&xargo)?;
+        let (cargo, cargo_fallback) =
+            create_mount_dir(cargo, "cargo-home", "CARGO_HOME", msg_info)?;
+        let (xargo, xargo_fallback) =
+            create_mount_dir(xargo, "xargo-home", "CROSS_XARGO_HOME", msg_info)?;
         if let Some(ref nix_store) = nix_store {
             file::create_dir_all(nix_store)?;
         }
@@ -320,6 +739,7 @@ impl ToolchainDirectories {
             cargo_mount_path,
             xargo_mount_path,
             sysroot_mount_path,
+            _fallback_dirs: cargo_fallback.into_iter().chain(xargo_fallback).collect(),
         })
     }
 
@@ -397,6 +817,24 @@ impl ToolchainDirectories {
     }
 }
 
+/// A directory of the host bind mounted into the container.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Mount {
+    pub host: String,
+    pub container: String,
+    pub readonly: bool,
+}
+
+impl Mount {
+    pub fn new(host: &str, container: &str, readonly: bool) -> Self {
+        Self {
+            host: host.to_owned(),
+            container: container.to_owned(),
+            readonly,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PackageDirectories {
     target: PathBuf,
@@ -416,7 +854,8 @@ impl PackageDirectories {
         // see ToolchainDirectories::assemble for creating directories
         create_target_dir(target)?;
 
-        // root is either workspace_root, or, if we're outside the workspace root, the current directory
+        // root is either workspace_root, or, if we're outside the workspace root, the current directory.
+        // with a `--manifest-path` outside of the current directory, it's the workspace of the manifest
         let host_root = if metadata.workspace_root.starts_with(cwd) {
             cwd
         } else {
@@ -427,7 +866,16 @@ impl PackageDirectories {
         // on Windows, we can not mount the directory name directly. Instead, we use wslpath to convert the path to a linux compatible path.
         // NOTE: on unix, host root has already found the mount path
         let mount_root = host_root.as_posix_absolute()?;
-        let mount_cwd = cwd.as_posix_absolute()?;
+        // cargo metadata canonicalizes the workspace root, the current directory may not be
+        let in_root = cwd.starts_with(&host_root)
+            || file::canonicalize(cwd).is_ok_and(|cwd| cwd.starts_with(&host_root));
+        // outside of the workspace, the current directory isn't mounted, so
+        // work from the workspace root, with the manifest path made absolute
+        let mount_cwd = if in_root {
+            cwd.as_posix_absolute()?
+        } else {
+            mount_root.clone()
+        };
 
         Ok((
             PackageDirectories {
@@ -469,9 +917,10 @@ impl Directories {
         metadata: CargoMetadata,
         cwd: &Path,
         toolchain: QualifiedToolchain,
+        msg_info: &mut MessageInfo,
     ) -> Result<(Self, CargoMetadata)> {
         let (package, metadata) = PackageDirectories::assemble(mount_finder, metadata, cwd)?;
-        let toolchain = ToolchainDirectories::assemble(mount_finder, toolchain)?;
+        let toolchain = ToolchainDirectories::assemble(mount_finder, toolchain, msg_info)?;
 
         Ok((Directories { toolchain, package }, metadata))
     }
@@ -660,6 +1109,8 @@ pub struct ContainerDataVolume<'a, 'b, 'c> {
     pub(crate) engine: &'a Engine,
     pub(crate) container: &'b str,
     pub(crate) toolchain_dirs: &'c ToolchainDirectories,
+    /// Compress the directories copied into the container.
+    pub(crate) compression: Option<RemoteCompression>,
 }
 
 impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
@@ -672,6 +1123,7 @@ impl<'a, 'b, 'c> ContainerDataVolume<'a, 'b, 'c> {
             engine,
             container,
             toolchain_dirs,
+            compression: None,
         }
     }
 }
@@ -784,6 +1236,35 @@ impl<'a, 'b> DockerContainer<'a, 'b> {
             .map(|output| output.status)
     }
 
+    /// If the kernel killed a process of the container after it ran out of
+    /// memory. The container must not have been removed yet.
+    pub fn oom_killed(&self, msg_info: &mut MessageInfo) -> bool {
+        self.engine
+            .subcommand("inspect")
+            .args(["--format", "{{.State.OOMKilled}}", self.name])
+            .run_and_get_stdout(msg_info)
+            .is_ok_and(|stdout| stdout.trim() == "true")
+    }
+
+    /// If the kernel killed a process of the container after it ran out of
+    /// memory, from the events of the engine since `since`, which are kept
+    /// after the container was removed.
+    pub fn oom_event(&self, since: time::SystemTime, msg_info: &mut MessageInfo) -> bool {
+        let (Ok(since), Ok(until)) = (time_to_millis(&since), now_as_millis()) else {
+            return false;
+        };
+        // the bounds are in seconds, and the events are only listed until `--until`
+        self.engine
+            .subcommand("events")
+            .args(["--since", &(since / 1000).to_string()])
+            .args(["--until", &(until / 1000 + 1).to_string()])
+            .args(["--filter", &format!("container={}", self.name)])
+            .args(["--filter", "event=oom"])
+            .args(["--format", "{{.Status}}"])
+            .run_and_get_stdout(msg_info)
+            .is_ok_and(|stdout| !stdout.trim().is_empty())
+    }
+
     pub fn state(&self, msg_info: &mut MessageInfo) -> Result<ContainerState> {
         let stdout = self
             .engine
@@ -796,211 +1277,1256 @@ impl<'a, 'b> DockerContainer<'a, 'b> {
     }
 }
 
-pub(crate) fn time_to_millis(timestamp: &time::SystemTime) -> Result<u64> {
-    Ok(timestamp
-        .duration_since(time::SystemTime::UNIX_EPOCH)?
-        .as_millis() as u64)
-}
-
-pub(crate) fn time_from_millis(millis: u64) -> time::SystemTime {
-    time::SystemTime::UNIX_EPOCH + time::Duration::from_millis(millis)
-}
-
-pub(crate) fn now_as_millis() -> Result<u64> {
-    time_to_millis(&time::SystemTime::now())
+/// How a failed command in a container was terminated, if it didn't exit
+/// by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case", tag = "reason")]
+pub enum Termination {
+    /// The kernel killed a process after the container ran out of memory.
+    OomKilled,
+    /// The command was killed by a signal.
+    Signal { signal: i32 },
 }
 
-const CACHEDIR_TAG: &str = "Signature: 8a477f597d28d172789f06886806bc55
-# This file is a cache directory tag created by cross.
-# For information about cache directory tags see https://bford.info/cachedir/";
-
-pub fn create_target_dir(path: &Path) -> Result<()> {
-    // cargo creates all paths to the target directory, and writes
-    // a cache dir tag only if the path doesn't previously exist.
-    if !path.exists() {
-        file::create_dir_all(path)?;
-        fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(path.join("CACHEDIR.TAG"))?
-            .write_all(CACHEDIR_TAG.as_bytes())?;
+impl Termination {
+    fn new(oom_killed: bool, status: &ExitStatus) -> Option<Termination> {
+        if oom_killed {
+            return Some(Termination::OomKilled);
+        }
+        // the engine and shells exit with 128 + the signal number.
+        match status.code()? {
+            code @ 129..=192 => Some(Termination::Signal { signal: code - 128 }),
+            _ => None,
+        }
     }
-    Ok(())
-}
 
-impl Engine {
-    pub fn command(&self) -> Command {
-        let mut command = Command::new(&self.path);
-        if self.needs_remote() {
-            // if we're using podman and not podman-remote, need `--remote`.
-            command.arg("--remote");
-        }
-        command
+    fn signal_name(signal: i32) -> Option<&'static str> {
+        Some(match signal {
+            1 => "SIGHUP",
+            2 => "SIGINT",
+            6 => "SIGABRT",
+            9 => "SIGKILL",
+            11 => "SIGSEGV",
+            15 => "SIGTERM",
+            _ => return None,
+        })
     }
 
-    pub fn subcommand(&self, cmd: &str) -> Command {
-        let mut command = self.command();
-        command.arg(cmd);
-        command
+    fn report(self, msg_info: &mut MessageInfo) -> Result<()> {
+        let suggestion = "reduce the number of parallel jobs with `--jobs` or `CARGO_BUILD_JOBS`, or increase the memory available to the container engine, or with `CROSS_CONTAINER_OPTS=\"--memory=8g\"`.";
+        match self {
+            Termination::OomKilled => {
+                msg_info.error("the container ran out of memory, and a process of the build was killed by the kernel.")?;
+                msg_info.note(suggestion)
+            }
+            Termination::Signal { signal } => {
+                let name = Termination::signal_name(signal)
+                    .map(|name| format!(" ({name})"))
+                    .unwrap_or_default();
+                msg_info.warn(format_args!(
+                    "the command in the container was terminated by signal {signal}{name}."
+                ))?;
+                if signal == 9 {
+                    msg_info.note(format_args!(
+                        "this is often caused by running out of memory. {suggestion}"
+                    ))?;
+                }
+                Ok(())
+            }
+        }
     }
 
-    #[track_caller]
-    pub(crate) fn run_and_get_status(
-        &self,
-        args: &[&str],
+    fn reported(
+        termination: Option<Termination>,
         msg_info: &mut MessageInfo,
-    ) -> Result<ExitStatus> {
-        self.command().args(args).run_and_get_status(msg_info, true)
+    ) -> Result<Option<Termination>> {
+        if let Some(termination) = termination {
+            termination.report(msg_info)?;
+        }
+        Ok(termination)
     }
 
-    #[track_caller]
-    pub(crate) fn run_and_get_output(
-        &self,
-        args: &[&str],
+    /// Why the command in `container` failed with `status`, which is
+    /// reported. The container must not have been removed yet.
+    pub(crate) fn inspect(
+        engine: &Engine,
+        container: &str,
+        status: &ExitStatus,
         msg_info: &mut MessageInfo,
-    ) -> Result<Output> {
-        self.command().args(args).run_and_get_output(msg_info)
+    ) -> Result<Option<Termination>> {
+        if status.success() {
+            return Ok(None);
+        }
+        let oom_killed = DockerContainer::new(engine, container).oom_killed(msg_info);
+        Termination::reported(Termination::new(oom_killed, status), msg_info)
     }
 
-    pub fn parse_opts(value: &str) -> Result<Vec<String>> {
-        shell_words::split(value)
-            .wrap_err_with(|| format!("could not parse docker opts of {}", value))
+    /// Why the command in `container`, started at `since`, failed with
+    /// `status`, from the events of the engine, for containers removed
+    /// once they exit.
+    pub(crate) fn from_events(
+        engine: &Engine,
+        container: &str,
+        since: time::SystemTime,
+        status: &ExitStatus,
+        msg_info: &mut MessageInfo,
+    ) -> Result<Option<Termination>> {
+        if status.success() {
+            return Ok(None);
+        }
+        let oom_killed = DockerContainer::new(engine, container).oom_event(since, msg_info);
+        Termination::reported(Termination::new(oom_killed, status), msg_info)
     }
+}
 
-    /// Register binfmt interpreters
-    pub(crate) fn register_binfmt(
-        &self,
-        target: &Target,
-        msg_info: &mut MessageInfo,
-    ) -> Result<()> {
-        let cmd = if target.is_windows() {
-            // https://www.kernel.org/doc/html/latest/admin-guide/binfmt-misc.html
-            "mount binfmt_misc -t binfmt_misc /proc/sys/fs/binfmt_misc && \
-                echo ':wine:M::MZ::/usr/bin/run-detectors:' > /proc/sys/fs/binfmt_misc/register"
-        } else {
-            "apt-get update && apt-get install --no-install-recommends --assume-yes \
-                binfmt-support qemu-user-static"
-        };
+// exit code when a container command exceeds its timeout,
+// matching the convention of coreutils `timeout`.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+// how often to poll the container command for completion.
+const TIMEOUT_POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// The number of bytes of the output kept to match transient engine errors.
+const OUTPUT_TAIL_LEN: usize = 8192;
+
+/// `docker run` and `podman run` exit with 125 if the engine itself failed.
+const ENGINE_ERROR_EXIT_CODE: i32 = 125;
+
+/// The delay before the first retry, increased for each attempt.
+pub const RETRY_DELAY: time::Duration = time::Duration::from_secs(2);
+
+/// Errors of the container engine known to be transient, in lowercase.
+const TRANSIENT_ENGINE_ERRORS: &[&str] = &[
+    "device or resource busy",
+    "is already in use by container",
+    "tls handshake timeout",
+    "i/o timeout",
+    "connection reset by peer",
+    "context deadline exceeded",
+    "unexpected eof",
+    "error creating overlay mount",
+    "toomanyrequests",
+];
+
+#[cfg(target_family = "unix")]
+pub(crate) fn timeout_exit_status() -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    // the raw wait status stores the exit code in the second byte.
+    ExitStatus::from_raw(TIMEOUT_EXIT_CODE << 8)
+}
 
-        let mut docker = self.subcommand("run");
-        docker.add_userns();
-        docker.arg("--privileged");
-        docker.arg("--rm");
-        docker.arg(UBUNTU_BASE);
-        docker.args(["sh", "-c", cmd]);
+#[cfg(target_family = "windows")]
+pub(crate) fn timeout_exit_status() -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(TIMEOUT_EXIT_CODE as u32)
+}
 
-        docker.run(msg_info, false)
-    }
+fn forward_output<R, W>(
+    mut reader: R,
+    mut writer: W,
+    last_output: Arc<Mutex<time::Instant>>,
+    tail: Option<Arc<Mutex<Vec<u8>>>>,
+) -> thread::JoinHandle<()>
+where
+    R: io::Read + Send + 'static,
+    W: io::Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buffer = [0u8; 8192];
+        while let Ok(count @ 1..) = reader.read(&mut buffer) {
+            if let Ok(mut last) = last_output.lock() {
+                *last = time::Instant::now();
+            }
+            if let Some(Ok(mut tail)) = tail.as_ref().map(|t| t.lock()) {
+                tail.extend_from_slice(&buffer[..count]);
+                let excess = tail.len().saturating_sub(OUTPUT_TAIL_LEN);
+                tail.drain(..excess);
+            }
+            if writer.write_all(&buffer[..count]).is_err() {
+                break;
+            }
+            writer.flush().ok();
+        }
+    })
 }
 
-fn validate_env_var<'a>(
-    var: &'a str,
-    warned: &mut bool,
-    var_type: &'static str,
-    var_syntax: &'static str,
+/// Runs the command with its output forwarded, remapping the paths of the
+/// JSON messages on stdout with `remap`, keeping the end of stderr in
+/// `stderr_tail`, and killing the container if it exceeds `timeout`.
+#[track_caller]
+fn run_and_forward_output(
+    docker: &mut Command,
+    engine: &Engine,
+    container_id: &str,
+    timeout: Option<time::Duration>,
+    remap: Option<&PathRemap>,
+    stderr_tail: Option<Arc<Mutex<Vec<u8>>>>,
     msg_info: &mut MessageInfo,
-) -> Result<(&'a str, Option<&'a str>)> {
-    let (key, value) = match var.split_once('=') {
-        Some((key, value)) => (key, Some(value)),
-        _ => (var, None),
+) -> Result<Option<ExitStatus>> {
+    use std::process::Stdio;
+
+    docker.debug(msg_info)?;
+    if timeout.is_some() || remap.is_some() {
+        docker.stdout(Stdio::piped());
+    }
+    docker.stderr(Stdio::piped());
+    let mut child = docker.spawn().map_err(|e| CommandError::CouldNotExecute {
+        source: Box::new(e),
+        command: docker.command_pretty(msg_info, |cmd| {
+            crate::extensions::STRIPPED_BINS.iter().any(|f| f == &cmd)
+        }),
+    })?;
+
+    let start = time::Instant::now();
+    let last_output = Arc::new(Mutex::new(start));
+    let mut forwarders = vec![];
+    if let Some(stdout) = child.stdout.take() {
+        let writer: Box<dyn io::Write + Send> = match remap {
+            Some(remap) => Box::new(RemapWriter::new(io::stdout(), remap.clone())),
+            None => Box::new(io::stdout()),
+        };
+        forwarders.push(forward_output(stdout, writer, last_output.clone(), None));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forwarders.push(forward_output(
+            stderr,
+            io::stderr(),
+            last_output.clone(),
+            stderr_tail,
+        ));
+    }
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            break None;
+        }
+        thread::sleep(TIMEOUT_POLL_INTERVAL);
     };
 
-    if value.is_none()
-        && !*warned
-        && !var
-            .chars()
-            .all(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '_' | '0'..='9'))
-    {
-        msg_info.warn(format_args!(
-            "got {var_type} of \"{var}\" which is not a valid environment variable name. the proper syntax is {var_syntax}"
+    if let (None, Some(timeout)) = (status, timeout) {
+        let container = DockerContainer::new(engine, container_id);
+        let state = container.state(msg_info);
+        container.stop(NO_TIMEOUT, msg_info).ok();
+        container.remove(msg_info).ok();
+        child.kill().ok();
+        child.wait().ok();
+
+        let silent_for = last_output
+            .lock()
+            .map(|last| last.elapsed())
+            .unwrap_or_default();
+        msg_info.error(format_args!(
+            "container command exceeded the timeout of {}s and was killed.",
+            timeout.as_secs()
         ))?;
-        *warned = true;
+        msg_info.status(format_args!(
+            " > last output was {}s ago",
+            silent_for.as_secs()
+        ))?;
+        match state {
+            Ok(state) => msg_info.status(format_args!(" > container state was {state:?}"))?,
+            Err(e) => msg_info.status(format_args!(" > unable to get container state: {e}"))?,
+        }
+        msg_info.note(
+            "the timeout can be changed with the `timeout` key of the target configuration.",
+        )?;
     }
 
-    if key == "CROSS_RUNNER" {
-        eyre::bail!(
-            "CROSS_RUNNER environment variable name is reserved and cannot be pass through"
-        );
+    for forwarder in forwarders {
+        forwarder.join().ok();
     }
 
-    Ok((key, value))
+    Ok(status)
 }
 
-impl CommandVariant {
+/// Returns `true` if cargo prints its messages as JSON, with
+/// `--message-format json` or one of its variants.
+pub(crate) fn uses_json_messages(args: &[String]) -> bool {
+    let mut args = args.iter().take_while(|a| *a != "--");
+    while let Some(arg) = args.next() {
+        let format = match arg.strip_prefix("--message-format") {
+            Some("") => args.next().map(String::as_str),
+            Some(rest) => rest.strip_prefix('='),
+            None => None,
+        };
+        if format.is_some_and(|f| f.split(',').any(|f| f.starts_with("json"))) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Maps the paths in the container back to the paths on the host, so the
+/// JSON messages of cargo can be used by editors.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PathRemap {
+    /// The container and host paths, the longest container paths first.
+    prefixes: Vec<(String, String)>,
+    /// The messages are not printed, since they were only requested by cross.
+    hide_messages: bool,
+    /// The artifacts of the messages, shared with the clones forwarding the
+    /// output.
+    artifacts: Arc<Mutex<Vec<CompilerArtifact>>>,
+}
+
+impl PathRemap {
+    pub(crate) fn new(paths: &DockerPaths, target_mount_path: &str) -> Self {
+        let toolchain_dirs = paths.directories.toolchain_directories();
+        let package_dirs = paths.directories.package_directories();
+        Self::from_prefixes([
+            (package_dirs.mount_root(), package_dirs.host_root()),
+            (target_mount_path, package_dirs.target()),
+            (toolchain_dirs.cargo_mount_path(), toolchain_dirs.cargo()),
+            (toolchain_dirs.xargo_mount_path(), toolchain_dirs.xargo()),
+            (
+                toolchain_dirs.sysroot_mount_path(),
+                toolchain_dirs.get_sysroot(),
+            ),
+        ])
+    }
+
+    fn from_prefixes<'a>(prefixes: impl IntoIterator<Item = (&'a str, &'a Path)>) -> Self {
+        let mut prefixes: Vec<(String, String)> = prefixes
+            .into_iter()
+            .filter_map(|(container, host)| {
+                let container = container.trim_end_matches('/');
+                let host = host.to_str()?.trim_end_matches(['/', '\\']);
+                (!container.is_empty() && container != host)
+                    .then(|| (container.to_owned(), host.to_owned()))
+            })
+            .collect();
+        prefixes.sort_by_key(|(container, _)| std::cmp::Reverse(container.len()));
+        PathRemap {
+            prefixes,
+            ..PathRemap::default()
+        }
+    }
+
+    /// Replace the container paths in `text`, in a single pass so host
+    /// paths are never replaced again.
+    fn remap_str(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        let mut previous = None;
+        while let Some(c) = rest.chars().next() {
+            // only replace paths which are not part of another path
+            let at_boundary =
+                previous.map_or(true, |p: char| !(p.is_alphanumeric() || "/._-".contains(p)));
+            let prefix = self.prefixes.iter().find(|(container, _)| {
+                rest.strip_prefix(container.as_str())
+                    .is_some_and(|tail| tail.is_empty() || !is_path_char(tail))
+            });
+            match prefix {
+                Some((container, host)) if at_boundary => {
+                    result.push_str(host);
+                    rest = &rest[container.len()..];
+                    previous = container.chars().last();
+                }
+                _ => {
+                    result.push(c);
+                    rest = &rest[c.len_utf8()..];
+                    previous = Some(c);
+                }
+            }
+        }
+        result
+    }
+
+    fn remap_value(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => *s = self.remap_str(s),
+            serde_json::Value::Array(values) => {
+                values.iter_mut().for_each(|v| self.remap_value(v));
+            }
+            serde_json::Value::Object(map) => {
+                map.values_mut().for_each(|v| self.remap_value(v));
+            }
+            _ => (),
+        }
+    }
+
+    /// Remap the paths of a JSON message, leaving other lines unchanged.
+    pub(crate) fn remap_line(&self, line: &str) -> Option<serde_json::Value> {
+        if !line.trim_start().starts_with('{') {
+            return None;
+        }
+        let mut value: serde_json::Value = serde_json::from_str(line).ok()?;
+        self.remap_value(&mut value);
+        Some(value)
+    }
+
+    /// Only record the artifacts of the messages, without printing them,
+    /// since they were only requested by cross.
+    pub(crate) fn hide_messages(mut self, hide: bool) -> Self {
+        self.hide_messages = hide;
+        self
+    }
+
+    fn record_artifact(&self, message: &serde_json::Value) {
+        if let (Some(artifact), Ok(mut artifacts)) = (
+            CompilerArtifact::from_message(message),
+            self.artifacts.lock(),
+        ) {
+            artifacts.push(artifact);
+        }
+    }
+
+    /// The artifacts cargo reported in the remapped messages.
+    pub(crate) fn take_artifacts(&self) -> Vec<CompilerArtifact> {
+        self.artifacts
+            .lock()
+            .map(|mut artifacts| std::mem::take(&mut *artifacts))
+            .unwrap_or_default()
+    }
+}
+
+/// The files of a `compiler-artifact` message of cargo, with the paths of
+/// the host.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompilerArtifact {
+    pub filenames: Vec<PathBuf>,
+    pub executable: Option<PathBuf>,
+}
+
+impl CompilerArtifact {
+    fn from_message(message: &serde_json::Value) -> Option<Self> {
+        if message["reason"] != "compiler-artifact" {
+            return None;
+        }
+        let filenames = message["filenames"]
+            .as_array()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| name.as_str().map(PathBuf::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(CompilerArtifact {
+            filenames,
+            executable: message["executable"].as_str().map(PathBuf::from),
+        })
+    }
+}
+
+/// The result of the command run in the container.
+#[derive(Debug)]
+pub struct ContainerRun {
+    pub status: ExitStatus,
+    /// The artifacts cargo reported in its JSON messages, for `cross flash`
+    /// and the build report.
+    pub artifacts: Vec<CompilerArtifact>,
+    /// How the command was terminated, if it failed without exiting by itself.
+    pub termination: Option<Termination>,
+}
+
+impl ContainerRun {
+    pub(crate) fn new(status: ExitStatus, remap: Option<&PathRemap>) -> Self {
+        ContainerRun {
+            status,
+            artifacts: remap.map(PathRemap::take_artifacts).unwrap_or_default(),
+            termination: None,
+        }
+    }
+
+    #[must_use]
+    pub(crate) fn with_termination(mut self, termination: Option<Termination>) -> Self {
+        self.termination = termination;
+        self
+    }
+}
+
+/// The container path continues with more characters of a file name.
+fn is_path_char(tail: &str) -> bool {
+    tail.chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || "._-".contains(c))
+}
+
+/// Forwards the output line by line, remapping the paths of JSON messages.
+struct RemapWriter<W: io::Write> {
+    inner: W,
+    remap: PathRemap,
+    buffer: Vec<u8>,
+}
+
+impl<W: io::Write> RemapWriter<W> {
+    fn new(inner: W, remap: PathRemap) -> Self {
+        RemapWriter {
+            inner,
+            remap,
+            buffer: vec![],
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let remapped = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| self.remap.remap_line(line.trim_end_matches(['\r', '\n'])));
+        match remapped {
+            Some(remapped) => {
+                self.remap.record_artifact(&remapped);
+                match self.remap.hide_messages {
+                    true => Ok(()),
+                    false => writeln!(self.inner, "{remapped}"),
+                }
+            }
+            None => self.inner.write_all(line),
+        }
+    }
+}
+
+impl<W: io::Write> io::Write for RemapWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(index) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=index).collect();
+            self.write_line(&line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // incomplete lines are kept until the rest of the message arrives.
+        self.inner.flush()
+    }
+}
+
+impl<W: io::Write> Drop for RemapWriter<W> {
+    fn drop(&mut self) {
+        let rest = std::mem::take(&mut self.buffer);
+        if !rest.is_empty() {
+            self.write_line(&rest).ok();
+        }
+        self.inner.flush().ok();
+    }
+}
+
+/// The shell script running the build command `cmd` in the container, with
+/// the `PATH` extended by the `container` config of the target after sourcing
+/// its profile script.
+fn build_script(
+    sysroot: &str,
+    container: Option<&CrossContainerConfig>,
+    cmd: &SafeCommand,
+) -> String {
+    let mut path = vec![];
+    if let Some(container) = container {
+        path.extend(
+            container
+                .path()
+                .iter()
+                .map(|p| shell_escape::unix::escape(p.into()).into_owned()),
+        );
+    }
+    path.push("\"$PATH\"".to_owned());
+    path.push(format!("\"{sysroot}/bin\""));
+
+    let mut script = String::new();
+    let profile_script = container.and_then(CrossContainerConfig::profile_script);
+    if let Some(profile_script) = profile_script {
+        script.push_str(&format!(
+            ". {} && ",
+            shell_escape::unix::escape(profile_script.into())
+        ));
+    }
+    script.push_str(&format!("PATH={}", path.join(":")));
+    if profile_script.is_some() {
+        script.push_str(" exec");
+    }
+    script.push_str(&format!(" {cmd:?}"));
+    script
+}
+
+/// Returns `true` if the output of the container engine matches an error of
+/// the engine which is known to be transient, so the run can be retried.
+pub fn is_transient_engine_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    TRANSIENT_ENGINE_ERRORS
+        .iter()
+        .any(|error| stderr.contains(error))
+}
+
+/// The number of times to retry a container run which failed with a
+/// transient engine error, from `CROSS_CONTAINER_RETRIES`.
+pub(crate) fn container_retries() -> Result<u32> {
+    match env::var("CROSS_CONTAINER_RETRIES") {
+        Ok(retries) => retries
+            .parse()
+            .wrap_err_with(|| format!("invalid CROSS_CONTAINER_RETRIES `{retries}`")),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Runs a `docker run` or `docker exec` command, retrying it up to `retries`
+/// times if the engine failed to start the container with a transient error,
+/// and remapping the paths of the JSON messages of cargo with `remap`.
+///
+/// Only failures of the engine itself are retried: the command in the
+/// container never ran, so cargo did not modify the target directory.
+/// Returns `None` if the command exceeded `timeout`, after the container has
+/// been killed and the diagnostics have been printed.
+#[track_caller]
+pub(crate) fn run_and_get_status_with_retries(
+    docker: &mut Command,
+    engine: &Engine,
+    container_id: &str,
+    timeout: Option<time::Duration>,
+    retries: u32,
+    remap: Option<&PathRemap>,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<ExitStatus>> {
+    let mut attempt = 0;
+    loop {
+        let stderr_tail = Arc::new(Mutex::new(vec![]));
+        let status = run_and_forward_output(
+            docker,
+            engine,
+            container_id,
+            timeout,
+            remap,
+            Some(stderr_tail.clone()),
+            msg_info,
+        )?;
+        let stderr = stderr_tail
+            .lock()
+            .map(|tail| String::from_utf8_lossy(&tail).into_owned())
+            .unwrap_or_default();
+        let engine_failed = status.is_some_and(|s| s.code() == Some(ENGINE_ERROR_EXIT_CODE));
+        if attempt == retries || !engine_failed || !is_transient_engine_error(&stderr) {
+            return Ok(status);
+        }
+
+        attempt += 1;
+        msg_info.note(format_args!(
+            "container engine failed with a transient error, retrying ({attempt}/{retries})."
+        ))?;
+        // the container may have been created before the engine failed.
+        DockerContainer::new(engine, container_id)
+            .remove(msg_info)
+            .ok();
+        thread::sleep(RETRY_DELAY * attempt);
+    }
+}
+
+pub(crate) fn time_to_millis(timestamp: &time::SystemTime) -> Result<u64> {
+    Ok(timestamp
+        .duration_since(time::SystemTime::UNIX_EPOCH)?
+        .as_millis() as u64)
+}
+
+pub(crate) fn time_from_millis(millis: u64) -> time::SystemTime {
+    time::SystemTime::UNIX_EPOCH + time::Duration::from_millis(millis)
+}
+
+pub(crate) fn now_as_millis() -> Result<u64> {
+    time_to_millis(&time::SystemTime::now())
+}
+
+const CACHEDIR_TAG: &str = "Signature: 8a477f597d28d172789f06886806bc55
+# This file is a cache directory tag created by cross.
+# For information about cache directory tags see https://bford.info/cachedir/";
+
+/// The label of the containers of the workspace at `workspace_root`, which is
+/// canonicalized so a workspace reached through a symlink has the same label.
+pub fn workspace_label(workspace_root: &Path) -> Result<String> {
+    let workspace_root = file::canonicalize(workspace_root)?;
+    Ok(format!(
+        "{}.workspace-root={}",
+        crate::CROSS_LABEL_DOMAIN,
+        workspace_root.to_utf8()?
+    ))
+}
+
+pub fn create_target_dir(path: &Path) -> Result<()> {
+    // cargo creates all paths to the target directory, and writes
+    // a cache dir tag only if the path doesn't previously exist.
+    if !path.exists() {
+        file::create_dir_all(path)?;
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path.join("CACHEDIR.TAG"))?
+            .write_all(CACHEDIR_TAG.as_bytes())?;
+    }
+    Ok(())
+}
+
+impl Engine {
+    pub fn command(&self) -> Command {
+        let mut command = engine_command(&self.path, self.kind, self.context.as_deref());
+        if self.needs_remote() {
+            // if we're using podman and not podman-remote, need `--remote`.
+            command.arg("--remote");
+        }
+        command
+    }
+
+    pub fn subcommand(&self, cmd: &str) -> Command {
+        let mut command = self.command();
+        command.arg(cmd);
+        command
+    }
+
+    /// Detect how cross runs in a container when `CROSS_CONTAINER_IN_CONTAINER`
+    /// is unset. In a sibling container setup, the socket of the engine of the
+    /// host is mounted into the container, and the engine knows the container
+    /// running cross and mounts the paths through its volumes. An engine
+    /// reached over the network, such as a docker-in-docker service, cannot
+    /// mount them at all.
+    pub(crate) fn detect_container_setup(&mut self, msg_info: &mut MessageInfo) -> Result<()> {
+        let mounts_socket = || {
+            file::read("/proc/self/mountinfo")
+                .is_ok_and(|m| mounts_engine_socket(&m, &engine_sockets()))
+        };
+        if cfg!(target_os = "linux")
+            && mounts_socket()
+            && docker_inspect_self(self, msg_info).is_ok()
+        {
+            msg_info.note("detected that cross runs in a container sharing the container engine of the host, mounting the paths through its volumes. set `CROSS_CONTAINER_IN_CONTAINER=false` to disable this detection.")?;
+            self.in_docker = true;
+        } else if uses_network_host() {
+            msg_info.warn("cross runs in a container, but the container engine is reached over the network and cannot mount the paths of this container. set `CROSS_REMOTE=1` to copy them into data volumes instead.")?;
+        }
+        Ok(())
+    }
+
+    /// Get the registry digest of a local image, if it was pulled from a registry.
+    pub fn image_digest(&self, image: &str, msg_info: &mut MessageInfo) -> Option<String> {
+        self.subcommand("image")
+            .args([
+                "inspect",
+                "--format",
+                r#"{{join .RepoDigests "\n"}}"#,
+                image,
+            ])
+            .run_and_get_stdout(msg_info)
+            .ok()?
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(ToOwned::to_owned)
+    }
+
+    /// Get the labels of a local image.
+    pub fn image_labels(
+        &self,
+        image: &str,
+        msg_info: &mut MessageInfo,
+    ) -> Result<BTreeMap<String, String>> {
+        let labels = self
+            .subcommand("image")
+            .args(["inspect", "--format", "{{json .Config.Labels}}", image])
+            .run_and_get_stdout(msg_info)?;
+        // images without labels have `null` labels
+        let labels: Option<BTreeMap<String, String>> = serde_json::from_str(labels.trim())
+            .wrap_err_with(|| format!("could not parse the labels of image `{image}`"))?;
+        Ok(labels.unwrap_or_default())
+    }
+
+    /// Get the ID of a local image, the digest of its configuration, or `None`
+    /// if the image doesn't exist.
+    pub fn image_id(&self, image: &str, msg_info: &mut MessageInfo) -> Option<String> {
+        self.subcommand("image")
+            .args(["inspect", "--format", "{{.Id}}", image])
+            .run_and_get_stdout(msg_info)
+            .ok()
+            .map(|id| id.trim().to_owned())
+            .filter(|id| !id.is_empty())
+    }
+
+    #[track_caller]
+    pub(crate) fn run_and_get_status(
+        &self,
+        args: &[&str],
+        msg_info: &mut MessageInfo,
+    ) -> Result<ExitStatus> {
+        self.command()
+            .args(args)
+            .container_output(msg_info)?
+            .run_and_get_status(msg_info, true)
+    }
+
+    #[track_caller]
+    pub(crate) fn run_and_get_output(
+        &self,
+        args: &[&str],
+        msg_info: &mut MessageInfo,
+    ) -> Result<Output> {
+        self.command().args(args).run_and_get_output(msg_info)
+    }
+
+    pub fn parse_opts(value: &str) -> Result<Vec<String>> {
+        shell_words::split(value)
+            .wrap_err_with(|| format!("could not parse docker opts of {}", value))
+    }
+
+    /// Register binfmt interpreters
+    pub(crate) fn register_binfmt(
+        &self,
+        target: &Target,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
+        let cmd = if target.is_windows() {
+            // https://www.kernel.org/doc/html/latest/admin-guide/binfmt-misc.html
+            "mount binfmt_misc -t binfmt_misc /proc/sys/fs/binfmt_misc && \
+                echo ':wine:M::MZ::/usr/bin/run-detectors:' > /proc/sys/fs/binfmt_misc/register"
+        } else {
+            "apt-get update && apt-get install --no-install-recommends --assume-yes \
+                binfmt-support qemu-user-static"
+        };
+
+        let mut docker = self.subcommand("run");
+        docker.add_userns(self.is_nested);
+        docker.arg("--privileged");
+        docker.arg("--rm");
+        docker.arg(UBUNTU_BASE);
+        docker.args(["sh", "-c", cmd]);
+
+        docker.run(msg_info, false)
+    }
+}
+
+/// The seccomp profile selected with `target.{}.seccomp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeccompProfile {
+    /// The default profile of the container engine.
+    Default,
+    Unconfined,
+    /// A custom profile, relative to the workspace root.
+    File(PathBuf),
+}
+
+impl SeccompProfile {
+    pub fn new(value: &str, workspace_root: &Path) -> Self {
+        match value {
+            "default" => SeccompProfile::Default,
+            "unconfined" => SeccompProfile::Unconfined,
+            path => SeccompProfile::File(workspace_root.join(path)),
+        }
+    }
+}
+
+fn validate_seccomp_profile(path: &Path) -> Result<()> {
+    let profile =
+        file::read(path).wrap_err_with(|| format!("could not read seccomp profile `{path:?}`"))?;
+    let value: serde_json::Value = serde_json::from_str(&profile)
+        .wrap_err_with(|| format!("seccomp profile `{path:?}` is not valid JSON"))?;
+    if !value.is_object() {
+        eyre::bail!("seccomp profile `{path:?}` must be a JSON object");
+    }
+    Ok(())
+}
+
+#[allow(unused_variables)] // target_os = "windows"
+fn seccomp_path(engine_type: EngineType, path: &Path) -> Result<String> {
+    #[cfg(target_os = "windows")]
+    if engine_type.is_podman() {
+        // podman weirdly expects a WSL path here, and fails otherwise
+        return path.as_posix_absolute();
+    }
+    Ok(path.to_utf8()?.to_owned())
+}
+
+/// The volume holding the seccomp profiles for remote podman.
+pub const SECCOMP_VOLUME: &str = "cross-seccomp";
+
+/// Copy a seccomp profile into [`SECCOMP_VOLUME`], returning its path on the
+/// remote host.
+///
+/// The docker CLI reads the profile on the client and sends its contents to
+/// the daemon, but remote podman resolves the path on the host running the
+/// service, where a profile written to the local target dir doesn't exist.
+fn remote_seccomp_path(
+    engine: &Engine,
+    name: &str,
+    profile: &[u8],
+    msg_info: &mut MessageInfo,
+) -> Result<String> {
+    let volume = DockerVolume::new(engine, SECCOMP_VOLUME);
+    if !volume.exists(msg_info)? {
+        volume.create(msg_info)?;
+    }
+
+    let mut import = engine.subcommand("volume");
+    import.args(["import", SECCOMP_VOLUME, "-"]);
+    import.stdin(Stdio::piped());
+    import.container_output(msg_info)?;
+    import.debug(msg_info)?;
+    let mut child = import
+        .spawn()
+        .wrap_err("could not run the container engine")?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre::eyre!("the input of the process is not piped"))?
+        .write_all(&tar_file(name, profile))?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(eyre::eyre!(
+            "could not copy the seccomp profile to the volume `{SECCOMP_VOLUME}`"
+        ))
+        .with_suggestion(|| "`podman volume import` requires podman 4.0 or later");
+    }
+
+    let mountpoint = engine
+        .subcommand("volume")
+        .args(["inspect", "--format", "{{.Mountpoint}}", SECCOMP_VOLUME])
+        .run_and_get_stdout(msg_info)?;
+    Ok(format!("{}/{name}", mountpoint.trim()))
+}
+
+/// Create a tarball containing a single file, in the ustar format.
+fn tar_file(name: &str, contents: &[u8]) -> Vec<u8> {
+    const BLOCK: usize = 512;
+
+    fn octal(field: &mut [u8], value: usize) {
+        let digits = format!("{value:0width$o}", width = field.len() - 1);
+        let (digits_field, nul) = field.split_at_mut(digits.len());
+        digits_field.copy_from_slice(digits.as_bytes());
+        nul.fill(0);
+    }
+
+    debug_assert!(name.len() < 100, "the name does not fit in the header");
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], contents.len());
+    octal(&mut header[136..148], 0);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // the checksum is computed with the checksum field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| b as usize).sum();
+    octal(&mut header[148..155], checksum);
+
+    let padding = (BLOCK - contents.len() % BLOCK) % BLOCK;
+    let mut tar = Vec::with_capacity(3 * BLOCK + contents.len() + padding);
+    tar.extend_from_slice(&header);
+    tar.extend_from_slice(contents);
+    tar.resize(tar.len() + padding + 2 * BLOCK, 0);
+    tar
+}
+
+pub(crate) fn validate_env_var<'a>(
+    var: &'a str,
+    warned: &mut bool,
+    var_type: &'static str,
+    var_syntax: &'static str,
+    msg_info: &mut MessageInfo,
+) -> Result<(&'a str, Option<&'a str>)> {
+    let (key, value) = match var.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        _ => (var, None),
+    };
+
+    if value.is_none()
+        && !*warned
+        && !var
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '_' | '0'..='9'))
+    {
+        msg_info.warn(format_args!(
+            "got {var_type} of \"{var}\" which is not a valid environment variable name. the proper syntax is {var_syntax}"
+        ))?;
+        *warned = true;
+    }
+
+    if key == "CROSS_RUNNER" {
+        eyre::bail!(
+            "CROSS_RUNNER environment variable name is reserved and cannot be pass through"
+        );
+    }
+
+    Ok((key, value))
+}
+
+impl CommandVariant {
     pub(crate) fn safe_command(&self) -> SafeCommand {
         SafeCommand::new(self.to_str())
     }
 }
 
+/// Host variables still passed through with `strict-env`, since they only
+/// affect how output is displayed or how the network is reached.
+const STRICT_ENV_ALLOWLIST: &[&str] = &[
+    "TERM",
+    "http_proxy",
+    "https_proxy",
+    "HTTPS_PROXY",
+    "HTTP_TIMEOUT",
+    "CARGO_TERM_COLOR",
+    "CARGO_TERM_VERBOSE",
+    "CARGO_TERM_PROGRESS_WHEN",
+    "CROSS_DEBUG",
+];
+
+/// Split the host environment variables `keys` into the ones implicitly
+/// passed through to the container and, with `strict`, the ones filtered
+/// out. Variables in `explicit` are already passed through by the caller.
+fn configuration_envvars<'a>(
+    keys: &'a [String],
+    strict: bool,
+    explicit: &[String],
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let other = &[
+        "http_proxy",
+        "TERM",
+        "RUSTDOCFLAGS",
+        "RUSTFLAGS",
+        "BROWSER",
+        "HTTPS_PROXY",
+        "HTTP_TIMEOUT",
+        "https_proxy",
+        "QEMU_STRACE",
+    ];
+    let cargo_prefix_skip = &[
+        "CARGO_HOME",
+        "CARGO_TARGET_DIR",
+        "CARGO_BUILD_TARGET",
+        "CARGO_BUILD_TARGET_DIR",
+        "CARGO_BUILD_RUSTC",
+        "CARGO_BUILD_RUSTC_WRAPPER",
+        "CARGO_BUILD_RUSTC_WORKSPACE_WRAPPER",
+        "CARGO_BUILD_RUSTDOC",
+    ];
+    let cross_prefix_skip = &[
+        "CROSS_RUNNER",
+        "CROSS_RUSTC_MAJOR_VERSION",
+        "CROSS_RUSTC_MINOR_VERSION",
+        "CROSS_RUSTC_PATCH_VERSION",
+        "CROSS_TARGET",
+        "CROSS_DEB_ARCH",
+        "CROSS_IMAGE_DIGEST",
+        "CROSS_CONTEXT",
+    ];
+    let is_passthrough = |key: &str| -> bool {
+        other.contains(&key)
+            || key.starts_with("CARGO_") && !cargo_prefix_skip.contains(&key)
+            || key.starts_with("CROSS_") && !cross_prefix_skip.contains(&key)
+    };
+
+    keys.iter()
+        .map(String::as_str)
+        .filter(|key| is_passthrough(key) && !explicit.iter().any(|e| e == key))
+        .partition(|key| !strict || STRICT_ENV_ALLOWLIST.contains(key))
+}
+
+/// Validate a device mapping, `HOST[:CONTAINER[:PERMISSIONS]]`, returning
+/// the host path.
+fn parse_device(device: &str) -> Result<&str> {
+    let mut parts = device.split(':');
+    let host = parts.next().unwrap_or_default();
+    let container = parts.next();
+    let permissions = parts.next();
+    let is_absolute = |path: &str| path.starts_with('/');
+    if !is_absolute(host) || container.is_some_and(|c| !is_absolute(c)) {
+        eyre::bail!("invalid device `{device}`, the device paths must be absolute");
+    }
+    if permissions.is_some_and(|p| p.is_empty() || !p.chars().all(|c| "rwm".contains(c))) {
+        eyre::bail!("invalid device `{device}`, the permissions must be a combination of `rwm`");
+    }
+    if parts.next().is_some() {
+        eyre::bail!("invalid device `{device}`, expected `HOST[:CONTAINER[:PERMISSIONS]]`");
+    }
+    Ok(host)
+}
+
+/// Validate a port range, `PORT[-PORT]`, returning the first port.
+fn parse_port_range(range: &str) -> Option<u16> {
+    let parse = |port: &str| port.parse::<u16>().ok().filter(|&p| p != 0);
+    match range.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            (start <= end).then_some(start)
+        }
+        None => parse(range),
+    }
+}
+
+/// Validate a published port, `[[IP:][HOST]:]CONTAINER[/PROTOCOL]`,
+/// returning the first host port, if any.
+fn parse_publish(publish: &str) -> Result<Option<u16>> {
+    let invalid = || eyre::eyre!("invalid published port `{publish}`");
+    let (ports, protocol) = match publish.rsplit_once('/') {
+        Some((ports, protocol)) => (ports, Some(protocol)),
+        None => (publish, None),
+    };
+    if protocol.is_some_and(|p| !matches!(p, "tcp" | "udp" | "sctp")) {
+        return Err(invalid().with_note(|| "the protocol must be `tcp`, `udp` or `sctp`"));
+    }
+    let (host, container) = match ports.rsplit_once(':') {
+        Some((host, container)) => (Some(host), container),
+        None => (None, ports),
+    };
+    // the IP address may be an IPv6 address in brackets, such as `[::1]`
+    let host = host.map(|h| h.rsplit_once(':').map_or(h, |(_, port)| port));
+    parse_port_range(container).ok_or_else(|| {
+        invalid().with_note(|| "the container port must be a port or a range of ports")
+    })?;
+    match host {
+        Some(host) if !host.is_empty() => parse_port_range(host).map(Some).ok_or_else(|| {
+            invalid()
+                .with_suggestion(|| "use `[[IP:][HOST]:]CONTAINER[/PROTOCOL]`, such as `8080:80`")
+        }),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(unix)]
+fn device_group(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.gid())
+}
+
+#[cfg(not(unix))]
+fn device_group(_: &fs::Metadata) -> Option<u32> {
+    None
+}
+
 pub(crate) trait DockerCommandExt {
-    fn add_configuration_envvars(&mut self);
+    fn add_configuration_envvars(
+        &mut self,
+        strict: bool,
+        explicit: &[String],
+        msg_info: &mut MessageInfo,
+    ) -> Result<()>;
     fn add_envvars(
         &mut self,
         options: &DockerOptions,
         dirs: &ToolchainDirectories,
         msg_info: &mut MessageInfo,
     ) -> Result<()>;
+    fn add_network_envvars(&mut self, args: &[String]);
     fn add_cwd(&mut self, paths: &DockerPaths) -> Result<()>;
-    fn add_build_command(&mut self, dirs: &ToolchainDirectories, cmd: &SafeCommand) -> &mut Self;
+    fn add_build_command(
+        &mut self,
+        dirs: &ToolchainDirectories,
+        options: &DockerOptions,
+        cmd: &SafeCommand,
+    ) -> &mut Self;
     fn add_user_id(&mut self, is_rootless: bool);
-    fn add_userns(&mut self);
+    fn add_container_user(&mut self, user: Option<&ContainerUser>) -> &mut Self;
+    fn add_ssh_agent(
+        &mut self,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()>;
+    fn add_network(&mut self, options: &DockerOptions);
+    fn add_cache_proxy(
+        &mut self,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()>;
+    fn add_userns(&mut self, is_nested: bool);
+    fn add_devices(&mut self, options: &DockerOptions, msg_info: &mut MessageInfo) -> Result<()>;
+    fn add_publish(&mut self, options: &DockerOptions, msg_info: &mut MessageInfo) -> Result<()>;
+    fn add_container_labels(&mut self, options: &DockerOptions, paths: &DockerPaths) -> Result<()>;
     fn add_seccomp(
         &mut self,
-        engine_type: EngineType,
-        target: &Target,
-        metadata: &CargoMetadata,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        msg_info: &mut MessageInfo,
     ) -> Result<()>;
     fn add_mounts(
         &mut self,
         options: &DockerOptions,
         paths: &DockerPaths,
-        mount_cb: impl Fn(&mut Command, &Path, &Path) -> Result<()>,
+        mount_cb: impl Fn(&mut Command, &Path, &Path, bool) -> Result<()>,
         store_cb: impl FnMut((String, String)),
         msg_info: &mut MessageInfo,
     ) -> Result<()>;
+    #[allow(clippy::too_many_arguments)]
+    fn add_context(
+        &mut self,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        args: &[String],
+        mounts: &[(String, String)],
+        target_dir: &str,
+        mount_cb: impl Fn(&mut Command, &Path, &Path, bool) -> Result<()>,
+        store_cb: impl FnMut((String, String)),
+    ) -> Result<TempDir>;
 }
 
-impl DockerCommandExt for Command {
-    fn add_configuration_envvars(&mut self) {
-        let other = &[
-            "http_proxy",
-            "TERM",
-            "RUSTDOCFLAGS",
-            "RUSTFLAGS",
-            "BROWSER",
-            "HTTPS_PROXY",
-            "HTTP_TIMEOUT",
-            "https_proxy",
-            "QEMU_STRACE",
-        ];
-        let cargo_prefix_skip = &[
-            "CARGO_HOME",
-            "CARGO_TARGET_DIR",
-            "CARGO_BUILD_TARGET_DIR",
-            "CARGO_BUILD_RUSTC",
-            "CARGO_BUILD_RUSTC_WRAPPER",
-            "CARGO_BUILD_RUSTC_WORKSPACE_WRAPPER",
-            "CARGO_BUILD_RUSTDOC",
-        ];
-        let cross_prefix_skip = &[
-            "CROSS_RUNNER",
-            "CROSS_RUSTC_MAJOR_VERSION",
-            "CROSS_RUSTC_MINOR_VERSION",
-            "CROSS_RUSTC_PATCH_VERSION",
-        ];
-        let is_passthrough = |key: &str| -> bool {
-            other.contains(&key)
-                || key.starts_with("CARGO_") && !cargo_prefix_skip.contains(&key)
-                || key.starts_with("CROSS_") && !cross_prefix_skip.contains(&key)
-        };
+/// Replace `{rust_sysroot}` with the sysroot of the Rust toolchain in the
+/// container, and `{deb_arch}` with the debian architecture of the target.
+fn template_build_env(value: &str, rust_sysroot: &str, deb_arch: Option<&str>) -> Result<String> {
+    let mut rendered = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| eyre::eyre!("unclosed `{{` in `{value}`"))?;
+        match &rest[start + 1..start + end] {
+            "rust_sysroot" => rendered.push_str(rust_sysroot),
+            "deb_arch" => rendered.push_str(
+                deb_arch.ok_or_else(|| eyre::eyre!("the target has no debian architecture"))?,
+            ),
+            "sysroot" => {
+                return Err(eyre::eyre!("unknown placeholder `{{sysroot}}` in `{value}`"))
+                    .with_suggestion(|| {
+                        "use `{rust_sysroot}` for the sysroot of the Rust toolchain, or the path of the C sysroot of the image"
+                    });
+            }
+            name => eyre::bail!("unknown placeholder `{{{name}}}` in `{value}`"),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
 
+/// The variables of `build-env` for `target`, templated with the sysroot
+/// of the Rust toolchain.
+pub(crate) fn build_env_vars(
+    config: &Config,
+    target: &Target,
+    rust_sysroot: &str,
+) -> Result<Vec<(String, String)>> {
+    let deb_arch = target.target().deb_arch();
+    let mut vars = vec![];
+    for (key, value) in config.build_env(target).unwrap_or_default() {
+        if key.is_empty() || key.contains('=') {
+            eyre::bail!("invalid environment variable `{key}` in `build-env`");
+        }
+        let value = template_build_env(&value, rust_sysroot, deb_arch)
+            .wrap_err_with(|| format!("when templating `build-env.{key}`"))?;
+        vars.push((key, value));
+    }
+    Ok(vars)
+}
+
+impl DockerCommandExt for Command {
+    fn add_configuration_envvars(
+        &mut self,
+        strict: bool,
+        explicit: &[String],
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
         // also need to accept any additional flags used to configure
         // cargo or cross, but only pass what's actually present.
-        for (key, _) in env::vars() {
-            if is_passthrough(&key) {
-                self.args(["-e", &key]);
+        let keys: Vec<String> = env::vars().map(|(key, _)| key).collect();
+        let (passed, filtered) = configuration_envvars(&keys, strict, explicit);
+        for key in passed {
+            self.args(["-e", key]);
+        }
+        if !filtered.is_empty() && msg_info.is_verbose() {
+            msg_info.info("strict-env: not passing through the host environment variables:")?;
+            for key in filtered {
+                msg_info.info(format_args!("- {key}"))?;
             }
         }
+        Ok(())
     }
 
     fn add_envvars(
@@ -1010,11 +2536,11 @@ impl DockerCommandExt for Command {
         msg_info: &mut MessageInfo,
     ) -> Result<()> {
         let mut warned = false;
-        for ref var in options
+        let passthrough = options
             .config
             .env_passthrough(&options.target)
-            .unwrap_or_default()
-        {
+            .unwrap_or_default();
+        for var in &passthrough {
             validate_env_var(
                 var,
                 &mut warned,
@@ -1028,21 +2554,50 @@ impl DockerCommandExt for Command {
             self.args(["-e", var]);
         }
 
-        let runner = options.config.runner(&options.target);
+        let runner = match options.config.runner(&options.target)? {
+            Some(runners) => {
+                interpreter::select_runner(&runners, &options.target, &options.engine, msg_info)?
+            }
+            None => None,
+        };
         let cross_runner = format!("CROSS_RUNNER={}", runner.unwrap_or_default());
         self.args(["-e", &format!("XARGO_HOME={}", dirs.xargo_mount_path())])
-            .args(["-e", &format!("CARGO_HOME={}", dirs.cargo_mount_path())])
+            .args(["-e", &format!("CARGO_HOME={}", options.cargo_home(dirs)?)])
             .args([
                 "-e",
                 &format!("CROSS_RUST_SYSROOT={}", dirs.sysroot_mount_path()),
             ])
             .args(["-e", "CARGO_TARGET_DIR=/target"])
             .args(["-e", &cross_runner]);
+        if options.target_in_env {
+            // for tools reading the target from the environment
+            self.args([
+                "-e",
+                &format!("CARGO_BUILD_TARGET={}", options.target.triple()),
+            ]);
+        }
         if options.command_variant.uses_zig() {
             // otherwise, zig has a permission error trying to create the cache
             self.args(["-e", "XDG_CACHE_HOME=/target/.zig-cache"]);
+            // the zig image is shared by all targets, so the runner of
+            // `cross test` and `cross run` is selected here.
+            if let Some(runner) = zig_runner(&options.target) {
+                let envvar = format!(
+                    "CARGO_TARGET_{}_RUNNER",
+                    options
+                        .target
+                        .triple()
+                        .to_uppercase()
+                        .replace(['-', '.'], "_")
+                );
+                self.args(["-e", &format!("{envvar}={runner}")]);
+            }
         }
-        self.add_configuration_envvars();
+        self.add_configuration_envvars(
+            options.config.strict_env(&options.target),
+            &passthrough,
+            msg_info,
+        )?;
 
         if let Some(username) = id::username().wrap_err("could not get username")? {
             self.args(["-e", &format!("USER={username}")]);
@@ -1070,20 +2625,60 @@ impl DockerCommandExt for Command {
         self.args(["-e", &format!("CROSS_RUSTC_MINOR_VERSION={}", minor)]);
         self.args(["-e", &format!("CROSS_RUSTC_PATCH_VERSION={}", patch)]);
 
+        // so build scripts can make target-specific decisions, such as
+        // locating the system libraries for the debian architecture.
+        self.args(["-e", &format!("CROSS_TARGET={}", options.target.triple())]);
+        if let Some(arch) = options.target.target().deb_arch() {
+            self.args(["-e", &format!("CROSS_DEB_ARCH={arch}")]);
+        }
+        if let Some(digest) = options.image_digest(msg_info) {
+            self.args(["-e", &format!("CROSS_IMAGE_DIGEST={digest}")]);
+        }
+
+        let sysroot = dirs.sysroot_mount_path();
+        for (key, value) in build_env_vars(&options.config, &options.target, sysroot)? {
+            self.args(["-e", &format!("{key}={value}")]);
+        }
+
+        // the last definition of a variable wins, so these are added last.
+        for var in &options.envs {
+            validate_env_var(
+                var,
+                &mut warned,
+                "environment variable",
+                "`--env ENVVAR=value`",
+                msg_info,
+            )?;
+            self.args(["-e", var]);
+        }
+
         Ok(())
     }
 
+    fn add_network_envvars(&mut self, args: &[String]) {
+        // the host cargo and the container cargo must agree on the registry
+        // protocol and offline mode, otherwise the cached index cannot be used.
+        for envvar in NetworkFlags::detect(args).envvars() {
+            self.args(["-e", envvar]);
+        }
+    }
+
     fn add_cwd(&mut self, paths: &DockerPaths) -> Result<()> {
         self.args(["-w", paths.mount_cwd()]);
 
         Ok(())
     }
 
-    fn add_build_command(&mut self, dirs: &ToolchainDirectories, cmd: &SafeCommand) -> &mut Self {
-        let build_command = format!(
-            "PATH=\"$PATH\":\"{}/bin\" {:?}",
+    fn add_build_command(
+        &mut self,
+        dirs: &ToolchainDirectories,
+        options: &DockerOptions,
+        cmd: &SafeCommand,
+    ) -> &mut Self {
+        let build_command = build_script(
             dirs.sysroot_mount_path(),
-            cmd
+            options.config.container(&options.target).as_ref(),
+            cmd,
         );
         self.args(["sh", "-c", &build_command])
     }
@@ -1097,9 +2692,163 @@ impl DockerCommandExt for Command {
         }
     }
 
-    fn add_userns(&mut self) {
+    fn add_container_user(&mut self, user: Option<&ContainerUser>) -> &mut Self {
+        // the command that follows is run as the user through `"$@"`
+        if let Some(user) = user {
+            self.args(["sh", "-c", &user.script(), "sh"]);
+        }
+        self
+    }
+
+    fn add_ssh_agent(
+        &mut self,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
+        let mounts = ssh_agent_mounts(options, paths, msg_info)?;
+        if mounts.is_empty() {
+            return Ok(());
+        }
+        for mount in &mounts {
+            let readonly = if mount.readonly { ":ro" } else { "" };
+            self.args([
+                "-v",
+                &format!("{}:{}{readonly}", mount.host, mount.container),
+            ]);
+        }
+        self.args(["-e", &format!("SSH_AUTH_SOCK={SSH_AUTH_SOCK_MOUNT}")]);
+        // the git library built into cargo ignores the agent and `GIT_SSH_COMMAND`
+        self.args(["-e", "CARGO_NET_GIT_FETCH_WITH_CLI=true"]);
+        if mounts
+            .iter()
+            .any(|mount| mount.container == KNOWN_HOSTS_MOUNT)
+        {
+            self.args([
+                "-e",
+                &format!(
+                    "GIT_SSH_COMMAND=ssh -o StrictHostKeyChecking=yes -o UserKnownHostsFile={KNOWN_HOSTS_MOUNT}"
+                ),
+            ]);
+        } else {
+            self.args(["-e", "GIT_SSH_COMMAND"]);
+        }
+        Ok(())
+    }
+
+    fn add_network(&mut self, options: &DockerOptions) {
+        if let Some(network) = options.config.network(&options.target) {
+            self.args(["--network", &network]);
+        }
+    }
+
+    fn add_cache_proxy(
+        &mut self,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
+        if !options.config.cache_proxy() {
+            return Ok(());
+        }
+        let network = options.config.network(&options.target);
+        if let Some(network @ ("host" | "none")) = network.as_deref() {
+            return msg_info.warn(format_args!(
+                "the cache proxy cannot be used with the `{network}` network"
+            ));
+        }
+        cache_proxy::start(&options.engine, network.as_deref(), msg_info)?;
+        if network.is_none() {
+            self.args(["--network", CACHE_PROXY_NAME]);
+        }
+        for envvar in cache_proxy::envvars(cache_proxy::host_no_proxy().as_deref()) {
+            self.args(["-e", &envvar]);
+        }
+
+        // the crates are cached in a shared registry, unless the cargo home
+        // of the host is mounted with its own registry.
+        let dirs = paths.directories.toolchain_directories();
+        let cargo_home = match options.config.mount_cargo_home()? {
+            None => Some(options.cargo_home(dirs)?.to_owned()),
+            Some(_) if options.engine.is_remote => Some(format!(
+                "{MOUNT_PREFIX}/{}",
+                dirs.cargo_mount_path_relative()?
+            )),
+            Some(_) => None,
+        };
+        if let Some(cargo_home) = cargo_home {
+            cache_proxy::create_registry(&options.engine, msg_info)?;
+            self.args(["-v", &cache_proxy::registry_volume(&cargo_home)]);
+        }
+        Ok(())
+    }
+
+    fn add_publish(&mut self, options: &DockerOptions, msg_info: &mut MessageInfo) -> Result<()> {
+        for publish in &options.publish {
+            let host = parse_publish(publish)?;
+            if options.engine.is_rootless && host.is_some_and(|p| p < 1024) {
+                msg_info.warn(format_args!(
+                    "rootless engines cannot publish ports below 1024 by default, such as `{publish}`."
+                ))?;
+            }
+            self.args(["-p", publish]);
+        }
+        Ok(())
+    }
+
+    fn add_container_labels(&mut self, options: &DockerOptions, paths: &DockerPaths) -> Result<()> {
+        // `cross-util containers` finds the containers of a workspace or target by these
+        self.args(["--label", &workspace_label(&paths.metadata.workspace_root)?]);
+        self.args([
+            "--label",
+            &format!(
+                "{}.for-cross-target={}",
+                crate::CROSS_LABEL_DOMAIN,
+                options.target
+            ),
+        ]);
+        Ok(())
+    }
+
+    fn add_devices(&mut self, options: &DockerOptions, msg_info: &mut MessageInfo) -> Result<()> {
+        let devices = options.devices();
+        if devices.is_empty() {
+            return Ok(());
+        }
+        let mut groups = vec![];
+        for device in &devices {
+            let host = parse_device(device)?;
+            self.args(["--device", device]);
+            if options.is_remote() {
+                // the device is on the remote host
+                continue;
+            }
+            match fs::metadata(host) {
+                // the container user needs the group owning the device,
+                // such as `dialout` for serial ports.
+                Ok(metadata) => groups.extend(device_group(&metadata)),
+                Err(_) => msg_info.warn(format_args!("device `{host}` does not exist"))?,
+            }
+        }
+        if options.engine.kind.is_podman() {
+            // keep the supplementary groups of the user, since rootless
+            // podman maps the device groups to `nogroup` otherwise.
+            self.args(["--group-add", "keep-groups"]);
+        } else {
+            groups.sort_unstable();
+            groups.dedup();
+            for gid in groups {
+                self.args(["--group-add", &gid.to_string()]);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_userns(&mut self, is_nested: bool) {
         let userns = match env::var("CROSS_CONTAINER_USER_NAMESPACE").ok().as_deref() {
             Some("none") => None,
+            // nested engines with cgroup v2 commonly reject `--userns host`
+            None | Some("auto") if is_nested => None,
             None | Some("auto") => Some("host".to_owned()),
             Some(ns) => Some(ns.to_owned()),
         };
@@ -1108,12 +2857,11 @@ impl DockerCommandExt for Command {
         }
     }
 
-    #[allow(unused_mut, clippy::let_and_return)]
     fn add_seccomp(
         &mut self,
-        engine_type: EngineType,
-        target: &Target,
-        metadata: &CargoMetadata,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        msg_info: &mut MessageInfo,
     ) -> Result<()> {
         // secured profile based off the docker documentation for denied syscalls:
         // https://docs.docker.com/engine/security/seccomp/#significant-syscalls-blocked-by-the-default-profile
@@ -1121,28 +2869,51 @@ impl DockerCommandExt for Command {
         // to fork the process, and which podman allows by default.
         const SECCOMP: &str = include_str!("seccomp.json");
 
-        // docker uses seccomp now on all installations
-        if target.needs_docker_seccomp() {
-            let seccomp = if engine_type.is_docker() && cfg!(target_os = "windows") {
+        let engine = &options.engine;
+        let target = &options.target;
+        if let Some(profile) = options.config.seccomp(target) {
+            match SeccompProfile::new(&profile, paths.workspace_root()) {
+                SeccompProfile::Default => (),
+                SeccompProfile::Unconfined => {
+                    msg_info.warn(format_args!(
+                        "running the container for `{target}` without a seccomp profile."
+                    ))?;
+                    self.args(["--security-opt", "seccomp=unconfined"]);
+                }
+                SeccompProfile::File(path) => {
+                    validate_seccomp_profile(&path)?;
+                    let path = if engine.needs_remote() {
+                        let name = format!("{}-custom.json", target.triple());
+                        remote_seccomp_path(engine, &name, file::read(&path)?.as_bytes(), msg_info)?
+                    } else {
+                        seccomp_path(engine.kind, &path)?
+                    };
+                    self.args(["--security-opt", &format!("seccomp={path}")]);
+                }
+            }
+            return Ok(());
+        }
+
+        // docker uses seccomp now on all installations. a nested engine may
+        // not see our profile on its filesystem, so it keeps its default.
+        if target.needs_docker_seccomp() && !engine.is_nested {
+            let seccomp = if engine.kind.is_docker() && cfg!(target_os = "windows") {
                 // docker on windows fails due to a bug in reading the profile
                 // https://github.com/docker/for-win/issues/12760
                 "unconfined".to_owned()
+            } else if engine.needs_remote() {
+                let name = format!("{}.json", target.triple());
+                remote_seccomp_path(engine, &name, SECCOMP.as_bytes(), msg_info)?
             } else {
-                #[allow(unused_mut)] // target_os = "windows"
-                let mut path = metadata
+                let path = paths
+                    .metadata
                     .target_directory
                     .join(target.triple())
                     .join("seccomp.json");
                 if !path.exists() {
                     write_file(&path, false)?.write_all(SECCOMP.as_bytes())?;
                 }
-                let mut path_string = path.to_utf8()?.to_owned();
-                #[cfg(target_os = "windows")]
-                if matches!(engine_type, EngineType::Podman | EngineType::PodmanRemote) {
-                    // podman weirdly expects a WSL path here, and fails otherwise
-                    path_string = path.as_posix_absolute()?;
-                }
-                path_string
+                seccomp_path(engine.kind, &path)?
             };
 
             self.args(["--security-opt", &format!("seccomp={}", seccomp)]);
@@ -1155,7 +2926,7 @@ impl DockerCommandExt for Command {
         &mut self,
         options: &DockerOptions,
         paths: &DockerPaths,
-        mount_cb: impl Fn(&mut Command, &Path, &Path) -> Result<()>,
+        mount_cb: impl Fn(&mut Command, &Path, &Path, bool) -> Result<()>,
         mut store_cb: impl FnMut((String, String)),
         msg_info: &mut MessageInfo,
     ) -> Result<()> {
@@ -1177,36 +2948,283 @@ impl DockerCommandExt for Command {
                 None => env::var(var),
             };
 
-            // NOTE: we use canonical paths on the host, since it's unambiguous.
-            // however, for the mounted paths, we use the same path as was
-            // provided. this avoids canonicalizing symlinks which then causes
-            // the mounted path to differ from the path expected on the host.
-            // for example, if `/tmp` is a symlink to `/private/tmp`, canonicalizing
-            // it would lead to us mounting `/tmp/process` to `/private/tmp/process`,
-            // which would cause any code relying on `/tmp/process` to break.
+            // NOTE: we use canonical paths on the host, since it's unambiguous.
+            // however, for the mounted paths, we use the same path as was
+            // provided. this avoids canonicalizing symlinks which then causes
+            // the mounted path to differ from the path expected on the host.
+            // for example, if `/tmp` is a symlink to `/private/tmp`, canonicalizing
+            // it would lead to us mounting `/tmp/process` to `/private/tmp/process`,
+            // which would cause any code relying on `/tmp/process` to break.
+
+            if let Ok(val) = value {
+                let canonical_path = file::canonicalize(&val)?;
+                let host_path = paths.mount_finder.find_path(&canonical_path, true)?;
+                let mount_path = Path::new(&val).as_posix_absolute()?;
+                mount_cb(self, host_path.as_ref(), mount_path.as_ref(), false)?;
+                self.args(["-e", &format!("{}={}", var, mount_path)]);
+                store_cb((val, mount_path));
+            }
+        }
+
+        if let Some(sdk) = options.config.sdk(&options.target) {
+            let canonical_path = file::canonicalize(sdk.path())
+                .wrap_err_with(|| format!("the sdk `{}` does not exist", sdk.path()))?;
+            let host_path = paths.mount_finder.find_path(&canonical_path, true)?;
+            let mount_path = Path::new(sdk.path()).as_posix_absolute()?;
+            mount_cb(self, host_path.as_ref(), mount_path.as_ref(), true)?;
+            for (key, value) in sdk.env() {
+                if key.is_empty() || key.contains('=') {
+                    eyre::bail!("invalid environment variable `{key}` in `sdk.env`");
+                }
+                self.args([
+                    "-e",
+                    &format!("{key}={}", value.replace("{sdk}", &mount_path)),
+                ]);
+            }
+            store_cb((sdk.path().to_owned(), mount_path));
+        }
+
+        let dependencies = paths.out_of_root_dependencies();
+        if !dependencies.is_empty() && !options.config.mount_path_dependencies() {
+            let list = dependencies
+                .iter()
+                .map(|path| format!("`{}`", path.to_string_lossy()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(eyre::eyre!(
+                "the path dependencies {list} are outside of the workspace root `{}`",
+                paths.host_root().to_string_lossy()
+            ))
+            .with_suggestion(|| {
+                "move them below the workspace root, or mount them with `build.mount-path-dependencies = true`"
+            });
+        }
+        for path in dependencies {
+            // NOTE: we use canonical paths here since cargo metadata
+            // always canonicalizes paths, so these should be relative
+            // to the mounted project directory.
+            let canonical_path = file::canonicalize(path)
+                .wrap_err_with(|| format!("path dependency {path:?} does not exist"))?;
+            if !paths.mount_finder.is_mounted(&canonical_path) {
+                // the engine would mount an empty directory from its own host.
+                return Err(eyre::eyre!(
+                    "path dependency `{}` is outside of the workspace root, and cannot be mounted since it's not in a volume of the container running cross",
+                    canonical_path.to_string_lossy()
+                ))
+                .with_suggestion(|| {
+                    "mount a parent directory of the dependency into the container running cross, or move it below the workspace root"
+                });
+            }
+            let host_path = paths.mount_finder.find_path(&canonical_path, true)?;
+            let mount_path = path.as_posix_absolute()?;
+            mount_cb(self, host_path.as_ref(), mount_path.as_ref(), false)?;
+            store_cb((path.to_utf8()?.to_owned(), mount_path));
+        }
+
+        // build scripts such as `vergen` read the git metadata, which for
+        // worktrees and submodules lives outside of the mounted workspace.
+        for path in paths.git_linked_directories(msg_info)? {
+            let host_path = paths.mount_finder.find_path(&path, true)?;
+            let mount_path = path.as_posix_absolute()?;
+            mount_cb(self, host_path.as_ref(), mount_path.as_ref(), true)?;
+            store_cb((path.to_utf8()?.to_owned(), mount_path));
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_context(
+        &mut self,
+        options: &DockerOptions,
+        paths: &DockerPaths,
+        args: &[String],
+        mounts: &[(String, String)],
+        target_dir: &str,
+        mount_cb: impl Fn(&mut Command, &Path, &Path, bool) -> Result<()>,
+        mut store_cb: impl FnMut((String, String)),
+    ) -> Result<TempDir> {
+        let manifest = options.context_manifest(paths, args, mounts, target_dir)?;
+        // the directory is mounted rather than the file, so remote engines
+        // can copy it like the other mounts. every container has its own,
+        // removed once the returned directory is dropped after the run.
+        // SAFETY: safe, since we're single-threaded.
+        let context_dir = unsafe { TempDir::new()? };
+        let dir = context_dir.path();
+        let path = dir.join(CONTEXT_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+            .wrap_err_with(|| format!("could not write the context manifest to {path:?}"))?;
+
+        let dir = file::canonicalize(dir)?;
+        let host_path = paths.mount_finder.find_path(&dir, true)?;
+        mount_cb(self, host_path.as_ref(), Path::new(CONTEXT_MOUNT_DIR), true)?;
+        self.args([
+            "-e",
+            &format!("CROSS_CONTEXT={CONTEXT_MOUNT_DIR}/{CONTEXT_FILE}"),
+        ]);
+        store_cb((dir.to_utf8()?.to_owned(), CONTEXT_MOUNT_DIR.to_owned()));
+
+        Ok(context_dir)
+    }
+}
+
+/// Where the SSH agent socket is mounted in the container.
+pub const SSH_AUTH_SOCK_MOUNT: &str = "/tmp/cross-ssh-agent.sock";
+/// Where the known hosts of the host are mounted in the container.
+const KNOWN_HOSTS_MOUNT: &str = "/tmp/cross-known-hosts";
+/// Docker Desktop cannot mount sockets from a macOS host, but forwards the
+/// agent of the host to this socket in the VM.
+const DOCKER_DESKTOP_SSH_AUTH_SOCK: &str = "/run/host-services/ssh-auth.sock";
+
+/// The socket of the SSH agent and the known hosts mounted with `ssh-agent`,
+/// or nothing if it's disabled or there is no agent. The known hosts are not
+/// mounted if `GIT_SSH_COMMAND` is set, since it's passed through instead.
+pub(crate) fn ssh_agent_mounts(
+    options: &DockerOptions,
+    paths: &DockerPaths,
+    msg_info: &mut MessageInfo,
+) -> Result<Vec<Mount>> {
+    if !options.config.ssh_agent(&options.target) {
+        return Ok(vec![]);
+    }
+    if cfg!(target_os = "windows") {
+        // the OpenSSH agent on Windows uses a named pipe, not a socket
+        msg_info.warn("`ssh-agent` is not supported on Windows hosts.")?;
+        return Ok(vec![]);
+    }
+    let source = ssh_agent_source(
+        options.engine.kind,
+        cfg!(target_os = "macos"),
+        env::var_os("SSH_AUTH_SOCK").map(PathBuf::from),
+        &paths.mount_finder,
+    )?;
+    let Some(source) = source else {
+        msg_info.warn("`ssh-agent` is enabled, but `SSH_AUTH_SOCK` is not set on the host.")?;
+        return Ok(vec![]);
+    };
+    let mut mounts = vec![Mount::new(&source, SSH_AUTH_SOCK_MOUNT, false)];
+    if env::var_os("GIT_SSH_COMMAND").is_some() {
+        return Ok(mounts);
+    }
+    let known_hosts = directories::BaseDirs::new()
+        .map(|d| d.home_dir().join(".ssh").join("known_hosts"))
+        .filter(|path| path.is_file());
+    let Some(known_hosts) = known_hosts else {
+        return Err(eyre::eyre!(
+            "`ssh-agent` requires the known hosts of the host in `~/.ssh/known_hosts`, which does not exist"
+        ))
+        .with_suggestion(|| {
+            "add the hosts of the git dependencies, such as with `ssh-keyscan github.com >> ~/.ssh/known_hosts`, or set `GIT_SSH_COMMAND`"
+        });
+    };
+    let known_hosts = paths.mount_finder.find_path(&known_hosts, true)?;
+    mounts.push(Mount::new(&known_hosts, KNOWN_HOSTS_MOUNT, true));
+    Ok(mounts)
+}
+
+/// The socket of the SSH agent to mount, translated for the engine and OS.
+fn ssh_agent_source(
+    kind: EngineType,
+    is_macos: bool,
+    auth_sock: Option<PathBuf>,
+    mount_finder: &MountFinder,
+) -> Result<Option<String>> {
+    if is_macos && kind.is_docker() {
+        return Ok(Some(DOCKER_DESKTOP_SSH_AUTH_SOCK.to_owned()));
+    }
+    auth_sock
+        .map(|sock| mount_finder.find_path(&sock, true))
+        .transpose()
+}
 
-            if let Ok(val) = value {
-                let canonical_path = file::canonicalize(&val)?;
-                let host_path = paths.mount_finder.find_path(&canonical_path, true)?;
-                let mount_path = Path::new(&val).as_posix_absolute()?;
-                mount_cb(self, host_path.as_ref(), mount_path.as_ref())?;
-                self.args(["-e", &format!("{}={}", var, mount_path)]);
-                store_cb((val, mount_path));
+fn is_valid_user_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// A user matching the host user, created in the container at startup from
+/// `container-user`, so it can be a member of supplementary groups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerUser {
+    uid: String,
+    gid: String,
+    name: String,
+    /// The supplementary groups with their GID on the host, if it exists there.
+    groups: Vec<(String, Option<u32>)>,
+}
+
+impl ContainerUser {
+    pub fn new(options: &DockerOptions, msg_info: &mut MessageInfo) -> Result<Option<Self>> {
+        let Some(config) = options.config.container_user(&options.target) else {
+            return Ok(None);
+        };
+        if options.engine.is_rootless {
+            msg_info.warn("`container-user` has no effect with a rootless container engine, since the container already runs as the host user.")?;
+            return Ok(None);
+        }
+        let mut groups = vec![];
+        for group in config.groups() {
+            if !is_valid_user_name(group) {
+                eyre::bail!("invalid group `{group}` in `container-user`");
             }
+            let gid = match group.parse() {
+                Ok(gid) => Some(gid),
+                Err(_) => id::group_id_from_name(group)?,
+            };
+            groups.push((group.clone(), gid));
         }
+        let name = id::username()?
+            .filter(|name| is_valid_user_name(name))
+            .unwrap_or_else(|| "cross".to_owned());
+
+        Ok(Some(ContainerUser {
+            uid: user_id(),
+            gid: group_id(),
+            name,
+            groups,
+        }))
+    }
 
-        for path in paths.workspace_dependencies() {
-            // NOTE: we use canonical paths here since cargo metadata
-            // always canonicalizes paths, so these should be relative
-            // to the mounted project directory.
-            let canonical_path = file::canonicalize(path)?;
-            let host_path = paths.mount_finder.find_path(&canonical_path, true)?;
-            let mount_path = path.as_posix_absolute()?;
-            mount_cb(self, host_path.as_ref(), mount_path.as_ref())?;
-            store_cb((path.to_utf8()?.to_owned(), mount_path));
+    /// The script run as root, which creates the user and groups if they
+    /// don't exist, then runs its arguments as the user.
+    fn script(&self) -> String {
+        let ContainerUser { uid, gid, name, .. } = self;
+        let mut script = vec![
+            "set -e".to_owned(),
+            format!("getent group {gid} >/dev/null || groupadd --gid {gid} {name}"),
+            format!(
+                "getent passwd {uid} >/dev/null || useradd --uid {uid} --gid {gid} --no-create-home {name}"
+            ),
+            "groups=".to_owned(),
+        ];
+        for (group, host_gid) in &self.groups {
+            match host_gid {
+                Some(host_gid) => {
+                    // the group may exist in the image with another GID
+                    script.push(format!(
+                        "getent group {host_gid} >/dev/null || groupadd --gid {host_gid} {group} 2>/dev/null || groupadd --gid {host_gid} cross-{group}"
+                    ));
+                    script.push(format!("groups=\"${{groups:+$groups,}}{host_gid}\""));
+                }
+                None => {
+                    script.push(format!(
+                        "group=$(getent group {group} | cut -d: -f3); [ -n \"$group\" ] || {{ echo \"group {group} does not exist\" 1>&2; exit 1; }}"
+                    ));
+                    script.push("groups=\"${groups:+$groups,}$group\"".to_owned());
+                }
+            }
         }
-
-        Ok(())
+        script.push(
+            "if [ -n \"$groups\" ]; then groups=\"--groups=$groups\"; else groups=--clear-groups; fi"
+                .to_owned(),
+        );
+        script.push(format!(
+            "exec setpriv --reuid={uid} --regid={gid} \"$groups\" -- \"$@\""
+        ));
+        script.join("\n")
     }
 }
 
@@ -1233,6 +3251,23 @@ pub enum GetImageError {
     Other(eyre::Report),
 }
 
+/// The runner of the zig image for `target`, which runs binaries natively
+/// or with qemu-user, depending on the architecture.
+fn zig_runner(target: &Target) -> Option<String> {
+    if !(target.is_linux() || target.is_bare_metal()) {
+        return None;
+    }
+    let arch = target.triple().split('-').next()?;
+    let arch = if arch.starts_with("thumb") || arch.starts_with("arm") {
+        "arm"
+    } else if arch.starts_with("riscv64") {
+        "riscv64"
+    } else {
+        arch
+    };
+    Some(format!("/zig-runner {arch}"))
+}
+
 fn get_target_name(target: &Target, uses_zig: bool) -> &str {
     if uses_zig {
         "zig"
@@ -1255,12 +3290,36 @@ fn get_user_image(
 
     if let Some(image) = &mut image {
         let target_name = get_target_name(target, uses_zig);
+        // only a tag or subtarget was provided, so it's a provided image
+        let is_provided = !matches!(image.reference, ImageReference::Name(_));
         image.reference.ensure_qualified(target_name);
+        if is_provided {
+            let name = with_registry_mirror(image.reference.get(), config);
+            image.reference = ImageReference::Name(name);
+        }
     }
 
     Ok(image)
 }
 
+/// Pull the images provided by cross from `build.registry-mirror`, such as
+/// `registry.corp/crossmirror`. Custom images are left untouched.
+fn with_registry_mirror(image_name: &str, config: &Config) -> String {
+    mirror_image_name(image_name, config.registry_mirror().as_deref())
+}
+
+fn mirror_image_name(image_name: &str, mirror: Option<&str>) -> String {
+    match (
+        mirror.map(|m| m.trim_end_matches('/')),
+        image_name.strip_prefix(CROSS_IMAGE),
+    ) {
+        (Some(mirror), Some(rest)) if !mirror.is_empty() && rest.starts_with('/') => {
+            format!("{mirror}{rest}")
+        }
+        _ => image_name.to_owned(),
+    }
+}
+
 fn get_provided_images_for_target(
     target_name: &str,
 ) -> Result<Vec<&'static ProvidedImage>, GetImageError> {
@@ -1288,10 +3347,11 @@ pub fn get_image_name(
 
     let target_name = get_target_name(target, uses_zig);
     let compatible = get_provided_images_for_target(target_name)?;
-    Ok(compatible
+    let image_name = compatible
         .first()
         .expect("should not be empty")
-        .default_image_name())
+        .default_image_name();
+    Ok(with_registry_mirror(&image_name, config))
 }
 
 pub fn get_image(
@@ -1339,7 +3399,7 @@ pub fn get_image(
         ));
     };
 
-    let image_name = pick.default_image_name();
+    let image_name = with_registry_mirror(&pick.default_image_name(), config);
     if pick.platforms.is_empty() {
         return Err(GetImageError::SpecifiedImageNoPlatform(image_name));
     }
@@ -1375,7 +3435,24 @@ fn docker_inspect_self_mountinfo(engine: &Engine, msg_info: &mut MessageInfo) ->
         .run_and_get_stdout(msg_info)
 }
 
+/// The output of `docker inspect` for the container running cross, for each
+/// engine, since it's used both to detect the setup and to find the mounts.
+static INSPECT_SELF: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
 fn docker_inspect_self(engine: &Engine, msg_info: &mut MessageInfo) -> Result<String> {
+    let key = engine_info_key(&engine.path, engine.context.as_deref());
+    let cached = INSPECT_SELF.lock().ok().and_then(|m| m.get(&key).cloned());
+    if let Some(output) = cached {
+        return Ok(output);
+    }
+    let output = inspect_self(engine, msg_info)?;
+    if let Ok(mut inspected) = INSPECT_SELF.lock() {
+        inspected.insert(key, output.clone());
+    }
+    Ok(output)
+}
+
+fn inspect_self(engine: &Engine, msg_info: &mut MessageInfo) -> Result<String> {
     // Try to find the container ID by looking at HOSTNAME, and fallback to
     // parsing `/proc/self/mountinfo` if HOSTNAME is unset or if there's no
     // container that matches it (necessary e.g. when the container uses
@@ -1420,13 +3497,22 @@ fn docker_read_mount_paths(
 ) -> Result<Vec<MountDetail>> {
     let output = docker_inspect_self(engine, msg_info)?;
     let info = serde_json::from_str(&output).wrap_err("failed to parse docker inspect output")?;
-    dockerinfo_parse_mounts(&info)
+    dockerinfo_parse_mounts(&info, msg_info)
 }
 
-fn dockerinfo_parse_mounts(info: &serde_json::Value) -> Result<Vec<MountDetail>> {
+fn dockerinfo_parse_mounts(
+    info: &serde_json::Value,
+    msg_info: &mut MessageInfo,
+) -> Result<Vec<MountDetail>> {
     let mut mounts = dockerinfo_parse_user_mounts(info);
-    let root_info = dockerinfo_parse_root_mount_path(info)?;
-    mounts.push(root_info);
+    // without the root directory, only the paths in volumes can be mounted,
+    // which is verified by `MountFinder::verify_mounted`.
+    match dockerinfo_parse_root_mount_path(info) {
+        Ok(root_info) => mounts.push(root_info),
+        Err(e) => msg_info.debug(format_args!(
+            "could not find the root directory of the container running cross: {e}"
+        ))?,
+    }
     Ok(mounts)
 }
 
@@ -1474,6 +3560,8 @@ fn dockerinfo_parse_user_mounts(info: &serde_json::Value) -> Vec<MountDetail> {
 #[derive(Debug, Default)]
 pub struct MountFinder {
     mounts: Vec<MountDetail>,
+    /// cross runs in a container, sharing the container engine of its host.
+    in_container: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -1491,12 +3579,20 @@ impl MountFinder {
             let lb = b.destination.as_os_str().len();
             la.cmp(&lb).reverse()
         });
-        MountFinder { mounts }
+        MountFinder {
+            mounts,
+            in_container: true,
+        }
     }
 
     pub fn create(engine: &Engine, msg_info: &mut MessageInfo) -> Result<MountFinder> {
         Ok(if engine.in_docker {
-            MountFinder::new(docker_read_mount_paths(engine, msg_info)?)
+            let mounts = docker_read_mount_paths(engine, msg_info)
+                .wrap_err("could not find the volumes of the container running cross")
+                .with_suggestion(|| {
+                    "unset `CROSS_CONTAINER_IN_CONTAINER` if cross doesn't run in a container sharing the container engine of the host, or use `CROSS_REMOTE=1` to copy the files into data volumes instead"
+                })?;
+            MountFinder::new(mounts)
         } else {
             MountFinder::default()
         })
@@ -1514,6 +3610,40 @@ impl MountFinder {
         path.to_path_buf()
     }
 
+    /// Returns `false` if cross runs in a container, and `path` is not in
+    /// any of its volumes, so the engine cannot mount it.
+    pub fn is_mounted(&self, path: &Path) -> bool {
+        !self.in_container
+            || self
+                .mounts
+                .iter()
+                .any(|info| path.starts_with(&info.destination))
+    }
+
+    /// Verify that the container engine can mount the `paths` of the
+    /// container running cross, each described by its name.
+    pub fn verify_mounted(&self, paths: &[(&str, &Path)]) -> Result<()> {
+        let unreachable: Vec<String> = paths
+            .iter()
+            .filter(|(_, path)| !self.is_mounted(path))
+            .map(|(name, path)| format!("{name} `{}`", path.to_string_lossy()))
+            .collect();
+        if unreachable.is_empty() {
+            return Ok(());
+        }
+        Err(eyre::eyre!(
+            "{} cannot be mounted, since {} not in a volume of the container running cross",
+            unreachable.join(", "),
+            if unreachable.len() == 1 { "it's" } else { "they're" }
+        ))
+        .with_note(|| {
+            "the container engine mounts the paths of its own host, which only has the volumes of the container running cross, and its root directory with the overlay storage driver"
+        })
+        .with_suggestion(|| {
+            "mount them into the container running cross at the same paths, or use `CROSS_REMOTE=1` to copy them into data volumes instead"
+        })
+    }
+
     fn find_path(&self, path: &Path, host: bool) -> Result<String> {
         if cfg!(target_os = "windows") && host {
             // On Windows, we can not mount the directory name directly.
@@ -1557,6 +3687,57 @@ mod tests {
     #[cfg(not(target_os = "windows"))]
     use crate::file::PathExt;
 
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn container_termination() {
+        use std::os::unix::process::ExitStatusExt;
+        let exit = |code: i32| ExitStatus::from_raw(code << 8);
+
+        assert_eq!(
+            Termination::new(true, &exit(101)),
+            Some(Termination::OomKilled)
+        );
+        assert_eq!(
+            Termination::new(false, &exit(137)),
+            Some(Termination::Signal { signal: 9 })
+        );
+        assert_eq!(Termination::new(false, &exit(101)), None);
+        assert_eq!(Termination::new(false, &timeout_exit_status()), None);
+        assert_eq!(Termination::signal_name(9), Some("SIGKILL"));
+    }
+
+    #[test]
+    fn mount_dir_fallback() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let mut msg_info = MessageInfo::default();
+        let path = tmp.path().join("home").join(".xargo");
+        let (created, guard) = create_mount_dir(
+            path.clone(),
+            "xargo-home",
+            "CROSS_XARGO_HOME",
+            &mut msg_info,
+        )?;
+        assert_eq!(created, path);
+        assert!(guard.is_none());
+        assert!(path.is_dir());
+
+        // a file in place of the home directory, like a read-only home.
+        let file = tmp.path().join("file");
+        std::fs::write(&file, "")?;
+        let (fallback, guard) = create_mount_dir(
+            file.join(".xargo"),
+            "xargo-home",
+            "CROSS_XARGO_HOME",
+            &mut msg_info,
+        )?;
+        assert!(fallback.starts_with(env::temp_dir()));
+        assert!(fallback.is_dir());
+        drop(guard);
+        assert!(!fallback.exists());
+
+        Ok(())
+    }
+
     #[test]
     fn test_docker_user_id() {
         let rootful = format!("\"engine\" \"--user\" \"{}:{}\"", id::user(), id::group());
@@ -1572,6 +3753,296 @@ mod tests {
         test(true, &rootless);
     }
 
+    #[test]
+    fn test_mirror_image_name() {
+        let image = format!("{CROSS_IMAGE}/aarch64-unknown-linux-gnu:main");
+        assert_eq!(
+            mirror_image_name(&image, Some("registry.corp/crossmirror/")),
+            "registry.corp/crossmirror/aarch64-unknown-linux-gnu:main"
+        );
+        assert_eq!(mirror_image_name(&image, None), image);
+        assert_eq!(mirror_image_name(&image, Some("")), image);
+        assert_eq!(
+            mirror_image_name("example.com/custom:latest", Some("registry.corp")),
+            "example.com/custom:latest"
+        );
+        assert_eq!(
+            mirror_image_name(&format!("{CROSS_IMAGE}-fork/image"), Some("registry.corp")),
+            format!("{CROSS_IMAGE}-fork/image")
+        );
+    }
+
+    #[test]
+    fn test_zig_runner() {
+        let runner = |triple| zig_runner(&Target::new_built_in(triple));
+        assert_eq!(
+            runner("aarch64-unknown-linux-musl").as_deref(),
+            Some("/zig-runner aarch64")
+        );
+        assert_eq!(
+            runner("armv7-unknown-linux-musleabihf").as_deref(),
+            Some("/zig-runner arm")
+        );
+        assert_eq!(
+            runner("riscv64gc-unknown-linux-gnu").as_deref(),
+            Some("/zig-runner riscv64")
+        );
+        assert_eq!(
+            runner("thumbv7em-none-eabihf").as_deref(),
+            Some("/zig-runner arm")
+        );
+        assert_eq!(runner("x86_64-pc-windows-gnu"), None);
+    }
+
+    #[test]
+    fn test_json_messages() {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert!(uses_json_messages(&args(&[
+            "build",
+            "--message-format",
+            "json"
+        ])));
+        assert!(uses_json_messages(&args(&[
+            "check",
+            "--message-format=short,json-diagnostic-rendered-ansi"
+        ])));
+        assert!(!uses_json_messages(&args(&[
+            "build",
+            "--message-format=short"
+        ])));
+        assert!(!uses_json_messages(&args(&[
+            "run",
+            "--",
+            "--message-format=json"
+        ])));
+    }
+
+    #[test]
+    fn test_path_remap() {
+        let remap = PathRemap::from_prefixes([
+            ("/project", Path::new("/home/user/my-project")),
+            ("/project/target", Path::new("/tmp/cargo-target")),
+            ("/cargo", Path::new("/home/user/.cargo")),
+            ("/rust", Path::new("/rust")),
+        ]);
+        let line = r#"{"reason":"compiler-message","target":{"src_path":"/project/src/main.rs"},"message":{"spans":[{"file_name":"/cargo/registry/src/index/serde-1.0.0/src/lib.rs"}],"rendered":"error: oops\n --> /project/src/main.rs:2:5\n"}}"#;
+        let remapped = remap.remap_line(line).unwrap();
+        assert_eq!(
+            remapped["target"]["src_path"],
+            "/home/user/my-project/src/main.rs"
+        );
+        assert_eq!(
+            remapped["message"]["spans"][0]["file_name"],
+            "/home/user/.cargo/registry/src/index/serde-1.0.0/src/lib.rs"
+        );
+        assert_eq!(
+            remapped["message"]["rendered"],
+            "error: oops\n --> /home/user/my-project/src/main.rs:2:5\n"
+        );
+
+        // longer prefixes win, and host paths are not replaced again
+        assert_eq!(
+            remap.remap_str("/project/target/debug/app"),
+            "/tmp/cargo-target/debug/app"
+        );
+        assert_eq!(remap.remap_str("/projects/other"), "/projects/other");
+        assert_eq!(remap.remap_str("/home/project/x"), "/home/project/x");
+        assert_eq!(remap.remap_line("   Compiling app v0.1.0"), None);
+
+        let mut output = vec![];
+        {
+            let mut writer = RemapWriter::new(&mut output, remap.clone());
+            writer.write_all(b"plain\n{\"src_path\":").unwrap();
+            writer.write_all(b"\"/project/src/lib.rs\"}\n").unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "plain\n{\"src_path\":\"/home/user/my-project/src/lib.rs\"}\n"
+        );
+
+        let mut output = vec![];
+        let remap = remap.hide_messages(true);
+        {
+            let mut writer = RemapWriter::new(&mut output, remap.clone());
+            writer
+                .write_all(b"{\"reason\":\"compiler-artifact\",\"filenames\":[\"/project/target/debug/app\"],\"executable\":\"/project/target/debug/app\"}\n")
+                .unwrap();
+            writer
+                .write_all(b"{\"reason\":\"build-finished\",\"success\":true}\n")
+                .unwrap();
+        }
+        assert!(output.is_empty());
+        let app = PathBuf::from("/tmp/cargo-target/debug/app");
+        assert_eq!(
+            remap.take_artifacts(),
+            [CompilerArtifact {
+                filenames: vec![app.clone()],
+                executable: Some(app),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_transient_engine_errors() {
+        assert!(is_transient_engine_error(
+            "docker: Error response from daemon: error mounting \"/proc\": device or resource busy."
+        ));
+        assert!(is_transient_engine_error(
+            "Error response from daemon: Get \"https://ghcr.io/v2/\": net/http: TLS handshake timeout"
+        ));
+        assert!(!is_transient_engine_error(
+            "docker: Error response from daemon: invalid mount config for type \"bind\""
+        ));
+        assert!(!is_transient_engine_error("error: could not compile `foo`"));
+    }
+
+    #[test]
+    fn test_template_build_env() -> Result<()> {
+        let sysroot = "/rust";
+        assert_eq!(
+            template_build_env("/usr/lib/{deb_arch}", sysroot, Some("arm64"))?,
+            "/usr/lib/arm64"
+        );
+        assert_eq!(
+            template_build_env("{rust_sysroot}/lib:{rust_sysroot}/bin", sysroot, None)?,
+            "/rust/lib:/rust/bin"
+        );
+        assert_eq!(template_build_env("plain", sysroot, None)?, "plain");
+        assert!(template_build_env("/usr/lib/{deb_arch}", sysroot, None).is_err());
+        assert!(template_build_env("{target}", sysroot, None).is_err());
+        assert!(template_build_env("{rust_sysroot", sysroot, None).is_err());
+        assert!(template_build_env("{sysroot}/lib", sysroot, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_device() -> Result<()> {
+        assert_eq!(parse_device("/dev/ttyUSB0")?, "/dev/ttyUSB0");
+        assert_eq!(parse_device("/dev/ttyUSB0:/dev/ttyS0")?, "/dev/ttyUSB0");
+        assert_eq!(
+            parse_device("/dev/bus/usb:/dev/bus/usb:rw")?,
+            "/dev/bus/usb"
+        );
+        assert!(parse_device("ttyUSB0").is_err());
+        assert!(parse_device("/dev/ttyUSB0:ttyS0").is_err());
+        assert!(parse_device("/dev/ttyUSB0:/dev/ttyS0:x").is_err());
+        assert!(parse_device("/dev/ttyUSB0:/dev/ttyS0:").is_err());
+        assert!(parse_device("/dev/ttyUSB0:/dev/ttyS0:rw:rw").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_publish() -> Result<()> {
+        assert_eq!(parse_publish("80")?, None);
+        assert_eq!(parse_publish("8080:80")?, Some(8080));
+        assert_eq!(parse_publish("127.0.0.1:8080:80/udp")?, Some(8080));
+        assert_eq!(parse_publish("127.0.0.1::80")?, None);
+        assert_eq!(parse_publish("[::1]:8080:80")?, Some(8080));
+        assert_eq!(parse_publish("8000-8010:8000-8010/tcp")?, Some(8000));
+        assert!(parse_publish("").is_err());
+        assert!(parse_publish("http").is_err());
+        assert!(parse_publish("0:80").is_err());
+        assert!(parse_publish("8080:65536").is_err());
+        assert!(parse_publish("8010-8000:80").is_err());
+        assert!(parse_publish("8080:80/http").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_digest() {
+        assert_eq!(
+            reference_digest("ghcr.io/cross-rs/aarch64-unknown-linux-gnu@sha256:abc"),
+            Some("sha256:abc")
+        );
+        assert_eq!(
+            reference_digest("ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main"),
+            None
+        );
+        assert_eq!(reference_digest("image@"), None);
+    }
+
+    #[test]
+    fn test_strict_env() {
+        let keys = [
+            "TERM",
+            "RUSTFLAGS",
+            "CARGO_TERM_COLOR",
+            "CARGO_BUILD_JOBS",
+            "CARGO_HOME",
+            "CROSS_CONTAINER_OPTS",
+            "HOME",
+        ]
+        .map(str::to_owned);
+        let explicit = ["CARGO_BUILD_JOBS".to_owned()];
+
+        let (passed, filtered) = configuration_envvars(&keys, false, &explicit);
+        assert_eq!(
+            passed,
+            [
+                "TERM",
+                "RUSTFLAGS",
+                "CARGO_TERM_COLOR",
+                "CROSS_CONTAINER_OPTS"
+            ]
+        );
+        assert!(filtered.is_empty());
+
+        let (passed, filtered) = configuration_envvars(&keys, true, &explicit);
+        assert_eq!(passed, ["TERM", "CARGO_TERM_COLOR"]);
+        assert_eq!(filtered, ["RUSTFLAGS", "CROSS_CONTAINER_OPTS"]);
+    }
+
+    #[test]
+    fn test_ssh_agent_source() -> Result<()> {
+        let finder = MountFinder::default();
+        let sock = Some(PathBuf::from("/tmp/ssh-XXXX/agent.123"));
+        assert_eq!(
+            ssh_agent_source(EngineType::Docker, false, sock.clone(), &finder)?,
+            Some("/tmp/ssh-XXXX/agent.123".to_owned())
+        );
+        assert_eq!(
+            ssh_agent_source(EngineType::Docker, true, sock.clone(), &finder)?,
+            Some(DOCKER_DESKTOP_SSH_AUTH_SOCK.to_owned())
+        );
+        assert_eq!(
+            ssh_agent_source(EngineType::Podman, true, sock, &finder)?,
+            Some("/tmp/ssh-XXXX/agent.123".to_owned())
+        );
+        assert_eq!(
+            ssh_agent_source(EngineType::Podman, false, None, &finder)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_user() {
+        let user = ContainerUser {
+            uid: "1000".to_owned(),
+            gid: "1000".to_owned(),
+            name: "user".to_owned(),
+            groups: vec![("dialout".to_owned(), Some(20)), ("video".to_owned(), None)],
+        };
+        let script = user.script();
+        assert!(script.contains("useradd --uid 1000 --gid 1000 --no-create-home user"));
+        assert!(script.contains("groupadd --gid 20 dialout"));
+        assert!(script.contains("getent group video | cut -d: -f3"));
+        assert!(script.ends_with("exec setpriv --reuid=1000 --regid=1000 \"$groups\" -- \"$@\""));
+
+        let mut cmd = Command::new("engine");
+        cmd.add_container_user(None)
+            .args(["sh", "-c", "cargo build"]);
+        assert_eq!(format!("{cmd:?}"), r#""engine" "sh" "-c" "cargo build""#);
+
+        assert!(is_valid_user_name("docker"));
+        assert!(!is_valid_user_name("--help"));
+        assert!(!is_valid_user_name("a;b"));
+    }
+
     #[test]
     fn test_docker_userns() {
         let var = "CROSS_CONTAINER_USER_NAMESPACE";
@@ -1584,7 +4055,7 @@ mod tests {
 
         let test = |expected| {
             let mut cmd = Command::new("engine");
-            cmd.add_userns();
+            cmd.add_userns(false);
             assert_eq!(expected, &format!("{cmd:?}"));
         };
         test(&host);
@@ -1601,12 +4072,131 @@ mod tests {
         env::set_var(var, "custom");
         test(&custom);
 
+        let test_nested = |expected| {
+            let mut cmd = Command::new("engine");
+            cmd.add_userns(true);
+            assert_eq!(expected, &format!("{cmd:?}"));
+        };
+        test_nested(&custom);
+
+        env::set_var(var, "auto");
+        test_nested(&none);
+
+        env::remove_var(var);
+        test_nested(&none);
+
         match old {
             Ok(v) => env::set_var(var, v),
             Err(_) => env::remove_var(var),
         }
     }
 
+    #[test]
+    fn test_build_script() -> Result<()> {
+        let mut cmd = SafeCommand::new("cargo");
+        cmd.args(["build", "--target", "aarch64-unknown-linux-gnu"]);
+        let sysroot = "/rust/toolchains/stable";
+        assert_eq!(
+            build_script(sysroot, None, &cmd),
+            r#"PATH="$PATH":"/rust/toolchains/stable/bin" cargo build --target aarch64-unknown-linux-gnu"#
+        );
+
+        let container: CrossContainerConfig = toml::from_str(
+            r#"
+            path = ["/opt/vendor/bin"]
+            profile-script = "/opt/vendor/env.sh"
+            "#,
+        )?;
+        assert_eq!(
+            build_script(sysroot, Some(&container), &cmd),
+            r#". /opt/vendor/env.sh && PATH=/opt/vendor/bin:"$PATH":"/rust/toolchains/stable/bin" exec cargo build --target aarch64-unknown-linux-gnu"#
+        );
+
+        // the configured paths are quoted for the shell, not expanded
+        let container: CrossContainerConfig = toml::from_str(
+            r#"
+            path = ["/opt/$VENDOR/bin", "/opt/`id`"]
+            profile-script = "/opt/vendor dir/env.sh"
+            "#,
+        )?;
+        assert_eq!(
+            build_script(sysroot, Some(&container), &cmd),
+            r#". '/opt/vendor dir/env.sh' && PATH='/opt/$VENDOR/bin':'/opt/`id`':"$PATH":"/rust/toolchains/stable/bin" exec cargo build --target aarch64-unknown-linux-gnu"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_workspace_label() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let workspace = tmp.path().join("workspace");
+        fs::create_dir(&workspace)?;
+        let link = tmp.path().join("link");
+        std::os::unix::fs::symlink(&workspace, &link)?;
+
+        assert_eq!(workspace_label(&link)?, workspace_label(&workspace)?);
+        assert!(workspace_label(&tmp.path().join("missing")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_file() {
+        let tar = tar_file("x86_64.json", b"{}");
+        assert_eq!(tar.len(), 4 * 512);
+        assert_eq!(&tar[..12], b"x86_64.json\0");
+        assert_eq!(&tar[124..136], b"00000000002\0");
+        assert_eq!(&tar[257..263], b"ustar\0");
+        assert_eq!(&tar[512..514], b"{}");
+        assert!(tar[514..].iter().all(|&b| b == 0));
+
+        let checksum: usize = tar[..512]
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    b as usize
+                }
+            })
+            .sum();
+        assert_eq!(&tar[148..155], format!("{checksum:06o}\0").as_bytes());
+
+        assert_eq!(tar_file("a", &[1; 512]).len(), 4 * 512);
+    }
+
+    #[test]
+    fn test_seccomp_profile() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let root = tmp.path();
+        assert_eq!(
+            SeccompProfile::new("default", root),
+            SeccompProfile::Default
+        );
+        assert_eq!(
+            SeccompProfile::new("unconfined", root),
+            SeccompProfile::Unconfined
+        );
+        assert_eq!(
+            SeccompProfile::new("ci/seccomp.json", root),
+            SeccompProfile::File(root.join("ci/seccomp.json"))
+        );
+
+        fs::write(
+            root.join("valid.json"),
+            r#"{"defaultAction": "SCMP_ACT_ALLOW"}"#,
+        )?;
+        fs::write(root.join("invalid.json"), "defaultAction")?;
+        fs::write(root.join("array.json"), "[]")?;
+        assert!(validate_seccomp_profile(&root.join("valid.json")).is_ok());
+        assert!(validate_seccomp_profile(&root.join("invalid.json")).is_err());
+        assert!(validate_seccomp_profile(&root.join("array.json")).is_err());
+        assert!(validate_seccomp_profile(&root.join("missing.json")).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_tag_only_image() -> Result<()> {
         let target: Target = TargetTriple::X86_64UnknownLinuxGnu.into();
@@ -1659,7 +4249,7 @@ mod tests {
 
         fn unset_env() -> Vec<(&'static str, Option<String>)> {
             let mut result = vec![];
-            let envvars = ["CARGO_HOME", "XARGO_HOME", "NIX_STORE"];
+            let envvars = ["CARGO_HOME", "CROSS_XARGO_HOME", "XARGO_HOME", "NIX_STORE"];
             for var in envvars {
                 result.push((var, env::var(var).ok()));
                 env::remove_var(var);
@@ -1677,7 +4267,14 @@ mod tests {
         }
 
         fn create_engine(msg_info: &mut MessageInfo) -> Result<Engine> {
-            Engine::from_path(get_container_engine()?, None, Some(false), msg_info)
+            Engine::from_path(
+                get_container_engine()?,
+                None,
+                Some(false),
+                None,
+                true,
+                msg_info,
+            )
         }
 
         fn cargo_metadata(subdir: bool, msg_info: &mut MessageInfo) -> Result<CargoMetadata> {
@@ -1737,7 +4334,13 @@ mod tests {
         ) -> Result<(Directories, CargoMetadata)> {
             let cwd = get_cwd()?;
             let toolchain = get_toolchain()?;
-            Directories::assemble(mount_finder, metadata, &cwd, toolchain)
+            Directories::assemble(
+                mount_finder,
+                metadata,
+                &cwd,
+                toolchain,
+                &mut MessageInfo::default(),
+            )
         }
 
         #[track_caller]
@@ -1768,6 +4371,26 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        #[cfg_attr(cross_sandboxed, ignore)]
+        fn test_manifest_outside_cwd() -> Result<()> {
+            let vars = unset_env();
+            let mount_finder = MountFinder::new(vec![]);
+            // such as with `--manifest-path ../other/Cargo.toml`
+            let mut metadata = cargo_metadata(false, &mut MessageInfo::default())?;
+            metadata.workspace_root = temp::dir()?.join("Documents").join("other");
+            fs::create_dir_all(&metadata.workspace_root)?;
+            let (directories, metadata) = get_directories(metadata, &mount_finder)?;
+            let package_dirs = directories.package_directories();
+            paths_equal(package_dirs.host_root(), &metadata.workspace_root)?;
+            let mount_root = metadata.workspace_root.as_posix_absolute()?;
+            assert_eq!(package_dirs.mount_root(), &mount_root);
+            assert_eq!(package_dirs.mount_cwd(), &mount_root);
+
+            reset_env(vars);
+            Ok(())
+        }
+
         #[test]
         #[cfg_attr(not(target_os = "linux"), ignore)]
         fn test_docker_in_docker() -> Result<()> {
@@ -1812,6 +4435,60 @@ mod tests {
         }
     }
 
+    mod git_linked_directories {
+        use super::*;
+        use std::fs;
+
+        #[test]
+        fn worktree_mounts_common_dir() -> Result<()> {
+            let tmp = tempfile::tempdir()?;
+            let tmp = file::canonicalize(tmp.path())?;
+            let main_git = tmp.join("main").join(".git");
+            let gitdir = main_git.join("worktrees").join("feature");
+            fs::create_dir_all(&gitdir)?;
+            fs::write(gitdir.join("commondir"), "../..\n")?;
+            let worktree = tmp.join("feature");
+            fs::create_dir_all(worktree.join("src"))?;
+            fs::write(
+                worktree.join(".git"),
+                format!("gitdir: {}\n", gitdir.to_utf8()?),
+            )?;
+
+            let msg_info = &mut MessageInfo::default();
+            assert_eq!(
+                git_linked_directories(&worktree, msg_info)?,
+                vec![main_git.clone()]
+            );
+            // the `.git` file is outside the root when building a subdirectory
+            assert_eq!(
+                git_linked_directories(&worktree.join("src"), msg_info)?,
+                vec![worktree.join(".git"), main_git]
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn stale_gitdir_is_skipped() -> Result<()> {
+            let tmp = tempfile::tempdir()?;
+            let tmp = file::canonicalize(tmp.path())?;
+            let worktree = tmp.join("moved");
+            fs::create_dir_all(&worktree)?;
+            fs::write(worktree.join(".git"), "gitdir: ../missing/.git\n")?;
+            let msg_info = &mut MessageInfo::default();
+            assert!(git_linked_directories(&worktree, msg_info)?.is_empty());
+            Ok(())
+        }
+
+        #[test]
+        fn regular_repository_mounts_nothing() -> Result<()> {
+            let tmp = tempfile::tempdir()?;
+            let tmp = file::canonicalize(tmp.path())?;
+            fs::create_dir_all(tmp.join(".git"))?;
+            assert!(git_linked_directories(&tmp, &mut MessageInfo::default())?.is_empty());
+            Ok(())
+        }
+    }
+
     mod mount_finder {
         use super::*;
 
@@ -1867,6 +4544,60 @@ mod tests {
                 finder.find_mount_path("/project/target")
             );
         }
+
+        #[test]
+        fn test_is_mounted() {
+            assert!(MountFinder::default().is_mounted(Path::new("/shared")));
+            let finder = MountFinder::new(vec![MountDetail {
+                source: PathBuf::from("/home/user/src"),
+                destination: PathBuf::from("/src"),
+            }]);
+            assert!(finder.is_mounted(Path::new("/src/shared")));
+            assert!(!finder.is_mounted(Path::new("/shared")));
+        }
+
+        #[test]
+        fn test_verify_mounted() {
+            let paths = [
+                ("the workspace", Path::new("/src/project")),
+                ("the cargo home", Path::new("/root/.cargo")),
+            ];
+            assert!(MountFinder::default().verify_mounted(&paths).is_ok());
+            // without the root directory, such as with another storage driver
+            let finder = MountFinder::new(vec![MountDetail {
+                source: PathBuf::from("/home/user/src"),
+                destination: PathBuf::from("/src"),
+            }]);
+            let err = finder.verify_mounted(&paths).unwrap_err();
+            assert!(err.to_string().starts_with("the cargo home `/root/.cargo`"));
+            assert!(finder.verify_mounted(&paths[..1]).is_ok());
+            let finder = MountFinder::new(vec![MountDetail {
+                source: PathBuf::from("/var/lib/docker/overlay2/container-id/merged"),
+                destination: PathBuf::from("/"),
+            }]);
+            assert!(finder.verify_mounted(&paths).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_out_of_root_paths() {
+        let root = Path::new("/home/user/ws/app");
+        let deps = [
+            "/home/user/ws/app/crates/core",
+            "/home/user/ws/shared",
+            "/home/user/ws/shared/macros",
+            "/home/user/ws/shared",
+            "/opt/vendor/sys",
+            "/home/user/ws/app-utils",
+        ];
+        assert_eq!(
+            out_of_root_paths(&[root], deps.iter().map(Path::new)),
+            [
+                Path::new("/home/user/ws/app-utils"),
+                Path::new("/home/user/ws/shared"),
+                Path::new("/opt/vendor/sys"),
+            ]
+        );
     }
 
     mod parse_docker_inspect {
@@ -0,0 +1,345 @@
+//! Prebuilt tools installed into the custom image from `tools`.
+
+use serde::{Deserialize, Serialize};
+
+use super::Architecture;
+use crate::errors::*;
+
+/// A tool in `target.{}.tools`: the `name` or `name@version` of a known
+/// tool, or a table providing the download URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Tool {
+    Known(String),
+    Custom(CustomTool),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CustomTool {
+    name: String,
+    version: Option<String>,
+    url: Option<String>,
+    bins: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Archive {
+    TarGz,
+    TarXz,
+    Zip,
+    Binary,
+}
+
+impl Archive {
+    fn from_url(url: &str) -> Self {
+        if url.ends_with(".tar.gz") || url.ends_with(".tgz") {
+            Archive::TarGz
+        } else if url.ends_with(".tar.xz") {
+            Archive::TarXz
+        } else if url.ends_with(".zip") {
+            Archive::Zip
+        } else {
+            Archive::Binary
+        }
+    }
+}
+
+struct KnownTool {
+    name: &'static str,
+    version: &'static str,
+    url: &'static str,
+    archive: Archive,
+    bins: &'static [&'static str],
+    arch: fn(&Architecture) -> Option<&'static str>,
+}
+
+fn nextest_arch(arch: &Architecture) -> Option<&'static str> {
+    match arch {
+        Architecture::Amd64 => Some("linux"),
+        Architecture::Arm64 => Some("linux-arm"),
+        _ => None,
+    }
+}
+
+fn rust_arch(arch: &Architecture) -> Option<&'static str> {
+    match arch {
+        Architecture::Amd64 => Some("x86_64"),
+        Architecture::Arm64 => Some("aarch64"),
+        _ => None,
+    }
+}
+
+fn protoc_arch(arch: &Architecture) -> Option<&'static str> {
+    match arch {
+        Architecture::Amd64 => Some("x86_64"),
+        Architecture::Arm64 => Some("aarch_64"),
+        _ => None,
+    }
+}
+
+const KNOWN_TOOLS: &[KnownTool] = &[
+    KnownTool {
+        name: "cargo-nextest",
+        version: "0.9.72",
+        url: "https://get.nexte.st/{version}/{arch}",
+        archive: Archive::TarGz,
+        bins: &["cargo-nextest"],
+        arch: nextest_arch,
+    },
+    KnownTool {
+        name: "sccache",
+        version: "0.8.1",
+        url: "https://github.com/mozilla/sccache/releases/download/v{version}/sccache-v{version}-{arch}-unknown-linux-musl.tar.gz",
+        archive: Archive::TarGz,
+        bins: &["sccache"],
+        arch: rust_arch,
+    },
+    KnownTool {
+        name: "protoc",
+        version: "27.3",
+        url: "https://github.com/protocolbuffers/protobuf/releases/download/v{version}/protoc-{version}-linux-{arch}.zip",
+        archive: Archive::Zip,
+        bins: &["protoc"],
+        arch: protoc_arch,
+    },
+    KnownTool {
+        name: "cargo-audit",
+        version: "0.20.0",
+        url: "https://github.com/rustsec/rustsec/releases/download/cargo-audit%2Fv{version}/cargo-audit-{arch}-unknown-linux-musl-v{version}.tgz",
+        archive: Archive::TarGz,
+        bins: &["cargo-audit"],
+        arch: rust_arch,
+    },
+    KnownTool {
+        name: "cargo-deny",
+        version: "0.16.1",
+        url: "https://github.com/EmbarkStudios/cargo-deny/releases/download/{version}/cargo-deny-{version}-{arch}-unknown-linux-musl.tar.gz",
+        archive: Archive::TarGz,
+        bins: &["cargo-deny"],
+        arch: rust_arch,
+    },
+    KnownTool {
+        name: "cargo-llvm-cov",
+        version: "0.6.11",
+        url: "https://github.com/taiki-e/cargo-llvm-cov/releases/download/v{version}/cargo-llvm-cov-{arch}-unknown-linux-musl.tar.gz",
+        archive: Archive::TarGz,
+        bins: &["cargo-llvm-cov"],
+        arch: rust_arch,
+    },
+];
+
+/// A tool with the download URL for the architecture of the image.
+#[derive(Debug, PartialEq, Eq)]
+struct ResolvedTool {
+    name: String,
+    url: String,
+    archive: Archive,
+    bins: Vec<String>,
+}
+
+fn known_tool(name: &str) -> Result<&'static KnownTool> {
+    KNOWN_TOOLS
+        .iter()
+        .find(|tool| tool.name == name)
+        .ok_or_else(|| eyre::eyre!("unknown tool `{name}`"))
+        .with_suggestion(|| {
+            let names: Vec<_> = KNOWN_TOOLS.iter().map(|tool| tool.name).collect();
+            format!(
+                "known tools are {}, or provide a `url` for the tool",
+                names.join(", ")
+            )
+        })
+}
+
+fn is_shell_safe(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '+'))
+}
+
+impl Tool {
+    /// The name of the tool, without the version.
+    pub fn name(&self) -> &str {
+        match self {
+            Tool::Known(spec) => spec.split('@').next().unwrap_or(spec),
+            Tool::Custom(tool) => &tool.name,
+        }
+    }
+
+    fn resolve(&self, arch: &Architecture) -> Result<ResolvedTool> {
+        let (name, version, url, bins) = match self {
+            Tool::Known(spec) => (
+                self.name(),
+                spec.split_once('@').map(|(_, version)| version),
+                None,
+                None,
+            ),
+            Tool::Custom(tool) => (
+                tool.name.as_str(),
+                tool.version.as_deref(),
+                tool.url.as_deref(),
+                tool.bins.clone(),
+            ),
+        };
+
+        let (template, archive, version, arch_name, bins) = match url {
+            Some(url) => (
+                url,
+                Archive::from_url(url),
+                version.unwrap_or("latest"),
+                rust_arch(arch),
+                bins.unwrap_or_else(|| vec![name.to_owned()]),
+            ),
+            None => {
+                let known = known_tool(name)?;
+                (
+                    known.url,
+                    known.archive,
+                    version.unwrap_or(known.version),
+                    (known.arch)(arch),
+                    bins.unwrap_or_else(|| known.bins.iter().map(|&b| b.to_owned()).collect()),
+                )
+            }
+        };
+
+        if !is_shell_safe(name) || !is_shell_safe(version) {
+            eyre::bail!("invalid tool `{name}` with version `{version}`");
+        }
+        if let Some(bin) = bins.iter().find(|bin| !is_shell_safe(bin)) {
+            eyre::bail!("invalid binary name `{bin}` for tool `{name}`");
+        }
+        let mut url = template.replace("{version}", version);
+        if url.contains("{arch}") {
+            let arch_name = arch_name.ok_or_else(|| {
+                eyre::eyre!("no prebuilt `{name}` is available for the `{arch}` architecture")
+            })?;
+            url = url.replace("{arch}", arch_name);
+        }
+        if url.contains(['\'', '"', '\\', '$', '`']) {
+            eyre::bail!("invalid URL `{url}` for tool `{name}`");
+        }
+
+        Ok(ResolvedTool {
+            name: name.to_owned(),
+            url,
+            archive,
+            bins,
+        })
+    }
+}
+
+impl ResolvedTool {
+    /// The `RUN` instruction downloading and installing the tool, which is
+    /// cached as its own layer.
+    fn layer(&self) -> String {
+        let dir = format!("/tmp/cross-tool-{}", self.name);
+        let bins = self.bins.join(" ");
+        let extract = match self.archive {
+            Archive::TarGz => "tar -xzf download".to_owned(),
+            Archive::TarXz => "tar -xJf download".to_owned(),
+            Archive::Zip => "unzip -q download".to_owned(),
+            Archive::Binary => format!("for bin in {bins}; do cp download \"$bin\"; done"),
+        };
+        format!(
+            "RUN set -e; mkdir -p {dir}; cd {dir}; \
+            curl --retry 3 -fsSL '{url}' -o download; \
+            {extract}; \
+            for bin in {bins}; do \
+            find . -type f -name \"$bin\" -exec install -m 755 {{}} /usr/local/bin/ \\; ; \
+            test -x \"/usr/local/bin/$bin\"; \
+            done; \
+            cd /; rm -rf {dir}",
+            url = self.url,
+        )
+    }
+}
+
+/// The instructions installing `tools` into an image for `arch`, after
+/// `curl`, and `unzip` for zip archives, if the image does not provide them.
+pub fn layers(tools: &[Tool], arch: &Architecture) -> Result<String> {
+    let tools = tools
+        .iter()
+        .map(|tool| tool.resolve(arch))
+        .collect::<Result<Vec<_>>>()?;
+    if tools.is_empty() {
+        return Ok(String::new());
+    }
+    let mut commands = vec!["curl"];
+    if tools.iter().any(|tool| tool.archive == Archive::Zip) {
+        commands.push("unzip");
+    }
+    let mut layers = vec![super::packages::missing_commands_layer(&commands)];
+    layers.extend(tools.iter().map(ResolvedTool::layer));
+    Ok(layers.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_known_tools() -> Result<()> {
+        let tool = Tool::Known("sccache@0.7.7".to_owned()).resolve(&Architecture::Arm64)?;
+        assert_eq!(
+            tool.url,
+            "https://github.com/mozilla/sccache/releases/download/v0.7.7/sccache-v0.7.7-aarch64-unknown-linux-musl.tar.gz"
+        );
+        assert_eq!(tool.bins, vec!["sccache".to_owned()]);
+
+        let tool = Tool::Known("cargo-nextest".to_owned()).resolve(&Architecture::Amd64)?;
+        assert_eq!(tool.url, "https://get.nexte.st/0.9.72/linux");
+        assert_eq!(tool.archive, Archive::TarGz);
+
+        assert!(Tool::Known("protoc".to_owned())
+            .resolve(&Architecture::S390x)
+            .is_err());
+        assert!(Tool::Known("unknown".to_owned())
+            .resolve(&Architecture::Amd64)
+            .is_err());
+        assert!(Tool::Known("sccache@$(id)".to_owned())
+            .resolve(&Architecture::Amd64)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tool_layers() -> Result<()> {
+        assert_eq!(layers(&[], &Architecture::Amd64)?, "");
+
+        let tools = [Tool::Known("sccache".to_owned())];
+        let script = layers(&tools, &Architecture::Amd64)?;
+        assert!(script.contains("for cmd in curl; do"));
+
+        let tools = [Tool::Known("protoc".to_owned())];
+        let script = layers(&tools, &Architecture::Amd64)?;
+        assert!(script.contains("for cmd in curl unzip; do"));
+        assert!(script.contains("unzip -q download"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_custom_tools() -> Result<()> {
+        let tool = Tool::Custom(CustomTool {
+            name: "just".to_owned(),
+            version: Some("1.34.0".to_owned()),
+            url: Some("https://example.com/just-{version}-{arch}.tar.gz".to_owned()),
+            bins: None,
+        })
+        .resolve(&Architecture::Amd64)?;
+        assert_eq!(
+            tool,
+            ResolvedTool {
+                name: "just".to_owned(),
+                url: "https://example.com/just-1.34.0-x86_64.tar.gz".to_owned(),
+                archive: Archive::TarGz,
+                bins: vec!["just".to_owned()],
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+//! Notices for images which do not match the running version of cross.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Image, CROSS_IMAGE, DEFAULT_IMAGE_VERSION};
+use crate::errors::*;
+use crate::shell::MessageInfo;
+
+/// Only nudge about upgrading the image once a day.
+const NOTICE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Compare the `tag` of a provided image with the running cross version.
+///
+/// Returns `None` if the tag cannot be compared, such as for local images.
+#[must_use]
+pub fn tag_is_current(tag: &str) -> Option<bool> {
+    let version = DEFAULT_IMAGE_VERSION;
+    if tag == version || tag.starts_with(&format!("{version}-")) {
+        Some(true)
+    } else if tag.starts_with(|c: char| c.is_ascii_digit()) || tag == "main" {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The tag of `name`, if it's a provided image for another release of cross.
+fn outdated_tag(name: &str) -> Option<&str> {
+    let (_, tag) = name
+        .strip_prefix(&format!("{CROSS_IMAGE}/"))?
+        .split_once(':')?;
+    let tag = tag.split('@').next().unwrap_or(tag);
+    (tag_is_current(tag) == Some(false)).then_some(tag)
+}
+
+fn notice_path() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|d| d.data_dir().join("cross-rs").join("upgrade-notice"))
+}
+
+/// Returns `true` if no notice was recorded in `path` within the interval,
+/// and records `now` as the time of the last notice.
+fn should_notify(path: &Path, now: SystemTime) -> Result<bool> {
+    let now = now.duration_since(UNIX_EPOCH)?.as_secs();
+    let last = fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok());
+    if last.is_some_and(|last| now.saturating_sub(last) < NOTICE_INTERVAL.as_secs()) {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, now.to_string())?;
+    Ok(true)
+}
+
+/// Suggest `cross-util images upgrade` if `image` is a provided image for
+/// another release of cross. This is rate-limited, and never fails the build.
+pub(crate) fn notify_outdated(image: &Image, msg_info: &mut MessageInfo) -> Result<()> {
+    let Some(tag) = outdated_tag(&image.name) else {
+        return Ok(());
+    };
+    let notify = notice_path()
+        .map(|path| should_notify(&path, SystemTime::now()).unwrap_or_default())
+        .unwrap_or_default();
+    if notify {
+        msg_info.note(format_args!(
+            "image `{image}` has the tag `{tag}`, but this version of cross uses `{DEFAULT_IMAGE_VERSION}`.
+Run `cross-util images upgrade` to pull the images matching this version."
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outdated_tags() {
+        let name = |tag: &str| format!("{CROSS_IMAGE}/aarch64-unknown-linux-gnu:{tag}");
+        let version = DEFAULT_IMAGE_VERSION;
+        let old = if version == "0.1.0" { "0.2.0" } else { "0.1.0" };
+
+        assert_eq!(outdated_tag(&name(version)), None);
+        assert_eq!(outdated_tag(&name(&format!("{version}-centos"))), None);
+        assert_eq!(outdated_tag(&name(old)), Some(old));
+        assert_eq!(outdated_tag(&name(&format!("{old}@sha256:abc"))), Some(old));
+        assert_eq!(outdated_tag(&name("local")), None);
+        assert_eq!(outdated_tag(&format!("example.com/image:{old}")), None);
+    }
+
+    #[test]
+    fn notice_rate_limit() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("cross-rs").join("upgrade-notice");
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert!(should_notify(&path, now)?);
+        assert!(!should_notify(&path, now + Duration::from_secs(60))?);
+        assert!(should_notify(&path, now + NOTICE_INTERVAL)?);
+
+        Ok(())
+    }
+}
@@ -34,6 +34,8 @@ pub trait CommandExt {
         status: ExitStatus,
         output: Option<&Output>,
     ) -> Result<(), CommandError>;
+    /// Write the output to the container log, if one is set with `--quiet-container`.
+    fn container_output(&mut self, msg_info: &MessageInfo) -> Result<&mut Self>;
     #[track_caller]
     fn run(&mut self, msg_info: &mut MessageInfo, silence_stdout: bool) -> Result<()>;
     #[track_caller]
@@ -140,6 +142,13 @@ impl CommandExt for Command {
         }
     }
 
+    fn container_output(&mut self, msg_info: &MessageInfo) -> Result<&mut Self> {
+        if let Some((stdout, stderr)) = msg_info.container_log_stdio()? {
+            self.stdout(stdout).stderr(stderr);
+        }
+        Ok(self)
+    }
+
     /// Runs the command to completion
     #[track_caller]
     fn run(&mut self, msg_info: &mut MessageInfo, silence_stdout: bool) -> Result<()> {
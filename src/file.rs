@@ -3,8 +3,6 @@ use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::Read;
-#[cfg(target_family = "windows")]
-use std::path::Prefix;
 use std::path::{Component, Path, PathBuf};
 
 use crate::errors::*;
@@ -31,50 +29,78 @@ pub trait PathExt {
     fn as_posix_absolute(&self) -> Result<String>;
 }
 
-#[cfg(target_family = "windows")]
-fn format_prefix(prefix: &str) -> Result<String> {
-    match prefix {
-        "" => Ok("".to_owned()),
-        _ => Ok(format!("/mnt/{}", prefix.to_lowercase())),
+/// Split the prefix of a Windows path, with `/` separators, into the
+/// directory it's mounted at in WSL-style paths, such as `c` for `C:` and
+/// `//?/C:`, and the rest of the path.
+#[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+fn split_windows_prefix(path: &str) -> Option<(String, &str)> {
+    let disk = |prefix: &str| {
+        let mut chars = prefix.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(c), Some(':'), None) if c.is_ascii_alphabetic() => Some(c.to_string()),
+            _ => None,
+        }
+    };
+    let unc = |server: &str, volume: &str| {
+        // the administrative shares of the drives, such as `\\localhost\c$`
+        match volume.strip_suffix('$') {
+            Some(d)
+                if server == "localhost"
+                    && d.len() == 1
+                    && d.chars().all(|c| c.is_ascii_alphabetic()) =>
+            {
+                d.to_owned()
+            }
+            _ => format!("{server}/{volume}"),
+        }
+    };
+    fn next(path: &str) -> (&str, &str) {
+        path.split_once('/').unwrap_or((path, ""))
     }
-}
-
-#[cfg(target_family = "windows")]
-fn fmt_disk(disk: u8) -> String {
-    (disk as char).to_string()
-}
 
-#[cfg(target_family = "windows")]
-fn fmt_ns_disk(disk: &std::ffi::OsStr) -> Result<String> {
-    let disk = disk.to_utf8()?;
-    Ok(match disk.len() {
-        // ns can be similar to `\\.\COM42`, or also `\\.\C:\`
-        2 => {
-            let c = disk.chars().next().expect("cannot be empty");
-            if c.is_ascii_alphabetic() && disk.ends_with(':') {
-                fmt_disk(c as u8)
-            } else {
-                disk.to_owned()
-            }
+    if let Some(rest) = path
+        .strip_prefix("//?/")
+        .or_else(|| path.strip_prefix("//./"))
+    {
+        let (first, rest) = next(rest);
+        if first.eq_ignore_ascii_case("UNC") {
+            let (server, rest) = next(rest);
+            let (volume, rest) = next(rest);
+            return Some((unc(server, volume), rest));
         }
-        _ => disk.to_owned(),
-    })
+        return Some((disk(first).unwrap_or_else(|| first.to_owned()), rest));
+    }
+    if let Some(rest) = path.strip_prefix("//") {
+        let (server, rest) = next(rest);
+        let (volume, rest) = next(rest);
+        return Some((unc(server, volume), rest));
+    }
+    let (first, rest) = next(path);
+    Some((disk(first)?, rest))
 }
 
-#[cfg(target_family = "windows")]
-fn fmt_unc(server: &std::ffi::OsStr, volume: &std::ffi::OsStr) -> Result<String> {
-    let server = server.to_utf8()?;
-    let volume = volume.to_utf8()?;
-    let bytes = volume.as_bytes();
-    if server == "localhost"
-        && bytes.len() == 2
-        && bytes[1] == b'$'
-        && bytes[0].is_ascii_alphabetic()
-    {
-        Ok(fmt_disk(bytes[0]))
-    } else {
-        Ok(format!("{}/{}", server, volume))
+/// Map an absolute Windows path to the path it's mounted at in the container,
+/// such as `C:\Users` to `/mnt/c/Users`. Paths without a prefix, such as
+/// `/mnt/c/Users`, are already POSIX paths.
+///
+/// This only depends on the path, so it's the same on every host.
+#[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+pub fn windows_to_posix(path: &str) -> Result<String> {
+    let path = path.replace('\\', "/");
+    let (prefix, rest) = match split_windows_prefix(&path) {
+        Some((prefix, rest)) => (format!("/mnt/{}", prefix.to_lowercase()), rest),
+        None if path.starts_with('/') => (String::new(), path.as_str()),
+        None => eyre::bail!("unable to map relative windows path `{path}` to a posix path."),
+    };
+    let mut output = prefix;
+    for component in rest.split('/').filter(|c| !c.is_empty()) {
+        output.push('/');
+        output.push_str(component);
+    }
+    if output.is_empty() {
+        output.push('/');
     }
+    Ok(output)
 }
 
 impl PathExt for Path {
@@ -115,46 +141,7 @@ impl PathExt for Path {
     // separators and will only work with absolute paths.
     #[cfg(target_family = "windows")]
     fn as_posix_absolute(&self) -> Result<String> {
-        let path = absolute_path(self)?;
-
-        let push = |p: &mut String, c: &str, r: bool| {
-            if !r {
-                p.push('/');
-            }
-            p.push_str(c);
-        };
-        // iterate over components to join them
-        let mut output = String::new();
-        let mut root_prefix = String::new();
-        let mut was_root = false;
-        for component in path.components() {
-            match component {
-                Component::Prefix(prefix) => {
-                    root_prefix = match prefix.kind() {
-                        Prefix::Verbatim(verbatim) => verbatim.to_utf8()?.to_owned(),
-                        Prefix::VerbatimUNC(server, volume) => fmt_unc(server, volume)?,
-                        // we should never get this, but it's effectively just
-                        // a root_prefix since we force absolute paths.
-                        Prefix::VerbatimDisk(disk) => fmt_disk(disk),
-                        Prefix::UNC(server, volume) => fmt_unc(server, volume)?,
-                        Prefix::DeviceNS(ns) => fmt_ns_disk(ns)?,
-                        Prefix::Disk(disk) => fmt_disk(disk),
-                    }
-                }
-                Component::RootDir => output = format!("{}/", format_prefix(&root_prefix)?),
-                Component::CurDir => push(&mut output, ".", was_root),
-                Component::ParentDir => push(&mut output, "..", was_root),
-                Component::Normal(path) => push(&mut output, path.to_utf8()?, was_root),
-            }
-            was_root = component == Component::RootDir;
-        }
-
-        // remove trailing '/'
-        if was_root {
-            output.truncate(output.len() - 1);
-        }
-
-        Ok(output)
+        windows_to_posix(absolute_path(self)?.to_utf8()?)
     }
 }
 
@@ -327,6 +314,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn windows_paths_to_posix() {
+        let posix = |path: &str| windows_to_posix(path).ok();
+        assert_eq!(posix(r"C:\"), Some("/mnt/c".to_owned()));
+        assert_eq!(
+            posix(r"C:\Users\cross"),
+            Some("/mnt/c/Users/cross".to_owned())
+        );
+        assert_eq!(
+            posix("d:/project/target"),
+            Some("/mnt/d/project/target".to_owned())
+        );
+        assert_eq!(posix(r"\\?\C:\Users"), Some("/mnt/c/Users".to_owned()));
+        assert_eq!(posix(r"\\.\C:\Users"), Some("/mnt/c/Users".to_owned()));
+        assert_eq!(
+            posix(r"\\localhost\c$\Users"),
+            Some("/mnt/c/Users".to_owned())
+        );
+        assert_eq!(
+            posix(r"\\?\UNC\localhost\c$\Users"),
+            Some("/mnt/c/Users".to_owned())
+        );
+        assert_eq!(
+            posix(r"\\Server\Share\project"),
+            Some("/mnt/server/share/project".to_owned())
+        );
+        assert_eq!(posix("/mnt/c/Users"), Some("/mnt/c/Users".to_owned()));
+        assert_eq!(posix("/"), Some("/".to_owned()));
+        assert_eq!(posix(r"Users\cross"), None);
+        assert_eq!(posix("C:"), Some("/mnt/c".to_owned()));
+    }
+
     #[test]
     #[cfg(target_family = "windows")]
     fn as_posix_prefix() {
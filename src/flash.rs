@@ -0,0 +1,109 @@
+//! `cross flash`: build in the container, then flash the artifact from the host.
+
+use std::path::PathBuf;
+
+use crate::cargo::Subcommand;
+use crate::cli::{self, Args};
+use crate::errors::*;
+
+/// Turn `cross flash` into the `cargo build` run in the container, with the
+/// JSON messages of cargo to find the executable. Returns if the messages
+/// were only requested by cross, and so are not printed.
+pub(crate) fn build_args(mut args: Args) -> (Args, bool) {
+    if let Some(index) = cli::subcommand_index(&args.cargo_args) {
+        args.cargo_args[index] = "build".to_owned();
+    }
+    let hide_messages = cli::request_json_messages(&mut args.cargo_args);
+    args.subcommand = Some(Subcommand::Build);
+    (args, hide_messages)
+}
+
+/// Get the value of the flag `name` in the cargo arguments.
+pub(crate) fn flag_value<'a>(cargo_args: &'a [String], names: &[&str]) -> Option<&'a str> {
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        for name in names {
+            if arg == name {
+                return args.next().map(String::as_str);
+            } else if let Some(value) = arg
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+            {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+/// The ELF to flash, from the executables reported by cargo for the build.
+pub(crate) fn executable(executables: &[PathBuf]) -> Result<PathBuf> {
+    match executables {
+        [executable] => Ok(executable.clone()),
+        [] => eyre::bail!("cargo did not build an executable to flash"),
+        _ => Err(eyre::eyre!(
+            "could not determine which binary to flash, found {}",
+            executables
+                .iter()
+                .filter_map(|e| e.file_name()?.to_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+        .with_suggestion(|| "select the binary with `--bin` or `--example`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    macro_rules! s {
+        ($x:literal) => {
+            $x.to_owned()
+        };
+    }
+
+    #[test]
+    fn flash_build_args() -> Result<()> {
+        let target_list = crate::rustc::TargetList {
+            triples: vec![s!("thumbv7em-none-eabihf")],
+        };
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+
+        let parsed = cli::parse_args(
+            args(&["--target", "thumbv7em-none-eabihf", "flash", "--release"]),
+            &target_list,
+        )?;
+        let (build, hide_messages) = build_args(parsed);
+        assert!(hide_messages);
+        assert_eq!(build.subcommand, Some(Subcommand::Build));
+        assert_eq!(
+            build.cargo_args,
+            args(&[
+                "--target",
+                "thumbv7em-none-eabihf",
+                "build",
+                "--message-format=json-render-diagnostics",
+                "--release"
+            ])
+        );
+
+        let parsed = cli::parse_args(args(&["flash", "--message-format=json"]), &target_list)?;
+        let (build, hide_messages) = build_args(parsed);
+        assert!(!hide_messages);
+        assert_eq!(build.cargo_args, args(&["build", "--message-format=json"]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn flash_executable() -> Result<()> {
+        let dir = Path::new("/ws/target/thumbv7em-none-eabihf/release");
+        assert_eq!(executable(&[dir.join("boot")])?, dir.join("boot"));
+        assert!(executable(&[]).is_err());
+        assert!(executable(&[dir.join("boot"), dir.join("firmware")]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cli::Args;
+use crate::errors::*;
+use crate::extensions::CommandExt;
+use crate::file;
+use crate::shell::MessageInfo;
+use crate::Target;
+
+/// When a hook is run relative to the containerized build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// The `pre-build-host` commands, before the image is built.
+    PreBuildHost,
+    Pre,
+    Post,
+}
+
+impl HookStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookStage::PreBuildHost => "pre-build-host",
+            HookStage::Pre => "pre",
+            HookStage::Post => "post",
+        }
+    }
+}
+
+/// The build environment exported to every hook process.
+#[derive(Debug)]
+pub struct HookEnv {
+    target: String,
+    deb_arch: Option<&'static str>,
+    profile: String,
+    workspace_root: PathBuf,
+    target_dir: PathBuf,
+}
+
+impl HookEnv {
+    pub fn new(
+        args: &Args,
+        target: &Target,
+        workspace_root: &Path,
+        target_dir: &Path,
+    ) -> Result<Self> {
+        // the hooks run from the workspace root, not the current directory
+        let target_dir = match &args.target_dir {
+            Some(dir) => file::absolute_path(dir)?,
+            None => target_dir.to_owned(),
+        };
+        Ok(HookEnv {
+            target: target.triple().to_owned(),
+            deb_arch: target.target().deb_arch(),
+            profile: profile(&args.cargo_args),
+            workspace_root: workspace_root.to_owned(),
+            target_dir,
+        })
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// The directory cargo writes the documentation to.
+    pub fn doc_dir(&self) -> PathBuf {
+        self.target_dir.join(&self.target).join("doc")
+    }
+
+    /// The directory cargo places the artifacts for the target and profile in.
+    pub fn artifact_dir(&self) -> PathBuf {
+        let profile_dir = match self.profile.as_str() {
+            "dev" | "test" => "debug",
+            "bench" => "release",
+            profile => profile,
+        };
+        self.target_dir.join(&self.target).join(profile_dir)
+    }
+}
+
+/// Get the cargo profile selected by `--release` or `--profile`.
+fn profile(cargo_args: &[String]) -> String {
+    let mut profile = "dev".to_owned();
+    // the arguments after `--` are for the program, not cargo
+    let cargo_args = cargo_args.split(|a| a == "--").next().unwrap_or_default();
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--release" || arg == "-r" {
+            profile = "release".to_owned();
+        } else if arg == "--profile" {
+            if let Some(value) = args.next() {
+                profile = value.clone();
+            }
+        } else if let Some(value) = arg.strip_prefix("--profile=") {
+            profile = value.to_owned();
+        }
+    }
+    profile
+}
+
+fn shell_command(hook: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", hook]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", hook]);
+        command
+    }
+}
+
+/// Run the host-side hooks for `stage`, in order, from the workspace root.
+///
+/// Fails on the first hook which does not exit successfully.
+pub fn run(
+    hooks: &[String],
+    stage: HookStage,
+    env: &HookEnv,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    for hook in hooks {
+        msg_info.note(format_args!("running {} hook `{hook}`", stage.as_str()))?;
+        let mut command = shell_command(hook);
+        if let Some(arch) = env.deb_arch {
+            command.env("CROSS_DEB_ARCH", arch);
+        }
+        command
+            .current_dir(&env.workspace_root)
+            .env("CROSS_HOOK", stage.as_str())
+            .env("CROSS_TARGET", &env.target)
+            .env("CROSS_PROFILE", &env.profile)
+            .env("CROSS_WORKSPACE_ROOT", &env.workspace_root)
+            .env("CROSS_TARGET_DIR", &env.target_dir)
+            .env("CROSS_ARTIFACT_DIR", env.artifact_dir())
+            .run(msg_info, false)
+            .wrap_err_with(|| format!("{} hook `{hook}` failed", stage.as_str()))?;
+    }
+    Ok(())
+}
+
+/// Run the `flasher` for `cross flash` from the workspace root, with the path
+/// to the artifact as its last argument.
+pub fn run_flasher(
+    flasher: &str,
+    artifact: &Path,
+    env: &HookEnv,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    if !artifact.is_file() {
+        eyre::bail!("the artifact to flash `{artifact:?}` does not exist");
+    }
+    let command = if cfg!(windows) {
+        format!("{flasher} \"%CROSS_FLASH_ARTIFACT%\"")
+    } else {
+        format!("{flasher} \"$CROSS_FLASH_ARTIFACT\"")
+    };
+    msg_info.note(format_args!("flashing with `{flasher}`"))?;
+    shell_command(&command)
+        .current_dir(&env.workspace_root)
+        .env("CROSS_TARGET", &env.target)
+        .env("CROSS_PROFILE", &env.profile)
+        .env("CROSS_FLASH_ARTIFACT", artifact)
+        .run(msg_info, false)
+        .wrap_err_with(|| format!("flasher `{flasher}` failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! s {
+        ($x:literal) => {
+            $x.to_owned()
+        };
+    }
+
+    #[test]
+    fn profile_from_args() {
+        assert_eq!(profile(&[s!("build")]), "dev");
+        assert_eq!(profile(&[s!("build"), s!("--release")]), "release");
+        assert_eq!(profile(&[s!("build"), s!("--profile"), s!("ci")]), "ci");
+        assert_eq!(profile(&[s!("build"), s!("--profile=ci")]), "ci");
+        assert_eq!(profile(&[s!("run"), s!("--"), s!("--release")]), "dev");
+        assert_eq!(
+            profile(&[s!("run"), s!("-r"), s!("--"), s!("--profile"), s!("x")]),
+            "release"
+        );
+    }
+}
@@ -77,3 +77,15 @@ pub fn username() -> Result<Option<String>> {
 
     Ok(Some(name.to_string_lossy().into_owned()))
 }
+
+#[cfg(target_os = "windows")]
+pub fn group_id_from_name(_: &str) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn group_id_from_name(name: &str) -> Result<Option<u32>> {
+    let group = nix::unistd::Group::from_name(name)
+        .wrap_err_with(|| format!("could not get group `{name}`"))?;
+    Ok(group.map(|g| g.gid.as_raw()))
+}
@@ -1,8 +1,10 @@
 use std::path::Path;
 
+use crate::docker::{Architecture, Engine};
 use crate::errors::*;
 use crate::file;
-use crate::Target;
+use crate::shell::MessageInfo;
+use crate::{Target, TargetTriple};
 
 /// Checks if the interpreters have been registered in the host system
 pub fn is_registered(target: &Target) -> Result<bool> {
@@ -25,3 +27,247 @@ pub fn is_registered(target: &Target) -> Result<bool> {
 
     Ok(ok)
 }
+
+/// The architecture of the binaries of `target`, if it's a docker platform.
+fn target_arch(target: &Target) -> Option<Architecture> {
+    match target.triple().split('-').next()? {
+        "i586" | "i686" => Some(Architecture::I386),
+        arch => Architecture::new(arch).ok(),
+    }
+}
+
+/// The name of the binfmt_misc handler registered by `qemu-user-static`.
+fn binfmt_name(arch: &Architecture) -> Option<&'static str> {
+    Some(match arch {
+        Architecture::I386 => "qemu-i386",
+        Architecture::Amd64 => "qemu-x86_64",
+        Architecture::Arm => "qemu-arm",
+        Architecture::Arm64 => "qemu-aarch64",
+        Architecture::Mips => "qemu-mips",
+        Architecture::Mips64 => "qemu-mips64",
+        Architecture::Mips64Le => "qemu-mips64el",
+        Architecture::MipsLe => "qemu-mipsel",
+        Architecture::Ppc64 => "qemu-ppc64",
+        Architecture::Ppc64Le => "qemu-ppc64le",
+        Architecture::Riscv64 => "qemu-riscv64",
+        Architecture::S390x => "qemu-s390x",
+        Architecture::LoongArch64 => "qemu-loongarch64",
+        Architecture::Wasm => return None,
+    })
+}
+
+/// Whether `runner` can run the binaries of `target`. `native` needs a host
+/// architecture able to run the binaries, or an interpreter registered with
+/// binfmt_misc. The other runners work if their binary `resolves`.
+fn runner_works(
+    runner: &str,
+    target: &Target,
+    host: Option<&Architecture>,
+    binfmt: impl Fn(&str) -> bool,
+    resolves: impl Fn(&str) -> bool,
+) -> bool {
+    if runner != "native" {
+        return resolves(runner);
+    }
+    if !(target.is_linux() || target.is_android()) {
+        return false;
+    }
+    let Some(arch) = target_arch(target) else {
+        return false;
+    };
+    let compatible = host.is_some_and(|host| {
+        *host == arch
+            || matches!(
+                (host, &arch),
+                (Architecture::Amd64, Architecture::I386)
+                    | (Architecture::Arm64, Architecture::Arm)
+            )
+    });
+    compatible || binfmt_name(&arch).is_some_and(binfmt)
+}
+
+fn binfmt_enabled(name: &str) -> bool {
+    file::read(Path::new("/proc/sys/fs/binfmt_misc").join(name))
+        .is_ok_and(|handler| handler.lines().next() == Some("enabled"))
+}
+
+/// Select the first runner of `runners` working for `target` on the host of
+/// `engine`. A single runner is always used as is.
+pub fn select_runner(
+    runners: &[String],
+    target: &Target,
+    engine: &Engine,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<String>> {
+    // the handlers of a remote host cannot be read.
+    let binfmt = |name: &str| !engine.is_remote && binfmt_enabled(name);
+    // the runners of the image are verified with its labels.
+    let in_image = |_: &str| true;
+    pick_runner(
+        runners,
+        target,
+        engine.arch.as_ref(),
+        binfmt,
+        in_image,
+        msg_info,
+    )
+}
+
+/// The binary of `runner` on this host, such as `qemu-aarch64` for `qemu-user`.
+fn runner_binary(runner: &str, target: &Target) -> Option<String> {
+    match runner {
+        "qemu-user" => qemu_user(target).map(ToOwned::to_owned),
+        _ => shell_words::split(runner).ok()?.into_iter().next(),
+    }
+}
+
+/// Select the first runner of `runners` working for `target` on this host,
+/// for the native backend. A single runner is always used as is.
+pub fn select_host_runner(
+    runners: &[String],
+    target: &Target,
+    host: &TargetTriple,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<String>> {
+    let arch = Architecture::from_target(host).ok();
+    let on_path = |runner: &str| {
+        runner_binary(runner, target).is_some_and(|binary| which::which(binary).is_ok())
+    };
+    pick_runner(
+        runners,
+        target,
+        arch.as_ref(),
+        binfmt_enabled,
+        on_path,
+        msg_info,
+    )
+}
+
+fn pick_runner(
+    runners: &[String],
+    target: &Target,
+    host: Option<&Architecture>,
+    binfmt: impl Fn(&str) -> bool,
+    resolves: impl Fn(&str) -> bool,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<String>> {
+    if runners.len() <= 1 {
+        return Ok(runners.first().cloned());
+    }
+    for runner in runners {
+        if runner_works(runner, target, host, &binfmt, &resolves) {
+            msg_info.debug(format_args!("using runner `{runner}` for {target}"))?;
+            return Ok(Some(runner.clone()));
+        }
+        msg_info.debug(format_args!(
+            "runner `{runner}` cannot run binaries for {target} on this host"
+        ))?;
+    }
+    Err(eyre::eyre!(
+        "none of the runners `{}` can run binaries for {target}",
+        runners.join("`, `")
+    ))
+}
+
+/// The `qemu-user` emulator of the binaries of `target`, such as `qemu-aarch64`.
+pub fn qemu_user(target: &Target) -> Option<&'static str> {
+    binfmt_name(&target_arch(target)?)
+}
+
+/// The runner of the native backend for `target` when none is configured:
+/// `qemu-user` if `host` cannot run its binaries, or `None` to run them
+/// directly.
+pub fn default_host_runner(target: &Target, host: &TargetTriple) -> Option<&'static str> {
+    let arch = Architecture::from_target(host).ok();
+    let runs_natively = runner_works("native", target, arch.as_ref(), |_| false, |_| true);
+    let emulated = (target.is_linux() || target.is_android()) && qemu_user(target).is_some();
+    (!runs_natively && emulated).then_some("qemu-user")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_host_runners() {
+        let x86_64 = TargetTriple::X86_64UnknownLinuxGnu;
+        let target = Target::new_built_in;
+
+        assert_eq!(
+            default_host_runner(&target("aarch64-unknown-linux-gnu"), &x86_64),
+            Some("qemu-user")
+        );
+        assert_eq!(
+            default_host_runner(&target("x86_64-unknown-linux-musl"), &x86_64),
+            None
+        );
+        assert_eq!(
+            default_host_runner(&target("i686-unknown-linux-gnu"), &x86_64),
+            None
+        );
+        // qemu-user only runs linux binaries
+        assert_eq!(
+            default_host_runner(&target("aarch64-pc-windows-msvc"), &x86_64),
+            None
+        );
+    }
+
+    #[test]
+    fn runner_probes() {
+        let armv7 = Target::new_built_in("armv7-unknown-linux-gnueabihf");
+        let i686 = Target::new_built_in("i686-unknown-linux-gnu");
+        let bare = Target::new_built_in("thumbv7em-none-eabihf");
+        let no_binfmt = |_: &str| false;
+        let resolves = |_: &str| true;
+
+        assert!(runner_works(
+            "native",
+            &armv7,
+            Some(&Architecture::Arm64),
+            no_binfmt,
+            resolves
+        ));
+        assert!(runner_works(
+            "native",
+            &i686,
+            Some(&Architecture::Amd64),
+            no_binfmt,
+            resolves
+        ));
+        assert!(!runner_works(
+            "native",
+            &armv7,
+            Some(&Architecture::Amd64),
+            no_binfmt,
+            resolves
+        ));
+        assert!(!runner_works("native", &armv7, None, no_binfmt, resolves));
+        assert!(runner_works(
+            "native",
+            &armv7,
+            Some(&Architecture::Amd64),
+            |name| name == "qemu-arm",
+            resolves
+        ));
+        assert!(!runner_works(
+            "native",
+            &bare,
+            Some(&Architecture::Arm),
+            no_binfmt,
+            resolves
+        ));
+        assert!(runner_works("qemu-user", &armv7, None, no_binfmt, resolves));
+        assert!(!runner_works("qemu-user", &armv7, None, no_binfmt, |_| {
+            false
+        }));
+
+        assert_eq!(
+            runner_binary("qemu-user", &armv7).as_deref(),
+            Some("qemu-arm")
+        );
+        assert_eq!(
+            runner_binary("'/opt/my sim/run' --fast", &armv7).as_deref(),
+            Some("/opt/my sim/run")
+        );
+    }
+}
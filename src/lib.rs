@@ -31,30 +31,48 @@
 #[cfg(test)]
 mod tests;
 
+mod artifact;
+mod batch;
 pub mod cargo;
+mod check;
+mod ci;
 pub mod cli;
 pub mod config;
+mod config_check;
 pub mod cross_toml;
+mod doc;
 pub mod docker;
 pub mod errors;
 mod extensions;
 pub mod file;
+mod flash;
+mod hooks;
 mod id;
 mod interpreter;
+pub mod migrate;
+mod native;
+pub mod prefetch;
+mod print;
+mod report;
 pub mod rustc;
 pub mod rustup;
+mod setup_cache;
+pub mod shard;
 pub mod shell;
+pub mod stats;
+mod targets;
 pub mod temp;
+mod version;
 
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
 
 use cli::Args;
 use color_eyre::owo_colors::OwoColorize;
 use color_eyre::{Help, SectionExt};
 use config::Config;
-use cross_toml::BuildStd;
+use cross_toml::{Backend, BuildStd};
 use rustc::{QualifiedToolchain, Toolchain};
 use rustc_version::Channel;
 use serde::{Deserialize, Serialize, Serializer};
@@ -221,6 +239,20 @@ impl TargetTriple {
             TargetTriple::Other(s) => s.as_str(),
         }
     }
+
+    /// Checks if the target links to musl instead of glibc, such as on Alpine
+    pub fn is_musl(&self) -> bool {
+        self.triple().contains("-musl")
+    }
+
+    /// The targets built for a pseudo-target of `cargo zigbuild`, such as
+    /// `universal2-apple-darwin` for universal macOS binaries.
+    pub fn zig_pseudo_targets(&self) -> Option<&'static [&'static str]> {
+        match self.triple() {
+            "universal2-apple-darwin" => Some(&["x86_64-apple-darwin", "aarch64-apple-darwin"]),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> From<&'a str> for TargetTriple {
@@ -507,9 +539,18 @@ fn warn_on_failure(
 }
 
 fn add_libc_version(triple: &str, zig_version: Option<&str>) -> String {
+    // only glibc targets have a version, which may already be in the triple,
+    // such as `aarch64-unknown-linux-gnu.2.17`.
+    let has_version = triple
+        .rsplit_once('-')
+        .is_some_and(|(_, env)| env.contains('.'));
+    // the pseudo-targets of `cargo zigbuild` are passed through unchanged
+    let is_pseudo = TargetTriple::from(triple).zig_pseudo_targets().is_some();
     match zig_version {
-        Some(libc) => format!("{triple}.{libc}"),
-        None => triple.to_owned(),
+        Some(libc) if triple.contains("-gnu") && !has_version && !is_pseudo => {
+            format!("{triple}.{libc}")
+        }
+        _ => triple.to_owned(),
     }
 }
 
@@ -517,13 +558,179 @@ pub fn run(
     args: Args,
     target_list: TargetList,
     msg_info: &mut MessageInfo,
+) -> Result<Option<ExitStatus>> {
+    // the cache directory is configured before anything is written to it
+    let needs_cache = args
+        .subcommand
+        .clone()
+        .is_some_and(|sc| sc.needs_docker(docker::Engine::is_remote(), args.in_container));
+    let mut metadata = None;
+    if needs_cache {
+        // the metadata is read quietly, and passed on to the build, which
+        // reads it again to report the errors if it cannot be read
+        metadata = cargo_metadata_with_args(None, Some(&args), &mut quiet_message_info())?;
+        if let Some(config) = metadata
+            .as_ref()
+            .and_then(|metadata| configure_cache_dir_with(metadata, Some(&args)))
+        {
+            evict_cache(&config, msg_info)?;
+        }
+    }
+    let report_path = args.build_report.clone();
+    let record_stats = stats::enabled();
+    let start = std::time::Instant::now();
+    let annotations = msg_info.ci_annotations;
+    let mut report =
+        (report_path.is_some() || record_stats || annotations).then(report::BuildReport::new);
+    let quiet_container = args.quiet_container
+        || env::var("CROSS_QUIET_CONTAINER")
+            .map(|s| config::bool_from_envvar(&s))
+            .unwrap_or_default();
+    if quiet_container {
+        let path = temp::dir()?.join(format!("container-{}.log", std::process::id()));
+        msg_info.set_container_log(path)?;
+    }
+    if annotations {
+        ci::add_problem_matcher(msg_info)?;
+    }
+    let shard = args.shard;
+    let result = run_build(args, target_list, metadata, report.as_mut(), None, msg_info).and_then(
+        |status| {
+            match (status, shard) {
+                // the tests are only listed and split in the container
+                (None, Some(shard)) => Err(shard_outside_container(shard)),
+                (status, _) => Ok(status),
+            }
+        },
+    );
+    if annotations {
+        ci::remove_problem_matcher(msg_info)?;
+        if let Err(err) = &result {
+            msg_info.print(ci::annotation("error", &format!("{err:#}")))?;
+        }
+    }
+    if let Some(path) = msg_info.container_log.take() {
+        let failed = match &result {
+            Ok(status) => status.is_some_and(|s| !s.success()),
+            Err(_) => true,
+        };
+        if failed {
+            msg_info.note(format_args!(
+                "the output of the container engine was written to `{}`",
+                path.to_utf8()?
+            ))?;
+        } else {
+            std::fs::remove_file(&path).ok();
+        }
+    }
+    if let Some(mut report) = report {
+        report.finish(&result, start.elapsed());
+        if let Some(path) = report_path {
+            // the report is written even if the build failed, and a report
+            // which cannot be written does not change the result of the build
+            if let Err(err) = report.write(&path) {
+                msg_info.warn(format_args!("{err:#}"))?;
+            }
+        }
+        if annotations {
+            if let Err(err) = ci::write_step_summary(&report) {
+                msg_info.warn(format_args!("could not write the job summary: {err}"))?;
+            }
+        }
+        if let Some(record) = report.to_stats().filter(|_| record_stats) {
+            if let Err(err) = stats::path().and_then(|path| stats::append(&path, &record)) {
+                msg_info.warn(format_args!("could not record build statistics: {err}"))?;
+            }
+        }
+    }
+    result
+}
+
+/// Use the `build.cache-dir` of the workspace for the cache directory, before
+/// anything is written to it, returning the configuration it was read from.
+///
+/// The configuration is read quietly, since it's read again for the build,
+/// which reports its errors and warnings.
+pub fn configure_cache_dir(args: Option<&Args>) -> Result<Option<Config>> {
+    let Some(metadata) = cargo_metadata_with_args(None, args, &mut quiet_message_info())? else {
+        return Ok(None);
+    };
+    Ok(configure_cache_dir_with(&metadata, args))
+}
+
+/// Like [`configure_cache_dir`], with the metadata of the workspace already
+/// read for the build.
+pub fn configure_cache_dir_with(metadata: &CargoMetadata, args: Option<&Args>) -> Option<Config> {
+    let mut quiet = quiet_message_info();
+    let toml = match args {
+        Some(args) => toml_with_profile(metadata, args, &mut quiet),
+        None => toml(metadata, &mut quiet),
+    };
+    let config = Config::new(Some(toml.ok()?));
+    if let Some(dir) = config.cache_dir() {
+        temp::set_cache_dir(metadata.workspace_root.join(dir));
+    }
+    Some(config)
+}
+
+fn quiet_message_info() -> MessageInfo {
+    let mut quiet: MessageInfo = Verbosity::Quiet.into();
+    quiet.ci_annotations = false;
+    quiet
+}
+
+/// Evict the least recently used entries above `build.cache-max-size`.
+fn evict_cache(config: &Config, msg_info: &mut MessageInfo) -> Result<()> {
+    let Some(max_size) = config.cache_max_size()? else {
+        return Ok(());
+    };
+    match temp::evict(max_size) {
+        Ok(evicted) if !evicted.is_empty() => msg_info.info(format_args!(
+            "evicted {} entries from the cache directory",
+            evicted.len()
+        )),
+        Ok(_) => Ok(()),
+        Err(err) => msg_info.warn(format_args!("could not evict cache entries: {err}")),
+    }
+}
+
+fn shard_outside_container(shard: shard::Shard) -> eyre::Report {
+    eyre::eyre!("`--shard {shard}` is only supported in the container").with_suggestion(|| {
+        "run the tests with an image of the target, without `--shard` on the host"
+    })
+}
+
+fn run_build(
+    args: Args,
+    target_list: TargetList,
+    metadata: Option<CargoMetadata>,
+    mut report: Option<&mut report::BuildReport>,
+    shared_setup: Option<&setup_cache::SetupCache>,
+    msg_info: &mut MessageInfo,
 ) -> Result<Option<ExitStatus>> {
     if args.version && args.subcommand.is_none() {
-        msg_info.print(concat!(
-            "cross ",
-            env!("CARGO_PKG_VERSION"),
-            crate::commit_info!()
-        ))?;
+        match args.version_format {
+            cli::VersionFormat::Human => msg_info.print(concat!(
+                "cross ",
+                env!("CARGO_PKG_VERSION"),
+                crate::commit_info!()
+            ))?,
+            cli::VersionFormat::Json => {
+                let info = version::VersionInfo::new();
+                msg_info.print(serde_json::to_string_pretty(&info)?)?;
+                return Ok(Some(ExitStatus::default()));
+            }
+        }
+    }
+
+    if args.subcommand == Some(Subcommand::Stats) {
+        stats::run(&args, msg_info)?;
+        return Ok(Some(ExitStatus::default()));
+    }
+
+    if args.subcommand == Some(Subcommand::Targets) {
+        targets::print(&target_list, args.version_format, msg_info)?;
+        return Ok(Some(ExitStatus::default()));
     }
 
     if let Some(Subcommand::Other(command)) = &args.subcommand {
@@ -533,10 +740,81 @@ pub fn run(
         return Ok(None);
     }
 
+    if let Some(shard) = args.shard {
+        if args.subcommand != Some(Subcommand::Test) {
+            eyre::bail!("`--shard {shard}` is only supported by `cross test`");
+        }
+    }
+
+    check_target_args(&args, msg_info)?;
+
+    if args.check_config_only {
+        return config_check::run(&args, target_list, msg_info);
+    }
+
+    if !args.targets.is_empty() && args.subcommand != Some(Subcommand::Prefetch) {
+        return check::run(args, target_list, msg_info);
+    }
+
+    if args.subcommand == Some(Subcommand::Batch) {
+        return batch::run(args, target_list, msg_info);
+    }
+
+    let is_flash = args.subcommand == Some(Subcommand::Flash);
+    let (mut args, mut hide_messages) = if is_flash {
+        flash::build_args(args)
+    } else {
+        (args, false)
+    };
+    // the artifacts of the report are the ones cargo reports for the build
+    if report.is_some() && matches!(args.subcommand, Some(Subcommand::Build | Subcommand::Rustc)) {
+        hide_messages |= cli::request_json_messages(&mut args.cargo_args);
+    }
+
     let host_version_meta = rustc::version_meta()?;
 
+    if args.subcommand == Some(Subcommand::Prefetch) {
+        let toml = match cargo_metadata_with_args(None, Some(&args), msg_info)? {
+            Some(metadata) => Some(toml_with_profile(&metadata, &args, msg_info)?),
+            None => None,
+        };
+        let config = Config::new(toml);
+        let host = host_version_meta.host();
+        prefetch::run(&args, &config, &target_list, host.triple(), msg_info)?;
+        return Ok(Some(ExitStatus::default()));
+    }
+
+    if args.subcommand == Some(Subcommand::Config) {
+        // the other `config` commands are cargo's, which run on the host
+        if !migrate::is_migrate(&args.cargo_args) {
+            return Ok(None);
+        }
+        let root = match cargo_metadata_with_args(None, Some(&args), msg_info)? {
+            Some(metadata) => metadata.workspace_root,
+            None => env::current_dir()?,
+        };
+        let paths: Vec<PathBuf> = cross_config_paths(&root)?
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect();
+        migrate::run(&paths, msg_info)?;
+        return Ok(Some(ExitStatus::default()));
+    }
+
     let cwd = std::env::current_dir()?;
-    if let Some(metadata) = cargo_metadata_with_args(None, Some(&args), msg_info)? {
+    let metadata = match metadata {
+        Some(metadata) => Some(metadata),
+        None => cargo_metadata_with_args(None, Some(&args), msg_info)?,
+    };
+    if let Some(mut metadata) = metadata {
+        let (config, target) =
+            setup_config(&host_version_meta, &metadata, &args, target_list, msg_info)?;
+        if config.backend(&target)? == Backend::Native {
+            if let Some(shard) = args.shard {
+                return Err(shard_outside_container(shard));
+            }
+            return native::run(&config, &target, &metadata, &args, msg_info);
+        }
         let CrossSetup {
             config,
             target,
@@ -548,24 +826,73 @@ pub fn run(
             is_remote,
             engine,
             image,
-        } = match setup(&host_version_meta, &metadata, &args, target_list, msg_info)? {
+        } = match setup_container(config, target, &args, msg_info)? {
             Some(setup) => setup,
+            _ if args.subcommand == Some(Subcommand::Print) => {
+                eyre::bail!("could not resolve the image for `cross print`");
+            }
             _ => {
                 return Ok(None);
             }
         };
 
         config.confusable_target(&target, msg_info)?;
+        // an explicit `--target-dir` is used as is
+        if config.isolate_target_dir() && args.target_dir.is_none() {
+            // tag the shared directory as a cache, for remote copies to skip it
+            docker::create_target_dir(&metadata.target_directory)?;
+            metadata.isolate_target_dir(target.triple());
+        }
+        if args.subcommand == Some(Subcommand::Print) {
+            let value = args
+                .print
+                .ok_or_else(|| eyre::eyre!("`cross print` requires a value to print"))
+                .with_suggestion(|| {
+                    "use one of `image`, `sysroot`, `runner`, `deb-arch`, or `mounts`"
+                })?;
+            if value == cli::PrintValue::Mounts {
+                if is_remote {
+                    eyre::bail!(
+                        "remote engines copy the files into data volumes instead of mounting them"
+                    );
+                }
+                let paths = docker_paths(&engine, metadata, cwd, &toolchain, &config, msg_info)?;
+                let options = docker::DockerOptions::new(
+                    engine,
+                    target,
+                    config,
+                    image,
+                    CommandVariant::create(uses_zig, uses_xargo)?,
+                    None,
+                    false,
+                );
+                let mounts = docker::mounts(&options, &paths, msg_info)?;
+                msg_info.print(print::mounts(&mounts, args.version_format)?)?;
+            } else {
+                let paths = docker_paths(&engine, metadata, cwd, &toolchain, &config, msg_info)?;
+                let toolchain_dirs = paths.directories.toolchain_directories();
+                let resolved = print::resolve(
+                    value,
+                    &config,
+                    &target,
+                    &image,
+                    toolchain_dirs,
+                    &engine,
+                    msg_info,
+                )?;
+                msg_info.print(resolved)?;
+            }
+            return Ok(Some(ExitStatus::default()));
+        }
+        if let Some(report) = report.as_mut() {
+            report.set_target(target.triple());
+        }
 
         let picked_generic_channel =
             matches!(toolchain.channel.as_str(), "stable" | "beta" | "nightly");
 
         if image.platform.target.is_supported(Some(&target)) {
-            if image.platform.architecture != toolchain.host().architecture {
-                msg_info.warn(format_args!(
-                    "toolchain `{toolchain}` may not run on image `{image}`"
-                ))?;
-            }
+            check_toolchain_platform(&toolchain, &image, &config, is_remote, msg_info)?;
             let mut is_nightly = toolchain.channel.contains("nightly");
             let mut rustc_version = None;
             if let Some((version, channel, commit)) = toolchain.rustc_version()? {
@@ -582,47 +909,154 @@ pub fn run(
                 rustc_version = Some(version);
             }
 
-            let available_targets = rustup::setup_rustup(&toolchain, msg_info)?;
-
-            rustup::setup_components(
-                &target,
-                uses_xargo,
-                build_std.enabled(),
+            let setup_cache = match shared_setup {
+                Some(setup_cache) => setup_cache.clone(),
+                None => setup_cache::SetupCache::new(!args.no_cache_setup)?,
+            };
+            // the pseudo-targets of `cargo zigbuild` need the std of each target
+            let rust_targets = match target.target().zig_pseudo_targets() {
+                Some(triples) => triples.iter().map(|t| Target::new_built_in(t)).collect(),
+                None => [target.clone()]
+                    .into_iter()
+                    .chain(args.extra_targets.iter().cloned())
+                    .collect::<Vec<_>>(),
+            };
+            for rust_target in &rust_targets {
+                rustup::setup(
+                    rust_target,
+                    uses_xargo,
+                    build_std.enabled(),
+                    &toolchain,
+                    is_nightly,
+                    &args,
+                    &setup_cache,
+                    msg_info,
+                )?;
+            }
+            // the toolchain is only installed by the setup above
+            check_msrv(
+                &metadata,
                 &toolchain,
-                is_nightly,
-                available_targets,
-                &args,
+                config.enforce_msrv(&target),
                 msg_info,
             )?;
 
-            let filtered_args =
+            let mut filtered_args =
                 get_filtered_args(zig_version, &args, &target, &config, is_nightly, &build_std);
+            let command_variant = CommandVariant::create(uses_zig, uses_xargo)?;
 
+            let is_publish = args.subcommand == Some(Subcommand::Publish);
+            if args.in_container && !is_publish {
+                msg_info.warn("`--in-container` is only used by `cross publish`, ignoring it.")?;
+            }
             let needs_docker = args
                 .subcommand
                 .clone()
-                .map_or(false, |sc| sc.needs_docker(is_remote));
+                .map_or(false, |sc| sc.needs_docker(is_remote, args.in_container));
             if target.needs_docker() && needs_docker {
-                let paths = docker::DockerPaths::create(
-                    &engine,
-                    metadata,
-                    cwd,
-                    toolchain.clone(),
-                    msg_info,
+                let hook_env = hooks::HookEnv::new(
+                    &args,
+                    &target,
+                    &metadata.workspace_root,
+                    &metadata.target_directory,
                 )?;
-                let options = docker::DockerOptions::new(
+                if let Some(report) = report.as_mut() {
+                    report.set_profile(hook_env.profile(), &hook_env.artifact_dir());
+                }
+                let is_coverage = args.subcommand == Some(Subcommand::LlvmCov);
+                let coverage_dir = metadata.target_directory.join(docker::COVERAGE_DIR);
+                let pre_build_host = config.pre_build_host(&target);
+                let pre_hooks = config.hooks_pre(&target);
+                let post_hooks = config.hooks_post(&target);
+                // the package is verified by cargo, and only built in `target/package`
+                let verify_artifacts = config.verify_artifacts(&target) && !is_publish;
+                // the artifacts verified are the ones cargo reports for the build
+                if verify_artifacts
+                    && matches!(args.subcommand, Some(Subcommand::Build | Subcommand::Rustc))
+                {
+                    hide_messages |= cli::request_json_messages(&mut filtered_args);
+                }
+                let flasher = if is_flash {
+                    let flasher = config
+                        .flasher(&target)
+                        .ok_or_else(|| eyre::eyre!("no flasher is configured for `{target}`"))
+                        .with_suggestion(|| {
+                            format!("set `target.{target}.flasher`, such as `probe-rs run --chip <CHIP>`")
+                        })?;
+                    Some(flasher)
+                } else {
+                    None
+                };
+                // the browser is opened from the host after the build
+                let doc_index = if args.subcommand.as_ref().is_some_and(Subcommand::documents)
+                    && doc::take_open(&mut filtered_args)
+                {
+                    Some(doc::index(
+                        &metadata,
+                        &args.cargo_args,
+                        &cwd,
+                        &hook_env.doc_dir(),
+                    )?)
+                } else {
+                    None
+                };
+                let paths = docker_paths(&engine, metadata, cwd, &toolchain, &config, msg_info)?;
+                let mut options = docker::DockerOptions::new(
                     engine,
                     target.clone(),
                     config,
                     image,
-                    crate::CommandVariant::create(uses_zig, uses_xargo)?,
+                    command_variant,
                     rustc_version,
                     false,
                 );
+                options.devices = args.devices.clone();
+                if args.subcommand == Some(Subcommand::Run) {
+                    // the configured ports, followed by the ones from the command line
+                    options.publish = options.config.publish(&target).unwrap_or_default();
+                    options.publish.extend(args.publish.iter().cloned());
+                } else if !args.publish.is_empty() {
+                    msg_info.warn("`--publish` is only used by `cross run`, ignoring it.")?;
+                }
+                options.runs_binaries = args
+                    .subcommand
+                    .clone()
+                    .is_some_and(|sc| sc.needs_interpreter());
+                options.skip_image_checks = args.skip_image_checks;
+                options.hide_messages = hide_messages;
+                options.target_in_env = target_in_env(&args, &target, &options.config);
+                options.shard = args.shard;
+                options.envs = args.envs.clone();
+                if is_publish {
+                    // the tokens are passed by name, so they're not in the command line
+                    let tokens: Vec<String> = env::vars()
+                        .map(|(key, _)| key)
+                        .filter(|key| cargo::is_registry_token_var(key))
+                        .collect();
+                    if !tokens.is_empty() {
+                        msg_info.warn(format_args!(
+                            "passing the registry tokens {} to the container, where they are exposed to the image `{}` and the build scripts.",
+                            tokens.iter().map(|t| format!("`{t}`")).collect::<Vec<_>>().join(", "),
+                            options.image,
+                        ))?;
+                    }
+                    options.envs.extend(tokens);
+                }
+                options.subcommand_tools = args
+                    .subcommand
+                    .as_ref()
+                    .and_then(Subcommand::tool)
+                    .into_iter()
+                    .collect();
 
                 if msg_info.should_fail() {
                     return Ok(None);
                 }
+                docker::notify_outdated(&options.image, msg_info)?;
+                if let Some(report) = report.as_mut() {
+                    let digest = options.engine.image_digest(&options.image.name, msg_info);
+                    report.set_image(&options.image.name, digest);
+                }
 
                 install_interpreter_if_needed(
                     &args,
@@ -631,7 +1065,16 @@ pub fn run(
                     &options,
                     msg_info,
                 )?;
-                let status = if let Some(status) = docker::run(
+                if let Some(commands) = &pre_build_host {
+                    hooks::run(
+                        commands,
+                        hooks::HookStage::PreBuildHost,
+                        &hook_env,
+                        msg_info,
+                    )?;
+                }
+                hooks::run(&pre_hooks, hooks::HookStage::Pre, &hook_env, msg_info)?;
+                let run = if let Some(run) = docker::run(
                     options,
                     paths,
                     &filtered_args,
@@ -640,13 +1083,46 @@ pub fn run(
                 )
                 .wrap_err("could not run container")?
                 {
-                    status
+                    run
                 } else {
                     return Ok(None);
                 };
+                let status = run.status;
 
                 let needs_host = args.subcommand.map_or(false, |sc| sc.needs_host(is_remote));
-                if !status.success() {
+                if status.success() {
+                    if verify_artifacts {
+                        let artifacts =
+                            report::reported_artifacts(&hook_env.artifact_dir(), &run.artifacts);
+                        artifact::verify(&artifacts, target.triple())?;
+                    }
+                    if let Some(report) = report.as_mut() {
+                        report.collect_artifacts(&hook_env.artifact_dir(), &run.artifacts);
+                    }
+                    hooks::run(&post_hooks, hooks::HookStage::Post, &hook_env, msg_info)?;
+                    if let Some(flasher) = &flasher {
+                        let executables: Vec<_> = run
+                            .artifacts
+                            .into_iter()
+                            .filter_map(|artifact| artifact.executable)
+                            .collect();
+                        let executable = flash::executable(&executables)?;
+                        hooks::run_flasher(flasher, &executable, &hook_env, msg_info)?;
+                    }
+                    if let Some(index) = &doc_index {
+                        doc::open(index, msg_info)?;
+                    }
+                    if is_coverage {
+                        msg_info.note(format_args!(
+                            "coverage data was written to `{}`, and can be merged on the host with `cargo llvm-cov report --target {}`",
+                            coverage_dir.to_utf8()?,
+                            target.triple()
+                        ))?;
+                    }
+                } else {
+                    if let Some(report) = report.as_mut() {
+                        report.set_termination(run.termination);
+                    }
                     warn_on_failure(&target, &toolchain, msg_info)?;
                 }
                 if !(status.success() && needs_host) {
@@ -681,6 +1157,95 @@ pub fn install_interpreter_if_needed(
     Ok(())
 }
 
+/// If `--target` was passed to cargo. Flags such as `--target-dir` do not
+/// select a target, and the arguments after `--` are for the compiler or the
+/// program, like `rustc -- --target`.
+fn has_target_flag(cargo_args: &[String]) -> bool {
+    cargo_args
+        .iter()
+        .take_while(|a| *a != "--")
+        .any(|a| a == "--target" || a.starts_with("--target="))
+}
+
+/// The targets passed to cargo with `--target`, in order.
+fn target_args(cargo_args: &[String]) -> Vec<&str> {
+    let mut targets = vec![];
+    let mut args = cargo_args.iter().take_while(|a| *a != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--target" {
+            targets.extend(args.next().map(String::as_str));
+        } else if let Some(target) = arg.strip_prefix("--target=") {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// Keep only the last `--target` of `cargo_args`: cargo would build all of
+/// them, but the image only provides the toolchain of a single target.
+fn last_target_arg(cargo_args: &[String]) -> Vec<String> {
+    let mut remaining = target_args(cargo_args).len();
+    let mut filtered = vec![];
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            filtered.push(arg.clone());
+            filtered.extend(args.by_ref().cloned());
+        } else if arg == "--target" || arg.starts_with("--target=") {
+            let value = if arg == "--target" { args.next() } else { None };
+            remaining = remaining.saturating_sub(1);
+            if remaining == 0 {
+                filtered.push(arg.clone());
+                filtered.extend(value.cloned());
+            }
+        } else {
+            filtered.push(arg.clone());
+        }
+    }
+    filtered
+}
+
+/// Note if `--target` was passed more than once with different targets, or
+/// fail with `--strict-args`. The last target is built.
+fn check_target_args(args: &Args, msg_info: &mut MessageInfo) -> Result<()> {
+    let targets = target_args(&args.cargo_args);
+    let Some(last) = targets.last() else {
+        return Ok(());
+    };
+    if targets.iter().all(|t| t == last) {
+        return Ok(());
+    }
+    let list = targets
+        .iter()
+        .map(|t| format!("`{t}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if args.strict_args {
+        return Err(eyre::eyre!("`--target` was passed more than once: {list}")).with_suggestion(
+            || "pass a single `--target`, or use `--targets` to check several targets",
+        );
+    }
+    msg_info.note(format_args!(
+        "`--target` was passed more than once ({list}), only the last target `{last}` is built. pass `--strict-args` to fail instead."
+    ))
+}
+
+/// If `auto-target` kept cross from passing the target to the subcommand, so
+/// it's passed to the tools in the container with `CARGO_BUILD_TARGET`.
+fn target_in_env(args: &Args, target: &Target, config: &Config) -> bool {
+    let needs_target = args.subcommand.as_ref().map_or(true, |sc| {
+        sc.target_flags(target.triple()).is_some() || sc.clone().needs_target_in_command()
+    });
+    let subcommand = cli::subcommand_index(&args.cargo_args).map(|i| args.cargo_args[i].as_str());
+    needs_target
+        && !args
+            .cargo_args
+            .iter()
+            .take_while(|a| *a != "--")
+            .any(|a| a.starts_with("--target"))
+        && !config.auto_target(target, subcommand)
+}
+
 /// Get filtered args to pass to cargo
 pub fn get_filtered_args(
     zig_version: Option<String>,
@@ -691,13 +1256,38 @@ pub fn get_filtered_args(
     build_std: &BuildStd,
 ) -> Vec<String> {
     let add_libc = |triple: &str| add_libc_version(triple, zig_version.as_deref());
-    let mut filtered_args = if args
+    let cargo_args = last_target_arg(&args.cargo_args);
+    let subcommand_name = cli::subcommand_index(&cargo_args);
+    let target_flags = args
+        .subcommand
+        .as_ref()
+        .and_then(|s| s.target_flags(target.triple()));
+    let mut filtered_args = if let Some(flags) = target_flags {
+        // these are global flags of the tool, so they must follow the subcommand
+        let mut filtered_args = cargo_args;
+        if !filtered_args.iter().any(|a| a.starts_with("--target"))
+            && config.auto_target(
+                target,
+                subcommand_name.map(|index| filtered_args[index].as_str()),
+            )
+        {
+            let index = subcommand_name.map_or(0, |index| index + 1);
+            let extra_flags = args.extra_targets.iter().flat_map(|target| {
+                args.subcommand
+                    .as_ref()
+                    .and_then(|s| s.target_flags(target.triple()))
+                    .unwrap_or_default()
+            });
+            filtered_args.splice(index..index, flags.into_iter().chain(extra_flags));
+        }
+        filtered_args
+    } else if args
         .subcommand
         .clone()
         .map_or(false, |s| !s.needs_target_in_command())
     {
         let mut filtered_args = Vec::new();
-        let mut args_iter = args.cargo_args.clone().into_iter();
+        let mut args_iter = cargo_args.into_iter();
         while let Some(arg) = args_iter.next() {
             if arg == "--target" {
                 args_iter.next();
@@ -709,14 +1299,19 @@ pub fn get_filtered_args(
         }
         filtered_args
     // Make sure --target is present
-    } else if !args.cargo_args.iter().any(|a| a.starts_with("--target")) {
-        let mut args_with_target = args.cargo_args.clone();
+    } else if !has_target_flag(&cargo_args)
+        && config.auto_target(
+            target,
+            subcommand_name.map(|index| cargo_args[index].as_str()),
+        )
+    {
+        let mut args_with_target = cargo_args;
         args_with_target.push("--target".to_owned());
         args_with_target.push(add_libc(target.triple()));
         args_with_target
     } else if zig_version.is_some() {
         let mut filtered_args = Vec::new();
-        let mut args_iter = args.cargo_args.clone().into_iter();
+        let mut args_iter = cargo_args.into_iter();
         while let Some(arg) = args_iter.next() {
             if arg == "--target" {
                 filtered_args.push("--target".to_owned());
@@ -731,7 +1326,7 @@ pub fn get_filtered_args(
         }
         filtered_args
     } else {
-        args.cargo_args.clone()
+        cargo_args
     };
 
     let is_test = args
@@ -763,17 +1358,66 @@ pub fn setup(
     target_list: TargetList,
     msg_info: &mut MessageInfo,
 ) -> Result<Option<CrossSetup>, color_eyre::Report> {
+    let (config, target) = setup_config(host_version_meta, metadata, args, target_list, msg_info)?;
+    setup_container(config, target, args, msg_info)
+}
+
+/// Read the configuration, and select the target to build for.
+fn setup_config(
+    host_version_meta: &rustc_version::VersionMeta,
+    metadata: &CargoMetadata,
+    args: &Args,
+    target_list: TargetList,
+    msg_info: &mut MessageInfo,
+) -> Result<(Config, Target)> {
     let host = host_version_meta.host();
-    let toml = toml(metadata, msg_info)?;
+    let toml = toml_with_profile(metadata, args, msg_info)?;
     let config = Config::new(Some(toml));
-    let target = args
-        .target
+    let target = select_target(&config, args, &target_list, &host);
+    Ok((config, target))
+}
+
+/// The target to build for: the one passed with `--target`, the configured
+/// one, or the host.
+fn select_target(
+    config: &Config,
+    args: &Args,
+    target_list: &TargetList,
+    host: &TargetTriple,
+) -> Target {
+    args.target
         .clone()
-        .or_else(|| config.target(&target_list))
-        .unwrap_or_else(|| Target::from(host.triple(), &target_list));
+        .or_else(|| config.target(target_list))
+        .unwrap_or_else(|| Target::from(host.triple(), target_list))
+}
+
+/// If `target` builds with xargo, and with zig.
+fn uses_xargo_and_zig(config: &Config, target: &Target) -> Result<(bool, bool)> {
+    let build_std = config.build_std(target).unwrap_or_default();
+    let is_zig_pseudo = target.target().zig_pseudo_targets().is_some();
+    let uses_xargo = !build_std.enabled()
+        && config
+            .xargo(target)
+            .unwrap_or(!target.is_builtin() && !is_zig_pseudo);
+    let uses_zig = config.zig(target).unwrap_or(false);
+    if is_zig_pseudo && !uses_zig {
+        return Err(eyre::eyre!(
+            "`{target}` is a pseudo-target of `cargo zigbuild`, which needs zig"
+        ))
+        .with_suggestion(|| format!("enable zig with `target.{target}.zig = true`"));
+    }
+    Ok((uses_xargo, uses_zig))
+}
+
+/// Resolve the toolchain, the container engine and the image of `target`.
+fn setup_container(
+    config: Config,
+    target: Target,
+    args: &Args,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<CrossSetup>> {
     let build_std = config.build_std(&target).unwrap_or_default();
-    let uses_xargo = !build_std.enabled() && config.xargo(&target).unwrap_or(!target.is_builtin());
-    let uses_zig = config.zig(&target).unwrap_or(false);
+    let (uses_xargo, uses_zig) = uses_xargo_and_zig(&config, &target)?;
     let zig_version = config.zig_version(&target);
     let image = match docker::get_image(&config, &target, uses_zig) {
         Ok(i) => i,
@@ -788,29 +1432,15 @@ pub fn setup(
             return Ok(None);
         }
     };
-    let default_toolchain = QualifiedToolchain::default(&config, msg_info)?;
-    let mut toolchain = if let Some(channel) = &args.channel {
-        let picked_toolchain: Toolchain = channel.parse()?;
-
-        if let Some(picked_host) = &picked_toolchain.host {
-            return Err(eyre::eyre!("the specified toolchain `{picked_toolchain}` can't be used"))
-                .with_suggestion(|| {
-                    format!(
-                        "try `cross +{}` instead",
-                        picked_toolchain.remove_host()
-                    )
-                }).with_section(|| format!(
-    r#"Overriding the toolchain in cross is only possible in CLI by specifying a channel and optional date: `+channel[-YYYY-MM-DD]`.
-To override the toolchain mounted in the image, set `target.{target}.image.toolchain = "{picked_host}"`"#).header("Note:".bright_cyan()));
-        }
-
-        default_toolchain.with_picked(picked_toolchain)?
-    } else {
-        default_toolchain
+    let mut toolchain = select_toolchain(&config, &target, args, msg_info)?;
+    let engine = container_engine(&config, &target, args, msg_info)?;
+    let is_remote = engine.is_remote;
+    config.check_lints(&target, is_remote, msg_info)?;
+    let platform = match &args.platform {
+        Some(platform) => Some(docker::ImagePlatform::from_override(platform)?),
+        None => config.platform(&target)?,
     };
-    let is_remote = docker::Engine::is_remote();
-    let engine = docker::Engine::new(None, Some(is_remote), msg_info)?;
-    let image = image.to_definite_with(&engine, msg_info)?;
+    let image = image.to_definite_with(&engine, platform.as_ref(), msg_info)?;
     toolchain.replace_host(&image.platform);
     Ok(Some(CrossSetup {
         config,
@@ -826,6 +1456,106 @@ To override the toolchain mounted in the image, set `target.{target}.image.toolc
     }))
 }
 
+/// The toolchain of the host: the default one, or the channel picked with
+/// `+channel` on the command line.
+pub(crate) fn select_toolchain(
+    config: &Config,
+    target: &Target,
+    args: &Args,
+    msg_info: &mut MessageInfo,
+) -> Result<QualifiedToolchain> {
+    let default_toolchain = QualifiedToolchain::default(config, msg_info)?;
+    let Some(channel) = &args.channel else {
+        return Ok(default_toolchain);
+    };
+    let picked_toolchain: Toolchain = channel.parse()?;
+
+    if let Some(picked_host) = &picked_toolchain.host {
+        return Err(eyre::eyre!("the specified toolchain `{picked_toolchain}` can't be used"))
+            .with_suggestion(|| {
+                format!(
+                    "try `cross +{}` instead",
+                    picked_toolchain.remove_host()
+                )
+            }).with_section(|| format!(
+r#"Overriding the toolchain in cross is only possible in CLI by specifying a channel and optional date: `+channel[-YYYY-MM-DD]`.
+To override the toolchain mounted in the image, set `target.{target}.image.toolchain = "{picked_host}"`"#).header("Note:".bright_cyan()));
+    }
+
+    default_toolchain.with_picked(picked_toolchain)
+}
+
+/// The container engine for `target`. A `build.podman-connection` selects
+/// podman, talking to the connection as a remote engine.
+pub(crate) fn container_engine(
+    config: &Config,
+    target: &Target,
+    args: &Args,
+    msg_info: &mut MessageInfo,
+) -> Result<docker::Engine> {
+    let podman_connection = config.podman_connection();
+    let is_remote = docker::Engine::is_remote() || podman_connection.is_some();
+    let name = config.engine(target).or_else(|| {
+        podman_connection
+            .as_ref()
+            .map(|_| docker::PODMAN.to_owned())
+    });
+    let engine = docker::Engine::from_name(
+        name.as_deref(),
+        None,
+        Some(is_remote),
+        podman_connection
+            .clone()
+            .or_else(|| config.container_context(target)),
+        !args.no_cache_setup,
+        msg_info,
+    )?;
+    if podman_connection.is_some() {
+        engine.check_podman_connection(msg_info)?;
+    }
+    Ok(engine)
+}
+
+/// Warn if the toolchain is older than the `rust-version` of the workspace,
+/// or fail with `enforce-msrv`.
+fn check_msrv(
+    metadata: &CargoMetadata,
+    toolchain: &QualifiedToolchain,
+    enforce: bool,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    let Some((package, rust_version)) = metadata.rust_version() else {
+        return Ok(());
+    };
+    let Some((version, _, _)) = toolchain.rustc_version()? else {
+        let message = format!(
+            "toolchain `{toolchain}` is not installed, unable to check the rust-version {rust_version} of package `{}`",
+            package.name
+        );
+        return if enforce {
+            Err(eyre::eyre!(message)).with_suggestion(|| {
+                format!("install the toolchain with `rustup toolchain add {toolchain} --profile minimal`")
+            })
+        } else {
+            msg_info.warn(message)
+        };
+    };
+    if cargo::satisfies_rust_version(&version, &rust_version) {
+        return Ok(());
+    }
+    let message = format!(
+        "toolchain `{toolchain}` is rustc {version}, but package `{}` requires rust-version {rust_version}",
+        package.name
+    );
+    if enforce {
+        Err(eyre::eyre!(message)).with_suggestion(|| {
+            format!("use a toolchain supporting the rust-version, such as `cross +{rust_version}`")
+        })
+    } else {
+        msg_info.warn(message)
+    }
+}
+
 #[derive(Debug)]
 pub struct CrossSetup {
     pub config: Config,
@@ -848,6 +1578,77 @@ pub(crate) enum VersionMatch {
     Different,
 }
 
+/// Check that `toolchain` can run on the image. Rustup toolchains are picked
+/// for the image, so this only fails for custom toolchains.
+fn check_toolchain_platform(
+    toolchain: &QualifiedToolchain,
+    image: &docker::Image,
+    config: &Config,
+    is_remote: bool,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    if toolchain.runs_on(&image.platform) {
+        return Ok(());
+    }
+    if config.host_toolchain() && !is_remote {
+        // a rustup toolchain for the image would replace the compiler of the
+        // custom toolchain for the whole build
+        return Err(eyre::eyre!(
+            "`build.host-toolchain` can't mount a toolchain for image `{image}` with the custom toolchain `{toolchain}`"
+        ))
+        .with_note(|| {
+            format!(
+                "`{toolchain}` can't run on the image, and the rustup toolchain for it would build the target crates instead of `{toolchain}`"
+            )
+        })
+        .with_suggestion(|| {
+            format!(
+                "use a custom toolchain built for `{}`, or unset `build.host-toolchain`",
+                image.platform.target
+            )
+        });
+    }
+    msg_info.warn(format_args!(
+        "toolchain `{toolchain}` may not run on image `{image}`"
+    ))?;
+    if image.platform.architecture == toolchain.host().architecture {
+        // only the C library differs, such as with a toolchain built on Alpine
+        msg_info.note(format_args!(
+            "`{toolchain}` is linked to {}, but the image provides {}, so its dynamic loader is missing. Use a custom toolchain built for `{}` instead.",
+            c_library(&toolchain.host().target),
+            c_library(&image.platform.target),
+            image.platform.target,
+        ))?;
+    }
+    Ok(())
+}
+
+fn c_library(target: &TargetTriple) -> &'static str {
+    match target.is_musl() {
+        true => "musl",
+        false => "glibc",
+    }
+}
+
+/// The paths of the host used by the container, with the cargo home
+/// dedicated to the project if it's configured.
+fn docker_paths(
+    engine: &docker::Engine,
+    metadata: CargoMetadata,
+    cwd: PathBuf,
+    toolchain: &QualifiedToolchain,
+    config: &Config,
+    msg_info: &mut MessageInfo,
+) -> Result<docker::DockerPaths> {
+    let mut paths =
+        docker::DockerPaths::create(engine, metadata, cwd, toolchain.clone(), msg_info)?;
+    if config.mount_cargo_home()? == Some(cross_toml::CargoHomeMode::Project) {
+        let project = docker::path_hash(paths.workspace_root(), docker::PATH_HASH_SHORT)?;
+        paths.set_cargo_home(&temp::cargo_home_dir()?.join(project))?;
+    }
+    Ok(paths)
+}
+
 pub(crate) fn warn_host_version_mismatch(
     host_version_meta: &rustc_version::VersionMeta,
     toolchain: &QualifiedToolchain,
@@ -901,40 +1702,178 @@ macro_rules! commit_info {
     };
 }
 
+/// Returns the paths to the `Cross.toml` files to read, ordered from the least
+/// to the most specific.
+///
+/// If `CROSS_CONFIG` is set, it is a list of paths separated like `PATH`.
+/// Otherwise, the system-wide and user configuration files are read first,
+/// followed by the project configuration files.
+fn cross_config_paths(root: &Path) -> Result<Vec<PathBuf>> {
+    ConfigSearch::from_env()?.paths(root)
+}
+
+/// The environment the configuration files are found from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ConfigSearch {
+    /// `CROSS_CONFIG`.
+    config: Option<std::ffi::OsString>,
+    /// `CROSS_CONFIG_SEARCH`.
+    search: Option<String>,
+    /// `$XDG_CONFIG_HOME`, defaulting to `~/.config`.
+    config_home: Option<PathBuf>,
+    cwd: PathBuf,
+}
+
+impl ConfigSearch {
+    fn from_env() -> Result<Self> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| home::home_dir().map(|home| home.join(".config")));
+        Ok(Self {
+            config: env::var_os("CROSS_CONFIG"),
+            search: env::var("CROSS_CONFIG_SEARCH").ok(),
+            config_home,
+            cwd: env::current_dir()?,
+        })
+    }
+
+    fn paths(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        if let Some(var) = &self.config {
+            return Ok(env::split_paths(var)
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect());
+        }
+
+        let mut paths = self.global_paths();
+        paths.extend(self.project_paths(root)?);
+        Ok(paths)
+    }
+
+    /// Returns the system-wide `/etc/cross/config.toml` and the user
+    /// `$XDG_CONFIG_HOME/cross/Cross.toml`.
+    fn global_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        if cfg!(unix) {
+            paths.push(PathBuf::from("/etc/cross/config.toml"));
+        }
+        if let Some(config_home) = &self.config_home {
+            paths.push(config_home.join("cross").join("Cross.toml"));
+        }
+        paths
+    }
+
+    /// Returns the `Cross.toml` files of the project.
+    ///
+    /// If `CROSS_CONFIG_SEARCH=parents`, every `Cross.toml` from the current
+    /// directory (if it's inside the workspace) or the workspace root up to the
+    /// root of the filesystem is used. Otherwise, only the workspace root is checked.
+    fn project_paths(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        match self.search.as_deref() {
+            Some("parents") => {
+                let start = if self.cwd.starts_with(root) {
+                    self.cwd.as_path()
+                } else {
+                    root
+                };
+                let mut paths: Vec<PathBuf> = start
+                    .ancestors()
+                    .map(|dir| dir.join("Cross.toml"))
+                    .filter(|path| path.exists())
+                    .collect();
+                paths.reverse();
+                Ok(paths)
+            }
+            Some("workspace") | None => Ok(vec![root.join("Cross.toml")]),
+            Some(search) => eyre::bail!(
+                "invalid value for `CROSS_CONFIG_SEARCH`: got `{search}`, expected `workspace` or `parents`"
+            ),
+        }
+    }
+}
+
+/// Merges the configuration files of `paths` which exist, the later files
+/// taking precedence.
+fn merge_cross_toml_files(
+    paths: &[PathBuf],
+    msg_info: &mut MessageInfo,
+) -> Result<Option<CrossToml>> {
+    let mut config: Option<CrossToml> = None;
+    for path in paths {
+        if path.exists() {
+            msg_info.debug(format_args!(
+                "using cross configuration from `{}`",
+                path.to_utf8()?
+            ))?;
+            let parsed = parse_cross_toml_file(path, msg_info)?;
+            config = Some(match config {
+                Some(config) => config.merge(parsed).wrap_err_with(|| {
+                    format!("could not merge the cross configuration from `{path:?}`")
+                })?,
+                None => parsed,
+            });
+        }
+    }
+    Ok(config)
+}
+
+fn parse_cross_toml_file(path: &Path, msg_info: &mut MessageInfo) -> Result<CrossToml> {
+    let cross_toml_str =
+        file::read(path).wrap_err_with(|| format!("could not read file `{path:?}`"))?;
+
+    let (config, _) =
+        CrossToml::parse_from_cross_str(&cross_toml_str, Some(path.to_utf8()?), msg_info)
+            .wrap_err_with(|| format!("failed to parse file `{path:?}` as TOML",))?;
+
+    Ok(config)
+}
+
+/// Parses the cross configuration, merging the profile selected with
+/// `--config-profile` or `CROSS_CONFIG_PROFILE` over it.
+pub fn toml_with_profile(
+    metadata: &CargoMetadata,
+    args: &Args,
+    msg_info: &mut MessageInfo,
+) -> Result<CrossToml> {
+    let toml = toml(metadata, msg_info)?;
+    let profile = args
+        .config_profile
+        .clone()
+        .or_else(|| env::var("CROSS_CONFIG_PROFILE").ok())
+        .filter(|p| !p.is_empty());
+    match profile {
+        Some(profile) => toml.with_profile(&profile),
+        None => Ok(toml),
+    }
+}
+
 /// Obtains the [`CrossToml`] from one of the possible locations
 ///
 /// These locations are checked in the following order:
-/// 1. If the `CROSS_CONFIG` variable is set, it tries to read the config from its value
-/// 2. Otherwise, the `Cross.toml` in the project root is used
+/// 1. If the `CROSS_CONFIG` variable is set, it tries to read the config from
+///    each of the paths in its value, with the later paths having precedence
+/// 2. Otherwise, `/etc/cross/config.toml`, then `~/.config/cross/Cross.toml`,
+///    then the `Cross.toml` in the project root is used, or if
+///    `CROSS_CONFIG_SEARCH=parents`, all `Cross.toml` files in the parent directories
 /// 3. Package and workspace metadata in the Cargo.toml
 ///
 /// The values from `CROSS_CONFIG` or `Cross.toml` are concatenated with the
 /// metadata in `Cargo.toml`, with `Cross.toml` having the highest priority.
+/// When multiple `Cross.toml` files are found, the ones closer to the current
+/// directory have precedence.
 pub fn toml(metadata: &CargoMetadata, msg_info: &mut MessageInfo) -> Result<CrossToml> {
     let root = &metadata.workspace_root;
-    let cross_config_path = match env::var("CROSS_CONFIG") {
-        Ok(var) => PathBuf::from(var),
-        Err(_) => root.join("Cross.toml"),
-    };
-
-    let mut config = if cross_config_path.exists() {
-        let cross_toml_str = file::read(&cross_config_path)
-            .wrap_err_with(|| format!("could not read file `{cross_config_path:?}`"))?;
-
-        let (config, _) = CrossToml::parse_from_cross_str(
-            &cross_toml_str,
-            Some(cross_config_path.to_utf8()?),
-            msg_info,
-        )
-        .wrap_err_with(|| format!("failed to parse file `{cross_config_path:?}` as TOML",))?;
-
-        config
-    } else {
-        // Checks if there is a lowercase version of this file
-        if root.join("cross.toml").exists() {
-            msg_info.warn("There's a file named cross.toml, instead of Cross.toml. You may want to rename it, or it won't be considered.")?;
+    let config = merge_cross_toml_files(&cross_config_paths(root)?, msg_info)?;
+
+    let mut config = match config {
+        Some(config) => config,
+        None => {
+            // Checks if there is a lowercase version of this file
+            if root.join("cross.toml").exists() {
+                msg_info.warn("There's a file named cross.toml, instead of Cross.toml. You may want to rename it, or it won't be considered.")?;
+            }
+            CrossToml::default()
         }
-        CrossToml::default()
     };
     let mut found: Option<std::borrow::Cow<'_, str>> = None;
 
@@ -981,3 +1920,276 @@ pub fn toml(metadata: &CargoMetadata, msg_info: &mut MessageInfo) -> Result<Cros
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod filtered_args_tests {
+    use super::*;
+
+    const TRIPLE: &str = "aarch64-unknown-linux-gnu";
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        let target_list = TargetList {
+            triples: vec![
+                TRIPLE.to_owned(),
+                "aarch64-unknown-linux-musl".to_owned(),
+                "x86_64-unknown-linux-gnu".to_owned(),
+            ],
+        };
+        cli::parse_args(args.iter().map(|&a| a.to_owned()).collect(), &target_list)
+    }
+
+    /// The build passing the filtered arguments to cargo in the container.
+    struct Build {
+        target: Target,
+        config: Config,
+        zig_version: Option<String>,
+        is_nightly: bool,
+        build_std: BuildStd,
+    }
+
+    impl Default for Build {
+        fn default() -> Self {
+            Build {
+                target: Target::new_built_in(TRIPLE),
+                config: Config::new(None),
+                zig_version: None,
+                is_nightly: false,
+                build_std: BuildStd::default(),
+            }
+        }
+    }
+
+    impl Build {
+        fn filtered(&self, args: &[&str]) -> Result<Vec<String>> {
+            Ok(get_filtered_args(
+                self.zig_version.clone(),
+                &parse(args)?,
+                &self.target,
+                &self.config,
+                self.is_nightly,
+                &self.build_std,
+            ))
+        }
+    }
+
+    fn filtered(args: &[&str]) -> Result<Vec<String>> {
+        Build::default().filtered(args)
+    }
+
+    #[test]
+    fn separator() -> Result<()> {
+        // `--target` after `--` is a flag of the program, not cargo's
+        assert_eq!(
+            filtered(&["run", "--", "--target", "foo"])?,
+            ["run", "--target", TRIPLE, "--", "--target", "foo"]
+        );
+        assert_eq!(
+            filtered(&["run", "--target", TRIPLE, "--", "--target", "foo"])?,
+            ["run", "--target", TRIPLE, "--", "--target", "foo"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn auto_target() -> Result<()> {
+        let (toml, _) = CrossToml::parse_from_cross_str(
+            "[build]\nauto-target = [\"llvm-cov\"]",
+            None,
+            &mut MessageInfo::default(),
+        )?;
+        let build = Build {
+            config: Config::new(Some(toml)),
+            ..Build::default()
+        };
+
+        // the value of a flag before the subcommand is not the subcommand
+        let llvm_cov = ["--color", "never", "llvm-cov"];
+        assert_eq!(build.filtered(&llvm_cov)?, llvm_cov);
+        assert!(target_in_env(
+            &parse(&llvm_cov)?,
+            &build.target,
+            &build.config
+        ));
+        assert_eq!(
+            build.filtered(&["--color", "never", "audit"])?,
+            [
+                "--color",
+                "never",
+                "audit",
+                "--target-arch",
+                "aarch64",
+                "--target-os",
+                "linux"
+            ]
+        );
+
+        // the target is only set in the environment if cross didn't pass it
+        for args in [
+            &["--color", "never", "build"][..],
+            &["llvm-cov", "--target", TRIPLE],
+            &["--color", "never", "metadata"],
+        ] {
+            assert!(!target_in_env(&parse(args)?, &build.target, &build.config));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_target() -> Result<()> {
+        let other = "x86_64-unknown-linux-gnu";
+
+        // the last target wins, like the target selecting the image
+        let args = parse(&["build", "--target", other, "--target", TRIPLE])?;
+        assert_eq!(args.target.as_ref().map(Target::triple), Some(TRIPLE));
+        assert_eq!(
+            filtered(&[
+                "build",
+                "--target=x86_64-unknown-linux-gnu",
+                "--release",
+                "--target",
+                TRIPLE,
+                "--",
+                "--target",
+                "foo"
+            ])?,
+            [
+                "build",
+                "--release",
+                "--target",
+                TRIPLE,
+                "--",
+                "--target",
+                "foo"
+            ]
+        );
+        assert_eq!(
+            filtered(&["build", "--target", TRIPLE, "--target", TRIPLE])?,
+            ["build", "--target", TRIPLE]
+        );
+
+        let mut msg_info = MessageInfo::default();
+        let conflicting = ["build", "--target", other, "--target", TRIPLE];
+        assert!(check_target_args(&parse(&conflicting)?, &mut msg_info).is_ok());
+        let mut strict = parse(&conflicting)?;
+        strict.strict_args = true;
+        assert!(check_target_args(&strict, &mut msg_info).is_err());
+        let duplicate = parse(&[
+            "build",
+            "--target",
+            TRIPLE,
+            "--target",
+            TRIPLE,
+            "--strict-args",
+        ])?;
+        assert!(duplicate.strict_args);
+        assert!(check_target_args(&duplicate, &mut msg_info).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn rustc() -> Result<()> {
+        let nightly = Build {
+            is_nightly: true,
+            ..Build::default()
+        };
+        assert!(parse(&["rustc", "--", "--target", "x"])?.target.is_none());
+
+        // the arguments after `--` are passed to rustc untouched
+        assert_eq!(
+            nightly.filtered(&[
+                "rustc",
+                "--release",
+                "--",
+                "--emit=asm",
+                "-Zunpretty=expanded"
+            ])?,
+            [
+                "rustc",
+                "--release",
+                "--target",
+                TRIPLE,
+                "--",
+                "--emit=asm",
+                "-Zunpretty=expanded"
+            ]
+        );
+        // `--target-dir` does not select a target
+        assert_eq!(
+            nightly.filtered(&["rustc", "--target-dir", "/tmp/t", "--", "--target", "x"])?,
+            [
+                "rustc",
+                "--target-dir",
+                "/target",
+                "--target",
+                TRIPLE,
+                "--",
+                "--target",
+                "x"
+            ]
+        );
+        let build_std = Build {
+            build_std: BuildStd::Bool(true),
+            ..nightly
+        };
+        assert_eq!(
+            build_std.filtered(&[
+                "rustdoc",
+                "--lib",
+                "--",
+                "--document-private-items",
+                "-Z",
+                "unstable-options"
+            ])?,
+            [
+                "rustdoc",
+                "--lib",
+                "--target",
+                TRIPLE,
+                "-Zbuild-std",
+                "--",
+                "--document-private-items",
+                "-Z",
+                "unstable-options"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn zig() -> Result<()> {
+        let zig = |triple: &str| Build {
+            target: Target::new_built_in(triple),
+            zig_version: Some("2.17".to_owned()),
+            ..Build::default()
+        };
+
+        let gnu = zig(TRIPLE);
+        assert_eq!(
+            gnu.filtered(&["test"])?,
+            ["test", "--target", "aarch64-unknown-linux-gnu.2.17"]
+        );
+        assert_eq!(
+            gnu.filtered(&["test", "--target", TRIPLE])?,
+            ["test", "--target", "aarch64-unknown-linux-gnu.2.17"]
+        );
+        // the glibc version is not added twice, nor to other targets
+        assert_eq!(
+            gnu.filtered(&["test", "--target=aarch64-unknown-linux-gnu.2.28"])?,
+            ["test", "--target=aarch64-unknown-linux-gnu.2.28"]
+        );
+        let musl = "aarch64-unknown-linux-musl";
+        assert_eq!(zig(musl).filtered(&["test"])?, ["test", "--target", musl]);
+        // the pseudo-targets of `cargo zigbuild` are passed through unchanged
+        let universal = "universal2-apple-darwin";
+        assert_eq!(
+            zig(universal).filtered(&["build", "--target", universal])?,
+            ["build", "--target", universal]
+        );
+        assert_eq!(
+            TargetTriple::from(universal).zig_pseudo_targets(),
+            Some(&["x86_64-apple-darwin", "aarch64-apple-darwin"][..])
+        );
+        assert_eq!(TargetTriple::from(TRIPLE).zig_pseudo_targets(), None);
+        Ok(())
+    }
+}
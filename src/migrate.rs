@@ -0,0 +1,362 @@
+//! The versions of the schema of `Cross.toml`, and the migration of files
+//! written for older versions.
+//!
+//! Files without `schema-version`, or with an older version, are checked for
+//! deprecated keys, which are otherwise silently ignored. The keys are
+//! replaced when the file is read, with a warning pointing at their line,
+//! and `cross config migrate` rewrites the file.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use toml::Spanned;
+
+use crate::cli;
+use crate::cross_toml::CrossToml;
+use crate::errors::*;
+use crate::file::{self, ToUtf8};
+use crate::shell::MessageInfo;
+
+/// The current version of the schema of `Cross.toml`.
+///
+/// Version 2 only accepts keys in kebab-case, such as `pre-build`: the
+/// snake-case spelling of older configurations is migrated.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The keys of a TOML document, with their spans.
+///
+/// Only the keys are spanned: the spans of implicit tables, such as
+/// `target` in `[target.x86_64-unknown-linux-gnu]`, are not available.
+#[derive(Debug, Default)]
+struct KeyTree {
+    keys: Vec<(Spanned<String>, KeyTree)>,
+}
+
+impl<'de> Deserialize<'de> for KeyTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = KeyTree;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a TOML value")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyTree, A::Error> {
+                let mut keys = vec![];
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    keys.push((key, map.next_value()?));
+                }
+                Ok(KeyTree { keys })
+            }
+
+            // the keys of arrays of tables are not migrated.
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<KeyTree, A::Error> {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(KeyTree::default())
+            }
+
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<KeyTree, E> {
+                Ok(KeyTree::default())
+            }
+
+            fn visit_i64<E: de::Error>(self, _: i64) -> Result<KeyTree, E> {
+                Ok(KeyTree::default())
+            }
+
+            fn visit_u64<E: de::Error>(self, _: u64) -> Result<KeyTree, E> {
+                Ok(KeyTree::default())
+            }
+
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<KeyTree, E> {
+                Ok(KeyTree::default())
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<KeyTree, E> {
+                Ok(KeyTree::default())
+            }
+        }
+
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+impl KeyTree {
+    fn parse(toml_str: &str) -> Result<KeyTree> {
+        Ok(toml::from_str(toml_str)?)
+    }
+
+    /// Every key with its dotted path, such as `build.env.passthrough`.
+    fn paths(&self) -> Vec<(String, &Spanned<String>)> {
+        let mut paths = vec![];
+        for (key, value) in &self.keys {
+            let path = key.get_ref().clone();
+            for (subpath, subkey) in value.paths() {
+                paths.push((format!("{path}.{subpath}"), subkey));
+            }
+            paths.push((path, key));
+        }
+        paths
+    }
+}
+
+/// A deprecated key, and the key replacing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// The dotted path of the key, such as `build.pre_build`.
+    pub path: String,
+    pub replacement: String,
+    /// The line of the key, starting at 1.
+    pub line: usize,
+    span: Range<usize>,
+}
+
+fn line_of(toml_str: &str, offset: usize) -> usize {
+    toml_str[..offset].matches('\n').count() + 1
+}
+
+/// Apply `migrations` to `toml_str`, replacing the keys in place so the
+/// comments and the layout of the file are kept.
+fn apply(toml_str: &str, migrations: &[Migration]) -> String {
+    let mut migrations: Vec<_> = migrations.iter().collect();
+    migrations.sort_by_key(|m| m.span.start);
+    let mut result = String::with_capacity(toml_str.len());
+    let mut last = 0;
+    for migration in migrations {
+        let old = migration.path.rsplit('.').next().unwrap_or_default();
+        let new = migration.replacement.rsplit('.').next().unwrap_or_default();
+        result.push_str(&toml_str[last..migration.span.start]);
+        // the key may be quoted, so only the name is replaced.
+        result.push_str(&toml_str[migration.span.clone()].replacen(old, new, 1));
+        last = migration.span.end;
+    }
+    result.push_str(&toml_str[last..]);
+    result
+}
+
+/// The version of the schema of the file, defaulting to 1.
+pub fn schema_version(config: &CrossToml) -> u32 {
+    config.schema_version.unwrap_or(1)
+}
+
+/// Find the deprecated keys among the `unused` keys of `toml_str`.
+///
+/// Only the replacements which are keys of the current schema are returned,
+/// so unknown keys are still reported as unused.
+pub fn check(toml_str: &str, unused: &BTreeSet<String>) -> Result<Vec<Migration>> {
+    let tree = KeyTree::parse(toml_str)?;
+    let paths = tree.paths();
+    let mut candidates = vec![];
+    for (path, key) in &paths {
+        if !unused.contains(path) || !key.get_ref().contains('_') {
+            continue;
+        }
+        let replacement_key = key.get_ref().replace('_', "-");
+        let replacement = match path.rsplit_once('.') {
+            Some((parent, _)) => format!("{parent}.{replacement_key}"),
+            None => replacement_key,
+        };
+        // renaming the key would define it twice.
+        if paths.iter().any(|(p, _)| *p == replacement) {
+            continue;
+        }
+        candidates.push(Migration {
+            path: path.clone(),
+            replacement,
+            line: line_of(toml_str, key.span().start),
+            span: key.span(),
+        });
+    }
+    if candidates.is_empty() {
+        return Ok(candidates);
+    }
+
+    let migrated = apply(toml_str, &candidates);
+    let (_, still_unused) = CrossToml::deserialize_with_unused(toml::Deserializer::new(&migrated))
+        .wrap_err("could not parse the migrated configuration")?;
+    candidates.retain(|m| !still_unused.contains(&m.replacement));
+    Ok(candidates)
+}
+
+/// Parse `toml_str` after replacing the deprecated keys, warning about each
+/// of them.
+pub(crate) fn migrate_str(
+    toml_str: &str,
+    unused: &BTreeSet<String>,
+    source: Option<&str>,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<String>> {
+    let migrations = check(toml_str, unused)?;
+    if migrations.is_empty() {
+        return Ok(None);
+    }
+    for migration in &migrations {
+        msg_info.warn(format_args!(
+            "{}{}: `{}` is deprecated, use `{}` instead",
+            source.map(|s| format!("{s}:")).unwrap_or_default(),
+            migration.line,
+            migration.path,
+            migration.replacement,
+        ))?;
+    }
+    msg_info.note("run `cross config migrate` to update the configuration")?;
+    Ok(Some(apply(toml_str, &migrations)))
+}
+
+/// Set `schema-version` to the current version, keeping the rest of the file.
+fn set_schema_version(toml_str: &str) -> Result<String> {
+    #[derive(serde::Deserialize)]
+    struct Version {
+        #[serde(rename = "schema-version")]
+        schema_version: Option<Spanned<toml::Value>>,
+    }
+
+    let version: Version = toml::from_str(toml_str)?;
+    let value = format!("{SCHEMA_VERSION}");
+    Ok(match version.schema_version {
+        Some(version) => {
+            let span = version.span();
+            format!(
+                "{}{value}{}",
+                &toml_str[..span.start],
+                &toml_str[span.end..]
+            )
+        }
+        // top-level keys must come before the first table.
+        None => format!("schema-version = {value}\n\n{toml_str}"),
+    })
+}
+
+/// Rewrite the file at `path` for the current schema, returning if it changed.
+pub fn migrate_file(path: &Path, msg_info: &mut MessageInfo) -> Result<bool> {
+    let toml_str = file::read(path).wrap_err_with(|| format!("could not read file `{path:?}`"))?;
+    let (config, unused) = CrossToml::deserialize_with_unused(toml::Deserializer::new(&toml_str))
+        .wrap_err_with(|| format!("failed to parse file `{path:?}` as TOML"))?;
+    if schema_version(&config) >= SCHEMA_VERSION {
+        return Ok(false);
+    }
+
+    let migrations = check(&toml_str, &unused)?;
+    for migration in &migrations {
+        msg_info.print(format_args!(
+            "{}:{}: `{}` -> `{}`",
+            path.to_utf8()?,
+            migration.line,
+            migration.path,
+            migration.replacement
+        ))?;
+    }
+    let migrated = set_schema_version(&apply(&toml_str, &migrations))?;
+    std::fs::write(path, migrated).wrap_err_with(|| format!("could not write file `{path:?}`"))?;
+    Ok(true)
+}
+
+/// If the command is `cross config migrate`, instead of a `cargo config`
+/// command such as `cargo config get`.
+pub(crate) fn is_migrate(cargo_args: &[String]) -> bool {
+    let Some(index) = cli::subcommand_index(cargo_args) else {
+        return false;
+    };
+    cargo_args[index + 1..]
+        .iter()
+        .take_while(|a| *a != "--")
+        .find(|a| !a.starts_with('-'))
+        .is_some_and(|a| a == "migrate")
+}
+
+/// `cross config migrate`: rewrite the `Cross.toml` files for the current
+/// version of the schema.
+pub(crate) fn run(paths: &[PathBuf], msg_info: &mut MessageInfo) -> Result<()> {
+    if paths.is_empty() {
+        return msg_info.print("no cross configuration file was found");
+    }
+    for path in paths {
+        if migrate_file(path, msg_info)? {
+            msg_info.print(format_args!("migrated `{}`", path.to_utf8()?))?;
+        } else {
+            msg_info.print(format_args!("`{}` is up to date", path.to_utf8()?))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_CONFIG: &str = r#"# build settings
+[build]
+pre_build = ["apt-get update"]
+xargo = false
+unknown_key = true
+
+[target.aarch64-unknown-linux-gnu]
+image = "my/image"
+"build_std" = true
+
+[target.aarch64-unknown-linux-gnu.env]
+passthrough = ["MY_VAR"]
+"#;
+
+    fn parse(toml_str: &str) -> Result<(CrossToml, BTreeSet<String>)> {
+        CrossToml::deserialize_with_unused(toml::Deserializer::new(toml_str))
+    }
+
+    #[test]
+    fn deprecated_keys() -> Result<()> {
+        let (config, unused) = parse(OLD_CONFIG)?;
+        assert_eq!(schema_version(&config), 1);
+        let migrations = check(OLD_CONFIG, &unused)?;
+        let found: Vec<_> = migrations
+            .iter()
+            .map(|m| (m.path.as_str(), m.replacement.as_str(), m.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("build.pre_build", "build.pre-build", 3),
+                (
+                    "target.aarch64-unknown-linux-gnu.build_std",
+                    "target.aarch64-unknown-linux-gnu.build-std",
+                    9
+                ),
+            ]
+        );
+
+        let migrated = apply(OLD_CONFIG, &migrations);
+        assert!(migrated.contains("\npre-build = [\"apt-get update\"]\n"));
+        assert!(migrated.contains("\n\"build-std\" = true\n"));
+        let (_, unused) = parse(&migrated)?;
+        assert_eq!(unused, BTreeSet::from(["build.unknown_key".to_owned()]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn schema_versions() -> Result<()> {
+        let migrated = set_schema_version(OLD_CONFIG)?;
+        assert!(migrated.starts_with("schema-version = 2\n\n# build settings\n"));
+        let (config, _) = parse(&migrated)?;
+        assert_eq!(schema_version(&config), SCHEMA_VERSION);
+
+        let migrated = set_schema_version("schema-version = 1 # old\n[build]\n")?;
+        assert_eq!(migrated, "schema-version = 2 # old\n[build]\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_command() {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert!(is_migrate(&args(&["config", "migrate"])));
+        assert!(is_migrate(&args(&["-v", "config", "-v", "migrate"])));
+        // `cargo config` commands
+        assert!(!is_migrate(&args(&["config", "get", "build.target"])));
+        assert!(!is_migrate(&args(&["-Zunstable-options", "config", "get"])));
+        assert!(!is_migrate(&args(&["config", "--", "migrate"])));
+    }
+}
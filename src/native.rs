@@ -0,0 +1,276 @@
+//! Experimental backend building on the host with the cross toolchains
+//! installed on it, such as crosstool-NG toolchains, instead of in a container.
+//!
+//! The arguments are filtered like for a container build, while the C
+//! toolchain, the runner, and the variables of `env.passthrough` and
+//! `build-env` are passed to cargo through the environment.
+
+use std::env;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use rustc_version::Channel;
+
+use crate::cargo::{cargo_command, CargoMetadata, Subcommand};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::cross_toml::CrossNativeConfig;
+use crate::errors::*;
+use crate::extensions::CommandExt;
+use crate::file::ToUtf8;
+use crate::shell::MessageInfo;
+use crate::{docker, hooks, interpreter, rustup, setup_cache, Target};
+
+/// The environment variables passing the C toolchain of `target` and the
+/// command running its binaries to cargo and the `cc` crate.
+fn native_env(
+    target: &Target,
+    native: &CrossNativeConfig,
+    runner: Option<&str>,
+) -> Vec<(String, String)> {
+    let lower = target.triple().replace(['-', '.'], "_");
+    let upper = lower.to_uppercase();
+    [
+        (format!("CC_{lower}"), native.cc()),
+        (format!("CXX_{lower}"), native.cxx()),
+        (format!("AR_{lower}"), native.ar()),
+        (format!("CARGO_TARGET_{upper}_LINKER"), native.linker()),
+        (format!("CARGO_TARGET_{upper}_RUNNER"), runner),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value?.to_owned())))
+    .collect()
+}
+
+/// The command running the binaries of `target` with `runner`, or `None` if
+/// they run directly on the host.
+fn runner_command(runner: &str, target: &Target, sysroot: Option<&str>) -> Result<Option<String>> {
+    match runner {
+        "native" => Ok(None),
+        "qemu-user" => {
+            let qemu = interpreter::qemu_user(target)
+                .ok_or_else(|| eyre::eyre!("`qemu-user` cannot run the binaries of {target}"))?;
+            Ok(Some(match sysroot {
+                Some(sysroot) => format!("{qemu} -L {sysroot}"),
+                None => qemu.to_owned(),
+            }))
+        }
+        _ => Err(eyre::eyre!(
+            "the runner `{runner}` is not supported by the native backend"
+        ))
+        .with_suggestion(|| format!("set `target.{target}.runner` to `qemu-user` or `native`")),
+    }
+}
+
+/// The sysroot of the C toolchain, with the libraries loaded by `qemu-user`.
+fn sysroot(native: &CrossNativeConfig, msg_info: &mut MessageInfo) -> Option<String> {
+    if let Some(sysroot) = native.sysroot() {
+        return Some(sysroot.to_owned());
+    }
+    let sysroot = Command::new(native.cc()?)
+        .arg("-print-sysroot")
+        .run_and_get_stdout(msg_info)
+        .ok()?;
+    Some(sysroot.trim().to_owned()).filter(|s| !s.is_empty())
+}
+
+/// Point `--target-dir` back to the directory on the host, since the parsed
+/// arguments use the mount point of the container.
+fn host_target_dir(args: Vec<String>, target_dir: Option<&Path>) -> Result<Vec<String>> {
+    let Some(target_dir) = target_dir else {
+        return Ok(args);
+    };
+    let target_dir = target_dir.to_utf8()?;
+    let mut filtered = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            filtered.push(arg);
+            filtered.extend(args.by_ref());
+        } else if arg == "--target-dir" {
+            filtered.push(arg);
+            if args.next().is_some() {
+                filtered.push(target_dir.to_owned());
+            }
+        } else if arg.starts_with("--target-dir=") {
+            filtered.push(format!("--target-dir={target_dir}"));
+        } else {
+            filtered.push(arg);
+        }
+    }
+    Ok(filtered)
+}
+
+/// Build `target` on the host, returning `None` for the subcommands which
+/// cargo runs on the host anyway.
+pub(crate) fn run(
+    config: &Config,
+    target: &Target,
+    metadata: &CargoMetadata,
+    args: &Args,
+    msg_info: &mut MessageInfo,
+) -> Result<Option<ExitStatus>> {
+    let subcommand = args.subcommand.clone();
+    if subcommand == Some(Subcommand::Print) {
+        eyre::bail!("`cross print` is not supported by the native backend, which uses no image");
+    }
+    if !subcommand
+        .clone()
+        .is_some_and(|sc| sc.needs_docker(false, false))
+    {
+        return Ok(None);
+    }
+    msg_info.info(format_args!(
+        "building {target} on the host with the experimental native backend"
+    ))?;
+
+    let toolchain = crate::select_toolchain(config, target, args, msg_info)?;
+    let build_std = config.build_std(target).unwrap_or_default();
+    let is_nightly = match toolchain.rustc_version()? {
+        Some((_, channel, _)) => channel == Channel::Nightly,
+        None => toolchain.channel.contains("nightly"),
+    };
+    let setup_cache = setup_cache::SetupCache::new(!args.no_cache_setup)?;
+    rustup::setup(
+        target,
+        false,
+        build_std.enabled(),
+        &toolchain,
+        is_nightly,
+        args,
+        &setup_cache,
+        msg_info,
+    )?;
+
+    let native = config.native(target).unwrap_or_default();
+    let runner = match subcommand.is_some_and(Subcommand::needs_interpreter) {
+        true => {
+            let host = &toolchain.host().target;
+            let runner = match config.runner(target)? {
+                Some(runners) => interpreter::select_host_runner(&runners, target, host, msg_info)?,
+                // binaries of another architecture are emulated, like in the images
+                None => interpreter::default_host_runner(target, host).map(ToOwned::to_owned),
+            };
+            match runner {
+                Some(runner) if runner == "qemu-user" => {
+                    runner_command(&runner, target, sysroot(&native, msg_info).as_deref())?
+                }
+                Some(runner) => runner_command(&runner, target, None)?,
+                None => None,
+            }
+        }
+        false => None,
+    };
+
+    let filtered_args =
+        crate::get_filtered_args(None, args, target, config, is_nightly, &build_std);
+    let filtered_args = host_target_dir(filtered_args, args.target_dir.as_deref())?;
+    let mut cargo = cargo_command();
+    if let Some(channel) = &args.channel {
+        cargo.arg(format!("+{channel}"));
+    }
+    cargo.args(&filtered_args);
+    for (key, value) in native_env(target, &native, runner.as_deref()) {
+        // the environment of the host takes precedence, like for the config.
+        if env::var_os(&key).is_none() {
+            cargo.env(key, value);
+        }
+    }
+    // cargo already inherits the variables of the host, so only the values
+    // set with `passthrough = ["VAR=value"]` are needed.
+    let mut warned = false;
+    for var in config.env_passthrough(target).unwrap_or_default() {
+        let (key, value) = docker::validate_env_var(
+            &var,
+            &mut warned,
+            "environment variable",
+            "`passthrough = [\"ENVVAR=value\"]`",
+            msg_info,
+        )?;
+        if let Some(value) = value {
+            cargo.env(key, value);
+        }
+    }
+    let sysroot = toolchain.get_sysroot().to_utf8()?;
+    cargo.envs(docker::build_env_vars(config, target, sysroot)?);
+
+    let hook_env = hooks::HookEnv::new(
+        args,
+        target,
+        &metadata.workspace_root,
+        &metadata.target_directory,
+    )?;
+    hooks::run(
+        &config.hooks_pre(target),
+        hooks::HookStage::Pre,
+        &hook_env,
+        msg_info,
+    )?;
+    let status = cargo.run_and_get_status(msg_info, false)?;
+    if status.success() {
+        hooks::run(
+            &config.hooks_post(target),
+            hooks::HookStage::Post,
+            &hook_env,
+            msg_info,
+        )?;
+    }
+    Ok(Some(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_environment() -> Result<()> {
+        let target = Target::new_built_in("aarch64-unknown-linux-gnu");
+        let native: CrossNativeConfig = toml::from_str(
+            r#"
+            cc = "aarch64-unknown-linux-gnu-gcc"
+            ar = "aarch64-unknown-linux-gnu-ar"
+            "#,
+        )?;
+        let runner = runner_command("qemu-user", &target, Some("/opt/x-tools/sysroot"))?;
+        assert_eq!(
+            native_env(&target, &native, runner.as_deref()),
+            [
+                (
+                    "CC_aarch64_unknown_linux_gnu",
+                    "aarch64-unknown-linux-gnu-gcc"
+                ),
+                (
+                    "AR_aarch64_unknown_linux_gnu",
+                    "aarch64-unknown-linux-gnu-ar"
+                ),
+                (
+                    "CARGO_TARGET_AARCH64_UNKNOWN_LINUX_GNU_LINKER",
+                    "aarch64-unknown-linux-gnu-gcc"
+                ),
+                (
+                    "CARGO_TARGET_AARCH64_UNKNOWN_LINUX_GNU_RUNNER",
+                    "qemu-aarch64 -L /opt/x-tools/sysroot"
+                ),
+            ]
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        );
+        assert_eq!(runner_command("native", &target, None)?, None);
+        assert!(runner_command("qemu-system", &target, None).is_err());
+
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert_eq!(
+            host_target_dir(
+                args(&["build", "--target-dir", "/target", "--", "--target-dir=x"]),
+                Some(Path::new("/work/target"))
+            )?,
+            args(&[
+                "build",
+                "--target-dir",
+                "/work/target",
+                "--",
+                "--target-dir=x"
+            ])
+        );
+        Ok(())
+    }
+}
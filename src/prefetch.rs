@@ -0,0 +1,236 @@
+//! `cross prefetch`: pull the images needed by the workspace ahead of the
+//! build, so CI can pull them in parallel with other setup steps.
+//!
+//! The images are resolved like for a build, so `image`, `zig`, and the base
+//! images of custom dockerfiles and `pre-build` are all honored.
+
+use std::sync::Mutex;
+use std::thread;
+
+use color_eyre::SectionExt;
+
+use crate::cli::Args;
+use crate::config::Config;
+use crate::docker::{self, Engine, GetImageError, Image};
+use crate::errors::*;
+use crate::rustc::TargetList;
+use crate::shell::MessageInfo;
+use crate::Target;
+
+/// The number of images pulled at the same time by default.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// An image which could not be pulled.
+#[derive(Debug)]
+pub struct PullFailure {
+    pub image: Image,
+    /// The last line written by the container engine.
+    pub error: String,
+}
+
+/// Pull `images` with `engine`, with at most `jobs` pulls at the same time.
+///
+/// Returns the images which could not be pulled.
+pub fn pull_images(engine: &Engine, images: &[Image], jobs: usize) -> Vec<PullFailure> {
+    let queue = Mutex::new(images.iter());
+    // the queue must not stay locked while an image is pulled.
+    let next = || queue.lock().ok().and_then(|mut q| q.next());
+    let failures = Mutex::new(vec![]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, images.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(image) = next() {
+                    if let Err(error) = pull(engine, image) {
+                        if let Ok(mut failures) = failures.lock() {
+                            failures.push(PullFailure {
+                                image: image.clone(),
+                                error,
+                            });
+                        }
+                    }
+                }
+            });
+        }
+    });
+    failures.into_inner().unwrap_or_default()
+}
+
+fn pull(engine: &Engine, image: &Image) -> Result<(), String> {
+    let mut command = engine.subcommand("pull");
+    image.platform.specify_platform(engine, &mut command);
+    let output = command
+        .arg(&image.name)
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("the container engine failed")
+        .trim()
+        .to_owned())
+}
+
+/// Parse `--targets` and `--jobs` of `cross prefetch`. Targets can be
+/// separated by commas or given with multiple flags.
+fn parse_args(cargo_args: &[String]) -> Result<(Vec<String>, usize)> {
+    let mut targets = vec![];
+    let mut jobs = DEFAULT_JOBS;
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !matches!(flag, "--targets" | "--jobs" | "-j") {
+            continue;
+        }
+        let value = value
+            .or_else(|| args.next().map(String::as_str))
+            .ok_or_else(|| eyre::eyre!("`{flag}` requires a value"))?;
+        if flag == "--targets" {
+            targets.extend(
+                value
+                    .split(',')
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_owned),
+            );
+        } else {
+            jobs = value
+                .parse()
+                .ok()
+                .filter(|&jobs| jobs > 0)
+                .ok_or_else(|| eyre::eyre!("invalid number of jobs `{value}`"))?;
+        }
+    }
+    Ok((targets, jobs))
+}
+
+/// The targets to prefetch: the ones passed on the command line, otherwise
+/// the default target and the targets configured in `Cross.toml`, and
+/// otherwise the host.
+fn targets(
+    requested: Vec<String>,
+    args: &Args,
+    config: &Config,
+    target_list: &TargetList,
+    host: &str,
+) -> Vec<Target> {
+    let mut targets: Vec<Target> = requested
+        .iter()
+        .map(|t| Target::from(t, target_list))
+        .chain(args.target.clone())
+        .collect();
+    if targets.is_empty() {
+        targets.extend(config.target(target_list));
+        targets.extend(config.configured_targets());
+    }
+    if targets.is_empty() {
+        targets.push(Target::from(host, target_list));
+    }
+    let mut unique = vec![];
+    for target in targets {
+        if !unique.contains(&target) {
+            unique.push(target);
+        }
+    }
+    unique
+}
+
+pub(crate) fn run(
+    args: &Args,
+    config: &Config,
+    target_list: &TargetList,
+    host: &str,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    let (mut requested, jobs) = parse_args(&args.cargo_args)?;
+    // `--targets` is also parsed by cross for `cross check`.
+    requested.splice(0..0, args.targets.iter().cloned());
+    let mut pulls: Vec<(Engine, Vec<Image>)> = vec![];
+    for target in targets(requested, args, config, target_list, host) {
+        let uses_zig = config.zig(&target).unwrap_or(false);
+        let image = match docker::get_image(config, &target, uses_zig) {
+            Ok(image) => image,
+            // the image is built from the dockerfile, so there is nothing to pull.
+            Err(GetImageError::NoCompatibleImages(..)) if config.dockerfile(&target).is_some() => {
+                continue;
+            }
+            Err(err) => {
+                msg_info.warn(err)?;
+                continue;
+            }
+        };
+        let engine = crate::container_engine(config, &target, args, msg_info)?;
+        let platform = match &args.platform {
+            Some(platform) => Some(docker::ImagePlatform::from_override(platform)?),
+            None => config.platform(&target)?,
+        };
+        let image = image.to_definite_with(&engine, platform.as_ref(), msg_info)?;
+        match pulls.iter_mut().find(|(e, _)| *e == engine) {
+            Some((_, images)) if images.contains(&image) => (),
+            Some((_, images)) => images.push(image),
+            None => pulls.push((engine, vec![image])),
+        }
+    }
+
+    let mut failures = vec![];
+    for (engine, images) in &pulls {
+        msg_info.info(format_args!(
+            "pulling {} images with `{}`",
+            images.len(),
+            engine.path.to_string_lossy()
+        ))?;
+        failures.extend(pull_images(engine, images, jobs));
+    }
+    if failures.is_empty() {
+        let count: usize = pulls.iter().map(|(_, images)| images.len()).sum();
+        return msg_info.print(format_args!("prefetched {count} images"));
+    }
+
+    let list = failures
+        .iter()
+        .map(|f| format!("{}: {}", f.image, f.error))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(eyre::eyre!("could not pull {} images", failures.len()))
+        .with_section(|| list.header("Failed images:"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefetch_args() -> Result<()> {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+
+        assert_eq!(parse_args(&args(&["prefetch"]))?, (vec![], DEFAULT_JOBS));
+        assert_eq!(
+            parse_args(&args(&[
+                "prefetch",
+                "--targets",
+                "aarch64-unknown-linux-gnu,armv7-unknown-linux-gnueabihf",
+                "--targets=riscv64gc-unknown-linux-gnu",
+                "-j",
+                "8",
+            ]))?,
+            (
+                args(&[
+                    "aarch64-unknown-linux-gnu",
+                    "armv7-unknown-linux-gnueabihf",
+                    "riscv64gc-unknown-linux-gnu",
+                ]),
+                8
+            )
+        );
+        assert!(parse_args(&args(&["prefetch", "--jobs=0"])).is_err());
+        assert!(parse_args(&args(&["prefetch", "--targets"])).is_err());
+
+        Ok(())
+    }
+}
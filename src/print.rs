@@ -0,0 +1,106 @@
+//! The values resolved by `cross print`, for build scripts and Makefiles.
+
+use crate::cli::{PrintValue, VersionFormat};
+use crate::config::Config;
+use crate::docker::{Engine, Image, Mount, ToolchainDirectories, PROVIDED_IMAGES};
+use crate::errors::*;
+use crate::interpreter;
+use crate::shell::MessageInfo;
+use crate::Target;
+
+/// The runner the provided image uses when `runner` is not configured.
+pub(crate) fn default_runner(target: &Target) -> Option<&'static str> {
+    let images = || {
+        PROVIDED_IMAGES
+            .iter()
+            .filter(|image| image.name == target.triple())
+    };
+    images()
+        .find(|image| image.sub.is_none())
+        .or_else(|| images().next())?
+        .runner
+}
+
+/// Resolve `value` for `target` the same way a build would.
+pub(crate) fn resolve(
+    value: PrintValue,
+    config: &Config,
+    target: &Target,
+    image: &Image,
+    toolchain_dirs: &ToolchainDirectories,
+    engine: &Engine,
+    msg_info: &mut MessageInfo,
+) -> Result<String> {
+    match value {
+        PrintValue::Image => Ok(image.name.clone()),
+        // the path of the toolchain mounted into the container
+        PrintValue::Sysroot => Ok(toolchain_dirs.sysroot_mount_path().to_owned()),
+        // the runner selected from the list of runners, like for a build
+        PrintValue::Runner => match config.runner(target)? {
+            Some(runners) => interpreter::select_runner(&runners, target, engine, msg_info)?,
+            None => None,
+        }
+        .or_else(|| default_runner(target).map(ToOwned::to_owned))
+        .ok_or_else(|| eyre::eyre!("target `{target}` does not have a runner")),
+        PrintValue::DebArch => target
+            .target()
+            .deb_arch()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| eyre::eyre!("target `{target}` does not have a debian architecture")),
+        PrintValue::Mounts => eyre::bail!("the mounts are resolved with the paths of the package"),
+    }
+}
+
+/// Format the directories of the host mounted into the container, as
+/// `HOST -> CONTAINER` lines or as a JSON array.
+pub(crate) fn mounts(mounts: &[Mount], format: VersionFormat) -> Result<String> {
+    Ok(match format {
+        VersionFormat::Human => mounts
+            .iter()
+            .map(|mount| {
+                let readonly = if mount.readonly { " (read-only)" } else { "" };
+                format!("{} -> {}{readonly}", mount.host, mount.container)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        VersionFormat::Json => serde_json::to_string_pretty(mounts)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_runners() {
+        let runner = |triple| default_runner(&Target::new_built_in(triple));
+        assert_eq!(runner("aarch64-unknown-linux-gnu"), Some("qemu-user"));
+        assert_eq!(runner("x86_64-unknown-linux-musl"), Some("native"));
+        assert_eq!(runner("x86_64-pc-windows-gnu"), Some("wine"));
+        assert_eq!(runner("i686-pc-windows-gnu"), Some("wine"));
+        assert_eq!(runner("aarch64-linux-android"), Some("qemu-user"));
+        assert_eq!(runner("i686-linux-android"), Some("qemu-user"));
+        assert_eq!(runner("x86_64-linux-android"), Some("qemu-user"));
+        assert_eq!(runner("thumbv7em-none-eabihf"), Some("qemu-system"));
+        assert_eq!(runner("wasm32-unknown-emscripten"), Some("node"));
+        assert_eq!(runner("x86_64-unknown-freebsd"), None);
+        assert_eq!(runner("x86_64-apple-darwin"), None);
+    }
+
+    #[test]
+    fn print_mounts() -> Result<()> {
+        let mounts = [
+            Mount::new("/home/user/project", "/project", false),
+            Mount::new("/home/user/.rustup/toolchains/stable", "/rust", true),
+        ];
+        assert_eq!(
+            self::mounts(&mounts, VersionFormat::Human)?,
+            "/home/user/project -> /project\n/home/user/.rustup/toolchains/stable -> /rust (read-only)"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&self::mounts(&mounts, VersionFormat::Json)?)?;
+        assert_eq!(json[1]["container"], "/rust");
+        assert_eq!(json[1]["readonly"], true);
+        Ok(())
+    }
+}
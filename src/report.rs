@@ -0,0 +1,251 @@
+//! The machine-readable summary written with `--build-report`.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::docker::{CompilerArtifact, Termination};
+use crate::errors::*;
+use crate::file::write_file;
+use crate::stats;
+
+/// Version of the report schema, incremented on breaking changes.
+pub const BUILD_REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Summary of a single invocation of cross.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildReport {
+    schema_version: u32,
+    success: bool,
+    exit_code: Option<i32>,
+    duration_ms: u64,
+    target: Option<String>,
+    profile: Option<String>,
+    /// The artifact directory existed before the build.
+    incremental: Option<bool>,
+    image: Option<String>,
+    image_digest: Option<String>,
+    artifacts: Vec<PathBuf>,
+    /// The build in the container ran out of memory or was killed by a signal.
+    termination: Option<Termination>,
+    error: Option<String>,
+}
+
+impl BuildReport {
+    #[must_use]
+    pub fn new() -> Self {
+        BuildReport {
+            schema_version: BUILD_REPORT_SCHEMA_VERSION,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_target(&mut self, target: &str) {
+        self.target = Some(target.to_owned());
+    }
+
+    pub fn set_profile(&mut self, profile: &str, artifact_dir: &Path) {
+        self.profile = Some(profile.to_owned());
+        self.incremental = Some(artifact_dir.is_dir());
+    }
+
+    pub fn set_image(&mut self, image: &str, digest: Option<String>) {
+        self.image = Some(image.to_owned());
+        self.image_digest = digest;
+    }
+
+    pub fn set_termination(&mut self, termination: Option<Termination>) {
+        self.termination = termination;
+    }
+
+    /// Record the artifacts cargo reported for this build directly in
+    /// `artifact_dir`, so files left by earlier builds are not included.
+    pub fn collect_artifacts(&mut self, artifact_dir: &Path, messages: &[CompilerArtifact]) {
+        self.artifacts = reported_artifacts(artifact_dir, messages);
+    }
+
+    /// The artifacts collected with [`BuildReport::collect_artifacts`].
+    pub fn artifacts(&self) -> &[PathBuf] {
+        &self.artifacts
+    }
+
+    /// Record the outcome of the run.
+    pub fn finish(&mut self, result: &Result<Option<ExitStatus>>, duration: Duration) {
+        self.duration_ms = duration.as_millis().try_into().unwrap_or(u64::MAX);
+        match result {
+            Ok(Some(status)) => {
+                self.success = status.success();
+                self.exit_code = status.code();
+            }
+            Ok(None) => {
+                self.success = false;
+                self.exit_code = None;
+            }
+            Err(err) => {
+                self.success = false;
+                self.exit_code = None;
+                self.error = Some(format!("{err:#}"));
+            }
+        }
+    }
+
+    /// The entry for the local build statistics, if the build ran in a container.
+    pub fn to_stats(&self) -> Option<stats::BuildRecord> {
+        Some(stats::BuildRecord::new(
+            self.target.clone()?,
+            self.profile.clone()?,
+            self.duration_ms,
+            self.success,
+            self.incremental.unwrap_or_default(),
+        ))
+    }
+
+    /// The report as markdown, for the job summary of CI services.
+    pub fn markdown_summary(&self) -> String {
+        let mut rows = vec![];
+        if let Some(target) = &self.target {
+            rows.push(("Target", format!("`{target}`")));
+        }
+        if let Some(image) = &self.image {
+            rows.push(("Image", format!("`{image}`")));
+        }
+        if let Some(profile) = &self.profile {
+            rows.push(("Profile", profile.clone()));
+        }
+        rows.push((
+            "Duration",
+            format!("{:.1}s", self.duration_ms as f64 / 1000.0),
+        ));
+        let result = match (self.success, self.exit_code) {
+            (true, _) => "succeeded".to_owned(),
+            (false, _) if self.termination == Some(Termination::OomKilled) => {
+                "ran out of memory".to_owned()
+            }
+            (false, Some(code)) => format!("failed with exit code {code}"),
+            (false, None) => "failed".to_owned(),
+        };
+        rows.push(("Result", result));
+
+        let mut summary = "### cross\n\n| | |\n|---|---|\n".to_owned();
+        for (name, value) in rows {
+            summary.push_str(&format!("| {name} | {value} |\n"));
+        }
+        if let Some(error) = &self.error {
+            summary.push_str(&format!("\n```text\n{error}\n```\n"));
+        }
+        summary.push('\n');
+        summary
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        write_file(path, true)
+            .and_then(|mut file| Ok(file.write_all(json.as_bytes())?))
+            .wrap_err_with(|| format!("could not write build report to `{path:?}`"))
+    }
+}
+
+/// The files cargo reported in its `compiler-artifact` messages which are
+/// directly in `artifact_dir`, without the dependencies in `deps`.
+pub(crate) fn reported_artifacts(
+    artifact_dir: &Path,
+    messages: &[CompilerArtifact],
+) -> Vec<PathBuf> {
+    let mut artifacts: Vec<PathBuf> = messages
+        .iter()
+        .flat_map(|message| message.filenames.iter().chain(&message.executable))
+        .filter(|path| path.parent() == Some(artifact_dir))
+        .cloned()
+        .collect();
+    artifacts.sort();
+    artifacts.dedup();
+    artifacts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_schema() -> Result<()> {
+        let mut report = BuildReport::new();
+        report.set_target("aarch64-unknown-linux-gnu");
+        report.finish(
+            &Err(eyre::eyre!("no container engine found")),
+            Duration::from_millis(1500),
+        );
+        let value = serde_json::to_value(&report)?;
+        assert_eq!(value["schema-version"], BUILD_REPORT_SCHEMA_VERSION);
+        assert_eq!(value["success"], false);
+        assert_eq!(value["exit-code"], serde_json::Value::Null);
+        assert_eq!(value["duration-ms"], 1500);
+        assert_eq!(value["target"], "aarch64-unknown-linux-gnu");
+        assert_eq!(value["error"], "no container engine found");
+
+        Ok(())
+    }
+
+    #[test]
+    fn markdown_summary() {
+        let mut report = BuildReport::new();
+        report.set_target("aarch64-unknown-linux-gnu");
+        report.set_image("ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main", None);
+        report.finish(&Ok(None), Duration::from_millis(12345));
+        let summary = report.markdown_summary();
+        assert!(summary.contains("| Target | `aarch64-unknown-linux-gnu` |"));
+        assert!(summary.contains("| Image | `ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main` |"));
+        assert!(summary.contains("| Duration | 12.3s |"));
+        assert!(summary.contains("| Result | failed |"));
+    }
+
+    #[test]
+    fn report_termination() -> Result<()> {
+        let mut report = BuildReport::new();
+        report.set_termination(Some(Termination::Signal { signal: 9 }));
+        let value = serde_json::to_value(&report)?;
+        assert_eq!(
+            value["termination"],
+            serde_json::json!({"reason": "signal", "signal": 9})
+        );
+
+        report.set_termination(Some(Termination::OomKilled));
+        let value = serde_json::to_value(&report)?;
+        assert_eq!(value["termination"]["reason"], "oom-killed");
+        assert!(report
+            .markdown_summary()
+            .contains("| Result | ran out of memory |"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_artifacts() {
+        let dir = Path::new("/target/aarch64-unknown-linux-gnu/release");
+        let mut report = BuildReport::new();
+        report.collect_artifacts(
+            dir,
+            &[
+                CompilerArtifact {
+                    filenames: vec![dir.join("deps").join("libserde-0123.rlib")],
+                    executable: None,
+                },
+                CompilerArtifact {
+                    filenames: vec![dir.join("libhello.rlib")],
+                    executable: None,
+                },
+                CompilerArtifact {
+                    filenames: vec![dir.join("hello")],
+                    executable: Some(dir.join("hello")),
+                },
+            ],
+        );
+        assert_eq!(
+            report.artifacts,
+            vec![dir.join("hello"), dir.join("libhello.rlib")]
+        );
+    }
+}
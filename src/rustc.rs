@@ -10,7 +10,7 @@ use crate::extensions::{env_program, CommandExt};
 use crate::shell::MessageInfo;
 use crate::TargetTriple;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TargetList {
     pub triples: Vec<String>,
 }
@@ -126,6 +126,14 @@ impl QualifiedToolchain {
         self
     }
 
+    /// Checks if the toolchain can run in an image for `platform`: it must be
+    /// for the same architecture, and link to the same C library, since the
+    /// dynamic loader of musl is missing from glibc images and vice versa.
+    pub fn runs_on(&self, platform: &ImagePlatform) -> bool {
+        self.host.architecture == platform.architecture
+            && self.host.target.is_musl() == platform.target.is_musl()
+    }
+
     /// Makes a good guess as to what the toolchain is compiled to run on.
     pub(crate) fn custom(
         name: &str,
@@ -212,6 +220,31 @@ impl QualifiedToolchain {
         }
     }
 
+    /// The toolchain installed at `sysroot`, such as a toolchain in
+    /// `$RUSTUP_HOME/toolchains`. Toolchains which cannot be parsed from
+    /// their name, like linked toolchains, are treated as custom toolchains.
+    pub fn from_sysroot(
+        sysroot: PathBuf,
+        config: &crate::config::Config,
+        msg_info: &mut MessageInfo,
+    ) -> Result<Self> {
+        let name = sysroot
+            .file_name()
+            .ok_or_else(|| eyre::eyre!("couldn't get name of toolchain at {sysroot:?}"))?
+            .to_str()
+            .ok_or_else(|| eyre::eyre!("toolchain was not utf-8"))?
+            .to_owned();
+
+        if config.custom_toolchain() {
+            return QualifiedToolchain::custom(&name, &sysroot, config, msg_info);
+        }
+        match QualifiedToolchain::parse(sysroot.clone(), &name, config, msg_info) {
+            Ok(toolchain) => Ok(toolchain),
+            // report why the name could not be parsed, not the fallback.
+            Err(e) => QualifiedToolchain::custom(&name, &sysroot, config, msg_info).or(Err(e)),
+        }
+    }
+
     /// Merge a "picked" toolchain, overriding set fields.
     pub fn with_picked(self, picked: Toolchain) -> Result<Self> {
         let date = picked.date.or(self.date);
@@ -392,6 +425,23 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn runs_on() {
+        let sysroot = Path::new("/tmp/cross/toolchains/stable-x86_64-unknown-linux-musl");
+        let musl = QualifiedToolchain::new(
+            "stable",
+            &None,
+            &ImagePlatform::from_target("x86_64-unknown-linux-musl".into()).unwrap(),
+            sysroot,
+            true,
+        );
+        assert!(!musl.runs_on(&ImagePlatform::X86_64_UNKNOWN_LINUX_GNU));
+        assert!(!musl.runs_on(&ImagePlatform::AARCH64_UNKNOWN_LINUX_GNU));
+        assert!(
+            musl.runs_on(&ImagePlatform::from_target("x86_64-unknown-linux-musl".into()).unwrap())
+        );
+    }
+
     #[test]
     fn hash_from_rustc() {
         assert_eq!(
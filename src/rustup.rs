@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use rustc_version::{Channel, Version};
@@ -6,6 +6,7 @@ use rustc_version::{Channel, Version};
 use crate::errors::*;
 pub use crate::extensions::{CommandExt, OutputExt};
 use crate::rustc::QualifiedToolchain;
+use crate::setup_cache::SetupCache;
 use crate::shell::{MessageInfo, Verbosity};
 use crate::Target;
 
@@ -266,6 +267,95 @@ pub fn component_is_installed(
     Ok(check_component(component, toolchain, msg_info)?.is_installed())
 }
 
+/// The targets and components missing from a toolchain.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Missing<'a> {
+    targets: Vec<&'a str>,
+    components: Vec<&'static str>,
+}
+
+impl Missing<'_> {
+    fn is_empty(&self) -> bool {
+        self.targets.is_empty() && self.components.is_empty()
+    }
+
+    fn describe(&self) -> String {
+        self.targets
+            .iter()
+            .map(|target| format!("target `{target}`"))
+            .chain(
+                self.components
+                    .iter()
+                    .map(|component| format!("component `{component}`")),
+            )
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The components installed in the toolchain, such as `rust-src` or
+/// `rust-std-aarch64-unknown-linux-gnu`.
+///
+/// rustup keeps the list in the sysroot, so reading it avoids running rustup
+/// at all when everything is already installed.
+fn installed_components(
+    toolchain: &QualifiedToolchain,
+    msg_info: &mut MessageInfo,
+) -> Result<Vec<String>> {
+    let path = toolchain.get_sysroot().join(RUSTLIB_COMPONENTS);
+    if let Ok(contents) = std::fs::read_to_string(path) {
+        return Ok(contents.lines().map(str::to_owned).collect());
+    }
+    Ok(rustup_command(msg_info, true)
+        .args(["component", "list", "--installed", "--toolchain"])
+        .arg(toolchain.to_string())
+        .run_and_get_stdout(msg_info)?
+        .lines()
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Install the toolchain, and the targets and components the build needs,
+/// unless a setup snapshot shows they already were.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn setup(
+    target: &Target,
+    uses_xargo: bool,
+    uses_build_std: bool,
+    toolchain: &QualifiedToolchain,
+    is_nightly: bool,
+    args: &crate::cli::Args,
+    cache: &SetupCache,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    let offline = crate::cargo::NetworkFlags::detect(&args.cargo_args).offline;
+    let key = format!(
+        "rustup\n{toolchain}\n{:?}\n{}\n{uses_xargo}\n{uses_build_std}\n{is_nightly}\n{offline}\n{:?}",
+        toolchain.get_sysroot(),
+        target.triple(),
+        args.subcommand,
+    );
+    if cache.get::<()>(&key).is_some() {
+        return msg_info.debug(format_args!(
+            "using the setup snapshot of toolchain `{toolchain}`"
+        ));
+    }
+    let available_targets = setup_rustup(toolchain, msg_info)?;
+    setup_components(
+        target,
+        uses_xargo,
+        uses_build_std,
+        toolchain,
+        is_nightly,
+        available_targets,
+        args,
+        msg_info,
+    )?;
+    // installing or removing a target or component changes the list
+    let components = toolchain.get_sysroot().join(RUSTLIB_COMPONENTS);
+    cache.put(&key, &[components], &(), msg_info)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn setup_components(
     target: &Target,
@@ -277,38 +367,154 @@ pub fn setup_components(
     args: &crate::cli::Args,
     msg_info: &mut MessageInfo,
 ) -> Result<(), color_eyre::Report> {
-    if !toolchain.is_custom {
-        // build-std overrides xargo, but only use it if it's a built-in
-        // tool but not an available target or doesn't have rust-std.
-
-        if !is_nightly && uses_build_std {
-            eyre::bail!(
-                "no rust-std component available for {}: must use nightly",
-                target.triple()
-            );
-        }
+    let host = toolchain.host().target.triple();
+    if toolchain.is_custom {
+        return check_custom_host_std(toolchain, host);
+    }
+    // build-std overrides xargo, but only use it if it's a built-in
+    // tool but not an available target or doesn't have rust-std.
+    if !is_nightly && uses_build_std {
+        eyre::bail!(
+            "no rust-std component available for {}: must use nightly",
+            target.triple()
+        );
+    }
 
-        if !uses_xargo
-            && !uses_build_std
-            && !available_targets.is_installed(target)
-            && available_targets.contains(target)
-        {
-            install(target, toolchain, msg_info)?;
-        } else if !component_is_installed("rust-src", toolchain, msg_info)? {
-            install_component("rust-src", toolchain, msg_info)?;
-        }
-        if args
-            .subcommand
-            .clone()
-            .map_or(false, |sc| sc == crate::Subcommand::Clippy)
-            && !component_is_installed("clippy", toolchain, msg_info)?
-        {
-            install_component("clippy", toolchain, msg_info)?;
+    let installed = installed_components(toolchain, msg_info)?;
+    let needs_std = !uses_xargo
+        && !uses_build_std
+        && !available_targets.is_installed(target)
+        && available_targets.contains(target);
+    let mut missing = missing_components(
+        target.triple(),
+        needs_std,
+        args.subcommand.as_ref(),
+        &installed,
+    );
+    if missing_host_std(host, &missing, &installed) {
+        msg_info.note(format_args!(
+            "toolchain `{toolchain}` is missing the std of its host `{host}`, which build scripts and proc macros are compiled for in the container."
+        ))?;
+        missing.targets.push(host);
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    // with `--offline` or `--frozen`, rustup cannot download anything either.
+    if crate::cargo::NetworkFlags::detect(&args.cargo_args).offline {
+        return skip_offline_install(&missing.describe(), msg_info);
+    }
+
+    let _spinner = msg_info.spinner(format_args!(
+        "installing {} for toolchain `{toolchain}`",
+        missing.describe()
+    ))?;
+    let toolchain = toolchain.to_string();
+    let rustup = |msg_info: &mut MessageInfo| {
+        let mut command = rustup_command(msg_info, false);
+        // the output of rustup would garble the spinner.
+        if msg_info.verbosity == Verbosity::Normal {
+            command.arg("--quiet");
         }
+        command
+    };
+    // rustup installs everything it is given in one transaction, so there
+    // is at most one call for the targets and one for the components.
+    if !missing.targets.is_empty() {
+        rustup(msg_info)
+            .args(["target", "add"])
+            .args(&missing.targets)
+            .args(["--toolchain", &toolchain])
+            .run(msg_info, false)
+            .wrap_err_with(|| {
+                format!("couldn't install `std` for {}", missing.targets.join(", "))
+            })?;
+    }
+    if !missing.components.is_empty() {
+        rustup(msg_info)
+            .args(["component", "add"])
+            .args(&missing.components)
+            .args(["--toolchain", &toolchain])
+            .run(msg_info, false)
+            .wrap_err_with(|| {
+                format!(
+                    "couldn't install the components {}",
+                    missing.components.join(", ")
+                )
+            })?;
     }
     Ok(())
 }
 
+/// The targets and components which must be installed, given the `installed`
+/// components of the toolchain.
+fn missing_components<'a>(
+    triple: &'a str,
+    needs_std: bool,
+    subcommand: Option<&crate::Subcommand>,
+    installed: &[String],
+) -> Missing<'a> {
+    // the components are suffixed with the host, such as `clippy-x86_64-unknown-linux-gnu`.
+    let is_installed = |component: &str| installed.iter().any(|c| c.starts_with(component));
+    let mut missing = Missing::default();
+    if needs_std {
+        if !installed.contains(&format!("rust-std-{triple}")) {
+            missing.targets.push(triple);
+        }
+    } else if !is_installed("rust-src") {
+        missing.components.push("rust-src");
+    }
+    if subcommand == Some(&crate::Subcommand::Clippy) && !is_installed("clippy") {
+        missing.components.push("clippy");
+    }
+    // `llvm-tools` matches both the current and the older `llvm-tools-preview` name
+    if subcommand == Some(&crate::Subcommand::LlvmCov) && !is_installed("llvm-tools") {
+        missing.components.push("llvm-tools-preview");
+    }
+    missing
+}
+
+/// Returns `true` if the std of the `host` of the toolchain, which runs the
+/// build scripts and proc macros in the container, must be installed.
+fn missing_host_std(host: &str, missing: &Missing<'_>, installed: &[String]) -> bool {
+    !missing.targets.contains(&host) && !installed.contains(&format!("rust-std-{host}"))
+}
+
+/// Custom toolchains cannot be changed with rustup, so fail early if the std
+/// of their host is missing, instead of with cryptic errors from the build
+/// scripts and proc macros.
+fn check_custom_host_std(toolchain: &QualifiedToolchain, host: &str) -> Result<()> {
+    let lib = toolchain
+        .get_sysroot()
+        .join("lib/rustlib")
+        .join(host)
+        .join("lib");
+    let has_std = std::fs::read_dir(&lib).is_ok_and(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .any(|e| e.file_name().to_string_lossy().starts_with("libstd-"))
+    });
+    if has_std {
+        return Ok(());
+    }
+    Err(eyre::eyre!(
+        "custom toolchain `{toolchain}` is missing the std of its host `{host}`"
+    ))
+    .with_note(|| {
+        "build scripts and proc macros are compiled for the host of the toolchain in the container"
+    })
+    .with_suggestion(|| {
+        format!("build the toolchain with the std of `{host}`, or add it to the sysroot at {lib:?}")
+    })
+}
+
+fn skip_offline_install(what: &str, msg_info: &mut MessageInfo) -> Result<()> {
+    msg_info.warn(format_args!(
+        "cargo is offline, not installing {what}. the build may fail if it is required."
+    ))
+}
+
 fn rustc_channel(version: &Version) -> Result<Channel> {
     match version
         .pre
@@ -324,26 +530,35 @@ fn rustc_channel(version: &Version) -> Result<Channel> {
     }
 }
 
+const MULTIRUST_CHANNEL_MANIFEST: &str = "lib/rustlib/multirust-channel-manifest.toml";
+/// The components installed by rustup, one per line.
+const RUSTLIB_COMPONENTS: &str = "lib/rustlib/components";
+
+/// Get the version of the toolchain in `sysroot` from its channel manifest,
+/// such as `"1.61.0 (fe5b13d68 2022-05-18)"`.
+pub fn sysroot_rustc_version_string(sysroot: &Path) -> Result<Option<String>> {
+    let path = sysroot.join(MULTIRUST_CHANNEL_MANIFEST);
+    if path.exists() {
+        let contents =
+            std::fs::read(&path).wrap_err_with(|| format!("couldn't open file `{path:?}`"))?;
+        let manifest: toml::value::Table = toml::from_str(std::str::from_utf8(&contents)?)?;
+        return Ok(manifest
+            .get("pkg")
+            .and_then(|pkg| pkg.get("rust"))
+            .and_then(|rust| rust.get("version"))
+            .and_then(|version| version.as_str())
+            .map(|version| version.to_owned()));
+    }
+    Ok(None)
+}
+
 impl QualifiedToolchain {
     fn multirust_channel_manifest_path(&self) -> PathBuf {
-        self.get_sysroot()
-            .join("lib/rustlib/multirust-channel-manifest.toml")
+        self.get_sysroot().join(MULTIRUST_CHANNEL_MANIFEST)
     }
 
     pub fn rustc_version_string(&self) -> Result<Option<String>> {
-        let path = self.multirust_channel_manifest_path();
-        if path.exists() {
-            let contents =
-                std::fs::read(&path).wrap_err_with(|| format!("couldn't open file `{path:?}`"))?;
-            let manifest: toml::value::Table = toml::from_str(std::str::from_utf8(&contents)?)?;
-            return Ok(manifest
-                .get("pkg")
-                .and_then(|pkg| pkg.get("rust"))
-                .and_then(|rust| rust.get("version"))
-                .and_then(|version| version.as_str())
-                .map(|version| version.to_owned()));
-        }
-        Ok(None)
+        sysroot_rustc_version_string(self.get_sysroot())
     }
 
     pub fn rustc_version(&self) -> Result<Option<(Version, Channel, String)>> {
@@ -360,3 +575,53 @@ impl QualifiedToolchain {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Subcommand;
+
+    #[test]
+    fn missing_components_test() {
+        let installed = |list: &[&str]| list.iter().map(|&c| c.to_owned()).collect::<Vec<_>>();
+        let triple = "aarch64-unknown-linux-gnu";
+
+        let all = installed(&[
+            "cargo-x86_64-unknown-linux-gnu",
+            "clippy-preview-x86_64-unknown-linux-gnu",
+            "rust-std-aarch64-unknown-linux-gnu",
+            "rust-src",
+        ]);
+        assert!(missing_components(triple, true, Some(&Subcommand::Clippy), &all).is_empty());
+        assert!(missing_components(triple, false, None, &all).is_empty());
+
+        let missing = missing_components(triple, true, Some(&Subcommand::Clippy), &installed(&[]));
+        assert_eq!(
+            missing,
+            Missing {
+                targets: vec![triple],
+                components: vec!["clippy"],
+            }
+        );
+        assert_eq!(
+            missing.describe(),
+            "target `aarch64-unknown-linux-gnu`, component `clippy`"
+        );
+        let host = "x86_64-unknown-linux-gnu";
+        assert!(missing_host_std(host, &missing, &all));
+        assert!(!missing_host_std(
+            host,
+            &missing,
+            &installed(&["rust-std-x86_64-unknown-linux-gnu"])
+        ));
+        assert!(!missing_host_std(triple, &missing, &installed(&[])));
+
+        assert_eq!(
+            missing_components(triple, false, Some(&Subcommand::LlvmCov), &installed(&[])),
+            Missing {
+                targets: vec![],
+                components: vec!["rust-src", "llvm-tools-preview"],
+            }
+        );
+    }
+}
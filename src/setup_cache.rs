@@ -0,0 +1,305 @@
+//! Snapshots of the setup evaluated before each build, so repeated
+//! invocations skip the `cargo metadata` and `rustup` subprocesses.
+//!
+//! A snapshot is keyed by everything its subprocess depends on, such as the
+//! arguments and the toolchain, and is only used within its time to live,
+//! and while the files it was evaluated from, such as the manifests or the
+//! components of the toolchain, are unchanged. `--no-cache-setup` evaluates
+//! the setup again, and `CROSS_SETUP_CACHE_TTL=0` disables the snapshots.
+//! The builds of a single invocation, such as the jobs of `cross batch`, can
+//! also share their snapshots in memory, with [`SetupCache::shared`].
+//! The snapshots are best-effort: failing to take one never fails the build.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::parse_duration;
+use crate::docker::custom;
+use crate::errors::*;
+use crate::file;
+use crate::shell::MessageInfo;
+use crate::temp;
+
+pub const CROSS_SETUP_CACHE_TTL_ENV: &str = "CROSS_SETUP_CACHE_TTL";
+
+/// How long a snapshot is used by default.
+const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// The version of the format of the snapshots.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The directory of the setup snapshots.
+pub fn dir() -> Result<PathBuf> {
+    temp::cache_dir().map(|p| p.join("setup"))
+}
+
+/// The modification time of a file a snapshot was evaluated from, or `None`
+/// if it didn't exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    path: PathBuf,
+    modified: Option<u128>,
+}
+
+impl Stamp {
+    fn new(path: &Path) -> Self {
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos());
+        Self {
+            path: path.to_owned(),
+            modified,
+        }
+    }
+
+    fn is_current(&self) -> bool {
+        *self == Self::new(&self.path)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot<T> {
+    version: u32,
+    key: String,
+    /// Seconds since the Unix epoch when the snapshot was taken.
+    created: u64,
+    stamps: Vec<Stamp>,
+    value: T,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The setup snapshots, or nothing if they are disabled.
+#[derive(Debug, Clone)]
+pub struct SetupCache {
+    dir: Option<PathBuf>,
+    ttl: Duration,
+    /// The snapshots taken by the previous builds of the same invocation,
+    /// which are used even if the snapshots on disk are disabled.
+    session: Option<Rc<RefCell<BTreeMap<String, serde_json::Value>>>>,
+}
+
+impl SetupCache {
+    /// The snapshots in the cache directory, unless `enabled` is false, such
+    /// as with `--no-cache-setup`.
+    pub fn new(enabled: bool) -> Result<Self> {
+        Self::with_ttl_env(enabled, CROSS_SETUP_CACHE_TTL_ENV)
+    }
+
+    /// The snapshots in the cache directory, used within the time to live
+    /// set by the environment variable `ttl_env`.
+    pub fn with_ttl_env(enabled: bool, ttl_env: &str) -> Result<Self> {
+        let ttl = match env::var(ttl_env) {
+            Ok(value) if !value.is_empty() => {
+                parse_duration(&value).wrap_err_with(|| format!("invalid `{ttl_env}`"))?
+            }
+            _ => DEFAULT_TTL,
+        };
+        let dir = match enabled && !ttl.is_zero() {
+            true => Some(dir()?),
+            false => None,
+        };
+        Ok(Self {
+            dir,
+            ttl,
+            session: None,
+        })
+    }
+
+    /// Share the snapshots with the clones of the cache in memory, so the
+    /// builds of a single invocation set up each toolchain once.
+    #[must_use]
+    pub fn shared(self) -> Self {
+        Self {
+            session: Some(Rc::default()),
+            ..self
+        }
+    }
+
+    /// The snapshots of `cargo metadata`, which are in the default cache
+    /// directory, since they're needed to read `build.cache-dir`.
+    pub fn metadata(enabled: bool) -> Result<Self> {
+        let mut cache = Self::new(enabled)?;
+        if cache.dir.is_some() {
+            cache.dir = Some(temp::default_cache_dir()?.join("setup"));
+        }
+        Ok(cache)
+    }
+
+    /// Snapshots that are never used nor taken.
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            ttl: Duration::ZERO,
+            session: None,
+        }
+    }
+
+    /// The snapshots of `cache`, or disabled snapshots if they could not be
+    /// opened, such as without a cache directory.
+    pub fn or_disabled(cache: Result<Self>, msg_info: &mut MessageInfo) -> Result<Self> {
+        match cache {
+            Ok(cache) => Ok(cache),
+            Err(err) => {
+                msg_info.debug(format_args!("not using the setup snapshots: {err:#}"))?;
+                Ok(Self::disabled())
+            }
+        }
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        // the keys can be longer than the buffer of `const_sha1`
+        let digest = custom::content_digest([key.as_bytes()]);
+        Some(self.dir.as_ref()?.join(format!("{digest}.json")))
+    }
+
+    /// The value of the snapshot for `key`, if it's still current.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let session = self
+            .session
+            .as_ref()
+            .and_then(|session| session.borrow().get(key).cloned());
+        if let Some(value) = session {
+            return serde_json::from_value(value).ok();
+        }
+        let contents = fs::read(self.path(key)?).ok()?;
+        let snapshot: Snapshot<T> = serde_json::from_slice(&contents).ok()?;
+        let expires = snapshot.created.saturating_add(self.ttl.as_secs());
+        (snapshot.version == SNAPSHOT_VERSION
+            && snapshot.key == key
+            && now() < expires
+            && snapshot.stamps.iter().all(Stamp::is_current))
+        .then_some(snapshot.value)
+    }
+
+    /// Take a snapshot of `value` for `key`, evaluated from the files `paths`.
+    /// If it cannot be written, the snapshot is skipped.
+    pub fn put<T: Serialize>(
+        &self,
+        key: &str,
+        paths: &[PathBuf],
+        value: &T,
+        msg_info: &mut MessageInfo,
+    ) -> Result<()> {
+        if let Some(session) = &self.session {
+            session
+                .borrow_mut()
+                .insert(key.to_owned(), serde_json::to_value(value)?);
+        }
+        let Some(path) = self.path(key) else {
+            return Ok(());
+        };
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            key: key.to_owned(),
+            created: now(),
+            stamps: paths.iter().map(|p| Stamp::new(p)).collect(),
+            value,
+        };
+        match write(&path, &snapshot) {
+            Ok(()) => Ok(()),
+            Err(err) => msg_info.debug(format_args!(
+                "unable to write the setup snapshot {path:?}: {err:#}"
+            )),
+        }
+    }
+}
+
+fn write<T: Serialize>(path: &Path, snapshot: &Snapshot<&T>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        file::create_dir_all(parent)?;
+    }
+    // write to a temporary file first, since another build may read it
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&tmp, serde_json::to_vec(snapshot)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let cache = SetupCache {
+            dir: Some(tmp.path().join("setup")),
+            ttl: DEFAULT_TTL,
+            session: None,
+        };
+        let manifest = tmp.path().join("Cargo.toml");
+        fs::write(&manifest, "[package]")?;
+        let msg_info = &mut MessageInfo::default();
+
+        assert_eq!(cache.get::<String>("metadata"), None);
+        cache.put(
+            "metadata",
+            std::slice::from_ref(&manifest),
+            &"value".to_owned(),
+            msg_info,
+        )?;
+        assert_eq!(cache.get::<String>("metadata"), Some("value".to_owned()));
+        assert_eq!(cache.get::<String>("rustup"), None);
+
+        // changing a file the snapshot was evaluated from invalidates it
+        let modified = fs::metadata(&manifest)?.modified()? + Duration::from_secs(1);
+        fs::File::options()
+            .write(true)
+            .open(&manifest)?
+            .set_modified(modified)?;
+        assert_eq!(cache.get::<String>("metadata"), None);
+
+        let expired = SetupCache {
+            ttl: Duration::ZERO,
+            ..cache.clone()
+        };
+        cache.put(
+            "metadata",
+            std::slice::from_ref(&manifest),
+            &"value".to_owned(),
+            msg_info,
+        )?;
+        assert_eq!(expired.get::<String>("metadata"), None);
+
+        let disabled = SetupCache::disabled();
+        disabled.put("metadata", &[], &"value".to_owned(), msg_info)?;
+        assert_eq!(disabled.get::<String>("metadata"), None);
+
+        // a snapshot which cannot be written is skipped
+        let unwritable = SetupCache {
+            dir: Some(manifest.join("setup")),
+            ..cache.clone()
+        };
+        unwritable.put("metadata", &[], &"value".to_owned(), msg_info)?;
+        assert_eq!(unwritable.get::<String>("metadata"), None);
+
+        // long keys, such as with many arguments, are hashed in chunks
+        let long_key = "rustup\n".repeat(1000);
+        cache.put(&long_key, &[], &"value".to_owned(), msg_info)?;
+        assert_eq!(cache.get::<String>(&long_key), Some("value".to_owned()));
+
+        // the builds of a single invocation share their snapshots in memory
+        let shared = SetupCache::disabled().shared();
+        shared.clone().put("rustup", &[], &(), msg_info)?;
+        assert_eq!(shared.get::<()>("rustup"), Some(()));
+        assert_eq!(SetupCache::disabled().get::<()>("rustup"), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! `cross test --shard K/N`: run a deterministic subset of the tests, so slow
+//! emulated test suites can be split across CI jobs.
+//!
+//! The tests are listed with `--list` inside the container, sorted, and every
+//! `N`th test starting at `K` is run with `--exact`, in batches of tests so the
+//! command line stays below the limit of the system. The exit code is the one
+//! of listing the tests if it fails, otherwise the one of the last batch which
+//! failed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::extensions::SafeCommand;
+
+/// The number of tests passed to a single test command.
+const BATCH_SIZE: usize = 500;
+
+/// The 1-based shard `index` out of `total` shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    index: u32,
+    total: u32,
+}
+
+impl FromStr for Shard {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || eyre::eyre!("invalid shard `{s}`, expected `K/N` such as `2/5`");
+        let (index, total) = s
+            .split_once('/')
+            .and_then(|(index, total)| Some((index.parse::<u32>().ok()?, total.parse().ok()?)))
+            .ok_or_else(invalid)?;
+        if index == 0 || index > total {
+            eyre::bail!("invalid shard `{s}`, the shard must be between 1 and {total}");
+        }
+        Ok(Shard { index, total })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
+impl Shard {
+    /// The shell script running the shard of the tests with `program`, such
+    /// as `cargo`, and the arguments of `cargo test`.
+    pub(crate) fn script(self, program: &str, args: &[String]) -> String {
+        let (cargo_args, test_args) = match args.iter().position(|a| a == "--") {
+            Some(index) => (&args[..index], &args[index + 1..]),
+            None => (args, &[][..]),
+        };
+        let command = |extra: &[&str]| {
+            let mut command = SafeCommand::new(program);
+            command
+                .args(cargo_args)
+                .arg(&"--")
+                .args(test_args)
+                .args(extra);
+            format!("{command:?}")
+        };
+        let list = command(&["--list", "--format", "terse"]);
+        let run = command(&["--exact"]);
+        let Shard { index, total } = self;
+        let skip = index - 1;
+
+        format!(
+            r#"set -e
+list="$(mktemp)"
+{list} > "$list"
+sed -n 's/: test$//p' "$list" | LC_ALL=C sort -u | awk '(NR - 1) % {total} == {skip}' > "$list.shard"
+count="$(wc -l < "$list.shard")"
+if [ "$count" -eq 0 ]; then
+    rm -f "$list" "$list.shard"
+    echo "shard {index}/{total} has no tests to run" 1>&2
+    exit 0
+fi
+echo "running $count tests in shard {index}/{total}" 1>&2
+status=0
+set --
+while IFS= read -r test; do
+    set -- "$@" "$test"
+    if [ "$#" -ge {BATCH_SIZE} ]; then
+        {run} "$@" || status=$?
+        set --
+    fi
+done < "$list.shard"
+if [ "$#" -gt 0 ]; then
+    {run} "$@" || status=$?
+fi
+rm -f "$list" "$list.shard"
+exit "$status""#
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_shard() -> Result<()> {
+        assert_eq!("2/5".parse::<Shard>()?, Shard { index: 2, total: 5 });
+        assert_eq!("1/1".parse::<Shard>()?.to_string(), "1/1");
+        assert!("0/5".parse::<Shard>().is_err());
+        assert!("6/5".parse::<Shard>().is_err());
+        assert!("2".parse::<Shard>().is_err());
+        assert!("a/b".parse::<Shard>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn shard_script() {
+        let args = ["test", "--release", "--", "--test-threads=1"].map(str::to_owned);
+        let script = Shard { index: 2, total: 5 }.script("cargo", &args);
+        assert!(script.contains(
+            "cargo test --release -- --test-threads=1 --list --format terse > \"$list\""
+        ));
+        assert!(script.contains("awk '(NR - 1) % 5 == 1'"));
+        assert!(
+            script.contains("cargo test --release -- --test-threads=1 --exact \"$@\" || status=$?")
+        );
+        assert!(script.contains(&format!("if [ \"$#\" -ge {BATCH_SIZE} ]; then")));
+        assert!(script.ends_with("exit \"$status\""));
+
+        let script = Shard { index: 1, total: 2 }.script("cargo", &["test".to_owned()]);
+        assert!(script.contains("cargo test -- --exact \"$@\" || status=$?"));
+    }
+}
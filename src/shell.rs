@@ -3,8 +3,15 @@
 
 use std::env;
 use std::fmt;
+use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use crate::config::bool_from_envvar;
 use crate::errors::Result;
@@ -141,6 +148,11 @@ pub struct MessageInfo {
     pub stderr_needs_erase: bool,
     pub cross_debug: bool,
     pub has_warned: bool,
+    /// The file the output of container engine commands is written to, with
+    /// `--quiet-container`. The output of cargo is not affected.
+    pub container_log: Option<PathBuf>,
+    /// Also print errors and warnings as GitHub Actions annotations.
+    pub ci_annotations: bool,
 }
 
 impl MessageInfo {
@@ -155,6 +167,8 @@ impl MessageInfo {
                 .map(bool_from_envvar)
                 .unwrap_or_default(),
             has_warned: false,
+            container_log: None,
+            ci_annotations: crate::ci::annotations_enabled(),
         }
     }
 
@@ -222,6 +236,72 @@ impl MessageInfo {
         Ok(())
     }
 
+    /// Show `message` with a spinner on stderr, until the spinner is dropped.
+    ///
+    /// The spinner is only animated at the normal verbosity and if stderr is
+    /// a terminal, otherwise `message` is printed as a status.
+    pub fn spinner<T: fmt::Display>(&mut self, message: T) -> Result<Spinner> {
+        let done = Arc::new(AtomicBool::new(false));
+        if self.verbosity != Verbosity::Normal || !io::Stderr::is_atty() {
+            self.status(message)?;
+            return Ok(Spinner { done, thread: None });
+        }
+
+        let message = message.to_string();
+        let thread = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut stderr = io::stderr();
+                for frame in ['|', '/', '-', '\\'].iter().cycle() {
+                    if done.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    write!(stderr, "\r{frame} {message}").ok();
+                    stderr.flush().ok();
+                    thread::sleep(Duration::from_millis(100));
+                }
+                // erase the spinner, so the next message starts on a clean line
+                write!(stderr, "\r\x1B[K").ok();
+            })
+        };
+        Ok(Spinner {
+            done,
+            thread: Some(thread),
+        })
+    }
+
+    /// Write the output of container engine commands to `path`, truncating it.
+    pub fn set_container_log(&mut self, path: PathBuf) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::File::create(&path)?;
+        self.container_log = Some(path);
+        Ok(())
+    }
+
+    /// If the output of container engine commands is written to the container log.
+    pub fn has_container_log(&self) -> bool {
+        self.container_log.is_some()
+    }
+
+    /// The stdout and stderr for container engine commands, if their output
+    /// is written to the container log.
+    pub fn container_log_stdio(&self) -> Result<Option<(Stdio, Stdio)>> {
+        let Some(path) = self.container_log.as_deref() else {
+            return Ok(None);
+        };
+        let open = |path: &Path| fs::OpenOptions::new().append(true).open(path);
+        Ok(Some((open(path)?.into(), open(path)?.into())))
+    }
+
+    fn annotate<T: fmt::Display>(&mut self, level: &str, message: &T) -> Result<()> {
+        if self.ci_annotations {
+            self.print(crate::ci::annotation(level, &message.to_string()))?;
+        }
+        Ok(())
+    }
+
     /// prints a red 'error' message and terminates.
     #[track_caller]
     pub fn fatal<T: fmt::Display>(&mut self, message: T, code: i32) -> ! {
@@ -234,6 +314,7 @@ impl MessageInfo {
     #[track_caller]
     pub fn error<T: fmt::Display>(&mut self, message: T) -> Result<()> {
         self.has_warned = true;
+        self.annotate("error", &message)?;
         self.stderr_check_erase()?;
         status!(@stderr cross_prefix!("error"), Some(&message), red, self)
     }
@@ -242,6 +323,7 @@ impl MessageInfo {
     #[track_caller]
     pub fn warn<T: fmt::Display>(&mut self, message: T) -> Result<()> {
         self.has_warned = true;
+        self.annotate("warning", &message)?;
         match self.verbosity {
             Verbosity::Quiet => Ok(()),
             _ => status!(@stderr
@@ -254,6 +336,10 @@ impl MessageInfo {
     }
 
     /// prints a cyan 'note' message.
+    ///
+    /// Use a note, not a warning, for anything cross recovers from or that
+    /// needs no action, such as a retried command: warnings fail the build
+    /// in CI, see [`MessageInfo::should_fail`].
     #[track_caller]
     pub fn note<T: fmt::Display>(&mut self, message: T) -> Result<()> {
         match self.verbosity {
@@ -449,6 +535,22 @@ fn get_verbosity(
     })
 }
 
+/// A spinner shown with [`MessageInfo::spinner`].
+#[derive(Debug)]
+pub struct Spinner {
+    done: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
 pub trait Stream {
     type TTY: IsTerminal;
     const OWO: owo_colors::Stream;
@@ -0,0 +1,375 @@
+//! Local build statistics, recorded when `CROSS_BUILD_STATS` is enabled.
+//!
+//! Nothing is ever sent anywhere: every run appends a line to a JSON lines
+//! file in the cross data directory, which `cross stats show` summarizes.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::bool_from_envvar;
+use crate::errors::*;
+use crate::shell::MessageInfo;
+use crate::{cli, Subcommand};
+
+pub const CROSS_BUILD_STATS_ENV: &str = "CROSS_BUILD_STATS";
+
+/// Returns `true` if recording the build statistics was opted into.
+#[must_use]
+pub fn enabled() -> bool {
+    env::var(CROSS_BUILD_STATS_ENV)
+        .map(|v| bool_from_envvar(&v))
+        .unwrap_or_default()
+}
+
+/// The file the statistics are stored in.
+pub fn path() -> Result<PathBuf> {
+    directories::BaseDirs::new()
+        .map(|d| d.data_dir().join("cross-rs").join("stats.jsonl"))
+        .ok_or(eyre::eyre!("unable to get data directory"))
+}
+
+/// A single run of cross for a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildRecord {
+    /// Seconds since the Unix epoch when the run finished.
+    pub timestamp: u64,
+    pub target: String,
+    pub profile: String,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// The artifact directory existed before the run, so the build was incremental.
+    pub incremental: bool,
+}
+
+impl BuildRecord {
+    #[must_use]
+    pub fn new(
+        target: String,
+        profile: String,
+        duration_ms: u64,
+        success: bool,
+        incremental: bool,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        BuildRecord {
+            timestamp,
+            target,
+            profile,
+            duration_ms,
+            success,
+            incremental,
+        }
+    }
+}
+
+/// Append `record` to the statistics file at `path`.
+pub fn append(path: &Path, record: &BuildRecord) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("could not open build statistics `{path:?}`"))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Read all records from `path`, skipping lines which cannot be parsed.
+pub fn read(path: &Path) -> Result<Vec<BuildRecord>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let contents = fs::read_to_string(path)
+        .wrap_err_with(|| format!("could not read build statistics `{path:?}`"))?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Summary of the successful runs for a target and profile.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub runs: usize,
+    pub last_ms: u64,
+    pub mean_ms: u64,
+    pub min_ms: u64,
+    pub max_ms: u64,
+    /// The durations of the most recent runs, oldest first.
+    pub trend: Vec<u64>,
+}
+
+/// Group the successful runs by target and profile.
+#[must_use]
+pub fn summarize(records: &[BuildRecord], trend_len: usize) -> BTreeMap<(String, String), Summary> {
+    let mut durations: BTreeMap<_, Vec<u64>> = BTreeMap::new();
+    for record in records.iter().filter(|r| r.success) {
+        durations
+            .entry((record.target.clone(), record.profile.clone()))
+            .or_default()
+            .push(record.duration_ms);
+    }
+    durations
+        .into_iter()
+        .map(|(key, durations)| {
+            let runs = durations.len();
+            let total: u64 = durations.iter().sum();
+            let summary = Summary {
+                runs,
+                last_ms: durations[runs - 1],
+                mean_ms: total / runs as u64,
+                min_ms: durations.iter().copied().min().unwrap_or_default(),
+                max_ms: durations.iter().copied().max().unwrap_or_default(),
+                trend: durations[runs.saturating_sub(trend_len)..].to_vec(),
+            };
+            (key, summary)
+        })
+        .collect()
+}
+
+/// Draw `values` as a sparkline, scaled from the smallest to the largest value.
+#[must_use]
+pub fn sparkline(values: &[u64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().copied().min().unwrap_or_default();
+    let max = values.iter().copied().max().unwrap_or_default();
+    let range = max - min;
+    values
+        .iter()
+        .map(|&v| {
+            ((v - min) * (BARS.len() as u64 - 1))
+                .checked_div(range)
+                .map_or(BARS[0], |i| BARS[i as usize])
+        })
+        .collect()
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+/// Print the summary of the recorded runs, only of `target` and `profile` if
+/// they are set, with the last `trend` runs drawn as a sparkline.
+pub fn show(
+    target: Option<&str>,
+    profile: Option<&str>,
+    trend: usize,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    let records: Vec<_> = read(&path()?)?
+        .into_iter()
+        .filter(|r| target.map_or(true, |t| r.target == t))
+        .filter(|r| profile.map_or(true, |p| r.profile == p))
+        .collect();
+    if records.is_empty() {
+        if !enabled() {
+            msg_info.note(format_args!(
+                "build statistics are only recorded with `{CROSS_BUILD_STATS_ENV}=1`"
+            ))?;
+        }
+        return msg_info.print("no builds recorded");
+    }
+
+    let header = [
+        "Target", "Profile", "Runs", "Last", "Mean", "Min", "Max", "Trend",
+    ]
+    .map(str::to_owned);
+    let mut rows = vec![header];
+    for ((target, profile), summary) in summarize(&records, trend) {
+        rows.push([
+            target,
+            profile,
+            summary.runs.to_string(),
+            seconds(summary.last_ms),
+            seconds(summary.mean_ms),
+            seconds(summary.min_ms),
+            seconds(summary.max_ms),
+            sparkline(&summary.trend),
+        ]);
+    }
+
+    let mut widths = [0; 8];
+    for row in &rows {
+        for (width, col) in widths.iter_mut().zip(row) {
+            *width = col.chars().count().max(*width);
+        }
+    }
+    for row in &rows {
+        let mut line = String::new();
+        for (col, width) in row.iter().zip(widths) {
+            line.push_str(&format!("{col:<width$}  "));
+        }
+        msg_info.print(line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// The arguments of `cross stats show`.
+#[derive(Debug, PartialEq, Eq)]
+struct ShowArgs {
+    target: Option<String>,
+    profile: Option<String>,
+    trend: usize,
+}
+
+impl ShowArgs {
+    fn parse(cargo_args: &[String]) -> Result<ShowArgs> {
+        let mut show = ShowArgs {
+            target: None,
+            profile: None,
+            trend: 20,
+        };
+        // skip the subcommand, and the optional `show` action
+        let mut args = cargo_args.iter().skip(1).peekable();
+        args.next_if(|arg| *arg == "show");
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_owned())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = |flag: &str| {
+                value
+                    .clone()
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| eyre::eyre!("`{flag}` requires a value"))
+            };
+            match flag {
+                "--target" => show.target = Some(value(flag)?),
+                "--profile" => show.profile = Some(value(flag)?),
+                "--trend" => {
+                    show.trend = value(flag)?
+                        .parse()
+                        .wrap_err("`--trend` requires a number of runs")?;
+                }
+                // verbosity and color were already parsed by cross
+                "--color" => drop(value(flag)?),
+                _ if cli::is_verbose(arg) != 0 || matches!(flag, "--quiet" | "-q") => {}
+                _ => {
+                    return Err(eyre::eyre!("unexpected argument `{arg}` for `cross stats`"))
+                        .with_suggestion(|| {
+                            "run `cross stats show [--target <TARGET>] [--profile <PROFILE>] [--trend <RUNS>]`"
+                        });
+                }
+            }
+        }
+        Ok(show)
+    }
+}
+
+/// `cross stats show`, summarizing the recorded runs.
+pub(crate) fn run(args: &cli::Args, msg_info: &mut MessageInfo) -> Result<()> {
+    debug_assert_eq!(args.subcommand, Some(Subcommand::Stats));
+    let show = ShowArgs::parse(&args.cargo_args)?;
+    self::show(
+        show.target.as_deref(),
+        show.profile.as_deref(),
+        show.trend,
+        msg_info,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(target: &str, duration_ms: u64, success: bool) -> BuildRecord {
+        BuildRecord {
+            timestamp: 0,
+            target: target.to_owned(),
+            profile: "dev".to_owned(),
+            duration_ms,
+            success,
+            incremental: true,
+        }
+    }
+
+    #[test]
+    fn summarize_records() {
+        let records = [
+            record("aarch64-unknown-linux-gnu", 4000, true),
+            record("aarch64-unknown-linux-gnu", 1000, false),
+            record("aarch64-unknown-linux-gnu", 2000, true),
+            record("aarch64-unknown-linux-gnu", 3000, true),
+            record("x86_64-pc-windows-gnu", 500, true),
+        ];
+        let summary = summarize(&records, 2);
+        assert_eq!(summary.len(), 2);
+        let key = ("aarch64-unknown-linux-gnu".to_owned(), "dev".to_owned());
+        assert_eq!(
+            summary[&key],
+            Summary {
+                runs: 3,
+                last_ms: 3000,
+                mean_ms: 3000,
+                min_ms: 2000,
+                max_ms: 4000,
+                trend: vec![2000, 3000],
+            }
+        );
+    }
+
+    #[test]
+    fn show_args() -> Result<()> {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert_eq!(
+            ShowArgs::parse(&args(&[
+                "stats",
+                "show",
+                "--target",
+                "aarch64-unknown-linux-gnu",
+                "--trend=5",
+                "-v"
+            ]))?,
+            ShowArgs {
+                target: Some("aarch64-unknown-linux-gnu".to_owned()),
+                profile: None,
+                trend: 5,
+            }
+        );
+        assert_eq!(
+            ShowArgs::parse(&args(&["stats", "--profile=release"]))?.profile,
+            Some("release".to_owned())
+        );
+        assert!(ShowArgs::parse(&args(&["stats", "clear"])).is_err());
+        assert!(ShowArgs::parse(&args(&["stats", "--target"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sparkline_scale() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[5, 5]), "▁▁");
+        assert_eq!(sparkline(&[0, 7, 14]), "▁▄█");
+    }
+
+    #[test]
+    fn append_and_read() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("stats").join("stats.jsonl");
+        append(&path, &record("aarch64-unknown-linux-gnu", 1000, true))?;
+        append(&path, &record("aarch64-unknown-linux-gnu", 2000, true))?;
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"not json\n")?;
+
+        let records = read(&path)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].duration_ms, 2000);
+        assert!(read(&tmp.path().join("missing.jsonl"))?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! `cross targets`: the targets known to rustc and cross, and what cross
+//! supports for each of them.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::cli::VersionFormat;
+use crate::docker::PROVIDED_IMAGES;
+use crate::errors::*;
+use crate::rustc::TargetList;
+use crate::shell::MessageInfo;
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct TargetInfo {
+    target: String,
+    /// The default image, if cross provides one.
+    image: Option<String>,
+    /// The subtargets of the provided images, such as `centos`.
+    subs: Vec<&'static str>,
+    /// The runner of the default image, taken from its Dockerfile.
+    runner: Option<&'static str>,
+    needs_qemu: bool,
+    /// `cross run`, `cross test`, and `cross bench` can run the binaries.
+    supports_run: bool,
+}
+
+impl TargetInfo {
+    fn new(triple: &str) -> Self {
+        let images: Vec<_> = PROVIDED_IMAGES
+            .iter()
+            .filter(|image| image.name == triple)
+            .collect();
+        let default = images
+            .iter()
+            .find(|image| image.sub.is_none())
+            .or_else(|| images.first());
+        let runner = default.and_then(|image| image.runner);
+
+        TargetInfo {
+            target: triple.to_owned(),
+            image: default.map(|image| image.default_image_name()),
+            subs: images.iter().filter_map(|image| image.sub).collect(),
+            runner,
+            needs_qemu: matches!(runner, Some("qemu-user" | "qemu-system")),
+            supports_run: runner.is_some(),
+        }
+    }
+}
+
+/// The targets of rustc and the provided images, sorted by name.
+fn targets(target_list: &TargetList) -> Vec<TargetInfo> {
+    let triples: BTreeSet<&str> = target_list
+        .triples
+        .iter()
+        .map(String::as_str)
+        .chain(PROVIDED_IMAGES.iter().map(|image| image.name))
+        .collect();
+    triples.into_iter().map(TargetInfo::new).collect()
+}
+
+fn table(targets: &[TargetInfo]) -> String {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    let rows: Vec<[String; 4]> = targets
+        .iter()
+        .map(|info| {
+            let mut image = info.image.clone().unwrap_or_else(|| "-".to_owned());
+            if !info.subs.is_empty() {
+                image = format!("{image} (+{})", info.subs.join(", "));
+            }
+            [
+                info.target.clone(),
+                image,
+                info.runner.unwrap_or("-").to_owned(),
+                yes_no(info.supports_run).to_owned(),
+            ]
+        })
+        .collect();
+    let header = ["TARGET", "IMAGE", "RUNNER", "RUN/TEST"].map(str::to_owned);
+    let width = |column: usize| {
+        rows.iter()
+            .chain([&header])
+            .map(|row| row[column].len())
+            .max()
+            .unwrap_or_default()
+    };
+    let widths = [width(0), width(1), width(2)];
+
+    [&header]
+        .into_iter()
+        .chain(&rows)
+        .map(|row| {
+            format!(
+                "{:w0$}  {:w1$}  {:w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub(crate) fn print(
+    target_list: &TargetList,
+    format: VersionFormat,
+    msg_info: &mut MessageInfo,
+) -> Result<()> {
+    let targets = targets(target_list);
+    match format {
+        VersionFormat::Human => msg_info.print(table(&targets)),
+        VersionFormat::Json => msg_info.print(serde_json::to_string_pretty(&targets)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_capabilities() {
+        let target_list = TargetList {
+            triples: vec![
+                "aarch64-unknown-linux-gnu".to_owned(),
+                "x86_64-unknown-linux-gnu".to_owned(),
+                "x86_64-unknown-uefi".to_owned(),
+            ],
+        };
+        let targets = targets(&target_list);
+        let info = |triple: &str| targets.iter().find(|t| t.target == triple).unwrap();
+
+        let aarch64 = info("aarch64-unknown-linux-gnu");
+        assert!(aarch64.image.is_some());
+        assert_eq!(aarch64.runner, Some("qemu-user"));
+        assert!(aarch64.needs_qemu && aarch64.supports_run);
+
+        let x86_64 = info("x86_64-unknown-linux-gnu");
+        assert_eq!(x86_64.runner, Some("native"));
+        assert!(!x86_64.needs_qemu);
+        assert!(x86_64.subs.contains(&"centos"));
+
+        let uefi = info("x86_64-unknown-uefi");
+        assert_eq!(uefi.image, None);
+        assert!(!uefi.supports_run);
+
+        let thumbv7em = info("thumbv7em-none-eabihf");
+        assert_eq!(thumbv7em.runner, Some("qemu-system"));
+        assert!(thumbv7em.needs_qemu && thumbv7em.supports_run);
+
+        // the capabilities match the runners of the provided images
+        for image in PROVIDED_IMAGES.iter().filter(|image| image.sub.is_none()) {
+            let info = info(image.name);
+            assert_eq!(info.runner, image.runner, "{}", image.name);
+            assert_eq!(info.supports_run, image.runner.is_some(), "{}", image.name);
+        }
+
+        // the provided images are listed even if rustc does not know them
+        assert!(targets.len() > target_list.triples.len());
+        assert!(table(&targets).starts_with("TARGET"));
+    }
+}
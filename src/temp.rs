@@ -1,8 +1,25 @@
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
 
 use crate::errors::Result;
 
+/// Overrides the cache directory, `build.cache-dir` in `Cross.toml`.
+pub const CROSS_CACHE_DIR_ENV: &str = "CROSS_CACHE_DIR";
+
+/// Cache entries used more recently are never evicted, since they may belong
+/// to a build which is still running.
+const EVICTION_MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Walking the cache is slow, so it's only checked for eviction this often.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The file whose modification time is when the cache was last checked for eviction.
+const EVICTION_STAMP: &str = "eviction-stamp";
+
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
 // open temporary directories and files so we ensure we cleanup on exit.
 static mut FILES: Vec<tempfile::NamedTempFile> = vec![];
 static mut DIRS: Vec<tempfile::TempDir> = vec![];
@@ -11,12 +28,182 @@ fn data_dir() -> Option<PathBuf> {
     directories::BaseDirs::new().map(|d| d.data_dir().to_path_buf())
 }
 
-pub fn dir() -> Result<PathBuf> {
+/// Use `path` for the cache directory, `build.cache-dir`, unless
+/// `CROSS_CACHE_DIR` is set. Only the first call has an effect.
+pub fn set_cache_dir(path: PathBuf) {
+    CACHE_DIR.set(path).ok();
+}
+
+/// The directory for temporary files and cached data, such as the
+/// fingerprints of remote volumes.
+pub fn cache_dir() -> Result<PathBuf> {
+    match CACHE_DIR.get() {
+        Some(dir) if std::env::var_os(CROSS_CACHE_DIR_ENV).map_or(true, |d| d.is_empty()) => {
+            Ok(dir.clone())
+        }
+        _ => default_cache_dir(),
+    }
+}
+
+/// The cache directory without `build.cache-dir`, which holds the snapshots
+/// of `cargo metadata` needed to find the configuration.
+pub fn default_cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(CROSS_CACHE_DIR_ENV).filter(|d| !d.is_empty()) {
+        return Ok(dir.into());
+    }
     data_dir()
-        .map(|p| p.join("cross-rs").join("tmp"))
+        .map(|p| p.join("cross-rs"))
         .ok_or(eyre::eyre!("unable to get data directory"))
 }
 
+pub fn dir() -> Result<PathBuf> {
+    cache_dir().map(|p| p.join("tmp"))
+}
+
+/// The cargo homes dedicated to each project, with `mount-cargo-home = "project"`.
+pub fn cargo_home_dir() -> Result<PathBuf> {
+    cache_dir().map(|p| p.join("cargo-home"))
+}
+
+/// The directory for the fingerprints of persistent remote volumes.
+pub fn fingerprint_dir() -> Result<PathBuf> {
+    cache_dir().map(|p| p.join("fingerprints"))
+}
+
+/// Parse a size such as `512M` or `5G`, using binary units.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (number, unit) = size
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((size, ""), |index| size.split_at(index));
+    let shift = match unit.trim().trim_end_matches(['B', 'b']) {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        "T" | "t" => 40,
+        _ => eyre::bail!("invalid size `{size}`, expected a number with a unit such as `5G`"),
+    };
+    let number: u64 = number
+        .parse()
+        .ok()
+        .ok_or_else(|| eyre::eyre!("invalid size `{size}`, expected a number"))?;
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| eyre::eyre!("size `{size}` is too large"))
+}
+
+/// An evictable entry of the cache directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// The size of the file or directory at `path`, without following symlinks.
+pub fn entry_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if metadata.is_dir() {
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| entry_size(&e.path()))
+            .sum()
+    } else {
+        metadata.len()
+    }
+}
+
+/// The temporary files and fingerprints in the cache directory.
+pub fn cache_entries() -> Result<Vec<CacheEntry>> {
+    let mut entries = vec![];
+    for dir in [dir()?, fingerprint_dir()?] {
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push(CacheEntry {
+                size: entry_size(&path),
+                path,
+                modified,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// The least recently used entries to remove so the cache is at most
+/// `max_size` bytes, skipping entries used since `now - EVICTION_MIN_AGE`.
+#[must_use]
+pub fn entries_to_evict(
+    mut entries: Vec<CacheEntry>,
+    max_size: u64,
+    now: SystemTime,
+) -> Vec<CacheEntry> {
+    let mut size: u64 = entries.iter().map(|e| e.size).sum();
+    entries.sort_by_key(|e| e.modified);
+    entries
+        .into_iter()
+        .filter(|e| {
+            now.duration_since(e.modified)
+                .is_ok_and(|age| age >= EVICTION_MIN_AGE)
+        })
+        .take_while(|e| {
+            let evict = size > max_size;
+            size = size.saturating_sub(e.size);
+            evict
+        })
+        .collect()
+}
+
+/// Remove a cache entry, either a file or a directory.
+pub fn remove_entry(entry: &CacheEntry) -> Result<()> {
+    if entry.path.is_dir() {
+        fs::remove_dir_all(&entry.path)?;
+    } else {
+        fs::remove_file(&entry.path)?;
+    }
+    Ok(())
+}
+
+/// If the cache, last checked for eviction at `checked`, is checked again.
+fn eviction_is_due(checked: Option<SystemTime>, now: SystemTime) -> bool {
+    checked.map_or(true, |checked| {
+        now.duration_since(checked)
+            .map_or(true, |age| age >= EVICTION_INTERVAL)
+    })
+}
+
+/// Evict the least recently used entries until the cache is at most
+/// `max_size` bytes, returning the evicted entries. Nothing is evicted if the
+/// cache was checked within the last `EVICTION_INTERVAL`.
+pub fn evict(max_size: u64) -> Result<Vec<CacheEntry>> {
+    let now = SystemTime::now();
+    let dir = cache_dir()?;
+    let stamp = dir.join(EVICTION_STAMP);
+    let checked = fs::metadata(&stamp).and_then(|m| m.modified()).ok();
+    if !eviction_is_due(checked, now) {
+        return Ok(vec![]);
+    }
+    fs::create_dir_all(&dir)?;
+    fs::write(&stamp, b"")?;
+
+    let evicted = entries_to_evict(cache_entries()?, max_size, now);
+    for entry in &evicted {
+        remove_entry(entry)?;
+    }
+    Ok(evicted)
+}
+
 pub(crate) fn has_tempfiles() -> bool {
     // SAFETY: safe, since we only check if the stack is empty.
     unsafe { !FILES.is_empty() || !DIRS.is_empty() }
@@ -125,3 +312,61 @@ impl Drop for TempDir {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() -> Result<()> {
+        assert_eq!(parse_size("1024")?, 1024);
+        assert_eq!(parse_size("512M")?, 512 << 20);
+        assert_eq!(parse_size("5G")?, 5 << 30);
+        assert_eq!(parse_size("5 GB")?, 5 << 30);
+        assert!(parse_size("5X").is_err());
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("99999999999T").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn eviction_interval() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert!(eviction_is_due(None, now));
+        assert!(!eviction_is_due(Some(now - Duration::from_secs(60)), now));
+        assert!(eviction_is_due(Some(now - EVICTION_INTERVAL), now));
+        // a stamp from the future, such as after the clock changed
+        assert!(eviction_is_due(Some(now + Duration::from_secs(60)), now));
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let entry = |name: &str, size, age| CacheEntry {
+            path: name.into(),
+            size,
+            modified: now - Duration::from_secs(age),
+        };
+        let entries = vec![
+            entry("recent", 100, 60),
+            entry("oldest", 30, 10_000),
+            entry("old", 30, 5_000),
+            entry("older", 30, 8_000),
+        ];
+        let names = |entries: Vec<CacheEntry>| -> Vec<PathBuf> {
+            entries.into_iter().map(|e| e.path).collect()
+        };
+
+        assert!(entries_to_evict(entries.clone(), 190, now).is_empty());
+        assert_eq!(
+            names(entries_to_evict(entries.clone(), 150, now)),
+            ["oldest", "older"].map(PathBuf::from)
+        );
+        // recently used entries are kept, even if the cache is too large
+        assert_eq!(
+            names(entries_to_evict(entries, 0, now)),
+            ["oldest", "older", "old"].map(PathBuf::from)
+        );
+    }
+}
@@ -154,3 +154,119 @@ fn check_newlines() -> crate::Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn config_search_paths() -> crate::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let tmp = crate::file::canonicalize(tmp.path())?;
+    let root = tmp.join("monorepo").join("workspace");
+    let member = root.join("member");
+    std::fs::create_dir_all(&member)?;
+    for dir in [&tmp.join("monorepo"), &root, &member] {
+        std::fs::write(dir.join("Cross.toml"), "")?;
+    }
+    let search = crate::ConfigSearch {
+        config: None,
+        search: None,
+        config_home: Some(tmp.join("config")),
+        cwd: member.clone(),
+    };
+    let global: Vec<PathBuf> = match cfg!(unix) {
+        true => vec!["/etc/cross/config.toml".into()],
+        false => vec![],
+    };
+    let user = tmp.join("config").join("cross").join("Cross.toml");
+
+    // the global files are read first, then the workspace root.
+    let mut expected = global.clone();
+    expected.extend([user.clone(), root.join("Cross.toml")]);
+    assert_eq!(search.paths(&root)?, expected);
+
+    let workspace = crate::ConfigSearch {
+        search: Some("workspace".to_owned()),
+        ..search.clone()
+    };
+    assert_eq!(workspace.paths(&root)?, expected);
+
+    // from the outermost parent to the current directory.
+    let parents = crate::ConfigSearch {
+        search: Some("parents".to_owned()),
+        ..search.clone()
+    };
+    let mut expected = global.clone();
+    expected.extend([
+        user.clone(),
+        tmp.join("monorepo").join("Cross.toml"),
+        root.join("Cross.toml"),
+        member.join("Cross.toml"),
+    ]);
+    assert_eq!(parents.paths(&root)?, expected);
+
+    // outside of the workspace, the search starts at the workspace root.
+    let outside = crate::ConfigSearch {
+        cwd: tmp.clone(),
+        ..parents.clone()
+    };
+    let mut expected = global.clone();
+    expected.extend([
+        user,
+        tmp.join("monorepo").join("Cross.toml"),
+        root.join("Cross.toml"),
+    ]);
+    assert_eq!(outside.paths(&root)?, expected);
+
+    let invalid = crate::ConfigSearch {
+        search: Some("all".to_owned()),
+        ..search.clone()
+    };
+    assert!(invalid.paths(&root).is_err());
+
+    // `CROSS_CONFIG` replaces the other locations.
+    let config = std::env::join_paths(["/etc/ci.toml", "", "Cross.ci.toml"])?;
+    let explicit = crate::ConfigSearch {
+        config: Some(config),
+        ..parents
+    };
+    assert_eq!(
+        explicit.paths(&root)?,
+        [PathBuf::from("/etc/ci.toml"), "Cross.ci.toml".into()]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn config_precedence() -> crate::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let global = tmp.path().join("config.toml");
+    let project = tmp.path().join("Cross.toml");
+    std::fs::write(
+        &global,
+        "[build]\nxargo = true\n\n[target.aarch64-unknown-linux-gnu]\nimage = \"global\"\n",
+    )?;
+    std::fs::write(
+        &project,
+        "[target.aarch64-unknown-linux-gnu]\nimage = \"project\"\n",
+    )?;
+    let target = crate::Target::new_built_in("aarch64-unknown-linux-gnu");
+    let msg_info = &mut crate::shell::MessageInfo::default();
+
+    let missing = tmp.path().join("missing.toml");
+    let paths = [global.clone(), missing.clone(), project];
+    let config = crate::merge_cross_toml_files(&paths, msg_info)?.expect("config files exist");
+    // the later files take precedence, and the others fill in the rest.
+    assert_eq!(
+        config.image(&target).map(|i| i.reference.get()),
+        Some("project")
+    );
+    assert_eq!(config.xargo(&target).build, Some(true));
+
+    let config = crate::merge_cross_toml_files(&[global], msg_info)?.expect("config file exists");
+    assert_eq!(
+        config.image(&target).map(|i| i.reference.get()),
+        Some("global")
+    );
+    assert_eq!(crate::merge_cross_toml_files(&[missing], msg_info)?, None);
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! The build information printed by `cross --version --format json`.
+
+use serde::Serialize;
+
+use crate::docker::{CROSS_IMAGE, DEFAULT_IMAGE_VERSION, PROVIDED_IMAGES};
+
+/// Information about the installed build of cross.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VersionInfo {
+    version: &'static str,
+    commit_hash: Option<&'static str>,
+    commit_date: Option<&'static str>,
+    /// `dev` for builds from a git checkout, otherwise `release`.
+    channel: &'static str,
+    host: &'static str,
+    image_repository: &'static str,
+    default_image_version: &'static str,
+    images: Vec<ProvidedImageInfo>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProvidedImageInfo {
+    target: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<&'static str>,
+    image: String,
+}
+
+impl VersionInfo {
+    #[must_use]
+    pub fn new() -> Self {
+        let commit = parse_commit_info(crate::commit_info());
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            commit_hash: commit.map(|(hash, _)| hash),
+            commit_date: commit.map(|(_, date)| date),
+            channel: if commit.is_some() { "dev" } else { "release" },
+            host: env!("CROSS_HOST_TRIPLE"),
+            image_repository: CROSS_IMAGE,
+            default_image_version: DEFAULT_IMAGE_VERSION,
+            images: PROVIDED_IMAGES
+                .iter()
+                .map(|image| ProvidedImageInfo {
+                    target: image.name,
+                    sub: image.sub,
+                    image: image.default_image_name(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Split the ` (hash date)` commit info into the hash and date.
+fn parse_commit_info(info: &str) -> Option<(&str, &str)> {
+    info.trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split_once(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_info() {
+        assert_eq!(
+            parse_commit_info(" (1f310e8 2024-05-01)"),
+            Some(("1f310e8", "2024-05-01"))
+        );
+        assert_eq!(parse_commit_info(""), None);
+    }
+}
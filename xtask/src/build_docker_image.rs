@@ -1,5 +1,6 @@
 use std::fmt::Write;
 use std::path::Path;
+use std::process::Command;
 
 use crate::util::{
     cargo_metadata, get_matrix, gha_error, gha_output, gha_print, DEFAULT_PLATFORMS,
@@ -9,6 +10,7 @@ use clap::Args;
 use cross::docker::{self, BuildCommandExt, BuildResultExt, ImagePlatform, Progress};
 use cross::shell::MessageInfo;
 use cross::{CommandExt, ToUtf8};
+use eyre::Context;
 
 #[derive(Args, Debug)]
 pub struct BuildDockerImage {
@@ -74,8 +76,17 @@ pub struct BuildDockerImage {
     /// Additional build arguments to pass to Docker.
     #[clap(long)]
     pub build_arg: Vec<String>,
-    // [os/arch[/variant]=]toolchain
-    #[clap(long, short = 'a', action = clap::builder::ArgAction::Append)]
+    /// Platforms to build the images for, as `[os/arch[/variant]=]toolchain`.
+    ///
+    /// With multiple platforms, each image is tagged with the platform as a
+    /// suffix, and `--push` combines them into a manifest list.
+    #[clap(
+        long,
+        short = 'a',
+        visible_alias = "arch",
+        value_delimiter = ',',
+        action = clap::builder::ArgAction::Append
+    )]
     pub platform: Vec<ImagePlatform>,
     /// Targets to build for
     #[clap()]
@@ -175,54 +186,11 @@ pub fn build_docker_image(
         platform
     };
 
+    // with multiple platforms, every platform is tagged with its own suffix,
+    // and the tags are combined into a manifest list once all are pushed.
+    let multi_arch = platforms.len() > 1;
     let mut results = vec![];
-    for (platform, (target, dockerfile)) in targets
-        .iter()
-        .flat_map(|t| platforms.iter().map(move |p| (p, t)))
-    {
-        if gha && targets.len() > 1 {
-            gha_print("::group::Build {target}");
-        } else {
-            msg_info.note(format_args!("Build {target} for {}", platform.target))?;
-        }
-        let mut docker_build = engine.command();
-        docker_build.invoke_build_command();
-        let has_buildkit = docker::Engine::has_buildkit();
-        docker_build.current_dir(&docker_root);
-
-        let docker_platform = platform.docker_platform();
-        let mut dockerfile = dockerfile.clone();
-        docker_build.args(["--platform", &docker_platform]);
-        let uppercase_triple = target.name.to_ascii_uppercase().replace('-', "_");
-        docker_build.args([
-            "--build-arg",
-            &format!("CROSS_TARGET_TRIPLE={}", uppercase_triple),
-        ]);
-        // add our platform, and determine if we need to use a native docker image
-        if has_native_image(docker_platform.as_str(), target, msg_info)? {
-            let dockerfile_name = match target.sub.as_deref() {
-                Some(sub) => format!("Dockerfile.native.{sub}"),
-                None => "Dockerfile.native".to_owned(),
-            };
-            let dockerfile_path = docker_root.join(&dockerfile_name);
-            if !dockerfile_path.exists() {
-                eyre::bail!(
-                    "unable to find native dockerfile named {dockerfile_name} for target {target}."
-                );
-            }
-            dockerfile = dockerfile_path.to_utf8()?.to_string();
-        }
-
-        if push {
-            docker_build.arg("--push");
-        } else if engine.kind.supports_output_flag() && no_output {
-            docker_build.args(["--output", "type=tar,dest=/dev/null"]);
-        } else if no_output {
-            msg_info.fatal("cannot specify `--no-output` with engine that does not support the `--output` flag", 1);
-        } else if has_buildkit {
-            docker_build.arg("--load");
-        }
-
+    'targets: for (target, dockerfile) in &targets {
         let mut tags = vec![];
 
         match (ref_type.as_deref(), ref_name.as_deref()) {
@@ -246,96 +214,180 @@ pub fn build_docker_image(
             tags = vec![target.image_name(&repository, tag)];
         }
 
-        if engine.kind.supports_pull_flag() {
-            docker_build.arg("--pull");
-        }
-        let base_name = format!("{repository}/{}", target.name);
-        if no_cache {
-            docker_build.arg("--no-cache");
-        } else if engine.kind.supports_cache_from_type() {
+        let execute = !dry_run && (force || !push || gha);
+        for platform in &platforms {
+            if gha && targets.len() > 1 {
+                gha_print("::group::Build {target}");
+            } else {
+                msg_info.note(format_args!("Build {target} for {}", platform.target))?;
+            }
+            let mut docker_build = engine.command();
+            docker_build.invoke_build_command();
+            let has_buildkit = docker::Engine::has_buildkit();
+            docker_build.current_dir(&docker_root);
+
+            let docker_platform = platform.docker_platform();
+            let mut dockerfile = dockerfile.clone();
+            docker_build.args(["--platform", &docker_platform]);
+            let uppercase_triple = target.name.to_ascii_uppercase().replace('-', "_");
             docker_build.args([
-                "--cache-from",
-                &cache_from.replace("{base_name}", &base_name),
+                "--build-arg",
+                &format!("CROSS_TARGET_TRIPLE={}", uppercase_triple),
             ]);
-        } else {
-            // we can't use `image_name` since podman doesn't support tags
-            // with `--cache-from`. podman only supports an image format
-            // of registry/repo although it does when pulling images. this
-            // affects building from cache with target+subs images since we
-            // can't use caches from registry. this is only an issue if
-            // building with podman without a local cache, which never
-            // happens in practice.
-            docker_build.args(["--cache-from", &base_name]);
-        }
+            // add our platform, and determine if we need to use a native docker image
+            if has_native_image(docker_platform.as_str(), target, msg_info)? {
+                let dockerfile_name = match target.sub.as_deref() {
+                    Some(sub) => format!("Dockerfile.native.{sub}"),
+                    None => "Dockerfile.native".to_owned(),
+                };
+                let dockerfile_path = docker_root.join(&dockerfile_name);
+                if !dockerfile_path.exists() {
+                    eyre::bail!(
+                        "unable to find native dockerfile named {dockerfile_name} for target {target}."
+                    );
+                }
+                dockerfile = dockerfile_path.to_utf8()?.to_string();
+            }
 
-        if push {
-            docker_build.args(["--cache-to", "type=inline"]);
-        } else if let Some(ref cache_to) = cache_to {
-            docker_build.args(["--cache-to", &cache_to.replace("{base_name}", &base_name)]);
-        }
+            if push {
+                docker_build.arg("--push");
+            } else if engine.kind.supports_output_flag() && no_output {
+                docker_build.args(["--output", "type=tar,dest=/dev/null"]);
+            } else if no_output {
+                msg_info.fatal("cannot specify `--no-output` with engine that does not support the `--output` flag", 1);
+            } else if has_buildkit {
+                docker_build.arg("--load");
+            }
 
-        for tag in &tags {
-            docker_build.args(["--tag", tag]);
-        }
+            if engine.kind.supports_pull_flag() {
+                docker_build.arg("--pull");
+            }
+            let base_name = format!("{repository}/{}", target.name);
+            if no_cache {
+                docker_build.arg("--no-cache");
+            } else if engine.kind.supports_cache_from_type() {
+                docker_build.args([
+                    "--cache-from",
+                    &cache_from.replace("{base_name}", &base_name),
+                ]);
+            } else {
+                // we can't use `image_name` since podman doesn't support tags
+                // with `--cache-from`. podman only supports an image format
+                // of registry/repo although it does when pulling images. this
+                // affects building from cache with target+subs images since we
+                // can't use caches from registry. this is only an issue if
+                // building with podman without a local cache, which never
+                // happens in practice.
+                docker_build.args(["--cache-from", &base_name]);
+            }
 
-        for label in labels
-            .as_deref()
-            .unwrap_or("")
-            .split('\n')
-            .filter(|s| !s.is_empty())
-        {
-            docker_build.args(["--label", label]);
-        }
+            if push {
+                docker_build.args(["--cache-to", "type=inline"]);
+            } else if let Some(ref cache_to) = cache_to {
+                docker_build.args(["--cache-to", &cache_to.replace("{base_name}", &base_name)]);
+            }
 
-        docker_build.cross_labels(&target.name, platform.target.triple());
-        docker_build.args(["--file", &dockerfile]);
+            for tag in &tags {
+                if multi_arch {
+                    docker_build.args(["--tag", &platform_tag(tag, platform)]);
+                } else {
+                    docker_build.args(["--tag", tag]);
+                }
+            }
 
-        docker_build.progress(progress)?;
-        docker_build.verbose(msg_info.verbosity);
-        for arg in &build_arg {
-            docker_build.args(["--build-arg", arg]);
-        }
+            for label in labels
+                .as_deref()
+                .unwrap_or("")
+                .split('\n')
+                .filter(|s| !s.is_empty())
+            {
+                docker_build.args(["--label", label]);
+            }
 
-        if let Some(opts) = &build_opts {
-            docker_build.args(docker::Engine::parse_opts(opts)?);
-        }
+            docker_build.cross_labels(&target.name, platform.target.triple());
+            docker_build.args(["--file", &dockerfile]);
 
-        docker_build.arg(match target.needs_workspace_root_context() {
-            true => root.as_path(),
-            false => Path::new("."),
-        });
+            docker_build.progress(progress)?;
+            docker_build.verbose(msg_info.verbosity);
+            for arg in &build_arg {
+                docker_build.args(["--build-arg", arg]);
+            }
 
-        if !dry_run && (force || !push || gha) {
-            let result = docker_build
-                .run(msg_info, false)
-                .engine_warning(engine)
-                .buildkit_warning();
-            if gha && targets.len() > 1 {
-                if let Err(e) = &result {
-                    // TODO: Determine what instruction errorred, and place warning on that line with appropriate warning
-                    gha_error(&format!("file=docker/{dockerfile},title=Build failed::{e}"));
-                }
+            if let Some(opts) = &build_opts {
+                docker_build.args(docker::Engine::parse_opts(opts)?);
             }
-            results.push(
-                result
-                    .map(|_| target.clone())
-                    .map_err(|e| (target.clone(), e)),
-            );
-            if !no_fastfail && results.last().unwrap().is_err() {
-                break;
+
+            docker_build.arg(match target.needs_workspace_root_context() {
+                true => root.as_path(),
+                false => Path::new("."),
+            });
+
+            if execute {
+                let result = docker_build
+                    .run(msg_info, false)
+                    .engine_warning(engine)
+                    .buildkit_warning();
+                if gha && targets.len() > 1 {
+                    if let Err(e) = &result {
+                        // TODO: Determine what instruction errorred, and place warning on that line with appropriate warning
+                        gha_error(&format!("file=docker/{dockerfile},title=Build failed::{e}"));
+                    }
+                }
+                let failed = result.is_err();
+                results.push(
+                    result
+                        .map(|_| target.clone())
+                        .map_err(|e| (target.clone(), e)),
+                );
+                if gha && targets.len() > 1 {
+                    gha_print("::endgroup::");
+                }
+                if failed {
+                    if no_fastfail {
+                        continue 'targets;
+                    }
+                    break 'targets;
+                }
+            } else {
+                docker_build.print(msg_info)?;
+                if !dry_run {
+                    msg_info.fatal("refusing to push, use --force to override", 1);
+                }
+                if gha && targets.len() > 1 {
+                    gha_print("::endgroup::");
+                }
             }
-        } else {
-            docker_build.print(msg_info)?;
-            if !dry_run {
-                msg_info.fatal("refusing to push, use --force to override", 1);
+        }
+
+        if multi_arch && push {
+            for tag in &tags {
+                let sources: Vec<_> = platforms.iter().map(|p| platform_tag(tag, p)).collect();
+                for mut command in manifest_commands(engine, tag, &sources) {
+                    if execute {
+                        let result = command
+                            .run(msg_info, false)
+                            .wrap_err_with(|| format!("when creating the manifest list `{tag}`"));
+                        let failed = result.is_err();
+                        results.push(
+                            result
+                                .map(|_| target.clone())
+                                .map_err(|e| (target.clone(), e)),
+                        );
+                        if failed && !no_fastfail {
+                            break 'targets;
+                        } else if failed {
+                            continue 'targets;
+                        }
+                    } else {
+                        command.print(msg_info)?;
+                    }
+                }
             }
         }
+
         if gha {
             gha_output("image", &tags[0])?;
             gha_output("images", &format!("'{}'", serde_json::to_string(&tags)?))?;
-            if targets.len() > 1 {
-                gha_print("::endgroup::");
-            }
         }
     }
     if gha {
@@ -351,6 +403,32 @@ pub fn build_docker_image(
     Ok(())
 }
 
+/// The tag of the image for a single platform of a manifest list.
+fn platform_tag(tag: &str, platform: &ImagePlatform) -> String {
+    format!("{tag}-{}", platform.docker_platform().replace('/', "-"))
+}
+
+/// The commands combining the images for each platform into the manifest
+/// list `tag`, and pushing it.
+fn manifest_commands(engine: &docker::Engine, tag: &str, sources: &[String]) -> Vec<Command> {
+    match engine.kind {
+        kind if kind.is_podman() => {
+            let mut create = engine.command();
+            create.args(["manifest", "create", tag]);
+            create.args(sources);
+            let mut push = engine.command();
+            push.args(["manifest", "push", "--all", tag, &format!("docker://{tag}")]);
+            vec![create, push]
+        }
+        _ => {
+            let mut create = engine.command();
+            create.args(["buildx", "imagetools", "create", "--tag", tag]);
+            create.args(sources);
+            vec![create]
+        }
+    }
+}
+
 fn has_native_image(
     platform: &str,
     target: &ImageTarget,
@@ -455,3 +533,24 @@ pub fn job_summary(
     }
     Ok(summary)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn platform_tags() -> cross::Result<()> {
+        let tag = "ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main";
+        let platform: ImagePlatform = "aarch64-unknown-linux-gnu".parse()?;
+        assert_eq!(
+            platform_tag(tag, &platform),
+            "ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main-linux-arm64"
+        );
+        let platform: ImagePlatform = "linux/arm/v7=armv7-unknown-linux-gnueabihf".parse()?;
+        assert_eq!(
+            platform_tag(tag, &platform),
+            "ghcr.io/cross-rs/aarch64-unknown-linux-gnu:main-linux-arm-v7"
+        );
+        Ok(())
+    }
+}
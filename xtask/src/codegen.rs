@@ -2,18 +2,19 @@ use clap::Args;
 use eyre::Context;
 use std::fmt::Write;
 
-use crate::util::{get_cargo_workspace, get_matrix};
+use crate::util::{get_cargo_workspace, get_matrix, ImageTarget};
 
 #[derive(Args, Debug)]
 pub struct Codegen {}
 
 pub fn codegen(Codegen { .. }: Codegen) -> cross::Result<()> {
     let path = get_cargo_workspace().join("src/docker/provided_images.rs");
-    std::fs::write(path, docker_images()).wrap_err("when writing src/docker/provided_images.rs")?;
+    std::fs::write(path, docker_images()?)
+        .wrap_err("when writing src/docker/provided_images.rs")?;
     Ok(())
 }
 
-pub fn docker_images() -> String {
+pub fn docker_images() -> cross::Result<String> {
     let mut images = String::from(
         r#"#![doc = "*** AUTO-GENERATED, do not touch. Run `cargo xtask codegen` to update ***"]
 use super::{ImagePlatform, ProvidedImage};
@@ -32,7 +33,8 @@ pub static PROVIDED_IMAGES: &[ProvidedImage] = &["#,
         ProvidedImage {{
             name: "{name}",
             platforms: &[{platform}],
-            sub: {sub}
+            sub: {sub},
+            runner: {runner}
         }},"#,
             name = image_target.target.clone(),
             platform = &image_target
@@ -50,13 +52,52 @@ pub static PROVIDED_IMAGES: &[ProvidedImage] = &["#,
                 format!(r#"Some("{}")"#, sub)
             } else {
                 "None".to_string()
+            },
+            runner = match image_runner(&image_target.to_image_target())? {
+                Some(runner) => format!(r#"Some("{runner}")"#),
+                None => "None".to_owned(),
             }
         )
         .expect("writing to string should not fail")
     }
 
     images.push_str("\n];\n");
-    images
+    Ok(images)
+}
+
+/// The runner the image uses by default, from the `CROSS_TARGET_RUNNER` of
+/// its Dockerfile.
+fn image_runner(target: &ImageTarget) -> cross::Result<Option<&'static str>> {
+    let dockerfile = get_cargo_workspace()
+        .join("docker")
+        .join(format!("Dockerfile.{target}"));
+    let content = cross::file::read(&dockerfile)?;
+    runner_from_dockerfile(&target.name, &content)
+        .wrap_err_with(|| format!("when reading the runner of `{dockerfile:?}`"))
+}
+
+fn runner_from_dockerfile(triple: &str, dockerfile: &str) -> cross::Result<Option<&'static str>> {
+    // the images run on x86_64, where x86 binaries run natively
+    let is_native = |arch: &str| matches!(arch, "x86_64" | "i586" | "i686");
+    let runner = dockerfile
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("ENV CROSS_TARGET_RUNNER="));
+    let Some(runner) = runner else {
+        // the binaries are run directly, which only works for native ones
+        let arch = triple.split('-').next().unwrap_or_default();
+        return Ok((triple.contains("-linux-") && is_native(arch)).then_some("native"));
+    };
+    let words: Vec<_> = runner.trim_matches('"').split_whitespace().collect();
+    Ok(Some(match words[..] {
+        // the runner scripts select `CROSS_RUNNER`, defaulting to these
+        ["/linux-runner" | "/qemu-runner", arch] if is_native(arch) => "native",
+        ["/linux-runner" | "/qemu-runner" | "/android-runner", _] => "qemu-user",
+        [.., "wine"] => "wine",
+        ["node"] => "node",
+        // bare-metal targets are emulated as a whole system
+        [qemu] if qemu.starts_with("qemu-") => "qemu-system",
+        _ => return Err(eyre::eyre!("unknown runner `{runner}`")),
+    }))
 }
 
 #[cfg(test)]
@@ -64,6 +105,51 @@ pub static PROVIDED_IMAGES: &[ProvidedImage] = &["#,
 pub fn ensure_correct_codegen() -> cross::Result<()> {
     let provided_images = crate::util::get_cargo_workspace().join("src/docker/provided_images.rs");
     let content = cross::file::read(provided_images)?;
-    assert_eq!(content.replace("\r\n", "\n"), docker_images());
+    assert_eq!(content.replace("\r\n", "\n"), docker_images()?);
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+pub fn dockerfile_runners() -> cross::Result<()> {
+    let runner = |triple, dockerfile| runner_from_dockerfile(triple, dockerfile);
+    assert_eq!(
+        runner(
+            "aarch64-unknown-linux-gnu",
+            r#"ENV CROSS_TARGET_RUNNER="/linux-runner aarch64""#
+        )?,
+        Some("qemu-user")
+    );
+    assert_eq!(
+        runner(
+            "x86_64-unknown-linux-musl",
+            r#"ENV CROSS_TARGET_RUNNER="/qemu-runner x86_64""#
+        )?,
+        Some("native")
+    );
+    assert_eq!(
+        runner(
+            "i686-linux-android",
+            r#"ENV CROSS_TARGET_RUNNER="/android-runner i686""#
+        )?,
+        Some("qemu-user")
+    );
+    assert_eq!(
+        runner("thumbv7em-none-eabihf", "ENV CROSS_TARGET_RUNNER=qemu-arm")?,
+        Some("qemu-system")
+    );
+    assert_eq!(
+        runner(
+            "wasm32-unknown-emscripten",
+            r#"ENV CROSS_TARGET_RUNNER="node""#
+        )?,
+        Some("node")
+    );
+    assert_eq!(
+        runner("x86_64-unknown-linux-gnu", "FROM ubuntu:20.04")?,
+        Some("native")
+    );
+    assert_eq!(runner("x86_64-unknown-freebsd", "FROM ubuntu:20.04")?, None);
+    assert!(runner("x86_64-unknown-linux-gnu", "ENV CROSS_TARGET_RUNNER=box64").is_err());
     Ok(())
 }
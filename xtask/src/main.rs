@@ -136,5 +136,5 @@ fn get_container_engine(
     } else {
         docker::get_container_engine()?
     };
-    docker::Engine::from_path(engine, None, None, msg_info)
+    docker::Engine::from_path(engine, None, None, None, true, msg_info)
 }